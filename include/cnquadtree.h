@@ -0,0 +1,73 @@
+/*
+ * C API of cnquadtree. Build libcnquadtree.a and a shared library to link with by running
+ * `cargo rustc --lib --release --features ffi --crate-type staticlib --crate-type cdylib`.
+ * See src/ffi.rs for the documentation of each function. Keep in sync with it.
+ */
+
+#ifndef CNQUADTREE_H
+#define CNQUADTREE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A tree with f64 coordinates and u64 items. Only used through pointers. */
+typedef struct CnqTree CnqTree;
+
+/* Bounds of a region. */
+typedef struct CnqBounds {
+    double min_x;
+    double min_y;
+    double max_x;
+    double max_y;
+} CnqBounds;
+
+/* Identifies a node. Only valid with the tree that returned it. */
+typedef struct CnqNodeId {
+    uint64_t key;
+    uint32_t tree;
+} CnqNodeId;
+
+/* Result of a call. */
+typedef enum CnqStatus {
+    CNQ_OK = 0,
+    CNQ_NULL_POINTER,
+    CNQ_INVALID_INDEX,
+    CNQ_OUT_OF_BOUNDS,
+    CNQ_BUFFER_TOO_SMALL,
+    CNQ_INVALID_DIRECTION,
+    CNQ_ALREADY_SUBDIVIDED,
+    CNQ_NOT_SUBDIVIDED,
+    CNQ_CHILDREN_SUBDIVIDED,
+    CNQ_CELL_TOO_SMALL,
+    CNQ_MAX_DEPTH_EXCEEDED,
+    CNQ_OUT_OF_NODES,
+    CNQ_OTHER,
+    CNQ_NOT_LEAF,
+    CNQ_CORRUPTED,
+    CNQ_INVALID_BOUNDS,
+} CnqStatus;
+
+CnqStatus cnq_tree_new(CnqBounds bounds, uint64_t item, CnqTree **out);
+void cnq_tree_free(CnqTree *tree);
+CnqStatus cnq_tree_root(const CnqTree *tree, CnqNodeId *out);
+CnqStatus cnq_tree_subdivide(CnqTree *tree, CnqNodeId node, const uint64_t *items,
+                             CnqNodeId *out_children);
+CnqStatus cnq_tree_pop_children(CnqTree *tree, CnqNodeId node, uint64_t *out_items);
+CnqStatus cnq_tree_locate(const CnqTree *tree, double x, double y, CnqNodeId *out);
+CnqStatus cnq_tree_neighbors(const CnqTree *tree, CnqNodeId node, uint32_t direction,
+                             CnqNodeId *out, size_t capacity, size_t *out_len);
+CnqStatus cnq_node_bounds(const CnqTree *tree, CnqNodeId node, CnqBounds *out);
+CnqStatus cnq_node_item(const CnqTree *tree, CnqNodeId node, uint64_t *out);
+CnqStatus cnq_node_set_item(CnqTree *tree, CnqNodeId node, uint64_t item);
+CnqStatus cnq_node_is_leaf(const CnqTree *tree, CnqNodeId node, bool *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CNQUADTREE_H */
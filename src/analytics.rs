@@ -0,0 +1,199 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashSet;
+
+/// Mass, centroid and second moments of a region, from [`CNQuadtree::region_moments`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Moments {
+    /// Total weighted area.
+    pub mass: f64,
+    /// Center of mass.
+    pub centroid: Point<f64>,
+    /// Second moments about the centroid divided by the mass, as `(xx, xy, yy)`: the
+    /// covariance of a point drawn from the region.
+    pub covariance: (f64, f64, f64),
+}
+
+impl Moments {
+    /// Returns the angle in radians from the x-axis to the region's major axis, in
+    /// `[-PI / 2, PI / 2]`. The y-axis points down, as with bounds.
+    pub fn orientation(&self) -> f64 {
+        let (xx, xy, yy) = self.covariance;
+        0.5 * (2.0 * xy).atan2(xx - yy)
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the total area of the leaves whose item satisfies `predicate`. Returns None if a
+    /// coordinate can't be converted to `f64`.
+    pub fn region_area<F>(&self, mut predicate: F) -> Option<f64>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut area = 0.0;
+        for leaf in self.leaves_morton() {
+            let node = self.get_node(leaf)?;
+            if predicate(node.get_item()) {
+                let Bounds(left, top, right, bottom) = self.bounds_f64(leaf)?;
+                area += (right - left) * (bottom - top);
+            }
+        }
+        Some(area)
+    }
+
+    /// Returns the total length of the boundary of the leaves whose item satisfies `predicate`:
+    /// the edges they share with leaves that don't, and those on the tree's bounds. Returns
+    /// None if a coordinate can't be converted to `f64`.
+    pub fn region_perimeter<F>(&self, mut predicate: F) -> Option<f64>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let region: HashSet<NodeId> = self
+            .leaves_morton()
+            .filter(|&leaf| {
+                self.get_node(leaf)
+                    .is_some_and(|node| predicate(node.get_item()))
+            })
+            .collect();
+        let mut perimeter = 0.0;
+        for &leaf in &region {
+            let Bounds(left, top, right, bottom) = self.bounds_f64(leaf)?;
+            perimeter += 2.0 * ((right - left) + (bottom - top));
+            for direction in [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ] {
+                for neighbor in self.get_neighbors(leaf, direction).unwrap_or_default() {
+                    if !region.contains(&neighbor) {
+                        continue;
+                    }
+                    let Bounds(n_left, n_top, n_right, n_bottom) = self.bounds_f64(neighbor)?;
+                    perimeter -= match direction {
+                        Cardinality::West | Cardinality::East => {
+                            bottom.min(n_bottom) - top.max(n_top)
+                        }
+                        Cardinality::North | Cardinality::South => {
+                            right.min(n_right) - left.max(n_left)
+                        }
+                    };
+                }
+            }
+        }
+        Some(perimeter)
+    }
+
+    /// Returns the center of mass of the leaves whose item satisfies `predicate`, weighting
+    /// them by area. Returns None if no leaf satisfies it or a coordinate can't be converted to
+    /// `f64`.
+    pub fn region_centroid<F>(&self, mut predicate: F) -> Option<Point<f64>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.region_moments(|item| if predicate(item) { 1.0 } else { 0.0 })
+            .map(|moments| moments.centroid)
+    }
+
+    /// Returns the mass, centroid and second moments of the leaves, treating each as uniformly
+    /// filled with the density `weight` returns for its item. A weight of 0 leaves a leaf out;
+    /// return 1 for leaves in a region to weight them by area only. Returns None if the total
+    /// mass is 0 or a coordinate can't be converted to `f64`.
+    pub fn region_moments<F>(&self, mut weight: F) -> Option<Moments>
+    where
+        F: FnMut(&T) -> f64,
+    {
+        let (mut mass, mut x, mut y, mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        for leaf in self.leaves_morton() {
+            let density = weight(self.get_node(leaf)?.get_item());
+            if density == 0.0 {
+                continue;
+            }
+            let Bounds(left, top, right, bottom) = self.bounds_f64(leaf)?;
+            let (width, height) = (right - left, bottom - top);
+            let m = density * width * height;
+            let (cx, cy) = ((left + right) / 2.0, (top + bottom) / 2.0);
+            mass += m;
+            x += m * cx;
+            y += m * cy;
+            // A uniform rectangle adds its own spread along each axis, but no correlation.
+            xx += m * (cx * cx + width * width / 12.0);
+            xy += m * cx * cy;
+            yy += m * (cy * cy + height * height / 12.0);
+        }
+        if mass == 0.0 {
+            return None;
+        }
+        let (cx, cy) = (x / mass, y / mass);
+        Some(Moments {
+            mass,
+            centroid: (cx, cy),
+            covariance: (
+                xx / mass - cx * cx,
+                xy / mass - cx * cy,
+                yy / mass - cy * cy,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_area_and_perimeter() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 0, 0, 1]).unwrap();
+        tree.subdivide(nw, [1, 1, 1, 0]).unwrap();
+        tree.subdivide(ne, [1, 0, 0, 0]).unwrap();
+
+        assert_eq!(tree.region_area(|&label| label == 1), Some(32.0));
+        assert_eq!(tree.region_area(|&label| label == 2), Some(0.0));
+        assert_eq!(tree.region_area(|_| true), Some(64.0));
+        // An L of four cells across the top left, and SE.
+        assert_eq!(
+            tree.region_perimeter(|&label| label == 1),
+            Some(20.0 + 16.0)
+        );
+        assert_eq!(tree.region_perimeter(|_| true), Some(32.0));
+        assert_eq!(tree.region_perimeter(|_| false), Some(0.0));
+    }
+
+    #[test]
+    fn region_moments() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 0, 0, 3]).unwrap();
+        tree.subdivide(nw, [1, 1, 0, 0]).unwrap();
+
+        // A 4 by 2 bar along the top left.
+        let bar = tree
+            .region_moments(|&label| if label == 1 { 1.0 } else { 0.0 })
+            .unwrap();
+        assert_eq!(bar.mass, 8.0);
+        assert_eq!(bar.centroid, (2.0, 1.0));
+        let (xx, xy, yy) = bar.covariance;
+        assert!((xx - 16.0 / 12.0).abs() < 1e-9);
+        assert_eq!(xy, 0.0);
+        assert!((yy - 4.0 / 12.0).abs() < 1e-9);
+        assert_eq!(bar.orientation(), 0.0);
+        assert_eq!(tree.region_centroid(|&label| label == 1), Some((2.0, 1.0)));
+        assert_eq!(tree.region_centroid(|&label| label == 2), None);
+
+        // SE weighs three times as much as the bar per unit of area.
+        let both = tree.region_moments(|&label| f64::from(label)).unwrap();
+        assert_eq!(both.mass, 8.0 + 48.0);
+        assert_eq!(
+            both.centroid,
+            ((16.0 + 48.0 * 6.0) / 56.0, (8.0 + 48.0 * 6.0) / 56.0)
+        );
+        assert!(both.orientation() > 0.0);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::budget::PostOrderCursor;
+use crate::error::{Error, PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode, SplitPolicy};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use slotmap::{DefaultKey, SecondaryMap};
+
+/// A tree keeping its items in an arena apart from its nodes, for large items.
+///
+/// The nodes of a [`CNQuadtree`] hold their items, so with large items every hop of a
+/// traversal or neighbor query pulls a whole item into the cache to read a few indices. Here
+/// the structure is a `CNQuadtree<(), S>`, available through [`ItemArena::tree`], whose nodes
+/// hold only their topology and bounds and are packed together, and each node's item lives in a
+/// parallel map read only when asked for.
+///
+/// This is a container beside [`CNQuadtree`], which still stores items in its nodes. Read-only
+/// queries go through [`ItemArena::tree`]; changes go through the methods here, which keep the
+/// items in step with the structure.
+#[derive(Clone, Debug)]
+pub struct ItemArena<T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: CNQuadtree<(), S>,
+    items: SecondaryMap<DefaultKey, T>,
+}
+
+impl<T, S> ItemArena<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a tree of a single leaf holding `item`.
+    pub fn new(item: T, bounds: impl Into<Bounds<S>>) -> Self {
+        Self::with_capacity(item, bounds, 0)
+    }
+
+    /// Returns a tree of a single leaf holding `item`, with room for at least `capacity` nodes
+    /// and items before reallocating.
+    pub fn with_capacity(item: T, bounds: impl Into<Bounds<S>>, capacity: usize) -> Self {
+        let tree = CNQuadtree::with_capacity((), bounds, capacity);
+        let mut items = SecondaryMap::with_capacity(capacity);
+        items.insert(tree.get_root().key, item);
+        Self { tree, items }
+    }
+
+    /// Returns the tree's structure.
+    pub fn tree(&self) -> &CNQuadtree<(), S> {
+        &self.tree
+    }
+
+    /// Returns the item of a node, or None if the node doesn't exist.
+    pub fn item(&self, index: NodeId) -> Option<&T> {
+        self.tree.get_node(index)?;
+        self.items.get(index.key)
+    }
+
+    /// Returns the item of a node mutably, or None if the node doesn't exist.
+    pub fn item_mut(&mut self, index: NodeId) -> Option<&mut T> {
+        self.tree.get_node(index)?;
+        self.items.get_mut(index.key)
+    }
+
+    /// Returns the leaf containing the point and its item, or None if the point is outside the
+    /// tree's bounds.
+    pub fn point_locate(&self, point: Point<S>) -> Option<(NodeId, &T)> {
+        let leaf = self.tree.point_locate(point)?;
+        Some((leaf, self.items.get(leaf.key)?))
+    }
+
+    /// Returns an iterator over the leaves and their items in Morton order.
+    pub fn leaves(&self) -> impl Iterator<Item = (NodeId, &T)> + '_ {
+        self.tree
+            .leaves_morton()
+            .filter_map(|leaf| Some((leaf, self.items.get(leaf.key)?)))
+    }
+
+    /// Subdivides a leaf, giving its children `items` in the order NW, NE, SW, SE. The leaf
+    /// keeps its item, as in [`CNQuadtree`].
+    pub fn subdivide(
+        &mut self,
+        index: NodeId,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>> {
+        let children = match self.tree.subdivide(index, [(); 4]) {
+            Ok(children) => children,
+            Err(e) => {
+                return Err(SubdivideError {
+                    items,
+                    source: e.source,
+                })
+            }
+        };
+        for (child, item) in children.iter().zip(items) {
+            self.items.insert(child.key, item);
+        }
+        Ok(children)
+    }
+
+    /// Subdivides a leaf at `middle` like [`CNQuadtree::subdivide_at`].
+    pub fn subdivide_at(
+        &mut self,
+        index: NodeId,
+        middle: Point<S>,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>> {
+        let children = match self.tree.subdivide_at(index, middle, [(); 4]) {
+            Ok(children) => children,
+            Err(e) => {
+                return Err(SubdivideError {
+                    items,
+                    source: e.source,
+                })
+            }
+        };
+        for (child, item) in children.iter().zip(items) {
+            self.items.insert(child.key, item);
+        }
+        Ok(children)
+    }
+
+    /// Removes the node's children, which must be leaves, and returns their items.
+    pub fn pop_children(&mut self, index: NodeId) -> Result<[T; 4], PopChildrenError> {
+        let children = self
+            .tree
+            .get_node(index)
+            .ok_or(PopChildrenError::InvalidIndex)?
+            .get_children_index()
+            .ok_or(PopChildrenError::NotSubdivided)?;
+        self.tree.pop_children(index)?;
+        Ok(children.map(|child| {
+            self.items
+                .remove(child.key)
+                .expect("a popped leaf has an item")
+        }))
+    }
+
+    /// Removes every node and replaces the root with a leaf holding `item`, like
+    /// [`CNQuadtree::clear`]. Returns the new root's index.
+    pub fn clear(&mut self, item: T) -> NodeId {
+        let root = self.tree.clear(());
+        self.items.clear();
+        self.items.insert(root.key, item);
+        root
+    }
+
+    /// Merges every family of four leaves holding equal items into their parent, which takes
+    /// their item, like [`CNQuadtree::compress`].
+    pub fn compress(&mut self) -> Result<(), Error>
+    where
+        T: PartialEq,
+    {
+        let mut cursor = PostOrderCursor::new(&self.tree);
+        while let Some(index) = cursor.next(&self.tree) {
+            let Some(children) = self
+                .tree
+                .get_node(index)
+                .and_then(|n| n.get_children_index())
+            else {
+                continue;
+            };
+            let leaves = children
+                .iter()
+                .all(|&child| self.tree.get_node(child).is_some_and(|n| n.is_leaf()));
+            let items = children.map(|child| self.items.get(child.key));
+            if leaves && items.iter().all(|item| item.is_some() && *item == items[0]) {
+                let [item, ..] = self.pop_children(index)?;
+                self.items.insert(index.key, item);
+            }
+        }
+        Ok(())
+    }
+
+    /// Subdivides leaves until no leaf has an edge neighbor more than one level deeper, like
+    /// [`CNQuadtree::balance`]. `split` returns the items of the children of a leaf being
+    /// subdivided, in the order NW, NE, SW, SE.
+    pub fn balance<F>(&mut self, mut split: F) -> Result<(), Error>
+    where
+        F: FnMut(Bounds<S>, &T) -> [T; 4],
+    {
+        self.tree.balance(|_, _| [(); 4])?;
+        // Parents come before their children, so new families get their items from parents
+        // that already have one.
+        for index in self.tree.level_order() {
+            let node = self.tree.get_node(index).ok_or(Error::DanglingIndex)?;
+            let Some(children) = node.get_children_index() else {
+                continue;
+            };
+            if self.items.contains_key(children[0].key) {
+                continue;
+            }
+            let item = self.items.get(index.key).ok_or(Error::DanglingIndex)?;
+            let items = split(node.get_bounds(), item);
+            for (child, item) in children.iter().zip(items) {
+                self.items.insert(child.key, item);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a batch, like [`CNQuadtree::begin_batch`].
+    pub fn begin_batch(&mut self) {
+        self.tree.begin_batch();
+    }
+
+    /// Ends a batch and recomputes the cardinal neighbors, like [`CNQuadtree::commit`].
+    pub fn commit(&mut self) {
+        self.tree.commit();
+    }
+
+    /// Sets the maximum depth, like [`CNQuadtree::set_max_depth`].
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.tree.set_max_depth(max_depth);
+    }
+
+    /// Sets the minimum cell size, like [`CNQuadtree::set_min_cell_size`].
+    pub fn set_min_cell_size(&mut self, size: S) {
+        self.tree.set_min_cell_size(size);
+    }
+
+    /// Sets the node limit, like [`CNQuadtree::set_node_limit`].
+    pub fn set_node_limit(&mut self, limit: Option<usize>) {
+        self.tree.set_node_limit(limit);
+    }
+
+    /// Sets the split policy, like [`CNQuadtree::set_split_policy`].
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.tree.set_split_policy(policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Cardinality;
+
+    #[test]
+    fn item_arena() {
+        let mut tree = ItemArena::new([0u8; 200], (0, 0, 8, 8));
+        let root = tree.tree().get_root();
+        let children = tree.subdivide(root, [[1; 200]; 4]).unwrap();
+        assert_eq!(tree.item(root), Some(&[0; 200]));
+        tree.item_mut(children[3]).unwrap()[0] = 2;
+        assert_eq!(tree.point_locate((7, 7)).unwrap().1[0], 2);
+        assert_eq!(tree.leaves().count(), 4);
+        // Neighbor queries go through the structure alone.
+        let east = tree.tree().neighbors_iter(children[0], Cardinality::East);
+        assert_eq!(east.collect::<Vec<_>>(), [children[1]]);
+
+        tree.subdivide(children[0], [[3; 200]; 4]).unwrap();
+        let err = tree.subdivide(children[0], [[4; 200]; 4]).unwrap_err();
+        assert_eq!(err.source, crate::Error::AlreadySubdivided);
+        assert_eq!(
+            tree.pop_children(root).unwrap_err(),
+            PopChildrenError::ChildrenSubdivided
+        );
+        assert_eq!(tree.pop_children(children[0]).unwrap(), [[3; 200]; 4]);
+        assert_eq!(tree.item(children[0]), Some(&[1; 200]));
+        assert_eq!(tree.items.len(), 5);
+
+        // Balancing gives the new children items split from their parents'.
+        let [_, nw_ne, ..] = tree.subdivide(children[0], [[5; 200]; 4]).unwrap();
+        tree.subdivide(nw_ne, [[6; 200]; 4]).unwrap();
+        tree.balance(|_, item| [*item; 4]).unwrap();
+        let (leaf, item) = tree.point_locate((5, 1)).unwrap();
+        assert_eq!(tree.tree()[leaf].level(), 2);
+        assert_eq!(item, &[1; 200]);
+        assert_eq!(tree.items.len(), tree.tree().node_count());
+
+        // Compressing merges families of equal leaves, cascading up to the root.
+        tree.clear([0; 200]);
+        let root = tree.tree().get_root();
+        let children = tree.subdivide(root, [[1; 200]; 4]).unwrap();
+        tree.subdivide(children[2], [[1; 200]; 4]).unwrap();
+        tree.compress().unwrap();
+        assert_eq!(tree.tree().node_count(), 1);
+        assert_eq!(tree.item(root), Some(&[1; 200]));
+        assert_eq!(tree.items.len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+//! Bevy integration, enabled by the `bevy` feature.
+//!
+//! [`CNQuadtreePlugin`] keeps a [`SpatialIndex`] resource holding the x and y translation of
+//! every entity with an [`Indexed`] marker and a [`GlobalTransform`]. The index is synced in
+//! [`PostUpdate`] after transform propagation, so it reflects the frame's final positions.
+//! Entities outside the index's bounds aren't indexed until they move back in.
+//!
+//! Systems can query the resource directly, or send [`RegionQuery`] events and read the
+//! matching [`RegionQueryResult`] events in the next frame.
+
+use crate::node::{Bounds, Point};
+use crate::points::CNPointQuadtree;
+use bevy_app::{App, Plugin, PostUpdate};
+use bevy_ecs::prelude::*;
+use bevy_transform::components::GlobalTransform;
+use bevy_transform::TransformSystem;
+use std::collections::HashMap;
+
+/// Adds a [`SpatialIndex`] and the systems keeping it in sync.
+#[derive(Clone, Debug)]
+pub struct CNQuadtreePlugin {
+    /// The indexed area, as `(min_x, min_y, max_x, max_y)`.
+    pub bounds: Bounds<f32>,
+    /// The number of entities a leaf holds before it's subdivided.
+    pub capacity: usize,
+    /// The deepest level leaves are subdivided to.
+    pub max_depth: usize,
+}
+
+impl CNQuadtreePlugin {
+    /// Returns a plugin indexing `bounds`, with a capacity of 8 and a maximum depth of 12.
+    pub fn new(bounds: impl Into<Bounds<f32>>) -> Self {
+        let bounds = bounds.into();
+        Self {
+            bounds,
+            capacity: 8,
+            max_depth: 12,
+        }
+    }
+}
+
+impl Plugin for CNQuadtreePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialIndex::new(
+            self.bounds,
+            self.capacity,
+            self.max_depth,
+        ))
+        .add_event::<RegionQuery>()
+        .add_event::<RegionQueryResult>()
+        .configure_sets(
+            PostUpdate,
+            CNQuadtreeSystems::Sync.after(TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                sync_index.in_set(CNQuadtreeSystems::Sync),
+                answer_region_queries.after(CNQuadtreeSystems::Sync),
+            ),
+        );
+    }
+}
+
+/// System sets of [`CNQuadtreePlugin`].
+#[derive(SystemSet, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CNQuadtreeSystems {
+    /// Updates the [`SpatialIndex`] from changed transforms.
+    Sync,
+}
+
+/// Marks an entity to be kept in the [`SpatialIndex`].
+#[derive(Component, Copy, Clone, Debug, Default)]
+pub struct Indexed;
+
+/// Asks for the indexed entities inside a region. Answered by a [`RegionQueryResult`] with the
+/// same tag.
+#[derive(Event, Copy, Clone, Debug)]
+pub struct RegionQuery {
+    /// Copied into the answering [`RegionQueryResult`] to match it with this query.
+    pub tag: u64,
+    /// The queried area, as `(min_x, min_y, max_x, max_y)`.
+    pub region: Bounds<f32>,
+}
+
+/// The entities inside the region of the [`RegionQuery`] with the same tag.
+#[derive(Event, Clone, Debug)]
+pub struct RegionQueryResult {
+    /// The tag of the answered [`RegionQuery`].
+    pub tag: u64,
+    /// The indexed entities inside the queried region.
+    pub entities: Vec<Entity>,
+}
+
+/// A bucket point quadtree of entity positions.
+#[derive(Resource)]
+pub struct SpatialIndex {
+    points: CNPointQuadtree<Entity, f32>,
+    positions: HashMap<Entity, Point<f32>>,
+}
+
+impl SpatialIndex {
+    /// Returns an empty index. See [`CNQuadtreePlugin`] for the parameters.
+    pub fn new(bounds: impl Into<Bounds<f32>>, capacity: usize, max_depth: usize) -> Self {
+        let bounds = bounds.into();
+        let points = CNPointQuadtree::from_points(bounds, max_depth, capacity, [])
+            .expect("an empty point set always fits");
+        Self {
+            points,
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Returns the underlying point quadtree.
+    pub fn points(&self) -> &CNPointQuadtree<Entity, f32> {
+        &self.points
+    }
+
+    /// Returns the position an entity is indexed at.
+    pub fn position(&self, entity: Entity) -> Option<Point<f32>> {
+        self.positions.get(&entity).copied()
+    }
+
+    /// Returns the entities inside the region.
+    pub fn query_region(
+        &self,
+        region: impl Into<Bounds<f32>>,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        let region = region.into();
+        self.points.query_region(region).map(|&(_, entity)| entity)
+    }
+
+    /// Indexes an entity at a position, replacing its previous one. Entities outside the
+    /// index's bounds are removed instead.
+    pub fn set(&mut self, entity: Entity, position: Point<f32>) {
+        if self.positions.get(&entity) == Some(&position) {
+            return;
+        }
+        self.remove(entity);
+        if self.points.insert(position, entity).is_ok() {
+            self.positions.insert(entity, position);
+        }
+    }
+
+    /// Removes an entity from the index.
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(position) = self.positions.remove(&entity) {
+            self.points.remove_where(position, |&e| e == entity);
+        }
+    }
+}
+
+type ChangedIndexed = (With<Indexed>, Changed<GlobalTransform>);
+
+fn sync_index(
+    mut index: ResMut<SpatialIndex>,
+    changed: Query<(Entity, &GlobalTransform), ChangedIndexed>,
+    mut removed: RemovedComponents<Indexed>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    for (entity, transform) in &changed {
+        let translation = transform.translation();
+        index.set(entity, (translation.x, translation.y));
+    }
+}
+
+fn answer_region_queries(
+    index: Res<SpatialIndex>,
+    mut queries: EventReader<RegionQuery>,
+    mut results: EventWriter<RegionQueryResult>,
+) {
+    for query in queries.read() {
+        results.send(RegionQueryResult {
+            tag: query.tag,
+            entities: index.query_region(query.region).collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_ecs::event::Events;
+    use bevy_transform::components::Transform;
+
+    #[test]
+    fn plugin() {
+        let mut app = App::new();
+        app.add_plugins(CNQuadtreePlugin {
+            capacity: 1,
+            ..CNQuadtreePlugin::new((0.0, 0.0, 64.0, 64.0))
+        });
+        let at = |x, y| GlobalTransform::from(Transform::from_xyz(x, y, 0.0));
+        let a = app.world_mut().spawn((Indexed, at(1.0, 1.0))).id();
+        let b = app.world_mut().spawn((Indexed, at(40.0, 40.0))).id();
+        let c = app.world_mut().spawn((Indexed, at(80.0, 1.0))).id();
+        app.world_mut().spawn(at(2.0, 2.0));
+        app.update();
+
+        let index = app.world().resource::<SpatialIndex>();
+        assert_eq!(index.points().len(), 2);
+        assert_eq!(index.position(c), None);
+        assert_eq!(
+            index
+                .query_region((0.0, 0.0, 32.0, 32.0))
+                .collect::<Vec<_>>(),
+            vec![a]
+        );
+
+        *app.world_mut().get_mut::<GlobalTransform>(a).unwrap() = at(50.0, 50.0);
+        app.world_mut().entity_mut(b).remove::<Indexed>();
+        app.world_mut().send_event(RegionQuery {
+            tag: 7,
+            region: Bounds(32.0, 32.0, 64.0, 64.0),
+        });
+        app.update();
+
+        let index = app.world().resource::<SpatialIndex>();
+        assert_eq!(index.points().len(), 1);
+        assert_eq!(index.position(a), Some((50.0, 50.0)));
+        let results = app.world().resource::<Events<RegionQueryResult>>();
+        let result = results.iter_current_update_events().next().unwrap();
+        assert_eq!((result.tag, result.entities.clone()), (7, vec![a]));
+    }
+}
@@ -0,0 +1,460 @@
+use crate::node::{child_bounds, Bounds, RegionQuadtreeNode, SplitPolicy};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+/// Magic bytes at the start of every encoded tree.
+pub const MAGIC: [u8; 4] = *b"CNQT";
+/// Version of the binary format written by [`CNQuadtree::save_to`].
+pub const FORMAT_VERSION: u16 = 2;
+
+// Layout of version 2, all integers little endian:
+//
+//   magic                  4 bytes
+//   version                u16
+//   min cell size          unit
+//   max depth              u64, u64::MAX if unlimited
+//   split policy           u8: 0 floor, 1 ceil, 2 median
+//   node limit             u64, u64::MAX if unlimited
+//   root bounds            4 units
+//   node count N           u64
+//   topology               ceil(N / 64) u64 words. Bit i is set if node i has children.
+//   rank directory         ceil(N / 64) u64 words. Word j is the number of set bits before
+//                          topology word j.
+//   items                  N items encoded by the item codec
+//
+// Version 1 is the same without the split policy and node limit, which load as the defaults.
+// A unit is a tag byte followed by either an i128 (tag 0) or the bits of an f64 (tag 1).
+// Nodes are numbered in level order: breadth first from the root, each node's children in NW,
+// NE, SW, SE order. That's Morton order within each level, but not the Morton order of the
+// whole tree, which would put each node's descendants right after it. The children of node i
+// are the four consecutive nodes starting at 1 + 4 * (number of set bits before i), which lets
+// a view find them from the rank directory without decoding the tree.
+
+/// Encodes and decodes items for [`CNQuadtree::save_to`] and [`CNQuadtree::load_from`].
+pub trait ItemCodec<T> {
+    fn encode(&mut self, item: &T, writer: &mut dyn Write) -> io::Result<()>;
+    fn decode(&mut self, reader: &mut dyn Read) -> io::Result<T>;
+}
+
+/// Codec for primitive numbers and `bool`, stored as their little endian bytes.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LeBytes;
+
+macro_rules! le_bytes_codec {
+    ($($t:ty),*) => {
+        $(
+            impl ItemCodec<$t> for LeBytes {
+                fn encode(&mut self, item: &$t, writer: &mut dyn Write) -> io::Result<()> {
+                    writer.write_all(&item.to_le_bytes())
+                }
+
+                fn decode(&mut self, reader: &mut dyn Read) -> io::Result<$t> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+le_bytes_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl ItemCodec<bool> for LeBytes {
+    fn encode(&mut self, item: &bool, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&[*item as u8])
+    }
+
+    fn decode(&mut self, reader: &mut dyn Read) -> io::Result<bool> {
+        match read_array::<1>(reader)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(invalid_data("invalid bool")),
+        }
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Writes the tree in the versioned binary format. Neighbors are not stored; they are rebuilt
+    /// by [`CNQuadtree::load_from`]. Child bounds aren't stored either, so trees with splits
+    /// other than [`SplitPolicy::Floor`](crate::SplitPolicy::Floor)'s fail with
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn save_to<W, C>(&self, writer: &mut W, codec: &mut C) -> io::Result<()>
+    where
+        W: Write,
+        C: ItemCodec<T>,
+    {
+        let order = self.level_order();
+        let mut topology = vec![0u64; order.len().div_ceil(64)];
+        for (i, &index) in order.iter().enumerate() {
+            let Some(node) = self.get_node(index) else {
+                continue;
+            };
+            if let Some(children) = node.get_children_index() {
+                topology[i / 64] |= 1 << (i % 64);
+                let nw = self.get_node(children[0]).map(|n| n.get_bounds());
+                if nw != child_bounds(node.get_bounds(), 0) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "tree isn't split at midpoints",
+                    ));
+                }
+            }
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        write_unit(writer, self.min_cell_size())?;
+        let max_depth = self.max_depth().map_or(u64::MAX, |d| d as u64);
+        writer.write_all(&max_depth.to_le_bytes())?;
+        let split_policy = match self.split_policy() {
+            SplitPolicy::Floor => 0u8,
+            SplitPolicy::Ceil => 1,
+            SplitPolicy::Median => 2,
+        };
+        writer.write_all(&[split_policy])?;
+        let node_limit = self.node_limit().map_or(u64::MAX, |n| n as u64);
+        writer.write_all(&node_limit.to_le_bytes())?;
+        let Bounds(left, top, right, bottom) = self
+            .get_node(self.get_root())
+            .ok_or_else(|| invalid_data("dangling index"))?
+            .get_bounds();
+        for unit in [left, top, right, bottom] {
+            write_unit(writer, unit)?;
+        }
+        writer.write_all(&(order.len() as u64).to_le_bytes())?;
+        for word in &topology {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        let mut rank = 0u64;
+        for word in &topology {
+            writer.write_all(&rank.to_le_bytes())?;
+            rank += word.count_ones() as u64;
+        }
+        for index in order {
+            let node = self
+                .get_node(index)
+                .ok_or_else(|| invalid_data("dangling index"))?;
+            codec.encode(node.get_item(), writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a tree written by [`CNQuadtree::save_to`] and rebuilds its neighbors.
+    pub fn load_from<R, C>(reader: &mut R, codec: &mut C) -> io::Result<Self>
+    where
+        R: Read,
+        C: ItemCodec<T>,
+    {
+        let header = Header::<S>::read(reader)?;
+        let len = header.len;
+        let words = len.div_ceil(64);
+        let topology = (0..words)
+            .map(|_| read_u64(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        for _ in 0..words {
+            read_u64(reader)?;
+        }
+        validate_topology(&topology, len)?;
+
+        let mut items = VecDeque::with_capacity(len);
+        for _ in 0..len {
+            items.push_back(codec.decode(reader)?);
+        }
+
+        let root_item = items
+            .pop_front()
+            .ok_or_else(|| invalid_data("tree has no nodes"))?;
+        let mut tree = Self::with_capacity(root_item, header.bounds, len);
+        let mut queue = VecDeque::from([tree.get_root()]);
+        let mut i = 0;
+        while let Some(index) = queue.pop_front() {
+            if topology[i / 64] & (1 << (i % 64)) != 0 {
+                let children_items = [(); 4].map(|_| items.pop_front());
+                let children_items = match children_items {
+                    [Some(nw), Some(ne), Some(sw), Some(se)] => [nw, ne, sw, se],
+                    _ => return Err(invalid_data("too few items")),
+                };
+                let children = tree
+                    .push_children(index, children_items)
+                    .map_err(|e| invalid_data(&e.to_string()))?;
+                queue.extend(children);
+            }
+            i += 1;
+        }
+        // Linked once for the whole tree rather than on every subdivision.
+        tree.rebuild_neighbors()
+            .map_err(|e| invalid_data(&e.to_string()))?;
+
+        tree.set_min_cell_size(header.min_cell_size);
+        tree.set_max_depth(header.max_depth);
+        tree.set_split_policy(header.split_policy);
+        tree.set_node_limit(header.node_limit);
+        Ok(tree)
+    }
+}
+
+/// The fixed-size start of an encoded tree.
+pub(crate) struct Header<S> {
+    pub(crate) min_cell_size: S,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) split_policy: SplitPolicy,
+    pub(crate) node_limit: Option<usize>,
+    pub(crate) bounds: Bounds<S>,
+    pub(crate) len: usize,
+}
+
+impl<S> Header<S>
+where
+    S: Copy + PartialEq + ToPrimitive + FromPrimitive,
+{
+    pub(crate) fn read<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        if read_array::<4>(reader)? != MAGIC {
+            return Err(invalid_data("not an encoded quadtree"));
+        }
+        let version = u16::from_le_bytes(read_array(reader)?);
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(invalid_data("unsupported format version"));
+        }
+
+        let min_cell_size = read_unit(reader)?;
+        let max_depth = read_limit(reader, "max depth is too large")?;
+        let (split_policy, node_limit) = if version >= 2 {
+            let split_policy = match read_array::<1>(reader)? {
+                [0] => SplitPolicy::Floor,
+                [1] => SplitPolicy::Ceil,
+                [2] => SplitPolicy::Median,
+                _ => return Err(invalid_data("invalid split policy")),
+            };
+            (split_policy, read_limit(reader, "node limit is too large")?)
+        } else {
+            (SplitPolicy::default(), None)
+        };
+        let bounds = Bounds(
+            read_unit(reader)?,
+            read_unit(reader)?,
+            read_unit(reader)?,
+            read_unit(reader)?,
+        );
+        let len = usize::try_from(read_u64(reader)?)
+            .map_err(|_| invalid_data("node count is too large"))?;
+
+        Ok(Self {
+            min_cell_size,
+            max_depth,
+            split_policy,
+            node_limit,
+            bounds,
+            len,
+        })
+    }
+}
+
+/// Checks that the topology describes a complete quadtree of `len` nodes.
+pub(crate) fn validate_topology(topology: &[u64], len: usize) -> io::Result<()> {
+    let internal: usize = topology.iter().map(|w| w.count_ones() as usize).sum();
+    if len != 1 + 4 * internal {
+        return Err(invalid_data("node count doesn't match topology"));
+    }
+    if !len.is_multiple_of(64) && topology.last().is_some_and(|w| w >> (len % 64) != 0) {
+        return Err(invalid_data("topology has bits past the last node"));
+    }
+    Ok(())
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub(crate) fn read_array<const N: usize>(reader: &mut (impl Read + ?Sized)) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub(crate) fn read_u64(reader: &mut (impl Read + ?Sized)) -> io::Result<u64> {
+    read_array(reader).map(u64::from_le_bytes)
+}
+
+/// Reads a limit stored as a u64, with u64::MAX for none.
+fn read_limit(reader: &mut (impl Read + ?Sized), too_large: &str) -> io::Result<Option<usize>> {
+    match read_u64(reader)? {
+        u64::MAX => Ok(None),
+        n => usize::try_from(n)
+            .map(Some)
+            .map_err(|_| invalid_data(too_large)),
+    }
+}
+
+pub(crate) fn write_unit<S, W>(writer: &mut W, unit: S) -> io::Result<()>
+where
+    S: Copy + PartialEq + ToPrimitive + FromPrimitive,
+    W: Write + ?Sized,
+{
+    match unit.to_i128() {
+        Some(i) if S::from_i128(i) == Some(unit) => {
+            writer.write_all(&[0])?;
+            writer.write_all(&i.to_le_bytes())
+        }
+        _ => {
+            let f = unit
+                .to_f64()
+                .filter(|&f| S::from_f64(f) == Some(unit))
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "unit can't be encoded")
+                })?;
+            writer.write_all(&[1])?;
+            writer.write_all(&f.to_le_bytes())
+        }
+    }
+}
+
+pub(crate) fn read_unit<S, R>(reader: &mut R) -> io::Result<S>
+where
+    S: FromPrimitive,
+    R: Read + ?Sized,
+{
+    let unit = match read_array::<1>(reader)? {
+        [0] => S::from_i128(i128::from_le_bytes(read_array(reader)?)),
+        [1] => S::from_f64(f64::from_le_bytes(read_array(reader)?)),
+        _ => return Err(invalid_data("invalid unit tag")),
+    };
+    unit.ok_or_else(|| invalid_data("unit doesn't fit the coordinate type"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::id::NodeId;
+    use crate::location::Cardinality;
+
+    #[test]
+    fn round_trip() {
+        let mut tree = CNQuadtree::<u16, i64>::new(0, (-64, -64, 64, 64));
+        let [_, ne, _, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [nw, ..] = tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        tree.subdivide(nw, [9, 10, 11, 12]).unwrap();
+        tree.subdivide(se, [13, 14, 15, 16]).unwrap();
+        tree.set_min_cell_size(2);
+        tree.set_max_depth(Some(5));
+        tree.set_split_policy(SplitPolicy::Ceil);
+        tree.set_node_limit(Some(40));
+
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+        let loaded =
+            CNQuadtree::<u16, i64>::load_from(&mut bytes.as_slice(), &mut LeBytes).unwrap();
+
+        assert!(loaded == tree);
+        assert_eq!(loaded.min_cell_size(), 2);
+        assert_eq!(loaded.max_depth(), Some(5));
+        assert_eq!(loaded.split_policy(), SplitPolicy::Ceil);
+        assert_eq!(loaded.node_limit(), Some(40));
+
+        // Version 1 has no split policy or node limit, which come after the max depth.
+        let mut v1 = bytes.clone();
+        v1[4..6].copy_from_slice(&1u16.to_le_bytes());
+        let limits = 4 + 2 + 17 + 8;
+        v1.drain(limits..limits + 9);
+        let old = CNQuadtree::<u16, i64>::load_from(&mut v1.as_slice(), &mut LeBytes).unwrap();
+        assert!(old == tree);
+        assert_eq!(old.split_policy(), SplitPolicy::Floor);
+        assert_eq!(old.node_limit(), None);
+        let leaf = loaded.point_locate((1, -63)).unwrap();
+        assert_eq!(*loaded.get_node(leaf).unwrap().get_item(), 9);
+        assert_eq!(
+            loaded
+                .get_neighbors(leaf, Cardinality::West)
+                .unwrap()
+                .into_vec(),
+            vec![loaded.point_locate((-1, -64)).unwrap()]
+        );
+
+        // Neighbors are linked once after loading, and match the ones kept while subdividing.
+        let bounds_of = |tree: &CNQuadtree<u16, i64>, leaves: &[NodeId]| {
+            leaves
+                .iter()
+                .map(|&leaf| tree.get_node(leaf).unwrap().get_bounds())
+                .collect::<Vec<_>>()
+        };
+        for (leaf, loaded_leaf) in tree.leaves_morton().zip(loaded.leaves_morton()) {
+            for direction in Cardinality::ALL {
+                let expected = tree.get_neighbors(leaf, direction).unwrap();
+                let actual = loaded.get_neighbors(loaded_leaf, direction).unwrap();
+                assert_eq!(bounds_of(&loaded, &actual), bounds_of(&tree, &expected));
+            }
+        }
+    }
+
+    #[test]
+    fn float_units() {
+        let mut tree = CNQuadtree::<bool, f32>::new(false, (0.0, 0.0, 1.5, 1.5));
+        tree.subdivide(tree.get_root(), [true, false, false, true])
+            .unwrap();
+
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+        let loaded =
+            CNQuadtree::<bool, f32>::load_from(&mut bytes.as_slice(), &mut LeBytes).unwrap();
+        assert!(loaded == tree);
+    }
+
+    #[test]
+    fn rejects_uneven_splits() {
+        let mut tree = CNQuadtree::<u8, u32>::new(0, (0, 0, 4, 4));
+        tree.subdivide_at(tree.get_root(), (1, 3), [1, 2, 3, 4])
+            .unwrap();
+        let mut bytes = Vec::new();
+        let error = tree.save_to(&mut bytes, &mut LeBytes).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let mut tree = CNQuadtree::<u8, u32>::new(0, (0, 0, 4, 4));
+        tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+
+        let load = |bytes: &[u8]| CNQuadtree::<u8, u32>::load_from(&mut &bytes[..], &mut LeBytes);
+        assert!(load(&bytes).is_ok());
+        assert!(load(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            load(&bad_magic).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // The topology word directly follows the header.
+        let topology = bytes.len() - 5 - 16;
+        let mut bad_topology = bytes.clone();
+        bad_topology[topology] = 0b11;
+        assert_eq!(
+            load(&bad_topology).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // Bounds that can't be subdivided.
+        let tiny = CNQuadtree::<u8, u32>::new(0, (0, 0, 1, 1));
+        let mut tiny_bytes = Vec::new();
+        tiny.save_to(&mut tiny_bytes, &mut LeBytes).unwrap();
+        let mut forged = tiny_bytes[..tiny_bytes.len() - 1 - 16 - 8].to_vec();
+        forged.extend(5u64.to_le_bytes());
+        forged.extend(1u64.to_le_bytes());
+        forged.extend(0u64.to_le_bytes());
+        forged.extend([0, 1, 2, 3, 4]);
+        let err = load(&forged).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), Error::CellTooSmall.to_string());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::id::NodeId;
+use crate::node::RegionQuadtreeNode;
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::any::Any;
+
+/// Items that can be viewed as [`Any`], such as boxed trait objects, so
+/// [`CNQuadtree::item_as`] can downcast them to their concrete types.
+///
+/// It's implemented for boxed [`Any`]. For a trait of your own, make [`Any`] one of its
+/// supertraits and implement this for its boxes:
+///
+/// ```
+/// use cnquadtree::{AsAny, CNQuadtree, RegionQuadtree, RegionQuadtreeNode};
+/// use std::any::Any;
+///
+/// trait Terrain: Any {
+///     fn cost(&self) -> f64;
+/// }
+///
+/// impl AsAny for Box<dyn Terrain> {
+///     fn as_any(&self) -> &dyn Any {
+///         &**self
+///     }
+///
+///     fn as_any_mut(&mut self) -> &mut dyn Any {
+///         &mut **self
+///     }
+/// }
+///
+/// struct Grass;
+/// impl Terrain for Grass {
+///     fn cost(&self) -> f64 {
+///         1.0
+///     }
+/// }
+///
+/// struct Water(f64);
+/// impl Terrain for Water {
+///     fn cost(&self) -> f64 {
+///         self.0
+///     }
+/// }
+///
+/// let mut tree: CNQuadtree<Box<dyn Terrain>> = CNQuadtree::new(Box::new(Grass), (0, 0, 8, 8));
+/// let [nw, ..] = tree
+///     .subdivide_with(tree.get_root(), |_, bounds, _| -> Box<dyn Terrain> {
+///         if bounds.0 == 0 && bounds.1 == 0 {
+///             Box::new(Water(4.0))
+///         } else {
+///             Box::new(Grass)
+///         }
+///     })
+///     .unwrap();
+/// assert_eq!(tree[nw].get_item().cost(), 4.0);
+/// assert!(tree.item_as::<Water>(nw).is_some());
+/// ```
+pub trait AsAny {
+    /// Returns the item as [`Any`].
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns the item as mutable [`Any`].
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl AsAny for Box<dyn Any> {
+    fn as_any(&self) -> &dyn Any {
+        &**self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut **self
+    }
+}
+
+impl AsAny for Box<dyn Any + Send> {
+    fn as_any(&self) -> &dyn Any {
+        &**self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut **self
+    }
+}
+
+impl AsAny for Box<dyn Any + Send + Sync> {
+    fn as_any(&self) -> &dyn Any {
+        &**self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        &mut **self
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    T: AsAny,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the node's item downcast to `U`, or None if the node doesn't exist or its item
+    /// isn't a `U`.
+    pub fn item_as<U: Any>(&self, index: NodeId) -> Option<&U> {
+        self.get_node(index)?.get_item().as_any().downcast_ref()
+    }
+
+    /// Returns the node's item downcast mutably to `U`, or None if the node doesn't exist or
+    /// its item isn't a `U`.
+    pub fn item_as_mut<U: Any>(&mut self, index: NodeId) -> Option<&mut U> {
+        self.get_node_mut(index)?
+            .get_item_mut()
+            .as_any_mut()
+            .downcast_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Location;
+
+    #[test]
+    fn boxed_items() {
+        let mut tree: CNQuadtree<Box<dyn Any>> = CNQuadtree::new(Box::new(0u8), (0, 0, 8, 8));
+        let children = tree
+            .subdivide_with(tree.get_root(), |location, _, parent| {
+                let parent = *parent.downcast_ref::<u8>().unwrap();
+                match location {
+                    Location::NorthWest => Box::new(String::from("nw")),
+                    _ => Box::new(parent + 1),
+                }
+            })
+            .unwrap();
+        assert_eq!(tree.item_as::<String>(children[0]).unwrap(), "nw");
+        assert_eq!(tree.item_as::<u8>(children[0]), None);
+        *tree.item_as_mut::<u8>(children[3]).unwrap() += 1;
+        assert_eq!(tree.item_as::<u8>(children[3]), Some(&2));
+        assert_eq!(tree.item_as::<u8>(tree.get_root()), Some(&0));
+
+        // Subdividing a subdivided node doesn't call the closure.
+        let result = tree.subdivide_with(tree.get_root(), |_, _, _| unreachable!());
+        assert_eq!(result, Err(crate::Error::AlreadySubdivided));
+    }
+}
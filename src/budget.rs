@@ -0,0 +1,473 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::time::{Duration, Instant};
+
+/// How much work an incremental operation may do per call.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Budget {
+    /// No limit. The operation runs to completion.
+    Unlimited,
+    /// Visit at most this many nodes.
+    Operations(usize),
+    /// Stop at the first node visited after this much time passed. Needs a monotonic clock, which
+    /// `wasm32-unknown-unknown` doesn't have.
+    Time(Duration),
+}
+
+/// Whether an incremental operation finished.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Progress {
+    /// The operation is complete.
+    Done,
+    /// The budget ran out. Call again to resume.
+    Pending,
+}
+
+impl Progress {
+    /// Returns true if the operation is complete.
+    pub fn is_done(self) -> bool {
+        self == Progress::Done
+    }
+}
+
+/// Tracks the use of a [`Budget`] during one call.
+struct Meter {
+    budget: Budget,
+    start: Option<Instant>,
+    used: usize,
+}
+
+impl Meter {
+    fn new(budget: Budget) -> Self {
+        let start = matches!(budget, Budget::Time(_)).then(Instant::now);
+        Self {
+            budget,
+            start,
+            used: 0,
+        }
+    }
+
+    /// Accounts for one node visit. Returns false if the budget is spent.
+    fn tick(&mut self) -> bool {
+        let spent = match self.budget {
+            Budget::Unlimited => false,
+            Budget::Operations(n) => self.used >= n,
+            Budget::Time(limit) => self.start.is_some_and(|start| start.elapsed() >= limit),
+        };
+        self.used += !spent as usize;
+        !spent
+    }
+}
+
+/// A post-order traversal that can be paused and resumed while the tree is modified. Nodes
+/// removed in between are skipped, and children created under nodes not yet visited are
+/// traversed. Children are visited before their parent, in the order NW, NE, SW, SE.
+#[derive(Clone, Debug)]
+pub struct PostOrderCursor {
+    /// Nodes left to visit, and whether their children were already queued.
+    stack: Vec<(NodeId, bool)>,
+}
+
+impl PostOrderCursor {
+    /// Returns a cursor at the start of the tree's traversal.
+    pub fn new<T, S>(tree: &CNQuadtree<T, S>) -> Self
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        Self {
+            stack: vec![(tree.get_root(), false)],
+        }
+    }
+
+    /// Returns true if every node was visited.
+    pub fn is_done(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Returns the next node, or None at the end of the traversal.
+    pub fn next<T, S>(&mut self, tree: &CNQuadtree<T, S>) -> Option<NodeId>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        while let Some((index, expanded)) = self.stack.pop() {
+            let Some(node) = tree.get_node(index) else {
+                continue;
+            };
+            match node.get_children_index() {
+                Some(children) if !expanded => {
+                    self.stack.push((index, true));
+                    self.stack
+                        .extend(children.into_iter().rev().map(|child| (child, false)));
+                }
+                _ => return Some(index),
+            }
+        }
+        None
+    }
+}
+
+/// Incremental merging of every family of four leaves holding equal items into their parent,
+/// which takes their item. Merges cascade up the tree. See [`CNQuadtree::compress`].
+#[derive(Clone, Debug)]
+pub struct Compress {
+    cursor: PostOrderCursor,
+}
+
+impl Compress {
+    /// Returns a job that compresses the whole tree, starting from its deepest nodes.
+    pub fn new<T, S>(tree: &CNQuadtree<T, S>) -> Self
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        Self {
+            cursor: PostOrderCursor::new(tree),
+        }
+    }
+
+    /// Continues compressing within the budget. Families changed between calls are compressed
+    /// if their parent wasn't visited yet.
+    pub fn run<T, S>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        budget: Budget,
+    ) -> Result<Progress, Error>
+    where
+        T: PartialEq,
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let mut meter = Meter::new(budget);
+        while meter.tick() {
+            let Some(index) = self.cursor.next(tree) else {
+                return Ok(Progress::Done);
+            };
+            let Some(children) = tree.get_node(index).and_then(|n| n.get_children_index()) else {
+                continue;
+            };
+            let nodes = children.map(|child| tree.get_node(child));
+            let uniform = nodes.iter().all(|node| {
+                node.zip(nodes[0]).is_some_and(|(node, first)| {
+                    node.is_leaf() && node.get_item() == first.get_item()
+                })
+            });
+            if uniform {
+                let [item, ..] = tree.pop_children(index)?;
+                let node = tree.get_node_mut(index).ok_or(Error::DanglingIndex)?;
+                *node.get_item_mut() = item;
+            }
+        }
+        Ok(if self.cursor.is_done() {
+            Progress::Done
+        } else {
+            Progress::Pending
+        })
+    }
+}
+
+/// Incremental 2:1 balancing: subdivides leaves until no leaf has an edge neighbor more than
+/// one level deeper. See [`CNQuadtree::balance`].
+#[derive(Clone, Debug)]
+pub struct Balance {
+    cursor: PostOrderCursor,
+    /// Leaves to check again because a neighbor was subdivided.
+    pending: Vec<NodeId>,
+}
+
+impl Balance {
+    /// Returns a job that balances the whole tree, starting from its deepest leaves.
+    pub fn new<T, S>(tree: &CNQuadtree<T, S>) -> Self
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        Self {
+            cursor: PostOrderCursor::new(tree),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Continues balancing within the budget. `split` returns the items of the children of a
+    /// leaf being subdivided, in the order NW, NE, SW, SE. Leaves subdivided between calls are
+    /// only balanced against if they weren't visited yet.
+    pub fn run<T, S, F>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        budget: Budget,
+        mut split: F,
+    ) -> Result<Progress, Error>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(Bounds<S>, &T) -> [T; 4],
+    {
+        let mut meter = Meter::new(budget);
+        while meter.tick() {
+            let Some(index) = self.pending.pop().or_else(|| self.cursor.next(tree)) else {
+                return Ok(Progress::Done);
+            };
+            let Some(node) = tree.get_node(index).filter(|n| n.is_leaf()) else {
+                continue;
+            };
+            let level = node.level();
+            let mut neighbors = Vec::new();
+            for direction in Cardinality::ALL {
+                neighbors.extend(tree.get_neighbors(index, direction)?);
+            }
+            let unbalanced = neighbors
+                .iter()
+                .filter_map(|&n| tree.get_node(n))
+                .any(|n| n.level() > level + 1);
+            if !unbalanced {
+                continue;
+            }
+
+            let items = split(node.get_bounds(), node.get_item());
+            let children = tree.subdivide(index, items).map_err(|e| e.source)?;
+            // The children may still be too coarse, and coarser neighbors may now be too.
+            self.pending.extend(neighbors);
+            self.pending.extend(children);
+        }
+        Ok(if self.pending.is_empty() && self.cursor.is_done() {
+            Progress::Done
+        } else {
+            Progress::Pending
+        })
+    }
+}
+
+/// Incremental recomputation of every leaf's cardinal neighbors, for trees whose neighbors
+/// were lost or corrupted. Each leaf is linked by descending from the root, so unlike
+/// [`CNQuadtree::rebuild_neighbors`] this works for any splits, at the cost of a descent per
+/// leaf and direction.
+#[derive(Clone, Debug)]
+pub struct Rebuild {
+    cursor: PostOrderCursor,
+}
+
+impl Rebuild {
+    /// Returns a job that relinks every leaf of the tree.
+    pub fn new<T, S>(tree: &CNQuadtree<T, S>) -> Self
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        Self {
+            cursor: PostOrderCursor::new(tree),
+        }
+    }
+
+    /// Continues relinking within the budget. Leaves not reached yet keep their old neighbors
+    /// until then; leaves created between calls are linked if their parent wasn't visited yet,
+    /// and otherwise got their neighbors from the subdivision.
+    pub fn run<T, S>(&mut self, tree: &mut CNQuadtree<T, S>, budget: Budget) -> Progress
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let mut meter = Meter::new(budget);
+        while meter.tick() {
+            let Some(index) = self.cursor.next(tree) else {
+                return Progress::Done;
+            };
+            tree.relink([index]);
+        }
+        if self.cursor.is_done() {
+            Progress::Done
+        } else {
+            Progress::Pending
+        }
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Merges every family of four leaves holding equal items into their parent, which takes
+    /// their item. Use [`Compress`] to spread the work over several calls.
+    pub fn compress(&mut self) -> Result<(), Error>
+    where
+        T: PartialEq,
+    {
+        Compress::new(self).run(self, Budget::Unlimited).map(|_| ())
+    }
+
+    /// Subdivides leaves until no leaf has an edge neighbor more than one level deeper. `split`
+    /// returns the items of the children of a leaf being subdivided, in the order NW, NE, SW,
+    /// SE. Use [`Balance`] to spread the work over several calls.
+    pub fn balance<F>(&mut self, split: F) -> Result<(), Error>
+    where
+        F: FnMut(Bounds<S>, &T) -> [T; 4],
+    {
+        Balance::new(self)
+            .run(self, Budget::Unlimited, split)
+            .map(|_| ())
+    }
+
+    /// Brings the tree into the canonical form of the field its leaves describe: the fewest
+    /// leaves if `balanced` is false, or the fewest leaves with no edge neighbor more than one
+    /// level deeper if true. Trees describing the same field get the same structure. Merges
+    /// equal siblings with [`CNQuadtree::compress`], then balances splitting leaves into copies
+    /// of themselves. Items of internal nodes are left as they are.
+    pub fn normalize(&mut self, balanced: bool) -> Result<(), Error>
+    where
+        T: Clone + PartialEq,
+    {
+        self.compress()?;
+        if balanced {
+            self.balance(|_, item| std::array::from_fn(|_| item.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns true if no leaf has an edge neighbor more than one level deeper.
+    fn is_balanced<T>(tree: &CNQuadtree<T, i32>) -> bool {
+        tree.leaves_morton().all(|leaf| {
+            let level = tree.get_node(leaf).unwrap().level();
+            [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ]
+            .into_iter()
+            .filter_map(|direction| tree.get_neighbors(leaf, direction).ok())
+            .flatten()
+            .all(|n| tree.get_node(n).unwrap().level() <= level + 1)
+        })
+    }
+
+    #[test]
+    fn compress() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 1, 1, 1]).unwrap();
+        tree.subdivide(nw, [1; 4]).unwrap();
+        let [ne_nw, ..] = tree.subdivide(ne, [2, 2, 2, 3]).unwrap();
+        tree.subdivide(ne_nw, [4; 4]).unwrap();
+
+        let mut job = Compress::new(&tree);
+        assert_eq!(
+            job.run(&mut tree, Budget::Operations(3)).unwrap(),
+            Progress::Pending
+        );
+        assert_eq!(tree.stats().nodes, 17);
+        assert!(job.run(&mut tree, Budget::Unlimited).unwrap().is_done());
+        assert_eq!(tree.stats().nodes, 9);
+        assert_eq!(*tree.get_node(ne_nw).unwrap().get_item(), 4);
+        assert_eq!(*tree.get_node(nw).unwrap().get_item(), 1);
+
+        tree.pop_children(ne).unwrap();
+        tree.compress().unwrap();
+        assert_eq!(tree.stats().nodes, 1);
+        assert_eq!(*tree.get_node(tree.get_root()).unwrap().get_item(), 1);
+    }
+
+    #[test]
+    fn balance() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        // A chain of subdivisions towards the center.
+        let mut leaf = tree.subdivide(tree.get_root(), [0; 4]).unwrap()[3];
+        for _ in 0..4 {
+            leaf = tree.subdivide(leaf, [0; 4]).unwrap()[0];
+        }
+        assert!(!is_balanced(&tree));
+
+        let mut job = Balance::new(&tree);
+        let mut calls = 0;
+        while !job
+            .run(&mut tree, Budget::Operations(4), |_, _| [1; 4])
+            .unwrap()
+            .is_done()
+        {
+            calls += 1;
+        }
+        assert!(calls > 1);
+        assert!(is_balanced(&tree));
+
+        let mut unbalanced = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut leaf = unbalanced.get_root();
+        for _ in 0..5 {
+            leaf = unbalanced.subdivide(leaf, [0; 4]).unwrap()[0];
+        }
+        unbalanced.balance(|_, _| [1; 4]).unwrap();
+        assert!(is_balanced(&unbalanced));
+    }
+
+    #[test]
+    fn rebuild() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.subdivide_at(nw, (8, 24), [0; 4]).unwrap();
+        tree.subdivide(ne, [0; 4]).unwrap();
+        let expected: Vec<Vec<_>> = tree
+            .leaves_morton()
+            .map(|leaf| {
+                let neighbors = Cardinality::ALL.map(|d| tree.get_neighbors(leaf, d).unwrap());
+                neighbors.into_iter().flatten().collect()
+            })
+            .collect();
+
+        // Neighbors lost by a batch that was never committed.
+        tree.begin_batch();
+        let leaves: Vec<_> = tree.leaves_morton().collect();
+        for leaf in leaves {
+            tree.subdivide(leaf, [0; 4]).unwrap();
+            tree.pop_children(leaf).unwrap();
+        }
+        let mut job = Rebuild::new(&tree);
+        let mut calls = 1;
+        while !job.run(&mut tree, Budget::Operations(2)).is_done() {
+            calls += 1;
+        }
+        assert!(calls > 1);
+        let actual: Vec<Vec<_>> = tree
+            .leaves_morton()
+            .map(|leaf| {
+                let neighbors = Cardinality::ALL.map(|d| tree.get_neighbors(leaf, d).unwrap());
+                neighbors.into_iter().flatten().collect()
+            })
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn normalize() {
+        fn leaves(tree: &CNQuadtree<i32, i32>) -> Vec<(Bounds<i32>, i32)> {
+            tree.leaves_morton()
+                .map(|leaf| {
+                    let node = tree.get_node(leaf).unwrap();
+                    (node.get_bounds(), *node.get_item())
+                })
+                .collect()
+        }
+
+        // The same field, with a single cell of 1 in the top left, split differently.
+        let mut a = CNQuadtree::new(0, (0, 0, 32, 32));
+        let mut leaf = a.get_root();
+        for _ in 0..3 {
+            leaf = a.subdivide(leaf, [0; 4]).unwrap()[0];
+        }
+        *a.get_node_mut(leaf).unwrap().get_item_mut() = 1;
+        let mut b = a.clone();
+        for point in [(20, 20), (30, 2), (30, 3)] {
+            let leaf = b.point_locate(point).unwrap();
+            b.subdivide(leaf, [0; 4]).unwrap();
+        }
+        assert_ne!(leaves(&a), leaves(&b));
+
+        a.normalize(false).unwrap();
+        b.normalize(false).unwrap();
+        assert_eq!(leaves(&a), leaves(&b));
+        assert_eq!(a.stats().nodes, 13);
+
+        a.normalize(true).unwrap();
+        b.normalize(true).unwrap();
+        assert_eq!(leaves(&a), leaves(&b));
+        assert!(is_balanced(&a));
+    }
+}
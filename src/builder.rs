@@ -0,0 +1,112 @@
+use crate::node::{Bounds, SplitPolicy};
+use crate::slottree::CNQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// Builder for a [`CNQuadtree`] whose options are set before the root is created.
+///
+/// Only options the tree keeps and checks on every subdivision are set here. Balancing and
+/// compression aren't modes the tree keeps up as it's edited, since items change through
+/// [`RegionQuadtreeNode::get_item_mut`](crate::RegionQuadtreeNode::get_item_mut) without the
+/// tree seeing it; run [`CNQuadtree::balance`], [`CNQuadtree::compress`] or
+/// [`CNQuadtree::normalize`] after edits instead. Trees don't wrap around, so leaves on the
+/// border have no neighbor past it.
+#[derive(Copy, Clone, Debug)]
+pub struct CNQuadtreeBuilder<S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    bounds: Bounds<S>,
+    capacity: usize,
+    max_depth: Option<usize>,
+    node_limit: Option<usize>,
+    min_cell_size: S,
+    split_policy: SplitPolicy,
+}
+
+impl<S> CNQuadtreeBuilder<S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a builder for a tree with the given root bounds and default options.
+    pub fn new(bounds: impl Into<Bounds<S>>) -> Self {
+        let bounds = bounds.into();
+        Self {
+            bounds,
+            capacity: 0,
+            max_depth: None,
+            node_limit: None,
+            min_cell_size: S::zero(),
+            split_policy: SplitPolicy::Floor,
+        }
+    }
+
+    /// Sets the number of nodes to allocate space for up front.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the deepest level a node may be created at. See [`CNQuadtree::set_max_depth`].
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Caps the number of nodes and allocates room for all of them up front. See
+    /// [`CNQuadtree::set_node_limit`].
+    pub fn node_limit(mut self, limit: usize) -> Self {
+        self.node_limit = Some(limit);
+        self
+    }
+
+    /// Sets the smallest width or height a child may have. See
+    /// [`CNQuadtree::set_min_cell_size`].
+    pub fn min_cell_size(mut self, size: S) -> Self {
+        self.min_cell_size = size;
+        self
+    }
+
+    /// Sets where subdivisions split nodes. See [`CNQuadtree::set_split_policy`].
+    pub fn split_policy(mut self, policy: SplitPolicy) -> Self {
+        self.split_policy = policy;
+        self
+    }
+
+    /// Builds the tree with `item` as the root's item.
+    pub fn build<T>(self, item: T) -> CNQuadtree<T, S> {
+        let mut tree = CNQuadtree::with_capacity(item, self.bounds, self.capacity);
+        tree.set_max_depth(self.max_depth);
+        tree.set_node_limit(self.node_limit);
+        tree.set_min_cell_size(self.min_cell_size);
+        tree.set_split_policy(self.split_policy);
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CNQuadtreeBuilder;
+    use crate::error::Error;
+    use crate::node::RegionQuadtreeNode;
+    use crate::tree::RegionQuadtree;
+
+    #[test]
+    fn build_with_options() {
+        let mut tree = CNQuadtreeBuilder::new((0, 0, 64, 64))
+            .capacity(64)
+            .max_depth(3)
+            .min_cell_size(16)
+            .build("root");
+        assert_eq!(tree.max_depth(), Some(3));
+        assert_eq!(tree.min_cell_size(), 16);
+        assert_eq!(
+            tree.get_node(tree.get_root()).unwrap().get_bounds(),
+            (0, 0, 64, 64)
+        );
+
+        let [nw, ..] = tree.subdivide(tree.get_root(), ["a"; 4]).unwrap();
+        let [nw_nw, ..] = tree.subdivide(nw, ["b"; 4]).unwrap();
+        let err = tree.subdivide(nw_nw, ["c"; 4]).unwrap_err();
+        assert_eq!(err.source, Error::CellTooSmall);
+    }
+}
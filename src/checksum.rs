@@ -0,0 +1,262 @@
+use crate::id::NodeId;
+use crate::node::RegionQuadtreeNode;
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Merkle-style hashes of every subtree of a tree, covering the bounds and items of the
+/// subtree's nodes and its shape. Replicas holding equal subtrees get equal hashes, so
+/// comparing them top-down finds the regions that differ without comparing every item.
+///
+/// Hashes don't follow the tree on their own. After mutating a node, call
+/// [`SubtreeChecksums::refresh`] with it to update it and its ancestors. Hashes use FNV-1a and
+/// are stable across runs and builds, but depend on the `Hash` implementations of the item and
+/// coordinate types, which for integers differ between little- and big-endian targets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubtreeChecksums {
+    hashes: HashMap<NodeId, Hashes>,
+}
+
+/// The hashes of a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Hashes {
+    /// Hash of the node's bounds and item.
+    own: u64,
+    /// Hash of the whole subtree.
+    subtree: u64,
+    /// The children when the node was hashed, to drop their hashes once they're removed.
+    children: Option<[NodeId; 4]>,
+}
+
+impl SubtreeChecksums {
+    /// Returns the hash of the subtree rooted at the node, or None if the node wasn't hashed.
+    pub fn get(&self, index: NodeId) -> Option<u64> {
+        self.hashes.get(&index).map(|hashes| hashes.subtree)
+    }
+
+    /// Rehashes the subtree rooted at `index`, then its ancestors. Hashes of removed nodes are
+    /// dropped. Call it with a node after changing its item, subdividing it or popping its
+    /// children.
+    pub fn refresh<T, S>(&mut self, tree: &CNQuadtree<T, S>, index: NodeId)
+    where
+        T: Hash,
+        S: Hash
+            + Copy
+            + Clone
+            + PartialOrd
+            + PartialEq
+            + NumAssign
+            + ToPrimitive
+            + NumOps
+            + FromPrimitive,
+    {
+        self.forget_descendants(index);
+        self.hash_subtree(tree, index);
+        let mut parent = tree.get_node(index).and_then(|n| n.get_parent_index());
+        while let Some(index) = parent {
+            self.hash_node(tree, index);
+            parent = tree.get_node(index).and_then(|n| n.get_parent_index());
+        }
+    }
+
+    /// Returns the topmost pairs of corresponding nodes whose subtrees differ, in depth-first
+    /// order. Both subtrees of a pair are replaced as a whole to make the trees equal: either
+    /// their own bounds or items differ, or one of them is a leaf and the other isn't.
+    pub fn diverging<T, U, S>(
+        &self,
+        tree: &CNQuadtree<T, S>,
+        other: &SubtreeChecksums,
+        other_tree: &CNQuadtree<U, S>,
+    ) -> Vec<(NodeId, NodeId)>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let mut result = Vec::new();
+        let mut stack = vec![(tree.get_root(), other_tree.get_root())];
+        while let Some((a, b)) = stack.pop() {
+            if self.get(a).is_some() && self.get(a) == other.get(b) {
+                continue;
+            }
+            let children = tree
+                .get_node(a)
+                .and_then(|n| n.get_children_index())
+                .zip(other_tree.get_node(b).and_then(|n| n.get_children_index()));
+            let own = |checksums: &SubtreeChecksums, id| checksums.hashes.get(&id).map(|h| h.own);
+            match children {
+                Some((a_children, b_children)) if own(self, a) == own(other, b) => {
+                    stack.extend(a_children.into_iter().zip(b_children).rev());
+                }
+                _ => result.push((a, b)),
+            }
+        }
+        result
+    }
+
+    /// Drops the hashes of the node's descendants as of when it was last hashed.
+    fn forget_descendants(&mut self, index: NodeId) {
+        let mut stack: Vec<NodeId> = Vec::new();
+        stack.extend(
+            self.hashes
+                .get(&index)
+                .and_then(|h| h.children)
+                .into_iter()
+                .flatten(),
+        );
+        while let Some(id) = stack.pop() {
+            if let Some(hashes) = self.hashes.remove(&id) {
+                stack.extend(hashes.children.into_iter().flatten());
+            }
+        }
+    }
+
+    fn hash_subtree<T, S>(&mut self, tree: &CNQuadtree<T, S>, index: NodeId)
+    where
+        T: Hash,
+        S: Hash
+            + Copy
+            + Clone
+            + PartialOrd
+            + PartialEq
+            + NumAssign
+            + ToPrimitive
+            + NumOps
+            + FromPrimitive,
+    {
+        // Parents come before their children, so hashing in reverse sees children first.
+        let mut order = vec![index];
+        let mut i = 0;
+        while let Some(&id) = order.get(i) {
+            if let Some(children) = tree.get_node(id).and_then(|n| n.get_children_index()) {
+                order.extend(children);
+            }
+            i += 1;
+        }
+        for id in order.into_iter().rev() {
+            self.hash_node(tree, id);
+        }
+    }
+
+    /// Hashes a node from its bounds, item and children's hashes.
+    fn hash_node<T, S>(&mut self, tree: &CNQuadtree<T, S>, index: NodeId)
+    where
+        T: Hash,
+        S: Hash
+            + Copy
+            + Clone
+            + PartialOrd
+            + PartialEq
+            + NumAssign
+            + ToPrimitive
+            + NumOps
+            + FromPrimitive,
+    {
+        let Some(node) = tree.get_node(index) else {
+            self.hashes.remove(&index);
+            return;
+        };
+        let mut hasher = Fnv1a::default();
+        node.get_bounds().hash(&mut hasher);
+        node.get_item().hash(&mut hasher);
+        let own = hasher.finish();
+        let children = node.get_children_index();
+        children
+            .map(|children| children.map(|child| self.get(child).unwrap_or(0)))
+            .hash(&mut hasher);
+        let subtree = hasher.finish();
+        self.hashes.insert(
+            index,
+            Hashes {
+                own,
+                subtree,
+                children,
+            },
+        );
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    T: Hash,
+    S: Hash
+        + Copy
+        + Clone
+        + PartialOrd
+        + PartialEq
+        + NumAssign
+        + ToPrimitive
+        + NumOps
+        + FromPrimitive,
+{
+    /// Returns the hashes of every subtree. See [`SubtreeChecksums`].
+    pub fn subtree_checksums(&self) -> SubtreeChecksums {
+        let mut checksums = SubtreeChecksums::default();
+        checksums.hash_subtree(self, self.get_root());
+        checksums
+    }
+}
+
+/// 64-bit FNV-1a, chosen over the standard library's hasher because its output is specified.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtree_checksums() {
+        let mut a = CNQuadtree::new(0, (0, 0, 16, 16));
+        let [nw, ne, sw, _] = a.subdivide(a.get_root(), [1, 2, 3, 4]).unwrap();
+        a.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        a.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        let (mut b, ids) = a.clone_with_ids();
+
+        let checksums = a.subtree_checksums();
+        let mut other = b.subtree_checksums();
+        assert_eq!(checksums.get(a.get_root()), other.get(b.get_root()));
+        assert!(checksums.diverging(&a, &other, &b).is_empty());
+        // Same content at different places hashes differently.
+        assert_ne!(checksums.get(nw), checksums.get(ne));
+
+        let ne_se = a.point_locate((15, 7)).unwrap();
+        let (b_ne_se, b_sw) = (ids[&ne_se], ids[&sw]);
+        *b.get_node_mut(b_ne_se).unwrap().get_item_mut() = 9;
+        other.refresh(&b, b_ne_se);
+        b.subdivide(b_sw, [0; 4]).unwrap();
+        other.refresh(&b, b_sw);
+        assert_eq!(other, b.subtree_checksums());
+        assert_eq!(
+            checksums.diverging(&a, &other, &b),
+            vec![(ne_se, b_ne_se), (sw, b_sw)]
+        );
+
+        b.pop_children(b_sw).unwrap();
+        *b.get_node_mut(b_sw).unwrap().get_item_mut() = 0;
+        other.refresh(&b, b_sw);
+        assert_eq!(other, b.subtree_checksums());
+        // Both subtrees are kept, but the items of their roots differ.
+        assert_eq!(
+            checksums.diverging(&a, &other, &b),
+            vec![(ne_se, b_ne_se), (sw, b_sw)]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::{Error, NeighborError, PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::location::{Cardinality, Location};
+use crate::node::{Bounds, CNNode, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::{Neighbors, RegionQuadtree};
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A read-only view of a tree restricted to a window, returned by [`CNQuadtree::clip_view`].
+/// It's a [`RegionQuadtree`] whose nodes are the tree's nodes overlapping the window with a
+/// non-zero area, so iteration, queries and neighbor lists only see leaves that intersect it.
+/// Nodes keep the tree's indices, levels and bounds, which may extend past the window.
+///
+/// Methods that modify the tree fail as if every index were invalid.
+pub struct ClipView<'a, T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: &'a CNQuadtree<T, S>,
+    window: Bounds<S>,
+}
+
+impl<T, S> Clone for ClipView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, S> Copy for ClipView<'_, T, S> where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive
+{
+}
+
+impl<T, S> ClipView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the window.
+    #[inline]
+    pub fn window(&self) -> Bounds<S> {
+        self.window
+    }
+
+    /// Renders the window like [`CNQuadtree::render_ascii`], with the grid spanning the window
+    /// instead of the tree. Cells outside the tree are blank.
+    pub fn render_ascii<F>(&self, width: usize, height: usize, glyph: F) -> String
+    where
+        F: FnMut(&T) -> char,
+    {
+        self.tree
+            .render_ascii_within(self.window, width, height, glyph)
+    }
+}
+
+impl<T, S> RegionQuadtree<T> for ClipView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Index = NodeId;
+    type Node = CNNode<T, NodeId, S>;
+
+    fn get_node(&self, index: Self::Index) -> Option<&Self::Node> {
+        self.tree
+            .get_node(index)
+            .filter(|node| self.window.intersects(node.get_bounds()))
+    }
+
+    /// Always returns None, as the view is read-only.
+    fn get_node_mut(&mut self, _: Self::Index) -> Option<&mut Self::Node> {
+        None
+    }
+
+    fn get_root(&self) -> Self::Index {
+        self.tree.get_root()
+    }
+
+    fn get_neighbors(
+        &self,
+        index: Self::Index,
+        direction: Cardinality,
+    ) -> Result<Neighbors<Self::Index>, NeighborError> {
+        self.get_node(index).ok_or(NeighborError::InvalidIndex)?;
+        let mut neighbors = self.tree.get_neighbors(index, direction)?;
+        neighbors.retain(|n| self.get_node(*n).is_some());
+        Ok(neighbors)
+    }
+
+    fn get_equal_or_larger_neighbor(
+        &self,
+        index: Self::Index,
+        direction: Cardinality,
+    ) -> Option<Self::Index> {
+        self.get_node(index)?;
+        self.tree
+            .get_equal_or_larger_neighbor(index, direction)
+            .filter(|&n| self.get_node(n).is_some())
+    }
+
+    /// Always fails with [`Error::InvalidIndex`], as the view is read-only.
+    fn subdivide(
+        &mut self,
+        _: Self::Index,
+        items: [T; 4],
+    ) -> Result<[Self::Index; 4], SubdivideError<T>> {
+        Err(SubdivideError {
+            items,
+            source: Error::InvalidIndex,
+        })
+    }
+
+    /// Always fails with [`PopChildrenError::InvalidIndex`], as the view is read-only.
+    fn pop_children(&mut self, _: Self::Index) -> Result<[T; 4], PopChildrenError> {
+        Err(PopChildrenError::InvalidIndex)
+    }
+
+    fn point_locate(&self, point: Point<S>) -> Option<Self::Index> {
+        if !self.window.contains(point) {
+            return None;
+        }
+        self.tree.point_locate(point)
+    }
+
+    fn point_locate_path(&self, point: Point<S>) -> Option<Vec<(Self::Index, Option<Location>)>> {
+        if !self.window.contains(point) {
+            return None;
+        }
+        self.tree.point_locate_path(point)
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a read-only view of the tree restricted to `window`, for example a viewport.
+    pub fn clip_view(&self, window: impl Into<Bounds<S>>) -> ClipView<'_, T, S> {
+        ClipView {
+            tree: self,
+            window: window.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_view() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ne, sw, _] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [_, nw_ne, _, nw_se] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+
+        let view = tree.clip_view((20, 8, 40, 24));
+        assert_eq!(view.window(), (20, 8, 40, 24));
+        assert_eq!(
+            view.leaves_morton().collect::<Vec<_>>(),
+            vec![nw_ne, nw_se, ne]
+        );
+        assert!(view.get_node(sw).is_none());
+        assert_eq!(view.point_locate((30, 20)), Some(nw_se));
+        assert_eq!(view.point_locate((10, 20)), None);
+        assert_eq!(view.point_locate_path((30, 20)).unwrap().len(), 3);
+        assert_eq!(view.region_locate((0, 0, 64, 64)).unwrap().len(), 3);
+
+        // Neighbors outside the window are left out.
+        assert_eq!(
+            tree.get_neighbors(nw_se, Cardinality::West).unwrap().len(),
+            1
+        );
+        assert!(view
+            .get_neighbors(nw_se, Cardinality::West)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            view.get_neighbors(nw_se, Cardinality::East)
+                .unwrap()
+                .into_vec(),
+            vec![ne]
+        );
+        assert_eq!(
+            view.get_equal_or_larger_neighbor(nw_se, Cardinality::South),
+            None
+        );
+
+        let digit = |&item: &i32| char::from_digit(item as u32, 10).unwrap();
+        assert_eq!(view.render_ascii(5, 2, digit), "66622\n88822\n");
+        assert_eq!(
+            tree.clip_view((56, 56, 72, 72)).render_ascii(2, 2, digit),
+            "4 \n  \n"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! A quadtree split into independently locked shards, for concurrent readers and writers working
+//! on different regions.
+
+use crate::error::Error;
+use crate::node::{child_bounds, midpoint, Bounds, Point};
+use crate::slottree::CNQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::sync::{LockResult, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A shard locked for reading.
+type ReadGuard<'a, T, S> = RwLockReadGuard<'a, CNQuadtree<T, S>>;
+
+/// A shard locked for writing.
+type WriteGuard<'a, T, S> = RwLockWriteGuard<'a, CNQuadtree<T, S>>;
+
+/// A tree whose bounds are split into `4^shard_depth` cells, each an independent [`CNQuadtree`]
+/// behind its own lock. Readers and writers only block each other when their regions share a
+/// shard. Shards are the cells a tree subdivided down to `shard_depth` would have, in Morton
+/// order.
+///
+/// Cardinal neighbors don't cross shard borders: a leaf on a shard's border has no neighbor on
+/// that side, as the shards are separate trees. Neighbor queries and anything built on them,
+/// such as balancing or pathfinding, stop at the borders, and a leaf can't be refined or merged
+/// across one: no leaf is larger than its shard.
+///
+/// A writer that panics while holding a shard's lock, such as inside user code run partway
+/// through an edit, may leave that shard inconsistent. Its lock is then poisoned, and locking
+/// it returns the [`PoisonError`] like [`RwLock`] does, so callers can check or rebuild the
+/// shard before calling [`ShardedQuadtree::clear_poison`].
+#[derive(Debug)]
+pub struct ShardedQuadtree<T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    bounds: Bounds<S>,
+    shard_depth: usize,
+    /// Bounds of each shard, kept outside the locks so finding shards never blocks.
+    shard_bounds: Vec<Bounds<S>>,
+    shards: Vec<RwLock<CNQuadtree<T, S>>>,
+}
+
+impl<T, S> ShardedQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a tree split into `4^shard_depth` shards. Each shard's root gets the item returned
+    /// by `item`, which is given the shard's bounds. Fails with [`Error::CellTooSmall`] if the
+    /// bounds can't be split that deep.
+    pub fn new<F>(
+        bounds: impl Into<Bounds<S>>,
+        shard_depth: usize,
+        mut item: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(Bounds<S>) -> T,
+    {
+        let bounds = bounds.into();
+        let mut cells = vec![bounds];
+        for _ in 0..shard_depth {
+            let mut next = Vec::with_capacity(cells.len() * 4);
+            for cell in cells {
+                let Bounds(left, top, right, bottom) = cell;
+                let (x_middle, y_middle) = (
+                    midpoint(left, right).ok_or(Error::UnitConversion)?,
+                    midpoint(top, bottom).ok_or(Error::UnitConversion)?,
+                );
+                if !(left < x_middle && x_middle < right && top < y_middle && y_middle < bottom) {
+                    return Err(Error::CellTooSmall);
+                }
+                for location in 0..4 {
+                    next.push(child_bounds(cell, location).ok_or(Error::UnitConversion)?);
+                }
+            }
+            cells = next;
+        }
+
+        let shards = cells
+            .iter()
+            .map(|&cell| RwLock::new(CNQuadtree::new(item(cell), cell)))
+            .collect();
+        Ok(Self {
+            bounds,
+            shard_depth,
+            shard_bounds: cells,
+            shards,
+        })
+    }
+
+    /// Returns the bounds of the whole tree.
+    #[inline]
+    pub fn bounds(&self) -> Bounds<S> {
+        self.bounds
+    }
+
+    /// Returns the number of shards.
+    #[inline]
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the bounds of the shard, or None if there's no such shard.
+    #[inline]
+    pub fn shard_bounds(&self, shard: usize) -> Option<Bounds<S>> {
+        self.shard_bounds.get(shard).copied()
+    }
+
+    /// Returns the shard containing the point, or None if the point is outside the bounds.
+    pub fn shard_of(&self, point: Point<S>) -> Option<usize> {
+        let Bounds(mut left, mut top, mut right, mut bottom) = self.bounds;
+        if !(left <= point.0 && point.0 < right && top <= point.1 && point.1 < bottom) {
+            return None;
+        }
+
+        let mut shard = 0;
+        for _ in 0..self.shard_depth {
+            let (x_middle, y_middle) = (midpoint(left, right)?, midpoint(top, bottom)?);
+            let east = point.0 >= x_middle;
+            let south = point.1 >= y_middle;
+            (left, right) = if east {
+                (x_middle, right)
+            } else {
+                (left, x_middle)
+            };
+            (top, bottom) = if south {
+                (y_middle, bottom)
+            } else {
+                (top, y_middle)
+            };
+            shard = shard * 4 + (east as usize | (south as usize) << 1);
+        }
+        Some(shard)
+    }
+
+    /// Returns the shards overlapping the region in ascending order.
+    pub fn shards_overlapping(&self, region: impl Into<Bounds<S>>) -> Vec<usize> {
+        let region = region.into();
+        self.shard_bounds
+            .iter()
+            .enumerate()
+            .filter(|(_, shard)| shard.intersects(region))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Locks the shard containing the point for reading. Returns None if the point is outside
+    /// the bounds.
+    pub fn read(&self, point: Point<S>) -> Option<LockResult<ReadGuard<'_, T, S>>> {
+        self.shard_of(point).map(|i| self.read_shard(i))
+    }
+
+    /// Locks the shard containing the point for writing. Returns None if the point is outside
+    /// the bounds.
+    pub fn write(&self, point: Point<S>) -> Option<LockResult<WriteGuard<'_, T, S>>> {
+        self.shard_of(point).map(|i| self.write_shard(i))
+    }
+
+    /// Locks the shard for reading. Fails if its lock is poisoned, with the guard inside the
+    /// error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no such shard.
+    pub fn read_shard(&self, shard: usize) -> LockResult<ReadGuard<'_, T, S>> {
+        self.shards[shard].read()
+    }
+
+    /// Locks the shard for writing. Fails like [`ShardedQuadtree::read_shard`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no such shard.
+    pub fn write_shard(&self, shard: usize) -> LockResult<WriteGuard<'_, T, S>> {
+        self.shards[shard].write()
+    }
+
+    /// Marks the shard's lock as no longer poisoned, once the shard was checked or rebuilt
+    /// after a writer panicked. Does nothing if there's no such shard.
+    pub fn clear_poison(&self, shard: usize) {
+        if let Some(lock) = self.shards.get(shard) {
+            lock.clear_poison();
+        }
+    }
+
+    /// Locks every shard overlapping the region for reading. Fails if any of their locks is
+    /// poisoned, with all the guards inside the error.
+    pub fn read_region(
+        &self,
+        region: impl Into<Bounds<S>>,
+    ) -> LockResult<Vec<ReadGuard<'_, T, S>>> {
+        let region = region.into();
+        collect_guards(
+            self.shards_overlapping(region)
+                .into_iter()
+                .map(|i| self.read_shard(i)),
+        )
+    }
+
+    /// Locks every shard overlapping the region for writing. Shards are locked in ascending
+    /// order, so writers of overlapping regions can't deadlock each other. Fails like
+    /// [`ShardedQuadtree::read_region`].
+    pub fn write_region(
+        &self,
+        region: impl Into<Bounds<S>>,
+    ) -> LockResult<Vec<WriteGuard<'_, T, S>>> {
+        let region = region.into();
+        collect_guards(
+            self.shards_overlapping(region)
+                .into_iter()
+                .map(|i| self.write_shard(i)),
+        )
+    }
+}
+
+/// Collects the guards, failing if any of their locks is poisoned.
+fn collect_guards<G>(locks: impl Iterator<Item = LockResult<G>>) -> LockResult<Vec<G>> {
+    let mut poisoned = false;
+    let guards = locks
+        .map(|lock| {
+            lock.unwrap_or_else(|e| {
+                poisoned = true;
+                e.into_inner()
+            })
+        })
+        .collect();
+    if poisoned {
+        Err(PoisonError::new(guards))
+    } else {
+        Ok(guards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::RegionQuadtreeNode;
+    use crate::tree::RegionQuadtree;
+    use std::thread;
+
+    #[test]
+    fn shards() {
+        let tree = ShardedQuadtree::new((0, 0, 64, 64), 2, |_| 0).unwrap();
+        assert_eq!(tree.shard_count(), 16);
+        assert_eq!(tree.shard_of((0, 0)), Some(0));
+        assert_eq!(tree.shard_of((16, 0)), Some(1));
+        assert_eq!(tree.shard_of((32, 0)), Some(4));
+        assert_eq!(tree.shard_of((63, 63)), Some(15));
+        assert_eq!(tree.shard_of((64, 0)), None);
+        assert_eq!(tree.shard_bounds(5), Some(Bounds(48, 0, 64, 16)));
+        assert_eq!(tree.shard_bounds(16), None);
+        assert_eq!(tree.read_shard(5).unwrap().bounds(), (48, 0, 64, 16));
+        assert_eq!(tree.shards_overlapping((10, 10, 20, 20)), vec![0, 1, 2, 3]);
+
+        assert_eq!(
+            ShardedQuadtree::new((0, 0, 2, 2), 2, |_| 0).unwrap_err(),
+            Error::CellTooSmall
+        );
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let tree = ShardedQuadtree::new((0, 0, 64, 64), 1, |_| 0).unwrap();
+        thread::scope(|scope| {
+            for (x, y) in [(0, 0), (32, 0), (0, 32), (32, 32)] {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for step in 0..32 {
+                        let mut shard = tree.write((x + step, y + step)).unwrap().unwrap();
+                        let leaf = shard.point_locate((x + step, y + step)).unwrap();
+                        let _ = shard.subdivide(leaf, [1; 4]);
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for _ in 0..32 {
+                    let shards = tree.read_region((0, 0, 64, 64)).unwrap();
+                    assert_eq!(shards.len(), 4);
+                }
+            });
+        });
+
+        for shard in tree.read_region((0, 0, 64, 64)).unwrap() {
+            let Bounds(left, top, ..) = shard.bounds();
+            let leaf = shard.point_locate((left, top)).unwrap();
+            assert!(shard.get_node(leaf).unwrap().level() > 3);
+        }
+    }
+
+    #[test]
+    fn poisoned_shards() {
+        let tree = ShardedQuadtree::new((0, 0, 64, 64), 1, |_| 0).unwrap();
+        let result = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _shard = tree.write_shard(3).unwrap();
+                    panic!("edit failed halfway");
+                })
+                .join()
+        });
+        assert!(result.is_err());
+
+        assert!(tree.read_shard(0).is_ok());
+        assert!(tree.write((40, 40)).unwrap().is_err());
+        let shards = tree.read_region((0, 0, 64, 64)).unwrap_err().into_inner();
+        assert_eq!(shards.len(), 4);
+        drop(shards);
+        tree.clear_poison(3);
+        assert!(tree.read_region((0, 0, 64, 64)).is_ok());
+    }
+}
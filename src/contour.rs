@@ -0,0 +1,207 @@
+use crate::corner_data::CornerData;
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashMap;
+
+/// Iso-lines extracted by [`CNQuadtree::dual_contour`]: a vertex in each leaf they cross, and
+/// segments joining the vertices of edge-adjacent leaves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Contour {
+    /// The vertices, each with the leaf it's in, in Morton order of the leaves.
+    pub vertices: Vec<(NodeId, Point<f64>)>,
+    /// Pairs of indices into `vertices` joined by a segment.
+    pub segments: Vec<(usize, usize)>,
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Extracts the lines where a field sampled at the corners of the leaves crosses `iso`, by
+    /// dual contouring. Leaves whose corners lie on both sides of `iso` get a vertex, placed
+    /// where the lines tangent to the field at the crossings on its sides meet, which keeps
+    /// sharp corners sharp. The vertices of edge-adjacent leaves are joined if their shared
+    /// edge crosses `iso`, so coarse leaves give long segments and fine leaves detailed ones.
+    ///
+    /// `gradient` returns the field's gradient at a crossing, or None to estimate it from the
+    /// samples, which rounds off sharp corners. Vertices are kept within their leaves, and
+    /// where the tangents are parallel they lie at the average of the crossings. Leaves with a
+    /// corner lacking a sample are skipped.
+    pub fn dual_contour<F>(
+        &self,
+        samples: &CornerData<f64, S>,
+        iso: f64,
+        mut gradient: F,
+    ) -> Contour
+    where
+        F: FnMut(Point<f64>) -> Option<Point<f64>>,
+    {
+        let mut contour = Contour::default();
+        let mut vertices = HashMap::new();
+        for leaf in self.leaves_morton() {
+            let (Some(bounds), Some([Some(&nw), Some(&ne), Some(&sw), Some(&se)])) =
+                (self.bounds_f64(leaf), samples.corners(self, leaf))
+            else {
+                continue;
+            };
+            let values = [nw - iso, ne - iso, sw - iso, se - iso];
+            if let Some(vertex) = place_vertex(bounds, values, &mut gradient) {
+                vertices.insert(leaf, contour.vertices.len());
+                contour.vertices.push((leaf, vertex));
+            }
+        }
+
+        for (i, &(leaf, _)) in contour.vertices.iter().enumerate() {
+            for direction in [Cardinality::East, Cardinality::South] {
+                for neighbor in self.neighbors_iter(leaf, direction) {
+                    let (Some(&j), Some(edge)) =
+                        (vertices.get(&neighbor), self.shared_edge(leaf, neighbor))
+                    else {
+                        continue;
+                    };
+                    let (start, end) = edge.segment;
+                    if let (Some(a), Some(b)) = (samples.get(start), samples.get(end)) {
+                        if (a - iso < 0.0) != (b - iso < 0.0) {
+                            contour.segments.push((i, j));
+                        }
+                    }
+                }
+            }
+        }
+        contour
+    }
+}
+
+/// Returns the vertex of a leaf from its corners' values relative to the iso value, in the
+/// order NW, NE, SW, SE, or None if the iso-line doesn't cross its sides.
+fn place_vertex<F>(bounds: Bounds<f64>, values: [f64; 4], gradient: &mut F) -> Option<Point<f64>>
+where
+    F: FnMut(Point<f64>) -> Option<Point<f64>>,
+{
+    let Bounds(left, top, right, bottom) = bounds;
+    let (width, height) = (right - left, bottom - top);
+    let [nw, ne, sw, se] = values;
+    // Sides as the corners they join, by local coordinates and value.
+    let sides = [
+        ((0.0, 0.0, nw), (1.0, 0.0, ne)),
+        ((1.0, 0.0, ne), (1.0, 1.0, se)),
+        ((0.0, 1.0, sw), (1.0, 1.0, se)),
+        ((0.0, 0.0, nw), (0.0, 1.0, sw)),
+    ];
+    // Each crossing and the field's normal there.
+    let mut crossings = Vec::new();
+    for ((u0, v0, a), (u1, v1, b)) in sides {
+        if (a < 0.0) == (b < 0.0) {
+            continue;
+        }
+        let t = a / (a - b);
+        let (u, v) = (u0 + (u1 - u0) * t, v0 + (v1 - v0) * t);
+        let point = (left + u * width, top + v * height);
+        // The gradient of the bilinear interpolation of the corners.
+        let estimate = || {
+            (
+                ((ne - nw) * (1.0 - v) + (se - sw) * v) / width,
+                ((sw - nw) * (1.0 - u) + (se - ne) * u) / height,
+            )
+        };
+        let (gx, gy) = gradient(point).unwrap_or_else(estimate);
+        let length = gx.hypot(gy);
+        let normal = if length > 0.0 {
+            (gx / length, gy / length)
+        } else {
+            (0.0, 0.0)
+        };
+        crossings.push((point, normal));
+    }
+    if crossings.is_empty() {
+        return None;
+    }
+
+    // Least squares for the point closest to every tangent line, relative to the crossings'
+    // average, dropping directions the tangents barely constrain.
+    let count = crossings.len() as f64;
+    let mass = crossings.iter().fold((0.0, 0.0), |(x, y), &((px, py), _)| {
+        (x + px / count, y + py / count)
+    });
+    let (mut a, mut b, mut c, mut r) = (0.0, 0.0, 0.0, (0.0, 0.0));
+    for &((px, py), (nx, ny)) in &crossings {
+        let distance = nx * (px - mass.0) + ny * (py - mass.1);
+        a += nx * nx;
+        b += nx * ny;
+        c += ny * ny;
+        r = (r.0 + nx * distance, r.1 + ny * distance);
+    }
+    let half_gap = ((a - c) / 2.0).hypot(b);
+    let eigenvalues = [(a + c) / 2.0 + half_gap, (a + c) / 2.0 - half_gap];
+    // Without coupling the eigenvectors are the axes, the larger eigenvalue's first.
+    let eigenvectors = if b.abs() > f64::EPSILON {
+        eigenvalues.map(|eigenvalue| (eigenvalue - c, b))
+    } else if a >= c {
+        [(1.0, 0.0), (0.0, 1.0)]
+    } else {
+        [(0.0, 1.0), (1.0, 0.0)]
+    };
+    let mut offset = (0.0, 0.0);
+    for (eigenvalue, (ex, ey)) in eigenvalues.into_iter().zip(eigenvectors) {
+        if eigenvalue <= 0.1 * eigenvalues[0] || eigenvalue <= 0.0 {
+            continue;
+        }
+        let length = ex.hypot(ey);
+        let (ex, ey) = (ex / length, ey / length);
+        let scale = (ex * r.0 + ey * r.1) / eigenvalue;
+        offset = (offset.0 + scale * ex, offset.1 + scale * ey);
+    }
+    Some((
+        (mass.0 + offset.0).clamp(left, right),
+        (mass.1 + offset.1).clamp(top, bottom),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns unit cells over `(0, 0, 8, 8)` with `field` sampled at their corners.
+    fn sampled(field: impl Fn(f64, f64) -> f64) -> (CNQuadtree<u8, i32>, CornerData<f64, i32>) {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        tree.refine_to_levels(|_| 3, |_, _| [0; 4]).unwrap();
+        let mut samples = CornerData::new();
+        for y in 0..=8 {
+            for x in 0..=8 {
+                samples.set((x, y), field(x as f64, y as f64)).unwrap();
+            }
+        }
+        (tree, samples)
+    }
+
+    #[test]
+    fn dual_contour() {
+        // A straight line through the middle of a column of cells.
+        let (tree, samples) = sampled(|x, _| x - 3.5);
+        let contour = tree.dual_contour(&samples, 0.0, |_| None);
+        assert_eq!(contour.vertices.len(), 8);
+        assert_eq!(contour.segments.len(), 7);
+        for &(leaf, (x, y)) in &contour.vertices {
+            let center = tree.center_f64(leaf).unwrap();
+            assert!((x - 3.5).abs() < 1e-9 && (y - center.1).abs() < 1e-9);
+        }
+
+        // The corner of a square stays sharp with exact gradients.
+        let (tree, samples) = sampled(|x, y| x.max(y));
+        let gradient = |(x, y): Point<f64>| Some(if x > y { (1.0, 0.0) } else { (0.0, 1.0) });
+        let contour = tree.dual_contour(&samples, 4.5, gradient);
+        assert_eq!(contour.vertices.len(), 9);
+        assert_eq!(contour.segments.len(), 8);
+        let corner = tree.point_locate((4, 4)).unwrap();
+        let &(_, (x, y)) = contour.vertices.iter().find(|v| v.0 == corner).unwrap();
+        assert!((x - 4.5).abs() < 1e-9 && (y - 4.5).abs() < 1e-9);
+        // Estimated gradients round it off, but keep it in its cell.
+        let contour = tree.dual_contour(&samples, 4.5, |_| None);
+        let &(_, (x, y)) = contour.vertices.iter().find(|v| v.0 == corner).unwrap();
+        assert!(4.25 < x && x < 4.5 && 4.25 < y && y < 4.5);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::error::Error;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    T: Clone,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Replaces the part of the tree within `region` with the structure and items of `other`
+    /// there. Nodes inside `region` become copies of `other`'s nodes, subdividing or collapsing
+    /// as needed. Leaves straddling its border are subdivided, their children taking the
+    /// leaf's item, until they lie inside or outside it or can't be subdivided further, in
+    /// which case they're kept. Run [`CNQuadtree::compress`] afterwards to merge leftover
+    /// splits.
+    ///
+    /// Both trees must have the same bounds, or it fails with [`Error::BoundsMismatch`]. Fails
+    /// if a node inside `region` can't be subdivided like `other`'s, for example because of
+    /// the tree's maximum depth, leaving the copy partly done.
+    pub fn copy_region_from(
+        &mut self,
+        other: &CNQuadtree<T, S>,
+        region: impl Into<Bounds<S>>,
+    ) -> Result<(), Error> {
+        let region = region.into();
+        if self.bounds() != other.bounds() {
+            return Err(Error::BoundsMismatch);
+        }
+        // Pairs of a node and the node of `other` covering it.
+        let mut stack = vec![(self.get_root(), other.get_root())];
+        while let Some((index, source)) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::InvalidIndex)?;
+            if !node.intersects(region) {
+                continue;
+            }
+            let source_node = other.get_node(source).ok_or(Error::InvalidIndex)?;
+            // A leaf of `other` larger than the node covers it, as if subdivided with its item.
+            let source_children = if source_node.get_bounds() == node.get_bounds() {
+                source_node.get_children_index()
+            } else {
+                None
+            };
+
+            if node.inside(region) {
+                let item = source_node.get_item().clone();
+                match source_children {
+                    None => self.collapse(index)?,
+                    Some(source_children) => {
+                        let children = match node.get_children_index() {
+                            Some(children) => children,
+                            None => {
+                                let items = source_children.map(|child| {
+                                    other.get_node(child).map(|n| n.get_item().clone())
+                                });
+                                if items.iter().any(Option::is_none) {
+                                    return Err(Error::DanglingIndex);
+                                }
+                                self.subdivide(index, items.map(Option::unwrap))
+                                    .map_err(|e| e.source)?
+                            }
+                        };
+                        stack.extend(children.into_iter().zip(source_children));
+                    }
+                }
+                if let Some(node) = self.get_node_mut(index) {
+                    *node.get_item_mut() = item;
+                }
+                continue;
+            }
+
+            let children = match node.get_children_index() {
+                Some(children) => children,
+                None => {
+                    let item = node.get_item();
+                    let items = std::array::from_fn(|_| item.clone());
+                    match self.subdivide(index, items) {
+                        Ok(children) => children,
+                        Err(e)
+                            if matches!(
+                                e.source,
+                                Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes
+                            ) =>
+                        {
+                            continue
+                        }
+                        Err(e) => return Err(e.source),
+                    }
+                }
+            };
+            match source_children {
+                Some(source_children) => {
+                    stack.extend(children.into_iter().zip(source_children));
+                }
+                None => stack.extend(children.map(|child| (child, source))),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_region_from() {
+        let mut source = CNQuadtree::new(1, (0, 0, 16, 16));
+        let [nw, ..] = source.subdivide(source.get_root(), [2, 3, 4, 5]).unwrap();
+        let [_, _, _, nw_se] = source.subdivide(nw, [6, 7, 8, 9]).unwrap();
+        source.subdivide(nw_se, [10, 11, 12, 13]).unwrap();
+
+        // Copying NW whole replicates its structure.
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        tree.copy_region_from(&source, (0, 0, 8, 8)).unwrap();
+        assert_eq!(tree.leaves_morton().count(), 10);
+        assert_eq!(tree.region_area(|&item| item >= 6), Some(64.0));
+        assert_eq!(tree.region_area(|&item| item == 0), Some(192.0));
+        let copied = tree.point_locate((7, 7)).unwrap();
+        assert_eq!(*tree.get_node(copied).unwrap().get_item(), 13);
+        let copied_nw = tree.point_locate_path((0, 0)).unwrap()[1].0;
+        assert_eq!(*tree.get_node(copied_nw).unwrap().get_item(), 2);
+
+        // A region cutting through leaves splits them at its border.
+        tree.copy_region_from(&source, (0, 0, 6, 16)).unwrap();
+        assert_eq!(tree.region_area(|&item| item == 4), Some(48.0));
+        assert_eq!(tree.region_area(|&item| item == 0), Some(192.0 - 48.0));
+        let kept = tree.point_locate((7, 1)).unwrap();
+        assert_eq!(*tree.get_node(kept).unwrap().get_item(), 7);
+
+        // Copying the whole tree makes an equal one.
+        let mut copy = CNQuadtree::new(1, (0, 0, 16, 16));
+        copy.copy_region_from(&tree, (0, 0, 16, 16)).unwrap();
+        assert_eq!(
+            copy.subtree_checksums().get(copy.get_root()),
+            tree.subtree_checksums().get(tree.get_root())
+        );
+        // Copying a leaf over a subdivided node collapses it.
+        tree.copy_region_from(&CNQuadtree::new(1, (0, 0, 16, 16)), (0, 0, 16, 16))
+            .unwrap();
+        assert_eq!(tree.leaves_morton().count(), 1);
+
+        assert_eq!(
+            tree.copy_region_from(&CNQuadtree::new(1, (0, 0, 8, 8)), (0, 0, 8, 8)),
+            Err(Error::BoundsMismatch)
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::edge_data::{key, Coordinate};
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::BTreeMap;
+
+/// Values attached to the corners of leaves, such as heights sampled at cell corners, each
+/// shared by every leaf meeting at the corner.
+///
+/// Values are stored by where corners lie rather than by the leaves meeting there, so they're
+/// kept as leaves are subdivided or merged. The corners a subdivision adds start out without
+/// values until [`CornerData::fill_children`] interpolates them, and corners inside a merged
+/// leaf keep their values for when it's subdivided again.
+#[derive(Clone, Debug)]
+pub struct CornerData<V, S = u32> {
+    /// The value at each corner, by x and then y, with the corner.
+    values: BTreeMap<(Coordinate, Coordinate), (Point<S>, V)>,
+}
+
+impl<V, S> Default for CornerData<V, S> {
+    fn default() -> Self {
+        Self {
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V, S> CornerData<V, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of corners holding values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if no corner holds a value.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Sets the value at a corner and returns the one it replaces. Fails with
+    /// [`Error::UnitConversion`] if a coordinate can't be converted to `f64`.
+    pub fn set(&mut self, corner: Point<S>, value: V) -> Result<Option<V>, Error> {
+        let corner_key = (key(corner.0)?, key(corner.1)?);
+        let old = self.values.insert(corner_key, (corner, value));
+        Ok(old.map(|(_, value)| value))
+    }
+
+    /// Returns the value at a corner.
+    pub fn get(&self, corner: Point<S>) -> Option<&V> {
+        let corner_key = (key(corner.0).ok()?, key(corner.1).ok()?);
+        self.values.get(&corner_key).map(|(_, value)| value)
+    }
+
+    /// Removes the value at a corner and returns it.
+    pub fn remove(&mut self, corner: Point<S>) -> Option<V> {
+        let corner_key = (key(corner.0).ok()?, key(corner.1).ok()?);
+        self.values.remove(&corner_key).map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over the corners holding values and their values, by x and then y.
+    pub fn iter(&self) -> impl Iterator<Item = (Point<S>, &V)> + '_ {
+        self.values.values().map(|(corner, value)| (*corner, value))
+    }
+
+    /// Returns the values at the corners of a node, in the order NW, NE, SW, SE. Returns None
+    /// if the node doesn't exist.
+    pub fn corners<T>(&self, tree: &CNQuadtree<T, S>, index: NodeId) -> Option<[Option<&V>; 4]> {
+        let Bounds(left, top, right, bottom) = tree.get_node(index)?.get_bounds();
+        Some([(left, top), (right, top), (left, bottom), (right, bottom)].map(|c| self.get(c)))
+    }
+
+    /// Gives the corners a subdivision of the node added, the middles of its sides and its
+    /// split point, values interpolated linearly from the node's corners. `lerp` returns the
+    /// value a fraction of the way from one value to another. Corners that already hold values,
+    /// such as those shared with finer neighbors, keep them, so surfaces stay continuous.
+    ///
+    /// Fails with [`Error::InvalidIndex`] if the node doesn't exist, [`Error::NotSubdivided`]
+    /// if it's a leaf, or [`Error::UnitConversion`] if a coordinate can't be converted to
+    /// `f64`. Corners that can't be interpolated because the node's corners lack values are
+    /// left without values.
+    pub fn fill_children<T, F>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        index: NodeId,
+        mut lerp: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&V, &V, f64) -> V,
+    {
+        let node = tree.get_node(index).ok_or(Error::InvalidIndex)?;
+        let [nw, ..] = node.get_children_index().ok_or(Error::NotSubdivided)?;
+        let Bounds(left, top, right, bottom) = node.get_bounds();
+        let middle = tree.get_node(nw).ok_or(Error::DanglingIndex)?.get_bounds();
+        let (x, y) = (middle.2, middle.3);
+        let fraction = |low: S, value: S, high: S| -> Result<f64, Error> {
+            let low = low.to_f64().ok_or(Error::UnitConversion)?;
+            let value = value.to_f64().ok_or(Error::UnitConversion)?;
+            let high = high.to_f64().ok_or(Error::UnitConversion)?;
+            Ok((value - low) / (high - low))
+        };
+        let (tx, ty) = (fraction(left, x, right)?, fraction(top, y, bottom)?);
+
+        let [Some(a), Some(b), Some(c), Some(d)] =
+            self.corners(tree, index).ok_or(Error::InvalidIndex)?
+        else {
+            return Ok(());
+        };
+        let (north, south) = (lerp(a, b, tx), lerp(c, d, tx));
+        let center = lerp(&north, &south, ty);
+        let added = [
+            ((x, top), north),
+            ((left, y), lerp(a, c, ty)),
+            ((x, y), center),
+            ((right, y), lerp(b, d, ty)),
+            ((x, bottom), south),
+        ];
+        for (corner, value) in added {
+            if self.get(corner).is_none() {
+                self.set(corner, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the value at a point interpolated bilinearly from the corners of the leaf
+    /// containing it, with `lerp` as in [`CornerData::fill_children`]. Returns None if the
+    /// point is outside the tree, a corner of the leaf lacks a value or a coordinate can't be
+    /// converted to `f64`.
+    pub fn sample<T, F>(&self, tree: &CNQuadtree<T, S>, point: Point<S>, mut lerp: F) -> Option<V>
+    where
+        F: FnMut(&V, &V, f64) -> V,
+    {
+        let leaf = tree.point_locate(point)?;
+        let Bounds(left, top, right, bottom) = tree.bounds_f64(leaf)?;
+        let (x, y) = (point.0.to_f64()?, point.1.to_f64()?);
+        let [Some(a), Some(b), Some(c), Some(d)] = self.corners(tree, leaf)? else {
+            return None;
+        };
+        let (tx, ty) = ((x - left) / (right - left), (y - top) / (bottom - top));
+        let (north, south) = (lerp(a, b, tx), lerp(c, d, tx));
+        Some(lerp(&north, &south, ty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_data() {
+        let lerp = |a: &f64, b: &f64, t: f64| a + (b - a) * t;
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let mut heights = CornerData::new();
+        for (corner, height) in [((0, 0), 0.0), ((8, 0), 8.0), ((0, 8), 16.0), ((8, 8), 24.0)] {
+            heights.set(corner, height).unwrap();
+        }
+        assert_eq!(heights.sample(&tree, (2, 4), lerp), Some(10.0));
+
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        // Values already set, such as by a finer neighbor, are kept.
+        heights.set((4, 0), 5.0).unwrap();
+        heights.fill_children(&tree, tree.get_root(), lerp).unwrap();
+        assert_eq!(heights.len(), 9);
+        assert_eq!(heights.get((4, 0)), Some(&5.0));
+        assert_eq!(heights.get((4, 4)), Some(&12.0));
+        assert_eq!(heights.get((8, 4)), Some(&16.0));
+        // NW and NE share two corners.
+        let [_, nw_ne, _, nw_se] = heights.corners(&tree, nw).unwrap();
+        let [ne_nw, _, ne_sw, _] = heights.corners(&tree, ne).unwrap();
+        assert_eq!((nw_ne, nw_se), (ne_nw, ne_sw));
+        assert_eq!(heights.sample(&tree, (2, 0), lerp), Some(2.5));
+
+        // Merging keeps the inner corners for the next subdivision.
+        tree.pop_children(tree.get_root()).unwrap();
+        assert_eq!(heights.sample(&tree, (2, 4), lerp), Some(10.0));
+        assert_eq!(heights.get((4, 4)), Some(&12.0));
+        assert_eq!(
+            heights.fill_children(&tree, tree.get_root(), lerp),
+            Err(Error::NotSubdivided)
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns, for each leaf, the distance to the nearest leaf satisfying `predicate`, which
+    /// is given the leaf's bounds and item. Leaves satisfying it are at 0.
+    ///
+    /// Distances are measured along paths between the centers of edge-adjacent leaves, found by
+    /// a best-first wavefront over cardinal neighbors, so they overestimate the straight-line
+    /// distance where paths bend. Returns an empty map if no leaf satisfies `predicate` or a
+    /// coordinate can't be converted to `f64`.
+    pub fn distance_to<F>(&self, mut predicate: F) -> HashMap<NodeId, f64>
+    where
+        F: FnMut(Bounds<S>, &T) -> bool,
+    {
+        let mut distances = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for leaf in self.leaves_morton() {
+            let Some(node) = self.get_node(leaf) else {
+                continue;
+            };
+            if predicate(node.get_bounds(), node.get_item()) {
+                distances.insert(leaf, 0.0);
+                queue.push(Reverse(Visit(0.0, leaf)));
+            }
+        }
+
+        while let Some(Reverse(Visit(distance, leaf))) = queue.pop() {
+            if distances.get(&leaf).is_some_and(|&d| d < distance) {
+                continue;
+            }
+            let Some(from) = self.center_f64(leaf) else {
+                return HashMap::new();
+            };
+            for direction in [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ] {
+                for neighbor in self.get_neighbors(leaf, direction).unwrap_or_default() {
+                    let Some(to) = self.center_f64(neighbor) else {
+                        return HashMap::new();
+                    };
+                    let next = distance + (to.0 - from.0).hypot(to.1 - from.1);
+                    if distances.get(&neighbor).is_none_or(|&d| next < d) {
+                        distances.insert(neighbor, next);
+                        queue.push(Reverse(Visit(next, neighbor)));
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    /// Partitions the leaves among seeds, assigning each reachable leaf the label of the seed
+    /// it is cheapest to reach from, with that cost, such as for influence maps or Voronoi-like
+    /// regions. `cost` is given the items of two edge-adjacent leaves and the distance between
+    /// their centers, and returns the cost of stepping from the first to the second, or None if
+    /// the step is blocked. Costs must not be negative.
+    ///
+    /// Every seed spreads at once by a best-first wavefront over cardinal neighbors, as in
+    /// [`CNQuadtree::distance_to`]. Seeds that aren't leaves are ignored, and leaves reached at
+    /// the same cost from several seeds keep the earliest seed's label. Returns an empty map if
+    /// a coordinate can't be converted to `f64`.
+    pub fn grow_regions<L, F>(
+        &self,
+        seeds: &[(NodeId, L)],
+        mut cost: F,
+    ) -> HashMap<NodeId, (L, f64)>
+    where
+        L: Clone,
+        F: FnMut(&T, &T, f64) -> Option<f64>,
+    {
+        let mut regions = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        // Seeds are numbered so ties go to the earliest.
+        let mut labels = HashMap::new();
+        for (seed, (leaf, label)) in seeds.iter().enumerate() {
+            if self.get_node(*leaf).is_none_or(|node| !node.is_leaf()) || regions.contains_key(leaf)
+            {
+                continue;
+            }
+            regions.insert(*leaf, (label.clone(), 0.0));
+            labels.insert(*leaf, seed);
+            queue.push(Reverse((Visit(0.0, *leaf), seed)));
+        }
+
+        while let Some(Reverse((Visit(distance, leaf), seed))) = queue.pop() {
+            if labels.get(&leaf) != Some(&seed) || regions[&leaf].1 < distance {
+                continue;
+            }
+            let (Some(from), Some(node)) = (self.center_f64(leaf), self.get_node(leaf)) else {
+                return HashMap::new();
+            };
+            for direction in Cardinality::ALL {
+                for neighbor in self.neighbors_iter(leaf, direction) {
+                    let (Some(to), Some(next_node)) =
+                        (self.center_f64(neighbor), self.get_node(neighbor))
+                    else {
+                        return HashMap::new();
+                    };
+                    let step = (to.0 - from.0).hypot(to.1 - from.1);
+                    let Some(step) = cost(node.get_item(), next_node.get_item(), step) else {
+                        continue;
+                    };
+                    let next = distance + step;
+                    let better = regions
+                        .get(&neighbor)
+                        .is_none_or(|&(_, d)| next < d || (next == d && seed < labels[&neighbor]));
+                    if better {
+                        regions.insert(neighbor, (seeds[seed].1.clone(), next));
+                        labels.insert(neighbor, seed);
+                        queue.push(Reverse((Visit(next, neighbor), seed)));
+                    }
+                }
+            }
+        }
+        regions
+    }
+
+    /// Returns the center of a node converted to `f64`.
+    pub(crate) fn center_f64(&self, index: NodeId) -> Option<(f64, f64)> {
+        let Bounds(left, top, right, bottom) = self.bounds_f64(index)?;
+        Some(((left + right) / 2.0, (top + bottom) / 2.0))
+    }
+}
+
+/// A leaf reached at a distance, ordered by distance.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Visit(pub(crate) f64, pub(crate) NodeId);
+
+impl PartialEq for Visit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Visit {}
+
+impl PartialOrd for Visit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Visit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to() {
+        let mut tree = CNQuadtree::new(false, (0, 0, 8, 8));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [false; 4]).unwrap();
+        let [ne_nw, ne_ne, ne_sw, ne_se] = tree.subdivide(ne, [false; 4]).unwrap();
+        *tree.get_node_mut(ne_ne).unwrap().get_item_mut() = true;
+
+        let distances = tree.distance_to(|_, &obstacle| obstacle);
+        assert_eq!(distances.len(), 7);
+        assert_eq!(distances[&ne_ne], 0.0);
+        assert_eq!(distances[&ne_nw], 2.0);
+        assert_eq!(distances[&ne_se], 2.0);
+        assert_eq!(distances[&ne_sw], 4.0);
+        // From NE's NW child at (5, 1) to NW's center at (2, 2).
+        assert_eq!(distances[&nw], 2.0 + 10f64.sqrt());
+        // Through NE's SE child at (7, 3) to SE's center at (6, 6).
+        assert_eq!(distances[&se], 2.0 + 10f64.sqrt());
+        assert_eq!(distances[&sw], distances[&se] + 4.0);
+
+        assert!(tree.distance_to(|_, _| false).is_empty());
+    }
+
+    #[test]
+    fn grow_regions() {
+        // Unit cells, with a slow one next to the west seed.
+        let mut tree = CNQuadtree::new(1.0, (0, 0, 8, 8));
+        tree.refine_to_levels(|_| 3, |_, &cost| [cost; 4]).unwrap();
+        let grid: Vec<Vec<NodeId>> = (0..8)
+            .map(|y| (0..8).map(|x| tree.point_locate((x, y)).unwrap()).collect())
+            .collect();
+        let cell = |x: usize, y: usize| grid[y][x];
+        let (west, east, slow) = (cell(0, 0), cell(7, 0), cell(1, 0));
+        *tree.get_node_mut(slow).unwrap().get_item_mut() = 5.0;
+        let step = |_: &f64, to: &f64, distance: f64| (*to < 10.0).then_some(distance * to);
+
+        let regions = tree.grow_regions(&[(west, 'w'), (east, 'e')], step);
+        assert_eq!(regions.len(), 64);
+        assert_eq!(regions[&west], ('w', 0.0));
+        assert_eq!(regions[&cell(6, 0)], ('e', 1.0));
+        assert_eq!(regions[&slow], ('w', 5.0));
+        // Going round the slow cell is cheaper than through it.
+        assert_eq!(regions[&cell(2, 0)], ('w', 4.0));
+        assert_eq!(regions[&cell(3, 7)].0, 'w');
+        assert_eq!(regions[&cell(4, 7)].0, 'e');
+
+        // Cells as far from both seeds go to the first.
+        let regions = tree.grow_regions(&[(west, 'a'), (cell(0, 2), 'b')], step);
+        assert_eq!(regions[&cell(0, 1)], ('a', 1.0));
+        let regions = tree.grow_regions(&[(cell(0, 2), 'b'), (west, 'a')], step);
+        assert_eq!(regions[&cell(0, 1)], ('b', 1.0));
+
+        // A wall down the middle keeps the east seed out of the west half.
+        for row in &grid {
+            *tree.get_node_mut(row[4]).unwrap().get_item_mut() = 10.0;
+        }
+        let regions = tree.grow_regions(&[(east, 'e'), (west, 'w')], step);
+        assert_eq!(regions[&cell(3, 7)].0, 'w');
+        assert!(!regions.contains_key(&cell(4, 3)));
+        assert!(tree.grow_regions::<char, _>(&[], step).is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::edges::LeafEdge;
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::Point;
+use crate::slottree::CNQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// The parts of a grid line holding values, by where they start along the line.
+type Line<E, S> = BTreeMap<Coordinate, Part<E, S>>;
+
+/// The ends of a part of an edge.
+type Segment<S> = (Point<S>, Point<S>);
+
+/// Values attached to the edges leaves share, such as wall flags or flow rates.
+///
+/// Values are stored by where edges lie rather than by the leaves either side, so they're kept
+/// as leaves are subdivided or merged: the edges of a subdivided leaf take the values of the
+/// parts of its old edges they lie on, and the edge of a merged leaf is made of the parts of
+/// the edges it replaces, which [`EdgeData::get`] returns in order. The edges between the
+/// children of a subdivided leaf start out without values.
+#[derive(Clone, Debug)]
+pub struct EdgeData<E, S = u32> {
+    /// The vertical grid lines holding values, by x, then the horizontal ones, by y.
+    lines: [BTreeMap<Coordinate, Line<E, S>>; 2],
+}
+
+/// A part of a grid line holding a value, from `start` to `end` along the line.
+#[derive(Clone, Debug)]
+struct Part<E, S> {
+    start: S,
+    end: S,
+    end_key: Coordinate,
+    value: E,
+}
+
+/// A coordinate converted to `f64` and ordered totally, to key values by.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Coordinate(pub(crate) f64);
+
+impl Eq for Coordinate {}
+
+impl PartialOrd for Coordinate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Coordinate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl<E, S> Default for EdgeData<E, S> {
+    fn default() -> Self {
+        Self {
+            lines: Default::default(),
+        }
+    }
+}
+
+impl<E, S> EdgeData<E, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if no edge holds a value.
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(BTreeMap::is_empty)
+    }
+
+    /// Sets the value of the whole edge `a` and `b` share, replacing the values along it.
+    /// Fails with [`Error::NotAdjacent`] if they don't share an edge, or
+    /// [`Error::UnitConversion`] if a coordinate can't be converted to `f64`.
+    pub fn set<T>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        a: NodeId,
+        b: NodeId,
+        value: E,
+    ) -> Result<(), Error>
+    where
+        E: Clone,
+    {
+        self.splice(tree, a, b, Some(value))
+    }
+
+    /// Removes the values along the edge `a` and `b` share. Fails like [`EdgeData::set`].
+    pub fn remove<T>(&mut self, tree: &CNQuadtree<T, S>, a: NodeId, b: NodeId) -> Result<(), Error>
+    where
+        E: Clone,
+    {
+        self.splice(tree, a, b, None)
+    }
+
+    /// Returns the values along the edge `a` and `b` share, each with the part of the edge
+    /// holding it, top to bottom or left to right. Parts without a value are skipped, and a
+    /// part holding a value can be longer than the edge. Returns an empty vector if they don't
+    /// share an edge or a coordinate can't be converted to `f64`.
+    pub fn get<T>(&self, tree: &CNQuadtree<T, S>, a: NodeId, b: NodeId) -> Vec<(Segment<S>, &E)> {
+        let Some(edge) = tree.shared_edge(a, b) else {
+            return Vec::new();
+        };
+        let (axis, line, start, end) = locate(&edge);
+        let (Ok(line_key), Ok(low), Ok(high)) = (key(line), key(start), key(end)) else {
+            return Vec::new();
+        };
+        let Some(parts) = self.lines[axis].get(&line_key) else {
+            return Vec::new();
+        };
+        let max = |a: S, b: S| if a < b { b } else { a };
+        let min = |a: S, b: S| if b < a { b } else { a };
+        let mut values: Vec<_> = parts
+            .range(..high)
+            .rev()
+            .take_while(|(_, part)| part.end_key > low)
+            .map(|(_, part)| {
+                let (from, to) = (max(part.start, start), min(part.end, end));
+                let segment = match axis {
+                    0 => ((line, from), (line, to)),
+                    _ => ((from, line), (to, line)),
+                };
+                (segment, &part.value)
+            })
+            .collect();
+        values.reverse();
+        values
+    }
+
+    /// Replaces the values along the edge `a` and `b` share with `value`.
+    fn splice<T>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        a: NodeId,
+        b: NodeId,
+        value: Option<E>,
+    ) -> Result<(), Error>
+    where
+        E: Clone,
+    {
+        let edge = tree.shared_edge(a, b).ok_or(Error::NotAdjacent)?;
+        let (axis, line, start, end) = locate(&edge);
+        let (line_key, low, high) = (key(line)?, key(start)?, key(end)?);
+        let parts = self.lines[axis].entry(line_key).or_default();
+
+        // Parts overlapping the edge, cut down to what lies outside it.
+        let overlapping: Vec<Coordinate> = parts
+            .range(..high)
+            .rev()
+            .take_while(|(_, part)| part.end_key > low)
+            .map(|(&start_key, _)| start_key)
+            .collect();
+        for start_key in overlapping {
+            let Some(part) = parts.remove(&start_key) else {
+                continue;
+            };
+            if start_key < low {
+                let before = Part {
+                    start: part.start,
+                    end: start,
+                    end_key: low,
+                    value: part.value.clone(),
+                };
+                parts.insert(start_key, before);
+            }
+            if part.end_key > high {
+                let after = Part {
+                    start: end,
+                    end: part.end,
+                    end_key: part.end_key,
+                    value: part.value,
+                };
+                parts.insert(high, after);
+            }
+        }
+
+        if let Some(value) = value {
+            let part = Part {
+                start,
+                end,
+                end_key: high,
+                value,
+            };
+            parts.insert(low, part);
+        }
+        if parts.is_empty() {
+            self.lines[axis].remove(&line_key);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the grid line an edge lies on, 0 for vertical and 1 for horizontal, its coordinate,
+/// and where the edge starts and ends along it.
+fn locate<S: Copy>(edge: &LeafEdge<S>) -> (usize, S, S, S) {
+    let ((x0, y0), (x1, y1)) = edge.segment;
+    match edge.direction {
+        Cardinality::East | Cardinality::West => (0, x0, y0, y1),
+        Cardinality::North | Cardinality::South => (1, y0, x0, x1),
+    }
+}
+
+/// Returns a coordinate's key, or fails with [`Error::UnitConversion`].
+pub(crate) fn key<S: ToPrimitive>(value: S) -> Result<Coordinate, Error> {
+    value.to_f64().map(Coordinate).ok_or(Error::UnitConversion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::RegionQuadtree;
+
+    #[test]
+    fn edge_data() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let mut walls = EdgeData::new();
+        walls.set(&tree, ne, nw, "wall").unwrap();
+        assert_eq!(walls.get(&tree, nw, ne), [(((4, 0), (4, 4)), &"wall")]);
+        assert_eq!(walls.set(&tree, nw, se, "wall"), Err(Error::NotAdjacent));
+
+        // Subdividing a side splits the edge between its children.
+        let [ne_nw, ne_ne, ne_sw, _] = tree.subdivide(ne, [0; 4]).unwrap();
+        assert_eq!(walls.get(&tree, nw, ne_nw), [(((4, 0), (4, 2)), &"wall")]);
+        assert_eq!(walls.get(&tree, ne_sw, nw), [(((4, 2), (4, 4)), &"wall")]);
+        assert!(walls.get(&tree, ne_nw, ne_ne).is_empty());
+        walls.set(&tree, nw, ne_sw, "door").unwrap();
+        walls.set(&tree, ne_sw, ne_nw, "door").unwrap();
+
+        // Merging them back joins the parts.
+        tree.pop_children(ne).unwrap();
+        assert_eq!(
+            walls.get(&tree, nw, ne),
+            [(((4, 0), (4, 2)), &"wall"), (((4, 2), (4, 4)), &"door")]
+        );
+        walls.remove(&tree, ne, nw).unwrap();
+        assert!(walls.get(&tree, nw, ne).is_empty());
+        // Empty lines are dropped, but the door between NE's children is kept inside NE.
+        walls.set(&tree, sw, se, "wall").unwrap();
+        walls.remove(&tree, sw, se).unwrap();
+        assert!(walls.lines[0].is_empty());
+        assert!(!walls.is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// An edge shared by two leaves, yielded by [`CNQuadtree::leaf_edges`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LeafEdge<S> {
+    /// The leaf to the west of or above the edge.
+    pub from: NodeId,
+    /// The leaf to the east of or below the edge.
+    pub to: NodeId,
+    /// The side of `from` the edge is on, either [`Cardinality::East`] or
+    /// [`Cardinality::South`].
+    pub direction: Cardinality,
+    /// The ends of the edge, top to bottom or left to right.
+    pub segment: (Point<S>, Point<S>),
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an iterator over every pair of leaves sharing an edge, each pair exactly once.
+    /// Leaves touching only at a corner don't share an edge. Pairs come in Morton order of
+    /// `from`, then east before south, each direction in the order of the neighbor chain.
+    pub fn leaf_edges(&self) -> impl Iterator<Item = LeafEdge<S>> + '_ {
+        self.leaves_morton().flat_map(move |leaf| {
+            [Cardinality::East, Cardinality::South]
+                .into_iter()
+                .flat_map(move |direction| {
+                    self.neighbors_iter(leaf, direction)
+                        .filter_map(move |neighbor| self.leaf_edge(leaf, neighbor, direction))
+                })
+        })
+    }
+
+    /// Returns the edge two nodes share, with `from` the one to the west of or above it, or
+    /// None if they only touch at a corner or don't touch.
+    pub(crate) fn shared_edge(&self, a: NodeId, b: NodeId) -> Option<LeafEdge<S>> {
+        let Bounds(left, top, right, bottom) = self.get_node(a)?.get_bounds();
+        let Bounds(n_left, n_top, n_right, n_bottom) = self.get_node(b)?.get_bounds();
+        let (across_y, across_x) = (
+            top < n_bottom && n_top < bottom,
+            left < n_right && n_left < right,
+        );
+        let (from, to, direction) = if right == n_left && across_y {
+            (a, b, Cardinality::East)
+        } else if n_right == left && across_y {
+            (b, a, Cardinality::East)
+        } else if bottom == n_top && across_x {
+            (a, b, Cardinality::South)
+        } else if n_bottom == top && across_x {
+            (b, a, Cardinality::South)
+        } else {
+            return None;
+        };
+        self.leaf_edge(from, to, direction)
+    }
+
+    fn leaf_edge(&self, from: NodeId, to: NodeId, direction: Cardinality) -> Option<LeafEdge<S>> {
+        let Bounds(left, top, right, bottom) = self.get_node(from)?.get_bounds();
+        let Bounds(n_left, n_top, n_right, n_bottom) = self.get_node(to)?.get_bounds();
+        let max = |a: S, b: S| if a < b { b } else { a };
+        let min = |a: S, b: S| if b < a { b } else { a };
+        let segment = match direction {
+            Cardinality::East => ((right, max(top, n_top)), (right, min(bottom, n_bottom))),
+            _ => ((max(left, n_left), bottom), (min(right, n_right), bottom)),
+        };
+        Some(LeafEdge {
+            from,
+            to,
+            direction,
+            segment,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn leaf_edges() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        assert_eq!(tree.leaf_edges().count(), 0);
+
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let [ne_nw, _, ne_sw, _] = tree.subdivide(ne, [0; 4]).unwrap();
+        let edges: Vec<_> = tree.leaf_edges().collect();
+        // Four around the center, four inside NE, and NE's children touch NW and SE once more.
+        assert_eq!(edges.len(), 10);
+        // East chains run bottom to top.
+        assert_eq!(
+            edges[..2],
+            [
+                LeafEdge {
+                    from: nw,
+                    to: ne_sw,
+                    direction: Cardinality::East,
+                    segment: ((4, 2), (4, 4)),
+                },
+                LeafEdge {
+                    from: nw,
+                    to: ne_nw,
+                    direction: Cardinality::East,
+                    segment: ((4, 0), (4, 2)),
+                },
+            ]
+        );
+        assert!(edges.contains(&LeafEdge {
+            from: sw,
+            to: se,
+            direction: Cardinality::East,
+            segment: ((4, 4), (4, 8)),
+        }));
+
+        let pairs: HashSet<_> = edges
+            .iter()
+            .map(|edge| (edge.from.min(edge.to), edge.from.max(edge.to)))
+            .collect();
+        assert_eq!(pairs.len(), edges.len());
+        // The edges of the leaves add up to the inner grid lines.
+        let length: u32 = edges
+            .iter()
+            .map(|edge| {
+                let ((x0, y0), (x1, y1)) = edge.segment;
+                (x1 - x0) + (y1 - y0)
+            })
+            .sum();
+        assert_eq!(length, 16 + 8);
+    }
+}
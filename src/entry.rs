@@ -0,0 +1,145 @@
+use crate::error::SubdivideError;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::tree::{RegionQuadtree, Unit};
+use std::marker::PhantomData;
+
+/// A view into the leaf containing a point. Constructed by [`RegionQuadtree::entry_at`].
+pub struct Entry<'a, T, Q, const N: usize = 4>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    tree: &'a mut Q,
+    index: Q::Index,
+    point: Point<Unit<T, Q, N>>,
+    _item: PhantomData<T>,
+}
+
+impl<'a, T, Q, const N: usize> Entry<'a, T, Q, N>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    pub(crate) fn new(tree: &'a mut Q, index: Q::Index, point: Point<Unit<T, Q, N>>) -> Self {
+        Self {
+            tree,
+            index,
+            point,
+            _item: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn node(&self) -> &Q::Node {
+        self.tree
+            .get_node(self.index.clone())
+            .expect("entry index should be valid")
+    }
+
+    /// Returns the index of the entry's leaf.
+    #[inline]
+    pub fn index(&self) -> Q::Index {
+        self.index.clone()
+    }
+
+    /// Returns the point used to locate the entry.
+    #[inline]
+    pub fn point(&self) -> Point<Unit<T, Q, N>> {
+        self.point
+    }
+
+    /// Returns the bounds of the entry's leaf.
+    #[inline]
+    pub fn bounds(&self) -> Bounds<Unit<T, Q, N>> {
+        self.node().get_bounds()
+    }
+
+    /// Returns the level of the entry's leaf.
+    #[inline]
+    pub fn level(&self) -> usize {
+        self.node().level()
+    }
+
+    /// Returns a shared reference to the leaf's item.
+    #[inline]
+    pub fn get(&self) -> &T {
+        self.node().get_item()
+    }
+
+    /// Returns a unique reference to the leaf's item.
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.tree
+            .get_node_mut(self.index.clone())
+            .expect("entry index should be valid")
+            .get_item_mut()
+    }
+
+    /// Consumes the entry and returns a unique reference to the leaf's item bound to the tree's
+    /// lifetime.
+    pub fn into_mut(self) -> &'a mut T {
+        self.tree
+            .get_node_mut(self.index)
+            .expect("entry index should be valid")
+            .get_item_mut()
+    }
+
+    /// Calls `f` with a unique reference to the leaf's item and returns the entry.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut T),
+    {
+        f(self.get_mut());
+        self
+    }
+
+    /// Subdivides the leaf with the items returned by `f` and moves the entry to the child
+    /// containing the entry's point. `f` is given the leaf's item and bounds.
+    pub fn or_subdivide_with<F>(self, f: F) -> Result<Self, SubdivideError<T, N>>
+    where
+        F: FnOnce(&T, Bounds<Unit<T, Q, N>>) -> [T; N],
+    {
+        let items = f(self.get(), self.bounds());
+        let children = self.tree.subdivide(self.index.clone(), items)?;
+        let index = children
+            .into_iter()
+            .find(|c| {
+                self.tree
+                    .get_node(c.clone())
+                    .is_some_and(|n| n.point_in(self.point))
+            })
+            .expect("a child should contain the entry's point");
+
+        Ok(Self { index, ..self })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::node::RegionQuadtreeNode;
+    use crate::slottree::CNQuadtree;
+    use crate::tree::RegionQuadtree;
+
+    #[test]
+    fn entry_at_out_of_bounds() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        assert!(tree.entry_at((16, 0)).is_none());
+    }
+
+    #[test]
+    fn entry_read_modify_write() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+
+        let entry = tree.entry_at((3, 12)).unwrap().and_modify(|i| *i += 1);
+        assert_eq!(entry.bounds(), (0, 0, 16, 16));
+        assert_eq!(*entry.get(), 1);
+
+        let entry = entry
+            .or_subdivide_with(|&i, _| [i * 10, i * 20, i * 30, i * 40])
+            .unwrap();
+        assert_eq!(entry.bounds(), (0, 8, 8, 16));
+        assert_eq!(entry.level(), 1);
+        *entry.into_mut() += 5;
+
+        let sw = tree.point_locate((3, 12)).unwrap();
+        assert_eq!(*tree.get_node(sw).unwrap().get_item(), 35);
+    }
+}
@@ -0,0 +1,109 @@
+use thiserror::Error;
+
+/// Error type for quadtree operations.
+#[derive(Debug, Error, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Error {
+    #[error("node index is invalid")]
+    InvalidIndex,
+    #[error("node is already subdivided")]
+    AlreadySubdivided,
+    #[error("node is not subdivided")]
+    NotSubdivided,
+    #[error("node's children are subdivided")]
+    ChildrenSubdivided,
+    #[error("node refers to a node that doesn't exist")]
+    DanglingIndex,
+    #[error("value cannot be represented by the coordinate type")]
+    UnitConversion,
+    #[error("arithmetic overflowed the coordinate type")]
+    Overflow,
+    /// A subdivision would create a child that is empty or smaller than the minimum cell size.
+    #[error("cell is too small to subdivide")]
+    CellTooSmall,
+    /// A subdivision would create children deeper than the tree's maximum depth.
+    #[error("subdivision would exceed the maximum depth")]
+    MaxDepthExceeded,
+    /// A subdivision would create more nodes than the tree's node limit.
+    #[error("subdivision would exceed the node limit")]
+    OutOfNodes,
+    /// Leaves given to build a tree leave gaps, overlap, or have malformed codes.
+    #[error("leaves don't tile the tree")]
+    InvalidTiling,
+    #[error("point is outside the tree's bounds")]
+    PointOutOfBounds,
+    /// Two trees combined by an operation have different bounds.
+    #[error("trees have different bounds")]
+    BoundsMismatch,
+    /// A neighbor chain stops before reaching the end of the node's side.
+    #[error("neighbor chain is broken")]
+    BrokenChain,
+    /// Subtrees given to an operation overlap, or one is given twice.
+    #[error("subtrees overlap")]
+    OverlappingSubtrees,
+    /// A transform collapses the plane onto a line or point, so it can't be inverted.
+    #[error("transform isn't invertible")]
+    SingularTransform,
+    /// Nodes given to an operation on the edge between them don't share one.
+    #[error("nodes don't share an edge")]
+    NotAdjacent,
+}
+
+/// Error type for quadtree subdivision.
+/// `items` are the passed items to the subdivision function.
+#[derive(Debug, Error)]
+#[error("{source}")]
+pub struct SubdivideError<T, const N: usize = 4> {
+    pub items: [T; N],
+    pub source: Error,
+}
+
+/// Error type for popping a node's children.
+#[derive(Debug, Error, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PopChildrenError {
+    #[error("node index is invalid")]
+    InvalidIndex,
+    /// The node is a leaf.
+    #[error("node is not subdivided")]
+    NotSubdivided,
+    /// At least one child is subdivided. Pop the grandchildren first.
+    #[error("node's children are subdivided")]
+    ChildrenSubdivided,
+    /// The tree's structure is inconsistent.
+    #[error("tree is corrupted: {0}")]
+    Corrupted(#[from] Error),
+}
+
+/// Error type for listing a node's neighbors. A border node has no neighbors at that side,
+/// which isn't an error.
+#[derive(Debug, Error, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NeighborError {
+    #[error("node index is invalid")]
+    InvalidIndex,
+    /// Only leaves keep cardinal neighbors.
+    #[error("node is subdivided")]
+    NotLeaf,
+    /// A neighbor doesn't exist or the chain is broken.
+    #[error("tree is corrupted: {0}")]
+    Corrupted(#[from] Error),
+}
+
+impl From<NeighborError> for Error {
+    fn from(error: NeighborError) -> Self {
+        match error {
+            NeighborError::InvalidIndex => Error::InvalidIndex,
+            NeighborError::NotLeaf => Error::AlreadySubdivided,
+            NeighborError::Corrupted(e) => e,
+        }
+    }
+}
+
+impl From<PopChildrenError> for Error {
+    fn from(error: PopChildrenError) -> Self {
+        match error {
+            PopChildrenError::InvalidIndex => Error::InvalidIndex,
+            PopChildrenError::NotSubdivided => Error::NotSubdivided,
+            PopChildrenError::ChildrenSubdivided => Error::ChildrenSubdivided,
+            PopChildrenError::Corrupted(e) => e,
+        }
+    }
+}
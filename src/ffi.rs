@@ -0,0 +1,498 @@
+//! C API over a [`CNQuadtree`] with `f64` coordinates and `u64` items, enabled by the `ffi`
+//! feature. The crate only builds as a Rust library by default; build static and shared
+//! libraries to link with by running
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type staticlib --crate-type cdylib
+//! ```
+//!
+//! They're declared by `include/cnquadtree.h`.
+//!
+//! Trees are opaque handles created by [`cnq_tree_new`] and released by [`cnq_tree_free`]. Items
+//! are plain `u64`s, typically indices or pointers into the host's own storage. Functions return
+//! a [`CnqStatus`] and write results through out pointers.
+
+use crate::error::{Error, NeighborError};
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+
+/// The tree behind a C handle.
+pub type CnqTree = CNQuadtree<u64, f64>;
+
+/// Bounds of a region.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CnqBounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+/// Identifies a node. Only valid with the tree that returned it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct CnqNodeId {
+    pub key: u64,
+    pub tree: u32,
+}
+
+/// Result of a C API call.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CnqStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer,
+    /// The node id doesn't belong to a node of the tree.
+    InvalidIndex,
+    /// The point is outside the tree's bounds.
+    OutOfBounds,
+    /// The output buffer is too small. The required length was written.
+    BufferTooSmall,
+    /// The direction isn't 0 (west), 1 (north), 2 (east) or 3 (south).
+    InvalidDirection,
+    AlreadySubdivided,
+    NotSubdivided,
+    ChildrenSubdivided,
+    CellTooSmall,
+    MaxDepthExceeded,
+    OutOfNodes,
+    /// Any other error. The tree is unchanged.
+    Other,
+    /// The node is subdivided, and only leaves have neighbors.
+    NotLeaf,
+    /// The tree's neighbor links are broken.
+    Corrupted,
+    /// The bounds aren't finite or their minimum isn't below their maximum on both axes.
+    InvalidBounds,
+}
+
+impl From<Error> for CnqStatus {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::InvalidIndex => CnqStatus::InvalidIndex,
+            Error::AlreadySubdivided => CnqStatus::AlreadySubdivided,
+            Error::NotSubdivided => CnqStatus::NotSubdivided,
+            Error::ChildrenSubdivided => CnqStatus::ChildrenSubdivided,
+            Error::CellTooSmall => CnqStatus::CellTooSmall,
+            Error::MaxDepthExceeded => CnqStatus::MaxDepthExceeded,
+            Error::OutOfNodes => CnqStatus::OutOfNodes,
+            Error::PointOutOfBounds => CnqStatus::OutOfBounds,
+            _ => CnqStatus::Other,
+        }
+    }
+}
+
+impl From<NeighborError> for CnqStatus {
+    fn from(error: NeighborError) -> Self {
+        match error {
+            NeighborError::InvalidIndex => CnqStatus::InvalidIndex,
+            NeighborError::NotLeaf => CnqStatus::NotLeaf,
+            NeighborError::Corrupted(_) => CnqStatus::Corrupted,
+        }
+    }
+}
+
+impl From<NodeId> for CnqNodeId {
+    fn from(id: NodeId) -> Self {
+        let (key, tree) = id.to_ffi();
+        Self { key, tree }
+    }
+}
+
+impl CnqNodeId {
+    fn to_node_id(self) -> Result<NodeId, CnqStatus> {
+        NodeId::from_ffi(self.key, self.tree).ok_or(CnqStatus::InvalidIndex)
+    }
+}
+
+/// Runs `f`, converting its error into the returned status.
+fn status<F: FnOnce() -> Result<(), CnqStatus>>(f: F) -> CnqStatus {
+    match f() {
+        Ok(()) => CnqStatus::Ok,
+        Err(status) => status,
+    }
+}
+
+/// Writes `value` through `out`, failing if it's null.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn write<V>(out: *mut V, value: V) -> Result<(), CnqStatus> {
+    if out.is_null() {
+        return Err(CnqStatus::NullPointer);
+    }
+    out.write(value);
+    Ok(())
+}
+
+/// Creates a tree with a single leaf covering `bounds` holding `item`, and writes its handle to
+/// `out`. Fails with [`CnqStatus::InvalidBounds`] if a coordinate is NaN or infinite or the
+/// bounds are empty or inverted.
+///
+/// # Safety
+///
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_new(
+    bounds: CnqBounds,
+    item: u64,
+    out: *mut *mut CnqTree,
+) -> CnqStatus {
+    status(|| {
+        let CnqBounds {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        } = bounds;
+        let finite = [min_x, min_y, max_x, max_y].iter().all(|v| v.is_finite());
+        if !finite || min_x >= max_x || min_y >= max_y {
+            return Err(CnqStatus::InvalidBounds);
+        }
+        if out.is_null() {
+            return Err(CnqStatus::NullPointer);
+        }
+        let tree = CNQuadtree::new(item, (min_x, min_y, max_x, max_y));
+        write(out, Box::into_raw(Box::new(tree)))
+    })
+}
+
+/// Releases a tree. Does nothing if `tree` is null.
+///
+/// # Safety
+///
+/// `tree` must be null or a handle returned by [`cnq_tree_new`] that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_free(tree: *mut CnqTree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Writes the root's id to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_root(tree: *const CnqTree, out: *mut CnqNodeId) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        write(out, tree.get_root().into())
+    })
+}
+
+/// Subdivides a leaf, giving its children `items[0..4]` in the order NW, NE, SW, SE, and writes
+/// the children's ids to `out_children[0..4]` in the same order. Neighbors are updated.
+///
+/// # Safety
+///
+/// `tree` must be a live handle, `items` valid for reading 4 values and `out_children` valid for
+/// writing 4 values.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_subdivide(
+    tree: *mut CnqTree,
+    node: CnqNodeId,
+    items: *const u64,
+    out_children: *mut CnqNodeId,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_mut().ok_or(CnqStatus::NullPointer)?;
+        if items.is_null() || out_children.is_null() {
+            return Err(CnqStatus::NullPointer);
+        }
+        let items = std::slice::from_raw_parts(items, 4);
+        let children = tree
+            .subdivide(node.to_node_id()?, [items[0], items[1], items[2], items[3]])
+            .map_err(|e| CnqStatus::from(e.source))?;
+        for (i, child) in children.into_iter().enumerate() {
+            out_children.add(i).write(child.into());
+        }
+        Ok(())
+    })
+}
+
+/// Removes a node's children, which must be leaves, and writes their items to `out_items[0..4]`
+/// in the order NW, NE, SW, SE. `out_items` may be null to discard them.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out_items` null or valid for writing 4 values.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_pop_children(
+    tree: *mut CnqTree,
+    node: CnqNodeId,
+    out_items: *mut u64,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_mut().ok_or(CnqStatus::NullPointer)?;
+        let items = tree
+            .pop_children(node.to_node_id()?)
+            .map_err(|e| CnqStatus::from(Error::from(e)))?;
+        if !out_items.is_null() {
+            for (i, item) in items.into_iter().enumerate() {
+                out_items.add(i).write(item);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes the id of the leaf containing the point to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_locate(
+    tree: *const CnqTree,
+    x: f64,
+    y: f64,
+    out: *mut CnqNodeId,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        let leaf = tree.point_locate((x, y)).ok_or(CnqStatus::OutOfBounds)?;
+        write(out, leaf.into())
+    })
+}
+
+/// Writes the leaves adjacent to a leaf at the given direction (0 west, 1 north, 2 east,
+/// 3 south) to `out[0..capacity]` and their count to `out_len`. If `capacity` is too small,
+/// nothing is written to `out`, `out_len` gets the required length and
+/// [`CnqStatus::BufferTooSmall`] is returned. Neighbors are ordered as in
+/// [`RegionQuadtree::get_neighbors`]. Returns [`CnqStatus::NotLeaf`] for internal nodes and
+/// [`CnqStatus::Corrupted`] if the neighbor links are broken.
+///
+/// # Safety
+///
+/// `tree` must be a live handle, `out` valid for writing `capacity` values and `out_len` valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_tree_neighbors(
+    tree: *const CnqTree,
+    node: CnqNodeId,
+    direction: u32,
+    out: *mut CnqNodeId,
+    capacity: usize,
+    out_len: *mut usize,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        let direction =
+            Cardinality::try_from(direction as usize).map_err(|_| CnqStatus::InvalidDirection)?;
+        let neighbors = tree.get_neighbors(node.to_node_id()?, direction)?;
+        write(out_len, neighbors.len())?;
+        if neighbors.len() > capacity {
+            return Err(CnqStatus::BufferTooSmall);
+        }
+        if !neighbors.is_empty() && out.is_null() {
+            return Err(CnqStatus::NullPointer);
+        }
+        for (i, neighbor) in neighbors.into_iter().enumerate() {
+            out.add(i).write(neighbor.into());
+        }
+        Ok(())
+    })
+}
+
+/// Writes a node's bounds to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_node_bounds(
+    tree: *const CnqTree,
+    node: CnqNodeId,
+    out: *mut CnqBounds,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        let node = tree
+            .get_node(node.to_node_id()?)
+            .ok_or(CnqStatus::InvalidIndex)?;
+        let Bounds(min_x, min_y, max_x, max_y) = node.get_bounds();
+        write(
+            out,
+            CnqBounds {
+                min_x,
+                min_y,
+                max_x,
+                max_y,
+            },
+        )
+    })
+}
+
+/// Writes a node's item to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_node_item(
+    tree: *const CnqTree,
+    node: CnqNodeId,
+    out: *mut u64,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        let node = tree
+            .get_node(node.to_node_id()?)
+            .ok_or(CnqStatus::InvalidIndex)?;
+        write(out, *node.get_item())
+    })
+}
+
+/// Replaces a node's item.
+///
+/// # Safety
+///
+/// `tree` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_node_set_item(
+    tree: *mut CnqTree,
+    node: CnqNodeId,
+    item: u64,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_mut().ok_or(CnqStatus::NullPointer)?;
+        let node = tree
+            .get_node_mut(node.to_node_id()?)
+            .ok_or(CnqStatus::InvalidIndex)?;
+        *node.get_item_mut() = item;
+        Ok(())
+    })
+}
+
+/// Writes whether a node is a leaf to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live handle and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn cnq_node_is_leaf(
+    tree: *const CnqTree,
+    node: CnqNodeId,
+    out: *mut bool,
+) -> CnqStatus {
+    status(|| {
+        let tree = tree.as_ref().ok_or(CnqStatus::NullPointer)?;
+        let node = tree
+            .get_node(node.to_node_id()?)
+            .ok_or(CnqStatus::InvalidIndex)?;
+        write(out, node.is_leaf())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn round_trip_through_c_api() {
+        unsafe {
+            let bounds = CnqBounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 8.0,
+                max_y: 8.0,
+            };
+            let mut tree = ptr::null_mut();
+            assert_eq!(cnq_tree_new(bounds, 7, &mut tree), CnqStatus::Ok);
+            for bad in [(f64::NAN, 8.0), (8.0, 0.0), (0.0, f64::INFINITY)] {
+                let bad_bounds = CnqBounds {
+                    min_x: bad.0,
+                    max_x: bad.1,
+                    ..bounds
+                };
+                assert_eq!(
+                    cnq_tree_new(bad_bounds, 7, &mut ptr::null_mut()),
+                    CnqStatus::InvalidBounds
+                );
+            }
+            let mut root = CnqNodeId::default();
+            assert_eq!(cnq_tree_root(tree, &mut root), CnqStatus::Ok);
+
+            let mut children = [CnqNodeId::default(); 4];
+            let items = [1, 2, 3, 4];
+            assert_eq!(
+                cnq_tree_subdivide(tree, root, items.as_ptr(), children.as_mut_ptr()),
+                CnqStatus::Ok
+            );
+            assert_eq!(
+                cnq_tree_subdivide(tree, root, items.as_ptr(), children.as_mut_ptr()),
+                CnqStatus::AlreadySubdivided
+            );
+
+            let mut leaf = CnqNodeId::default();
+            assert_eq!(cnq_tree_locate(tree, 5.0, 1.0, &mut leaf), CnqStatus::Ok);
+            assert_eq!(leaf, children[1]);
+            assert_eq!(
+                cnq_tree_locate(tree, 8.0, 1.0, &mut leaf),
+                CnqStatus::OutOfBounds
+            );
+
+            let mut item = 0;
+            assert_eq!(cnq_node_set_item(tree, children[1], 9), CnqStatus::Ok);
+            assert_eq!(cnq_node_item(tree, children[1], &mut item), CnqStatus::Ok);
+            assert_eq!(item, 9);
+
+            let mut out_bounds = CnqBounds::default();
+            assert_eq!(
+                cnq_node_bounds(tree, children[3], &mut out_bounds),
+                CnqStatus::Ok
+            );
+            assert_eq!((out_bounds.min_x, out_bounds.max_y), (4.0, 8.0));
+
+            let mut len = 0;
+            assert_eq!(
+                cnq_tree_neighbors(tree, children[0], 2, ptr::null_mut(), 0, &mut len),
+                CnqStatus::BufferTooSmall
+            );
+            assert_eq!(len, 1);
+            let mut neighbors = [CnqNodeId::default(); 1];
+            assert_eq!(
+                cnq_tree_neighbors(tree, children[0], 2, neighbors.as_mut_ptr(), 1, &mut len),
+                CnqStatus::Ok
+            );
+            assert_eq!(neighbors[0], children[1]);
+            assert_eq!(
+                cnq_tree_neighbors(tree, children[0], 4, neighbors.as_mut_ptr(), 1, &mut len),
+                CnqStatus::InvalidDirection
+            );
+            assert_eq!(
+                cnq_tree_neighbors(tree, root, 2, neighbors.as_mut_ptr(), 1, &mut len),
+                CnqStatus::NotLeaf
+            );
+
+            let mut popped = [0; 4];
+            assert_eq!(
+                cnq_tree_pop_children(tree, root, popped.as_mut_ptr()),
+                CnqStatus::Ok
+            );
+            assert_eq!(popped, [1, 9, 3, 4]);
+            let mut is_leaf = false;
+            assert_eq!(cnq_node_is_leaf(tree, root, &mut is_leaf), CnqStatus::Ok);
+            assert!(is_leaf);
+            assert_eq!(
+                cnq_node_item(tree, children[0], &mut item),
+                CnqStatus::InvalidIndex
+            );
+            assert_eq!(
+                cnq_tree_root(ptr::null(), &mut root),
+                CnqStatus::NullPointer
+            );
+
+            cnq_tree_free(tree);
+        }
+    }
+}
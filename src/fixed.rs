@@ -0,0 +1,336 @@
+use num_traits::{FromPrimitive, Num, One, ToPrimitive, Zero};
+use std::fmt::{self, Display, Formatter};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Rem, RemAssign, Sub, SubAssign};
+use thiserror::Error;
+
+/// A signed fixed-point number with `FRAC` fractional bits stored in an `i64`, for coordinates
+/// that need sub-integer precision with exact, platform-independent arithmetic. The default of
+/// 16 fractional bits covers ±2^47 in steps of 2^-16.
+///
+/// Arithmetic panics on overflow and division by zero. Products and quotients are truncated
+/// towards zero, so halving an extent rounds like integers do and trees split the same way
+/// everywhere.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed<const FRAC: u32 = 16>(i64);
+
+/// Error returned when parsing a [`Fixed`] fails.
+#[derive(Debug, Error, Copy, Clone, Eq, PartialEq, Hash)]
+#[error("invalid fixed-point number")]
+pub struct ParseFixedError;
+
+impl<const FRAC: u32> Fixed<FRAC> {
+    /// The smallest positive value.
+    pub const EPSILON: Self = Self(1);
+
+    /// Returns the number whose underlying representation is `bits`, that is `bits / 2^FRAC`.
+    #[inline]
+    pub const fn from_bits(bits: i64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the underlying representation.
+    #[inline]
+    pub const fn to_bits(self) -> i64 {
+        self.0
+    }
+
+    /// Returns `2^FRAC`, the representation of one.
+    #[inline]
+    const fn scale() -> i64 {
+        1 << FRAC
+    }
+
+    /// Parses `[+-]digits[.digits]` without going through a float, which can't hold every
+    /// value when `FRAC` is large.
+    fn parse_decimal(s: &str) -> Option<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit());
+        if !all_digits || whole.len() + fraction.len() == 0 {
+            return None;
+        }
+        let whole = whole.bytes().try_fold(0u128, |n, b| {
+            n.checked_mul(10)?.checked_add(u128::from(b - b'0'))
+        })?;
+
+        // 10^19 * 2^FRAC fits in a u128 for every FRAC an i64 can scale by, and digits past the
+        // 19th can't change the rounding: they add less than one to a remainder that rounds up
+        // from half of the denominator, which is even.
+        const DIGITS: u32 = 19;
+        let kept = &fraction[..fraction.len().min(DIGITS as usize)];
+        let numerator = kept
+            .bytes()
+            .fold(0u128, |n, b| n * 10 + u128::from(b - b'0'))
+            * 10u128.pow(DIGITS - kept.len() as u32);
+        let denominator = 10u128.pow(DIGITS);
+        let scaled = numerator << FRAC;
+        let mut magnitude = scaled / denominator;
+        if 2 * (scaled % denominator) >= denominator {
+            magnitude += 1;
+        }
+
+        let magnitude = whole
+            .checked_mul(1 << FRAC)?
+            .checked_add(magnitude)
+            .and_then(|m| i128::try_from(m).ok())?;
+        let bits = if negative { -magnitude } else { magnitude };
+        i64::try_from(bits).ok().map(Self)
+    }
+
+    fn narrow(wide: i128, operation: &str) -> Self {
+        match i64::try_from(wide) {
+            Ok(bits) => Self(bits),
+            Err(_) => panic!("fixed-point {operation} overflowed"),
+        }
+    }
+}
+
+impl<const FRAC: u32> Display for Fixed<FRAC> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&(self.0 as f64 / Self::scale() as f64), f)
+    }
+}
+
+impl<const FRAC: u32> Add for Fixed<FRAC> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(
+            self.0
+                .checked_add(rhs.0)
+                .expect("fixed-point addition overflowed"),
+        )
+    }
+}
+
+impl<const FRAC: u32> Sub for Fixed<FRAC> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self(
+            self.0
+                .checked_sub(rhs.0)
+                .expect("fixed-point subtraction overflowed"),
+        )
+    }
+}
+
+impl<const FRAC: u32> Mul for Fixed<FRAC> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::narrow(
+            i128::from(self.0) * i128::from(rhs.0) / i128::from(Self::scale()),
+            "multiplication",
+        )
+    }
+}
+
+impl<const FRAC: u32> Div for Fixed<FRAC> {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        assert!(rhs.0 != 0, "fixed-point division by zero");
+        Self::narrow(
+            i128::from(self.0) * i128::from(Self::scale()) / i128::from(rhs.0),
+            "division",
+        )
+    }
+}
+
+impl<const FRAC: u32> Rem for Fixed<FRAC> {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self {
+        Self(self.0 % rhs.0)
+    }
+}
+
+macro_rules! impl_assign {
+    ($($trait:ident $method:ident $op:tt),*) => {
+        $(
+            impl<const FRAC: u32> $trait for Fixed<FRAC> {
+                fn $method(&mut self, rhs: Self) {
+                    *self = *self $op rhs;
+                }
+            }
+        )*
+    };
+}
+
+impl_assign!(
+    AddAssign add_assign +,
+    SubAssign sub_assign -,
+    MulAssign mul_assign *,
+    DivAssign div_assign /,
+    RemAssign rem_assign %
+);
+
+impl<const FRAC: u32> Zero for Fixed<FRAC> {
+    fn zero() -> Self {
+        Self(0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl<const FRAC: u32> One for Fixed<FRAC> {
+    fn one() -> Self {
+        Self(Self::scale())
+    }
+}
+
+impl<const FRAC: u32> Num for Fixed<FRAC> {
+    type FromStrRadixErr = ParseFixedError;
+
+    /// Parses decimal numbers with an optional sign and fraction, or integers in other radices.
+    /// Decimals are parsed exactly and rounded to the nearest representable value, with ties
+    /// away from zero like [`FromPrimitive::from_f64`].
+    fn from_str_radix(s: &str, radix: u32) -> Result<Self, ParseFixedError> {
+        if radix == 10 {
+            Self::parse_decimal(s).ok_or(ParseFixedError)
+        } else {
+            let value = i64::from_str_radix(s, radix).map_err(|_| ParseFixedError)?;
+            Self::from_i64(value).ok_or(ParseFixedError)
+        }
+    }
+}
+
+impl<const FRAC: u32> ToPrimitive for Fixed<FRAC> {
+    /// Truncates towards zero.
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0 / Self::scale())
+    }
+
+    /// Truncates towards zero.
+    fn to_u64(&self) -> Option<u64> {
+        self.to_i64().and_then(|i| u64::try_from(i).ok())
+    }
+
+    fn to_i128(&self) -> Option<i128> {
+        self.to_i64().map(i128::from)
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.0 as f64 / Self::scale() as f64)
+    }
+}
+
+impl<const FRAC: u32> FromPrimitive for Fixed<FRAC> {
+    fn from_i64(n: i64) -> Option<Self> {
+        n.checked_mul(Self::scale()).map(Self)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        i64::try_from(n).ok().and_then(Self::from_i64)
+    }
+
+    fn from_i128(n: i128) -> Option<Self> {
+        i64::try_from(n).ok().and_then(Self::from_i64)
+    }
+
+    /// Rounds to the nearest representable value. Returns None for NaN and out of range values.
+    fn from_f64(n: f64) -> Option<Self> {
+        let bits = (n * Self::scale() as f64).round();
+        // i64::MAX rounds up to 2^63 as an f64, so the upper bound is exclusive.
+        if bits.is_finite() && bits >= i64::MIN as f64 && bits < i64::MAX as f64 {
+            Some(Self(bits as i64))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::LeBytes;
+    use crate::location::Cardinality;
+    use crate::node::RegionQuadtreeNode;
+    use crate::slottree::CNQuadtree;
+    use crate::tree::RegionQuadtree;
+
+    type F = Fixed<16>;
+
+    fn f(value: f64) -> F {
+        F::from_f64(value).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(f(1.5) + f(0.25), f(1.75));
+        assert_eq!(f(1.5) * f(-2.0), f(-3.0));
+        assert_eq!(f(1.0) / f(3.0), F::from_bits(21845));
+        assert_eq!(f(-1.0) / f(3.0), F::from_bits(-21845));
+        assert_eq!(f(5.5) % f(2.0), f(1.5));
+        assert_eq!(f(-2.75).to_i64(), Some(-2));
+        assert_eq!(F::from_i64(3), Some(f(3.0)));
+        assert_eq!(F::from_i64(1 << 50), None);
+        assert_eq!(F::from_f64(f64::NAN), None);
+        assert_eq!(F::from_str_radix("-0.125", 10), Ok(f(-0.125)));
+        assert_eq!(F::from_str_radix("ff", 16), Ok(f(255.0)));
+        assert_eq!(F::from_str_radix("x", 10), Err(ParseFixedError));
+        assert_eq!(F::from_str_radix("+.5", 10), Ok(f(0.5)));
+        assert_eq!(F::from_str_radix("3.", 10), Ok(f(3.0)));
+        assert_eq!(F::from_str_radix("1e3", 10), Err(ParseFixedError));
+        assert_eq!(F::from_str_radix("-.", 10), Err(ParseFixedError));
+        assert_eq!(F::from_str_radix("1.5.", 10), Err(ParseFixedError));
+        // Halfway between two steps rounds away from zero, however many digits follow.
+        assert_eq!(
+            F::from_str_radix("0.00000762939453125", 10),
+            Ok(F::from_bits(1))
+        );
+        assert_eq!(
+            F::from_str_radix("-0.0000076293945312499999999999", 10),
+            Ok(F::from_bits(0))
+        );
+        // Beyond the 53 bits an f64 keeps.
+        assert_eq!(
+            Fixed::<32>::from_str_radix("123456789.000000001", 10),
+            Ok(Fixed::<32>::from_bits((123456789 << 32) + 4))
+        );
+        assert_eq!(
+            Fixed::<62>::from_str_radix("-2", 10),
+            Ok(Fixed::<62>::from_bits(i64::MIN))
+        );
+        assert_eq!(Fixed::<62>::from_str_radix("2", 10), Err(ParseFixedError));
+        assert_eq!(f(0.5).to_string(), "0.5");
+    }
+
+    #[test]
+    fn tree_operations() {
+        let mut tree = CNQuadtree::new(0u8, (f(0.0), f(0.0), f(1.0), f(0.75)));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            tree.get_node(nw).unwrap().get_bounds(),
+            (f(0.0), f(0.0), f(0.5), f(0.375))
+        );
+        let [_, _, ne_sw, _] = tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        assert_eq!(tree.point_locate((f(0.5), f(0.2))), Some(ne_sw));
+        assert_eq!(tree.point_locate((f(1.0), f(0.2))), None);
+        assert_eq!(tree.get_neighbors(nw, Cardinality::East).unwrap().len(), 2);
+        assert_eq!(
+            tree.region_locate((f(0.4), f(0.1), f(0.6), f(0.2)))
+                .unwrap()
+                .len(),
+            3
+        );
+
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+        let loaded = CNQuadtree::<u8, F>::load_from(&mut bytes.as_slice(), &mut LeBytes).unwrap();
+        assert!(loaded == tree);
+
+        // Halving stops at the smallest step.
+        let mut tree = CNQuadtree::new((), (F::zero(), F::zero(), F::EPSILON, F::EPSILON));
+        assert!(tree.subdivide(tree.get_root(), [(); 4]).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+/// A tree flattened into parallel arrays indexed by dense `u32`s, for uploading to the GPU.
+/// Nodes are in breadth-first order, so a node's four children are contiguous and ordered NW,
+/// NE, SW, SE. Returned by [`CNQuadtree::to_flat_arrays`](crate::CNQuadtree::to_flat_arrays).
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlatArrays<S, P> {
+    /// Each node's bounds as min x, min y, max x, max y.
+    pub bounds: Vec<[S; 4]>,
+    /// Each node's level. The root is at level 0.
+    pub levels: Vec<u32>,
+    /// Each node's parent, or [`FlatArrays::NONE`] for the root.
+    pub parents: Vec<u32>,
+    /// Each node's first child, or [`FlatArrays::NONE`] for leaves. The other children follow it.
+    pub first_child: Vec<u32>,
+    /// Each node's cardinal neighbors in the order West, North, East, South, or
+    /// [`FlatArrays::NONE`] if absent.
+    pub neighbors: Vec<[u32; 4]>,
+    /// Each node's payload.
+    pub items: Vec<P>,
+}
+
+impl<S, P> FlatArrays<S, P> {
+    /// Marks a missing parent, child or neighbor.
+    pub const NONE: u32 = u32::MAX;
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns true if there are no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
@@ -0,0 +1,201 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use std::f64::consts::PI;
+
+/// Maps geographic coordinates to the plane a [`GeoQuadtree`] splits in. Projected y must grow
+/// southwards, like the tree's y-axis, so that north is up.
+pub trait Projection {
+    /// Projects a `(longitude, latitude)` in degrees. Returns None if the point can't be
+    /// projected.
+    fn project(&self, point: Point<f64>) -> Option<Point<f64>>;
+    /// Returns the `(longitude, latitude)` in degrees of a projected point. Returns None if it's
+    /// outside the projection's range.
+    fn unproject(&self, point: Point<f64>) -> Option<Point<f64>>;
+}
+
+/// The Web Mercator projection onto the unit square, as used by slippy map tiles: (0, 0) is the
+/// north-west corner at 180° W, 85.05° N, and (1, 1) the south-east corner. Latitudes beyond
+/// [`WebMercator::MAX_LATITUDE`] can't be projected.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WebMercator;
+
+impl WebMercator {
+    /// The latitude projected to the top and bottom of the square, in degrees.
+    pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+}
+
+impl Projection for WebMercator {
+    fn project(&self, (lon, lat): Point<f64>) -> Option<Point<f64>> {
+        let max = Self::MAX_LATITUDE;
+        if !(-180.0..=180.0).contains(&lon) || !(-max..=max).contains(&lat) {
+            return None;
+        }
+        let y = (PI / 4.0 + lat.to_radians() / 2.0).tan().ln();
+        Some((
+            (lon + 180.0) / 360.0,
+            (0.5 - y / (2.0 * PI)).clamp(0.0, 1.0),
+        ))
+    }
+
+    fn unproject(&self, (x, y): Point<f64>) -> Option<Point<f64>> {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+        let lat = (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees();
+        Some((x * 360.0 - 180.0, lat))
+    }
+}
+
+/// The equirectangular projection, which keeps degrees as they are but flips latitude so it
+/// grows southwards. Splits halve longitude and latitude ranges.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn project(&self, (lon, lat): Point<f64>) -> Option<Point<f64>> {
+        ((-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat)).then_some((lon, -lat))
+    }
+
+    fn unproject(&self, (x, y): Point<f64>) -> Option<Point<f64>> {
+        self.project((x, -y)).map(|_| (x, -y))
+    }
+}
+
+/// A quadtree over a geographic area, queried in WGS84 `(longitude, latitude)` degrees but split
+/// in a projected plane. Geographic bounds are `(west, south, east, north)`.
+///
+/// The underlying tree, in projected coordinates, is available through [`GeoQuadtree::tree`]
+/// and [`GeoQuadtree::tree_mut`] for everything that doesn't take coordinates.
+pub struct GeoQuadtree<T, P = WebMercator> {
+    tree: CNQuadtree<T, f64>,
+    projection: P,
+}
+
+impl<T, P> GeoQuadtree<T, P>
+where
+    P: Projection,
+{
+    /// Returns a tree covering `bounds`, given as `(west, south, east, north)` in degrees. Fails
+    /// with [`Error::PointOutOfBounds`] if a corner can't be projected, or
+    /// [`Error::CellTooSmall`] if the area is empty.
+    pub fn new(item: T, bounds: impl Into<Bounds<f64>>, projection: P) -> Result<Self, Error> {
+        let bounds = bounds.into();
+        let Bounds(west, south, east, north) = bounds;
+        let (left, top) = projection
+            .project((west, north))
+            .ok_or(Error::PointOutOfBounds)?;
+        let (right, bottom) = projection
+            .project((east, south))
+            .ok_or(Error::PointOutOfBounds)?;
+        if !(left < right && top < bottom) {
+            return Err(Error::CellTooSmall);
+        }
+        Ok(Self {
+            tree: CNQuadtree::new(item, (left, top, right, bottom)),
+            projection,
+        })
+    }
+
+    /// Returns the tree in projected coordinates.
+    #[inline]
+    pub fn tree(&self) -> &CNQuadtree<T, f64> {
+        &self.tree
+    }
+
+    /// Returns the tree in projected coordinates for editing.
+    #[inline]
+    pub fn tree_mut(&mut self) -> &mut CNQuadtree<T, f64> {
+        &mut self.tree
+    }
+
+    /// Returns the projection.
+    #[inline]
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Returns the leaf containing a `(longitude, latitude)` in degrees. Returns None if it's
+    /// outside the tree or can't be projected.
+    pub fn point_locate(&self, point: Point<f64>) -> Option<NodeId> {
+        self.tree.point_locate(self.projection.project(point)?)
+    }
+
+    /// Returns the leaves overlapping an area given as `(west, south, east, north)` in degrees.
+    /// Returns None if it's outside the tree or can't be projected.
+    pub fn region_locate(&self, bounds: impl Into<Bounds<f64>>) -> Option<Vec<NodeId>> {
+        let bounds = bounds.into();
+        let Bounds(west, south, east, north) = bounds;
+        let (left, top) = self.projection.project((west, north))?;
+        let (right, bottom) = self.projection.project((east, south))?;
+        self.tree.region_locate((left, top, right, bottom))
+    }
+
+    /// Returns a node's area as `(west, south, east, north)` in degrees.
+    pub fn geo_bounds(&self, index: NodeId) -> Option<Bounds<f64>> {
+        let Bounds(left, top, right, bottom) = self.tree.get_node(index)?.get_bounds();
+        let (west, north) = self.projection.unproject((left, top))?;
+        let (east, south) = self.projection.unproject((right, bottom))?;
+        Some(Bounds(west, south, east, north))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Bounds<f64>, b: Bounds<f64>) -> bool {
+        [(a.0, b.0), (a.1, b.1), (a.2, b.2), (a.3, b.3)]
+            .iter()
+            .all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn web_mercator() {
+        let max = WebMercator::MAX_LATITUDE;
+        assert_eq!(WebMercator.project((0.0, 0.0)), Some((0.5, 0.5)));
+        let (x, y) = WebMercator.project((-180.0, max)).unwrap();
+        assert!(x == 0.0 && y.abs() < 1e-12);
+        let (lon, lat) = WebMercator
+            .unproject(WebMercator.project((13.4, 52.5)).unwrap())
+            .unwrap();
+        assert!((lon - 13.4).abs() < 1e-9 && (lat - 52.5).abs() < 1e-9);
+        assert_eq!(WebMercator.project((0.0, 89.0)), None);
+        assert_eq!(WebMercator.project((0.0, f64::NAN)), None);
+
+        let mut tree = GeoQuadtree::new(0, (-180.0, -max, 180.0, max), WebMercator).unwrap();
+        let root = tree.tree().get_root();
+        let [_, ne, sw, _] = tree.tree_mut().subdivide(root, [1, 2, 3, 4]).unwrap();
+        assert_eq!(tree.point_locate((13.4, 52.5)), Some(ne));
+        assert_eq!(tree.point_locate((-70.0, -33.4)), Some(sw));
+        assert_eq!(tree.point_locate((0.0, 89.0)), None);
+        assert!(close(
+            tree.geo_bounds(ne).unwrap(),
+            Bounds(0.0, 0.0, 180.0, max)
+        ));
+        assert_eq!(
+            tree.region_locate((-10.0, 40.0, 10.0, 60.0))
+                .map(|r| r.len()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn custom_projection() {
+        let mut tree = GeoQuadtree::new('.', (0.0, 40.0, 20.0, 60.0), Equirectangular).unwrap();
+        let root = tree.tree().get_root();
+        let [nw, ..] = tree
+            .tree_mut()
+            .subdivide(root, ['a', 'b', 'c', 'd'])
+            .unwrap();
+        assert_eq!(tree.geo_bounds(nw), Some(Bounds(0.0, 50.0, 10.0, 60.0)));
+        assert_eq!(tree.point_locate((5.0, 55.0)), Some(nw));
+        assert_eq!(tree.point_locate((25.0, 55.0)), None);
+        assert_eq!(
+            GeoQuadtree::new((), (10.0, 0.0, 0.0, 10.0), Equirectangular).err(),
+            Some(Error::CellTooSmall)
+        );
+    }
+}
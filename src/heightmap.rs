@@ -0,0 +1,179 @@
+use crate::error::Error;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// The least-squares plane through the heights a node covers, stored by trees built with
+/// [`CNQuadtree::from_heightmap`]. Internal nodes hold the plane of their whole area, which
+/// serves as a coarser level of detail.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct HeightPlane {
+    /// The mean height, which the plane passes through at the center.
+    pub mean: f64,
+    /// The center of the node's samples.
+    pub center: Point<f64>,
+    /// How much the height changes per unit along x and y.
+    pub slope: (f64, f64),
+    /// The largest difference between a covered height and the plane.
+    pub error: f64,
+}
+
+impl HeightPlane {
+    /// Returns the plane's height at a point.
+    #[inline]
+    pub fn height_at(&self, (x, y): Point<f64>) -> f64 {
+        self.mean + self.slope.0 * (x - self.center.0) + self.slope.1 * (y - self.center.1)
+    }
+
+    /// Fits a plane to the samples of `rows` in columns `left..right` and rows `top..bottom`,
+    /// with sample `(i, j)` at the center of its cell, `(i + 0.5, j + 0.5)`.
+    fn fit(rows: &[Vec<f64>], Bounds(left, top, right, bottom): Bounds<usize>) -> Self {
+        let samples = || {
+            rows[top..bottom]
+                .iter()
+                .enumerate()
+                .flat_map(move |(j, row)| {
+                    row[left..right]
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, &z)| ((left + i) as f64 + 0.5, (top + j) as f64 + 0.5, z))
+                })
+        };
+        let count = ((right - left) * (bottom - top)) as f64;
+        let center = ((left + right) as f64 / 2.0, (top + bottom) as f64 / 2.0);
+        // Samples lie on a grid, so x and y are uncorrelated and each slope is a simple
+        // regression on its own axis.
+        let (mut sum, mut xz, mut yz, mut xx, mut yy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (x, y, z) in samples() {
+            let (dx, dy) = (x - center.0, y - center.1);
+            sum += z;
+            xz += dx * z;
+            yz += dy * z;
+            xx += dx * dx;
+            yy += dy * dy;
+        }
+        let slope = |products: f64, squares: f64| {
+            if squares > 0.0 {
+                products / squares
+            } else {
+                0.0
+            }
+        };
+        let mut plane = Self {
+            mean: sum / count,
+            center,
+            slope: (slope(xz, xx), slope(yz, yy)),
+            error: 0.0,
+        };
+        plane.error = samples()
+            .map(|(x, y, z)| (z - plane.height_at((x, y))).abs())
+            .fold(0.0, f64::max);
+        plane
+    }
+}
+
+impl<S> CNQuadtree<HeightPlane, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Builds a terrain tree from a heightmap given as rows of heights, top row first. The tree
+    /// covers `(0, 0, width, height)` with one unit per sample, and every node holds the
+    /// [`HeightPlane`] fitted to the heights it covers. Leaves are subdivided until their plane
+    /// is within `error_threshold` of every height they cover, or they can't be split further.
+    ///
+    /// Fails with [`Error::InvalidTiling`] if there are no heights or rows differ in length,
+    /// and with [`Error::UnitConversion`] if the size can't be represented by the coordinate
+    /// type.
+    pub fn from_heightmap(rows: &[Vec<f64>], error_threshold: f64) -> Result<Self, Error> {
+        let width = rows.first().map_or(0, Vec::len);
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err(Error::InvalidTiling);
+        }
+        let size = |n: usize| S::from_usize(n).ok_or(Error::UnitConversion);
+        let cells = |Bounds(l, t, r, b): Bounds<S>| -> Result<Bounds<usize>, Error> {
+            let to_usize = |value: S| value.to_usize().ok_or(Error::UnitConversion);
+            Ok(Bounds(
+                to_usize(l)?,
+                to_usize(t)?,
+                to_usize(r)?,
+                to_usize(b)?,
+            ))
+        };
+
+        let bounds = Bounds(S::zero(), S::zero(), size(width)?, size(rows.len())?);
+        let mut tree = Self::new(HeightPlane::fit(rows, cells(bounds)?), bounds);
+        tree.begin_batch();
+        let mut stack = vec![tree.get_root()];
+        while let Some(index) = stack.pop() {
+            let node = tree.get_node(index).ok_or(Error::DanglingIndex)?;
+            if node.get_item().error <= error_threshold {
+                continue;
+            }
+            // The children's bounds aren't known until after the split.
+            match tree.subdivide(index, [HeightPlane::default(); 4]) {
+                Ok(children) => {
+                    for child in children {
+                        let node = tree.get_node_mut(child).ok_or(Error::DanglingIndex)?;
+                        *node.get_item_mut() = HeightPlane::fit(rows, cells(node.get_bounds())?);
+                        stack.push(child);
+                    }
+                }
+                Err(e) => match e.source {
+                    Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes => {}
+                    e => return Err(e),
+                },
+            }
+        }
+        tree.commit();
+        Ok(tree)
+    }
+
+    /// Returns the height at a point from the plane of the leaf containing it. Returns None if
+    /// the point is outside the tree.
+    pub fn sample_height(&self, point: Point<f64>) -> Option<f64> {
+        let leaf = self.point_locate((S::from_f64(point.0)?, S::from_f64(point.1)?))?;
+        Some(self.get_node(leaf)?.get_item().height_at(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_heightmap() {
+        let slope: Vec<Vec<f64>> = (0..8)
+            .map(|y| (0..8).map(|x| f64::from(2 * x + y)).collect())
+            .collect();
+        let tree = CNQuadtree::<HeightPlane, u32>::from_heightmap(&slope, 0.01).unwrap();
+        assert_eq!(tree.node_count(), 1);
+        assert_eq!(tree.bounds(), (0, 0, 8, 8));
+        let height = tree.sample_height((3.5, 1.5)).unwrap();
+        assert!((height - 7.0).abs() < 1e-9);
+        assert_eq!(tree.sample_height((8.5, 0.0)), None);
+
+        // A peak in one corner of flat ground is refined around it.
+        let mut peak = vec![vec![0.0; 8]; 8];
+        peak[1][1] = 10.0;
+        let tree = CNQuadtree::<HeightPlane, u32>::from_heightmap(&peak, 0.5).unwrap();
+        assert_eq!(tree.sample_height((1.5, 1.5)), Some(10.0));
+        let far = tree.point_locate((6, 6)).unwrap();
+        assert_eq!(tree.get_node(far).unwrap().level(), 1);
+        for (y, row) in peak.iter().enumerate() {
+            for (x, &z) in row.iter().enumerate() {
+                let height = tree
+                    .sample_height((x as f64 + 0.5, y as f64 + 0.5))
+                    .unwrap();
+                assert!((height - z).abs() <= 0.5);
+            }
+        }
+        let root = tree.get_node(tree.get_root()).unwrap().get_item();
+        assert!((root.mean - 10.0 / 64.0).abs() < 1e-9);
+
+        assert_eq!(
+            CNQuadtree::<HeightPlane, u32>::from_heightmap(&[vec![1.0], vec![]], 0.0).err(),
+            Some(Error::InvalidTiling)
+        );
+    }
+}
@@ -0,0 +1,474 @@
+use crate::distance::Visit;
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// A hierarchical pathfinding (HPA*) graph over a tree's leaves, for maps too large to search
+/// leaf by leaf. The nodes at one level of the tree, and leaves above it, are clusters. Each
+/// maximal run of open leaves along the edge between two clusters is an entrance, crossed at
+/// its middle by a pair of portal leaves, and the cheapest paths between the portals of each
+/// cluster are found when the graph is built. Paths are found over the portals, then refined
+/// into leaves by searching within each cluster they cross.
+///
+/// Crossing entrances at their middles makes paths slightly costlier than
+/// [`CNQuadtree::shortest_path`]'s in exchange for searching far fewer nodes.
+///
+/// The graph doesn't follow the tree on its own. After changing items or the tree's structure,
+/// call [`PortalGraph::update`] with the region that changed, which only rebuilds the clusters
+/// it touches.
+#[derive(Clone, Debug, Default)]
+pub struct PortalGraph {
+    level: usize,
+    clusters: HashMap<NodeId, Cluster>,
+    /// The cluster of each portal.
+    portals: HashMap<NodeId, NodeId>,
+}
+
+#[derive(Clone, Debug)]
+struct Cluster {
+    bounds: Bounds<f64>,
+    /// The cluster's portals, each with the portal in another cluster it steps to and the cost
+    /// of that step.
+    entrances: Vec<(NodeId, NodeId, f64)>,
+    /// The cost of the cheapest path within the cluster from each portal to the others.
+    links: HashMap<NodeId, Vec<(NodeId, f64)>>,
+}
+
+impl PortalGraph {
+    /// Returns the level of the clusters.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Returns the number of clusters.
+    pub fn cluster_count(&self) -> usize {
+        self.clusters.len()
+    }
+
+    /// Returns the number of portal leaves.
+    pub fn portal_count(&self) -> usize {
+        self.portals.len()
+    }
+
+    /// Rebuilds the clusters touching `region`, including those only sharing an edge or corner
+    /// with it, and the entrances of their neighbors. `cost` must be the one the graph was
+    /// built with, applied to the tree's current items.
+    pub fn update<T, S, F>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        region: impl Into<Bounds<S>>,
+        cost: F,
+    ) where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(&T) -> Option<f64>,
+    {
+        let Bounds(left, top, right, bottom) = region.into();
+        let (Some(left), Some(top), Some(right), Some(bottom)) =
+            (left.to_f64(), top.to_f64(), right.to_f64(), bottom.to_f64())
+        else {
+            return;
+        };
+        let region = Bounds(left, top, right, bottom);
+        let touches = |b: Bounds<f64>| {
+            b.0 <= region.2 && region.0 <= b.2 && b.1 <= region.3 && region.1 <= b.3
+        };
+        let mut leaf_cost = tree.cached_leaf_cost(cost);
+
+        let touched: Vec<(NodeId, Bounds<f64>)> = tree
+            .clusters(self.level)
+            .into_iter()
+            .filter_map(|cluster| Some((cluster, tree.bounds_f64(cluster)?)))
+            .filter(|&(_, bounds)| touches(bounds))
+            .collect();
+        let touched_ids: HashSet<NodeId> = touched.iter().map(|&(id, _)| id).collect();
+        let mut removed: HashSet<NodeId> = self
+            .clusters
+            .iter()
+            .filter(|(_, cluster)| touches(cluster.bounds))
+            .map(|(&id, _)| id)
+            .collect();
+        removed.extend(&touched_ids);
+        let mut relink = touched_ids.clone();
+
+        // Drop the removed clusters and the other ends of their entrances.
+        for id in &removed {
+            let Some(cluster) = self.clusters.remove(id) else {
+                continue;
+            };
+            for (portal, other, _) in cluster.entrances {
+                self.portals.remove(&portal);
+                let Some(&owner) = self.portals.get(&other) else {
+                    continue;
+                };
+                if removed.contains(&owner) {
+                    continue;
+                }
+                if let Some(neighbor) = self.clusters.get_mut(&owner) {
+                    neighbor.entrances.retain(|e| (e.0, e.1) != (other, portal));
+                    if !neighbor.entrances.iter().any(|e| e.0 == other) {
+                        self.portals.remove(&other);
+                    }
+                    relink.insert(owner);
+                }
+            }
+        }
+
+        for &(id, bounds) in &touched {
+            let cluster = Cluster {
+                bounds,
+                entrances: Vec::new(),
+                links: HashMap::new(),
+            };
+            self.clusters.insert(id, cluster);
+        }
+        for &(id, _) in &touched {
+            for direction in Cardinality::ALL {
+                let entrances = tree.entrances(id, direction, self.level, &mut leaf_cost);
+                for (portal, other, owner, step) in entrances {
+                    // Rebuilt neighbors add the entrances on their east and south sides.
+                    let rebuilt = touched_ids.contains(&owner);
+                    if rebuilt && matches!(direction, Cardinality::West | Cardinality::North) {
+                        continue;
+                    }
+                    let Some(neighbor) = self.clusters.get_mut(&owner) else {
+                        continue;
+                    };
+                    neighbor.entrances.push((other, portal, step));
+                    relink.insert(owner);
+                    if let Some(cluster) = self.clusters.get_mut(&id) {
+                        cluster.entrances.push((portal, other, step));
+                    }
+                }
+            }
+        }
+
+        for id in relink {
+            let Some(cluster) = self.clusters.get_mut(&id) else {
+                continue;
+            };
+            let mut portals: Vec<NodeId> = cluster.entrances.iter().map(|e| e.0).collect();
+            portals.sort();
+            portals.dedup();
+            cluster.links.clear();
+            for &portal in &portals {
+                self.portals.insert(portal, id);
+                let Some(search) = tree.search(portal, None, Some(cluster.bounds), &mut leaf_cost)
+                else {
+                    continue;
+                };
+                let links = portals
+                    .iter()
+                    .filter(|&&other| other != portal)
+                    .filter_map(|&other| Some((other, *search.costs.get(&other)?)))
+                    .collect();
+                cluster.links.insert(portal, links);
+            }
+        }
+    }
+
+    /// Returns a path of edge-adjacent leaves from `from` to `to`, both included, and its cost,
+    /// like [`CNQuadtree::shortest_path`] but searching over the graph's portals and only
+    /// searching leaves within the clusters of the ends and the clusters the path crosses.
+    /// `cost` must be the one the graph was built with.
+    ///
+    /// Returns None if either index isn't a leaf, an end can't be entered, `to` can't be
+    /// reached over the graph or a coordinate can't be converted to `f64`.
+    pub fn find_path<T, S, F>(
+        &self,
+        tree: &CNQuadtree<T, S>,
+        from: NodeId,
+        to: NodeId,
+        cost: F,
+    ) -> Option<(Vec<NodeId>, f64)>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(&T) -> Option<f64>,
+    {
+        let mut leaf_cost = tree.cached_leaf_cost(cost);
+        leaf_cost(from)?;
+        leaf_cost(to)?;
+        let start_cluster = tree.cluster_of(from, self.level)?;
+        let goal_cluster = tree.cluster_of(to, self.level)?;
+        let start_bounds = self.clusters.get(&start_cluster)?.bounds;
+        let goal_bounds = self.clusters.get(&goal_cluster)?.bounds;
+        // Costs are symmetric, so searching from `to` gives the costs of reaching it.
+        let start = tree.search(from, None, Some(start_bounds), &mut leaf_cost)?;
+        let goal = tree.search(to, None, Some(goal_bounds), &mut leaf_cost)?;
+
+        // Each hop is from a node, within a cluster or None when stepping between clusters.
+        let mut costs = HashMap::from([(from, 0.0)]);
+        let mut previous: HashMap<NodeId, (NodeId, Option<NodeId>)> = HashMap::new();
+        let mut queue = BinaryHeap::from([Reverse(Visit(0.0, from))]);
+        while let Some(Reverse(Visit(total, node))) = queue.pop() {
+            if node == to {
+                break;
+            }
+            if costs.get(&node).is_some_and(|&c| c < total) {
+                continue;
+            }
+            let mut hops = Vec::new();
+            if node == from {
+                let portals = self.clusters[&start_cluster].links.keys();
+                hops.extend(portals.filter_map(|&portal| {
+                    Some((portal, *start.costs.get(&portal)?, Some(start_cluster)))
+                }));
+                if start_cluster == goal_cluster {
+                    hops.extend(start.costs.get(&to).map(|&c| (to, c, Some(start_cluster))));
+                }
+            }
+            if let Some(&id) = self.portals.get(&node) {
+                let cluster = self.clusters.get(&id)?;
+                let links = cluster.links.get(&node).into_iter().flatten();
+                hops.extend(links.map(|&(portal, cost)| (portal, cost, Some(id))));
+                let entrances = cluster.entrances.iter().filter(|e| e.0 == node);
+                hops.extend(entrances.map(|&(_, other, cost)| (other, cost, None)));
+                if id == goal_cluster {
+                    hops.extend(goal.costs.get(&node).map(|&c| (to, c, Some(id))));
+                }
+            }
+            for (next, cost, within) in hops {
+                let next_total = total + cost;
+                if costs.get(&next).is_none_or(|&c| next_total < c) {
+                    costs.insert(next, next_total);
+                    previous.insert(next, (node, within));
+                    queue.push(Reverse(Visit(next_total, next)));
+                }
+            }
+        }
+
+        let total = *costs.get(&to)?;
+        let mut hops = Vec::new();
+        let mut node = to;
+        while node != from {
+            let (previous, within) = previous[&node];
+            hops.push((previous, node, within));
+            node = previous;
+        }
+        let mut path = vec![from];
+        for (a, b, within) in hops.into_iter().rev() {
+            let Some(id) = within else {
+                path.push(b);
+                continue;
+            };
+            let bounds = self.clusters.get(&id)?.bounds;
+            let search = tree.search(a, Some(b), Some(bounds), &mut leaf_cost)?;
+            let (steps, _) = search.path_to(b)?;
+            path.extend(&steps[1..]);
+        }
+        Some((path, total))
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Builds a hierarchical pathfinding graph whose clusters are the nodes at `level`, and
+    /// leaves above it. `cost` is as in [`CNQuadtree::shortest_path`]. See [`PortalGraph`].
+    pub fn portal_graph<F>(&self, level: usize, cost: F) -> PortalGraph
+    where
+        F: FnMut(&T) -> Option<f64>,
+    {
+        let mut graph = PortalGraph {
+            level,
+            ..Default::default()
+        };
+        graph.update(self, self.bounds(), cost);
+        graph
+    }
+
+    /// Returns the nodes at `level` and the leaves above it.
+    fn clusters(&self, level: usize) -> Vec<NodeId> {
+        let mut clusters = Vec::new();
+        let mut stack = vec![self.get_root()];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.get_node(index) else {
+                continue;
+            };
+            match node.get_children_index() {
+                Some(children) if node.level() < level => stack.extend(children),
+                _ => clusters.push(index),
+            }
+        }
+        clusters
+    }
+
+    /// Returns the cluster a leaf is in: its ancestor at `level`, or itself if above it.
+    fn cluster_of(&self, leaf: NodeId, level: usize) -> Option<NodeId> {
+        let mut index = leaf;
+        loop {
+            let node = self.get_node(index)?;
+            if node.level() <= level {
+                return Some(index);
+            }
+            index = node.get_parent_index()?;
+        }
+    }
+
+    /// Returns the entrances on a cluster's side in `direction`: the portal in the cluster,
+    /// the portal it steps to, that portal's cluster and the cost of the step.
+    fn entrances<F>(
+        &self,
+        cluster: NodeId,
+        direction: Cardinality,
+        level: usize,
+        leaf_cost: &mut F,
+    ) -> Vec<(NodeId, NodeId, NodeId, f64)>
+    where
+        F: FnMut(NodeId) -> Option<f64>,
+    {
+        let digits = match direction {
+            Cardinality::West => [0, 2],
+            Cardinality::North => [0, 1],
+            Cardinality::East => [1, 3],
+            Cardinality::South => [2, 3],
+        };
+        // Pairs of a leaf on the side and one across it, by where their shared edge starts
+        // and ends along the side.
+        let mut pairs = Vec::new();
+        let mut stack = vec![cluster];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.get_node(index) else {
+                continue;
+            };
+            if let Some(children) = node.get_children_index() {
+                stack.extend(digits.map(|digit| children[digit]));
+                continue;
+            }
+            let Some(bounds) = self.bounds_f64(index) else {
+                continue;
+            };
+            for other in self.neighbors_iter(index, direction) {
+                let Some(other_bounds) = self.bounds_f64(other) else {
+                    continue;
+                };
+                let (start, end) = match direction {
+                    Cardinality::West | Cardinality::East => {
+                        (bounds.1.max(other_bounds.1), bounds.3.min(other_bounds.3))
+                    }
+                    Cardinality::North | Cardinality::South => {
+                        (bounds.0.max(other_bounds.0), bounds.2.min(other_bounds.2))
+                    }
+                };
+                pairs.push((start, end, index, other));
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut entrances = Vec::new();
+        let mut run: Vec<(NodeId, NodeId, NodeId, f64)> = Vec::new();
+        let mut run_end = None;
+        for (start, end, leaf, other) in pairs {
+            let crossing = self.crossing(leaf, other, level, leaf_cost);
+            let continues = run_end == Some(start)
+                && matches!((run.last(), crossing), (Some(last), Some(next)) if last.2 == next.2);
+            if !continues && !run.is_empty() {
+                entrances.push(run[run.len() / 2]);
+                run.clear();
+            }
+            run_end = Some(end);
+            run.extend(crossing);
+        }
+        if !run.is_empty() {
+            entrances.push(run[run.len() / 2]);
+        }
+        entrances
+    }
+
+    /// Returns a step between adjacent leaves in different clusters as in
+    /// [`CNQuadtree::entrances`], or None if either can't be entered.
+    fn crossing<F>(
+        &self,
+        leaf: NodeId,
+        other: NodeId,
+        level: usize,
+        leaf_cost: &mut F,
+    ) -> Option<(NodeId, NodeId, NodeId, f64)>
+    where
+        F: FnMut(NodeId) -> Option<f64>,
+    {
+        let (here, there) = (leaf_cost(leaf)?, leaf_cost(other)?);
+        let (from, to) = (self.center_f64(leaf)?, self.center_f64(other)?);
+        let step = (to.0 - from.0).hypot(to.1 - from.1) * (here + there) / 2.0;
+        Some((leaf, other, self.cluster_of(other, level)?, step))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portal_graph() {
+        // Unit cells in 4 by 4 clusters, with a wall down the middle open at one cell.
+        let mut tree = CNQuadtree::new(1.0, (0, 0, 16, 16));
+        tree.refine_to_levels(|_| 4, |_, &cost| [cost; 4]).unwrap();
+        let grid: Vec<Vec<NodeId>> = (0..16)
+            .map(|y| {
+                (0..16)
+                    .map(|x| tree.point_locate((x, y)).unwrap())
+                    .collect()
+            })
+            .collect();
+        let cell = |x: usize, y: usize| grid[y][x];
+        for row in &grid {
+            *tree.get_node_mut(row[8]).unwrap().get_item_mut() = 10.0;
+        }
+        *tree.get_node_mut(cell(8, 3)).unwrap().get_item_mut() = 1.0;
+        let cost = |&cost: &f64| (cost < 10.0).then_some(cost);
+
+        let mut graph = tree.portal_graph(2, cost);
+        assert_eq!(graph.cluster_count(), 16);
+        let (from, to) = (cell(1, 12), cell(14, 12));
+        let (path, total) = graph.find_path(&tree, from, to, cost).unwrap();
+        assert_eq!((path[0], path[path.len() - 1]), (from, to));
+        assert!(path.contains(&cell(8, 3)));
+        // Every step is between adjacent unit cells and costs 1.
+        assert!(tree.smooth_path(&path, (1.5, 12.5), (14.5, 12.5)).is_some());
+        assert_eq!(total, (path.len() - 1) as f64);
+        let (_, optimal) = tree.shortest_path(from, to, cost).unwrap();
+        assert!(optimal <= total && total <= optimal * 1.2);
+        // Within a cluster.
+        let (path, total) = graph
+            .find_path(&tree, cell(0, 0), cell(3, 0), cost)
+            .unwrap();
+        assert_eq!((path.len(), total), (4, 3.0));
+
+        // Updating around a change matches rebuilding the graph.
+        *tree.get_node_mut(cell(8, 3)).unwrap().get_item_mut() = 10.0;
+        graph.update(&tree, (8, 3, 9, 4), cost);
+        assert_eq!(graph.find_path(&tree, from, to, cost), None);
+        *tree.get_node_mut(cell(8, 13)).unwrap().get_item_mut() = 1.0;
+        graph.update(&tree, (8, 13, 9, 14), cost);
+        let rebuilt = tree.portal_graph(2, cost);
+        assert_eq!(graph.portal_count(), rebuilt.portal_count());
+        let (path, total) = graph.find_path(&tree, from, to, cost).unwrap();
+        assert!(path.contains(&cell(8, 13)));
+        assert_eq!(
+            Some(total),
+            rebuilt.find_path(&tree, from, to, cost).map(|p| p.1)
+        );
+
+        // Merging cells into a larger leaf.
+        let parent = tree
+            .get_node(cell(0, 0))
+            .unwrap()
+            .get_parent_index()
+            .unwrap();
+        tree.pop_children(parent).unwrap();
+        graph.update(&tree, (0, 0, 2, 2), cost);
+        let rebuilt = tree.portal_graph(2, cost);
+        assert_eq!(graph.portal_count(), rebuilt.portal_count());
+        let (path, total) = graph.find_path(&tree, parent, to, cost).unwrap();
+        assert_eq!(path[0], parent);
+        assert_eq!(
+            Some(total),
+            rebuilt.find_path(&tree, parent, to, cost).map(|p| p.1)
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use slotmap::DefaultKey;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Identifies a node of a [`CNQuadtree`](crate::CNQuadtree). Ids carry a tag of the tree that
+/// created them, so an id used with another tree finds no node instead of an unrelated one.
+/// Clones get tags of their own too; [`CNQuadtree::clone_with_ids`](crate::CNQuadtree::clone_with_ids)
+/// maps ids to a clone.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct NodeId {
+    pub(crate) key: DefaultKey,
+    pub(crate) tree: TreeId,
+}
+
+/// Tag distinguishing the ids of different trees.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub(crate) struct TreeId(NonZeroU32);
+
+impl TreeId {
+    /// Returns a tag not used by any live tree, unless over `u32::MAX` trees were created.
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        loop {
+            if let Some(id) = NonZeroU32::new(NEXT.fetch_add(1, Ordering::Relaxed)) {
+                return Self(id);
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "ffi", feature = "wasm"))]
+impl NodeId {
+    /// Returns the id as plain integers for passing across the C and JavaScript APIs.
+    pub(crate) fn to_ffi(self) -> (u64, u32) {
+        (slotmap::Key::data(&self.key).as_ffi(), self.tree.0.get())
+    }
+
+    /// Inverse of [`NodeId::to_ffi`]. Returns None if the tree tag is zero.
+    pub(crate) fn from_ffi(key: u64, tree: u32) -> Option<Self> {
+        Some(Self {
+            key: slotmap::KeyData::from_ffi(key).into(),
+            tree: TreeId(NonZeroU32::new(tree)?),
+        })
+    }
+}
+
+/// What a [`NodeId`] refers to in a tree, as returned by
+/// [`CNQuadtree::index_status`](crate::CNQuadtree::index_status).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum IndexStatus {
+    /// The id finds a node.
+    Live,
+    /// The id was never handed out by the tree, for example because it belongs to another tree.
+    NeverExisted,
+    /// The node was removed and its slot in the store is empty.
+    Removed,
+    /// The node was removed and its slot in the store now holds another node, which the id
+    /// doesn't find.
+    Recycled,
+}
@@ -0,0 +1,218 @@
+//! egui widget for inspecting a [`CNQuadtree`], enabled by the `egui` feature.
+
+use crate::id::NodeId;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use egui::{Color32, Pos2, Rect, Response, Sense, Stroke, Ui, Vec2, Widget};
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// Colors of the cardinal neighbor links in the following order: West, North, East, South.
+const NEIGHBOR_COLORS: [Color32; 4] = [
+    Color32::from_rgb(230, 80, 80),
+    Color32::from_rgb(80, 200, 80),
+    Color32::from_rgb(80, 140, 240),
+    Color32::from_rgb(230, 190, 60),
+];
+
+/// Draws a tree's leaves scaled to the widget. Clicking a leaf selects it; the selected node is
+/// filled and arrows are drawn from it to its cardinal neighbors, colored west red, north green,
+/// east blue and south yellow. Dangling neighbor links are drawn as red crosses at the
+/// selected node's center.
+pub struct TreeInspector<'a, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: &'a CNQuadtree<T, S>,
+    selected: &'a mut Option<NodeId>,
+    size: Vec2,
+}
+
+impl<'a, T, S> TreeInspector<'a, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an inspector of `tree` storing its selection in `selected`.
+    pub fn new(tree: &'a CNQuadtree<T, S>, selected: &'a mut Option<NodeId>) -> Self {
+        Self {
+            tree,
+            selected,
+            size: Vec2::splat(256.0),
+        }
+    }
+
+    /// Sets the size the widget asks for. Defaults to 256 by 256 points.
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+}
+
+impl<T, S> Widget for TreeInspector<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (mut response, painter) = ui.allocate_painter(self.size, Sense::click());
+        let screen = Screen::new(self.tree.bounds(), response.rect);
+
+        // Ids from another tree or of removed nodes select nothing.
+        if self
+            .selected
+            .is_some_and(|id| self.tree.get_node(id).is_none())
+        {
+            *self.selected = None;
+        }
+        if response.clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                *self.selected = screen.to_tree(pos).and_then(|p| self.tree.point_locate(p));
+                response.mark_changed();
+            }
+        }
+        let hovered = response
+            .hover_pos()
+            .and_then(|pos| screen.to_tree(pos))
+            .and_then(|p| self.tree.point_locate(p));
+
+        let visuals = ui.visuals();
+        let stroke = Stroke::new(1.0, visuals.weak_text_color());
+        for leaf in self.tree.leaves_morton() {
+            let Some(node) = self.tree.get_node(leaf) else {
+                continue;
+            };
+            let rect = screen.to_screen(node.get_bounds());
+            if Some(leaf) == hovered {
+                painter.rect_filled(rect, 0.0, visuals.widgets.hovered.bg_fill);
+            }
+            painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Inside);
+        }
+
+        let Some(node) = self.selected.and_then(|id| self.tree.get_node(id)) else {
+            return response;
+        };
+        let rect = screen.to_screen(node.get_bounds());
+        painter.rect_filled(rect, 0.0, visuals.selection.bg_fill);
+        for (neighbor, color) in node
+            .get_cardinal_neighbors_index()
+            .into_iter()
+            .zip(NEIGHBOR_COLORS)
+        {
+            let Some(neighbor) = neighbor else {
+                continue;
+            };
+            match self.tree.get_node(neighbor) {
+                Some(neighbor) => {
+                    let target = screen.to_screen(neighbor.get_bounds()).center();
+                    painter.arrow(
+                        rect.center(),
+                        target - rect.center(),
+                        Stroke::new(2.0, color),
+                    );
+                }
+                None => {
+                    let r = 4.0;
+                    let red = Stroke::new(2.0, Color32::RED);
+                    let c = rect.center();
+                    painter.line_segment([c - Vec2::splat(r), c + Vec2::splat(r)], red);
+                    painter.line_segment([c + Vec2::new(r, -r), c + Vec2::new(-r, r)], red);
+                }
+            }
+        }
+        let Bounds(left, top, right, bottom) = node.get_bounds();
+        response.on_hover_text(format!(
+            "level {}\n({:?}, {:?}) - ({:?}, {:?})",
+            node.level(),
+            left.to_f64(),
+            top.to_f64(),
+            right.to_f64(),
+            bottom.to_f64()
+        ))
+    }
+}
+
+/// Maps between tree units and screen positions.
+struct Screen<S> {
+    bounds: Bounds<S>,
+    rect: Rect,
+}
+
+impl<S> Screen<S>
+where
+    S: Copy + ToPrimitive + FromPrimitive,
+{
+    fn new(bounds: Bounds<S>, rect: Rect) -> Self {
+        Self { bounds, rect }
+    }
+
+    fn extent(&self) -> Option<(f64, f64, f64, f64)> {
+        let Bounds(left, top, right, bottom) = self.bounds;
+        Some((
+            left.to_f64()?,
+            top.to_f64()?,
+            right.to_f64()?,
+            bottom.to_f64()?,
+        ))
+    }
+
+    fn to_screen(&self, bounds: Bounds<S>) -> Rect {
+        let Some((left, top, right, bottom)) = self.extent() else {
+            return Rect::NOTHING;
+        };
+        let map = |x: S, y: S| {
+            let x = (x.to_f64().unwrap_or(left) - left) / (right - left);
+            let y = (y.to_f64().unwrap_or(top) - top) / (bottom - top);
+            self.rect.min + Vec2::new(x as f32, y as f32) * self.rect.size()
+        };
+        Rect::from_min_max(map(bounds.0, bounds.1), map(bounds.2, bounds.3))
+    }
+
+    fn to_tree(&self, pos: Pos2) -> Option<(S, S)> {
+        let (left, top, right, bottom) = self.extent()?;
+        let offset = (pos - self.rect.min) / self.rect.size();
+        Some((
+            S::from_f64(left + offset.x as f64 * (right - left))?,
+            S::from_f64(top + offset.y as f64 * (bottom - top))?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_mapping() {
+        let rect = Rect::from_min_size(Pos2::new(10.0, 10.0), Vec2::splat(100.0));
+        let screen = Screen::new(Bounds(0u32, 0, 64, 64), rect);
+        assert_eq!(
+            screen.to_screen(Bounds(32, 0, 64, 32)),
+            Rect::from_min_max(Pos2::new(60.0, 10.0), Pos2::new(110.0, 60.0))
+        );
+        assert_eq!(screen.to_tree(Pos2::new(60.0, 35.0)), Some((32, 16)));
+        assert_eq!(screen.to_tree(Pos2::new(0.0, 35.0)), None);
+    }
+
+    #[test]
+    fn draws_selection() {
+        let mut tree = CNQuadtree::new((), (0u32, 0, 64, 64));
+        let children = tree.subdivide(tree.get_root(), [(); 4]).unwrap();
+        let mut selected = Some(children[0]);
+        let ctx = egui::Context::default();
+        let output = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.add(TreeInspector::new(&tree, &mut selected));
+            });
+        });
+        assert_eq!(selected, Some(children[0]));
+        // Four leaf outlines, the selection fill and two neighbor arrows, on top of the panel.
+        assert!(output.shapes.len() >= 7);
+
+        let other = CNQuadtree::new((), (0u32, 0, 64, 64));
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.add(TreeInspector::new(&other, &mut selected));
+            });
+        });
+        assert_eq!(selected, None);
+    }
+}
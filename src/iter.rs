@@ -0,0 +1,205 @@
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::tree::{Containment, RegionQuadtree, Unit};
+
+/// A lazy iterator over the leaves overlapping a region, in depth-first NW, NE, SW, SE order.
+/// Subtrees lying entirely within the region are not tested further.
+/// Constructed by [`RegionQuadtree::region_iter`].
+pub struct RegionIter<'a, T, Q, const N: usize = 4>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    tree: &'a Q,
+    region: Bounds<Unit<T, Q, N>>,
+    /// Nodes left to visit, paired with whether an ancestor already lies inside the region.
+    stack: Vec<(Q::Index, bool)>,
+}
+
+impl<'a, T, Q, const N: usize> RegionIter<'a, T, Q, N>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    pub(crate) fn new(tree: &'a Q, region: Bounds<Unit<T, Q, N>>) -> Self {
+        let root = tree.get_root();
+        let stack = match tree.get_node(root.clone()) {
+            Some(node) if node.intersects(region) => vec![(root, false)],
+            _ => Vec::new(),
+        };
+
+        Self {
+            tree,
+            region,
+            stack,
+        }
+    }
+}
+
+impl<'a, T, Q, const N: usize> Iterator for RegionIter<'a, T, Q, N>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    type Item = (Q::Index, Containment);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((index, inside)) = self.stack.pop() {
+            let node = match self.tree.get_node(index.clone()) {
+                None => continue,
+                Some(n) => n,
+            };
+            let inside = inside || node.inside(self.region);
+
+            match node.get_children_index() {
+                None if inside => return Some((index, Containment::FullyInside)),
+                None => return Some((index, Containment::PartiallyOverlapping)),
+                Some(children) => {
+                    for child in children.into_iter().rev() {
+                        let overlaps = inside
+                            || self
+                                .tree
+                                .get_node(child.clone())
+                                .is_some_and(|c| c.intersects(self.region));
+                        if overlaps {
+                            self.stack.push((child, inside));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// A space-filling curve along which leaves are visited.
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+enum Curve {
+    Morton,
+    Hilbert,
+}
+
+/// Maps a quadrant of the canonical Hilbert curve to the actual quadrant by swapping the x and y
+/// axes and then flipping both.
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug, Default)]
+struct Orientation {
+    swap: bool,
+    flip: bool,
+}
+
+impl Orientation {
+    /// Quadrants, as (x, y), in the order the canonical Hilbert curve visits them, each with the
+    /// orientation of the curve within it.
+    const HILBERT: [((usize, usize), Orientation); 4] = [
+        (
+            (0, 0),
+            Orientation {
+                swap: true,
+                flip: false,
+            },
+        ),
+        (
+            (0, 1),
+            Orientation {
+                swap: false,
+                flip: false,
+            },
+        ),
+        (
+            (1, 1),
+            Orientation {
+                swap: false,
+                flip: false,
+            },
+        ),
+        (
+            (1, 0),
+            Orientation {
+                swap: true,
+                flip: true,
+            },
+        ),
+    ];
+
+    fn apply(self, (x, y): (usize, usize)) -> (usize, usize) {
+        let (x, y) = if self.swap { (y, x) } else { (x, y) };
+        if self.flip {
+            (1 - x, 1 - y)
+        } else {
+            (x, y)
+        }
+    }
+
+    /// Returns the orientation that applies `inner` and then `self`.
+    fn compose(self, inner: Orientation) -> Orientation {
+        Orientation {
+            swap: self.swap ^ inner.swap,
+            flip: self.flip ^ inner.flip,
+        }
+    }
+}
+
+/// An iterator over a tree's leaves along a space-filling curve. Constructed by
+/// [`RegionQuadtree::leaves_morton`] and [`RegionQuadtree::leaves_hilbert`].
+pub struct LeafIter<'a, T, Q, const N: usize = 4>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    tree: &'a Q,
+    curve: Curve,
+    /// Nodes left to visit, paired with the orientation of the curve within them.
+    stack: Vec<(Q::Index, Orientation)>,
+}
+
+impl<'a, T, Q, const N: usize> LeafIter<'a, T, Q, N>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    pub(crate) fn morton(tree: &'a Q) -> Self {
+        Self::new(tree, Curve::Morton)
+    }
+
+    pub(crate) fn hilbert(tree: &'a Q) -> Self {
+        Self::new(tree, Curve::Hilbert)
+    }
+
+    fn new(tree: &'a Q, curve: Curve) -> Self {
+        Self {
+            tree,
+            curve,
+            stack: vec![(tree.get_root(), Orientation::default())],
+        }
+    }
+}
+
+impl<'a, T, Q, const N: usize> Iterator for LeafIter<'a, T, Q, N>
+where
+    Q: RegionQuadtree<T, N>,
+{
+    type Item = Q::Index;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((index, orientation)) = self.stack.pop() {
+            let children = match self.tree.get_node(index.clone()) {
+                None => continue,
+                Some(node) => match node.get_children_index() {
+                    None => return Some(index),
+                    Some(c) => c,
+                },
+            };
+
+            match self.curve {
+                Curve::Hilbert if N == 4 => {
+                    for (quadrant, inner) in Orientation::HILBERT.into_iter().rev() {
+                        let (x, y) = orientation.apply(quadrant);
+                        let child = children[x | (y << 1)].clone();
+                        self.stack.push((child, orientation.compose(inner)));
+                    }
+                }
+                // Other branching factors have no quadrants to orient, so they fall back to Morton.
+                Curve::Morton | Curve::Hilbert => self
+                    .stack
+                    .extend(children.into_iter().rev().map(|c| (c, orientation))),
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,281 @@
+use crate::error::{Error, PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::location::Location;
+use crate::node::RegionQuadtreeNode;
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A history of edits to a tree supporting undo and redo.
+///
+/// Edits made through [`Journal::subdivide`], [`Journal::pop_children`] and
+/// [`Journal::set_item`] are recorded; edits made directly on the tree aren't, and undoing
+/// past them may fail or undo the wrong thing. Undoing an edit applies its inverse, whose
+/// neighbor fix-ups restore the cardinal neighbors exactly. Nodes are recorded by their path
+/// from the root, so edits still apply after the nodes they touched were removed and
+/// recreated, but recreated nodes get new ids.
+#[derive(Clone, Debug)]
+pub struct Journal<T> {
+    /// Inverses of the applied edits, most recent last.
+    undo: Vec<Edit<T>>,
+    /// Undone edits, most recently undone last.
+    redo: Vec<Edit<T>>,
+}
+
+/// An edit of the node at a path.
+#[derive(Clone, Debug)]
+enum Edit<T> {
+    Subdivide(Vec<Location>, [T; 4]),
+    PopChildren(Vec<Location>),
+    SetItem(Vec<Location>, T),
+}
+
+impl<T> Default for Journal<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl<T> Journal<T> {
+    /// Returns an empty journal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns true if there's an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns true if there's an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets every edit.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Subdivides a leaf of `tree` and records it. Clears the edits to redo.
+    pub fn subdivide<S>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        index: NodeId,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let Some(path) = path(tree, index) else {
+            return Err(SubdivideError {
+                items,
+                source: Error::InvalidIndex,
+            });
+        };
+        let children = tree.subdivide(index, items)?;
+        self.record(Edit::PopChildren(path));
+        Ok(children)
+    }
+
+    /// Pops the children of a node of `tree` and records it. Clears the edits to redo.
+    pub fn pop_children<S>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        index: NodeId,
+    ) -> Result<[T; 4], PopChildrenError>
+    where
+        T: Clone,
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let path = path(tree, index).ok_or(PopChildrenError::InvalidIndex)?;
+        let items = tree.pop_children(index)?;
+        self.record(Edit::Subdivide(path, items.clone()));
+        Ok(items)
+    }
+
+    /// Replaces the item of a node of `tree`, records it, and returns the old item. Clears the
+    /// edits to redo. Returns None if the node doesn't exist.
+    pub fn set_item<S>(&mut self, tree: &mut CNQuadtree<T, S>, index: NodeId, item: T) -> Option<T>
+    where
+        T: Clone,
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let path = path(tree, index)?;
+        let old = std::mem::replace(tree.get_node_mut(index)?.get_item_mut(), item);
+        self.record(Edit::SetItem(path, old.clone()));
+        Some(old)
+    }
+
+    /// Undoes the most recent edit. Returns false if there's none. Fails if the tree was
+    /// edited outside the journal so the edit no longer applies, keeping it to undo.
+    pub fn undo<S>(&mut self, tree: &mut CNQuadtree<T, S>) -> Result<bool, Error>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        step(tree, &mut self.undo, &mut self.redo)
+    }
+
+    /// Redoes the most recently undone edit. Returns false if there's none. Fails if the tree
+    /// was edited outside the journal so the edit no longer applies, keeping it to redo.
+    pub fn redo<S>(&mut self, tree: &mut CNQuadtree<T, S>) -> Result<bool, Error>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        step(tree, &mut self.redo, &mut self.undo)
+    }
+
+    fn record(&mut self, inverse: Edit<T>) {
+        self.undo.push(inverse);
+        self.redo.clear();
+    }
+}
+
+/// Applies the last edit of `from` and pushes its inverse onto `to`.
+fn step<T, S>(
+    tree: &mut CNQuadtree<T, S>,
+    from: &mut Vec<Edit<T>>,
+    to: &mut Vec<Edit<T>>,
+) -> Result<bool, Error>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    let Some(edit) = from.pop() else {
+        return Ok(false);
+    };
+    match apply(tree, edit) {
+        Ok(inverse) => {
+            to.push(inverse);
+            Ok(true)
+        }
+        Err((edit, error)) => {
+            from.push(edit);
+            Err(error)
+        }
+    }
+}
+
+/// Applies an edit and returns its inverse, or gives it back with the error.
+fn apply<T, S>(tree: &mut CNQuadtree<T, S>, edit: Edit<T>) -> Result<Edit<T>, (Edit<T>, Error)>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    let path = match &edit {
+        Edit::Subdivide(path, _) | Edit::PopChildren(path) | Edit::SetItem(path, _) => path,
+    };
+    let Some(index) = resolve(tree, path) else {
+        return Err((edit, Error::InvalidIndex));
+    };
+    match edit {
+        Edit::Subdivide(path, items) => match tree.subdivide(index, items) {
+            Ok(_) => Ok(Edit::PopChildren(path)),
+            Err(e) => Err((Edit::Subdivide(path, e.items), e.source)),
+        },
+        Edit::PopChildren(path) => match tree.pop_children(index) {
+            Ok(items) => Ok(Edit::Subdivide(path, items)),
+            Err(e) => Err((Edit::PopChildren(path), e.into())),
+        },
+        Edit::SetItem(path, item) => match tree.get_node_mut(index) {
+            Some(node) => Ok(Edit::SetItem(
+                path,
+                std::mem::replace(node.get_item_mut(), item),
+            )),
+            None => Err((Edit::SetItem(path, item), Error::InvalidIndex)),
+        },
+    }
+}
+
+/// Returns the locations leading from the root to the node.
+fn path<T, S>(tree: &CNQuadtree<T, S>, mut index: NodeId) -> Option<Vec<Location>>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    let mut path = Vec::with_capacity(tree.get_node(index)?.level());
+    while let Some(parent) = tree.get_node(index)?.get_parent_index() {
+        path.push(tree.location_among_siblings(index)?);
+        index = parent;
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Returns the node at the end of a path from the root.
+fn resolve<T, S>(tree: &CNQuadtree<T, S>, path: &[Location]) -> Option<NodeId>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    path.iter().try_fold(tree.get_root(), |index, &location| {
+        Some(tree.get_node(index)?.get_children_index()?[location as usize])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the tree's leaves as bounds, items and cardinal neighbors' bounds.
+    fn snapshot(tree: &CNQuadtree<i32, u32>) -> Vec<String> {
+        tree.leaves_morton()
+            .map(|leaf| {
+                let node = tree.get_node(leaf).unwrap();
+                let neighbors = node
+                    .get_cardinal_neighbors_index()
+                    .map(|n| n.map(|n| tree.get_node(n).unwrap().get_bounds()));
+                format!(
+                    "{:?} {} {:?}",
+                    node.get_bounds(),
+                    node.get_item(),
+                    neighbors
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        let mut journal = Journal::new();
+        assert!(!journal.can_undo());
+        assert_eq!(journal.undo(&mut tree), Ok(false));
+
+        let mut snapshots = vec![snapshot(&tree)];
+        let root = tree.get_root();
+        let [nw, ne, ..] = journal.subdivide(&mut tree, root, [1, 2, 3, 4]).unwrap();
+        snapshots.push(snapshot(&tree));
+        let [_, _, _, ne_se] = journal.subdivide(&mut tree, ne, [5, 6, 7, 8]).unwrap();
+        snapshots.push(snapshot(&tree));
+        journal.subdivide(&mut tree, nw, [9, 10, 11, 12]).unwrap();
+        snapshots.push(snapshot(&tree));
+        assert_eq!(journal.set_item(&mut tree, ne_se, 13), Some(8));
+        snapshots.push(snapshot(&tree));
+        journal.pop_children(&mut tree, nw).unwrap();
+        snapshots.push(snapshot(&tree));
+
+        for expected in snapshots.iter().rev().skip(1) {
+            assert_eq!(journal.undo(&mut tree), Ok(true));
+            assert_eq!(&snapshot(&tree), expected);
+        }
+        assert_eq!(journal.undo(&mut tree), Ok(false));
+        for expected in snapshots.iter().skip(1) {
+            assert_eq!(journal.redo(&mut tree), Ok(true));
+            assert_eq!(&snapshot(&tree), expected);
+        }
+        assert!(!journal.can_redo());
+
+        // A new edit drops the undone ones.
+        journal.undo(&mut tree).unwrap();
+        let leaf = tree.point_locate((1, 1)).unwrap();
+        journal.set_item(&mut tree, leaf, 14).unwrap();
+        assert!(!journal.can_redo());
+
+        // An edit that no longer applies is kept.
+        journal.undo(&mut tree).unwrap();
+        tree.collapse(root).unwrap();
+        assert_eq!(journal.undo(&mut tree), Err(Error::InvalidIndex));
+        assert!(journal.can_undo());
+    }
+}
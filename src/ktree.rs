@@ -0,0 +1,478 @@
+use crate::error::{Error, PopChildrenError, SubdivideError};
+use crate::id::{NodeId, TreeId};
+use crate::location::{Cardinality, Location};
+use crate::node::{midpoint, Bounds, CNNode, Point, RegionQuadtreeNode};
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use slotmap::{DefaultKey, SlotMap};
+
+/// A tree whose internal nodes have `N` children, linked by the same cardinal neighbors as
+/// [`CNQuadtree`](crate::CNQuadtree), so the [`RegionQuadtree`] methods such as
+/// [`RegionQuadtree::get_neighbors`] and [`RegionQuadtree::relocate`] work unchanged. `N` is one
+/// of:
+///
+/// - 2, a bintree whose nodes are split into west and east halves at even levels and north and
+///   south halves at odd levels.
+/// - 4, a quadtree, with children in NW, NE, SW, SE order.
+/// - 16, a quadtree with two levels fused into each node. Children are the grandchildren of a
+///   quadtree node in Morton order, so a shallow tree takes half as many hops to descend.
+///
+/// Nodes are split at midpoints, rounding like [`SplitPolicy::Floor`](crate::SplitPolicy::Floor).
+pub struct CNKTree<T, S, const N: usize>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    store: SlotMap<DefaultKey, CNNode<T, NodeId, S, N>>,
+    root_key: NodeId,
+    /// Tag of the ids of this tree's nodes.
+    id: TreeId,
+}
+
+/// A binary space partition alternating between vertical and horizontal splits.
+pub type CNBintree<T, S = u32> = CNKTree<T, S, 2>;
+
+/// A quadtree whose nodes each hold two quadtree levels.
+pub type CNFusedQuadtree<T, S = u32> = CNKTree<T, S, 16>;
+
+impl<T, S, const N: usize> CNKTree<T, S, N>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Rejects branching factors other than 2, 4 and 16 at compile time.
+    const BRANCHING: () = assert!(N == 2 || N == 4 || N == 16, "N must be 2, 4 or 16");
+
+    /// Returns a tree with a single root node holding `item` and covering `bounds`, given as
+    /// `(min_x, min_y, max_x, max_y)`. `N` must be 2, 4 or 16, which is checked at compile time.
+    /// The bounds aren't checked, but each minimum should be below its maximum, as nodes whose
+    /// halves would be empty fail to subdivide with [`Error::CellTooSmall`].
+    pub fn new(item: T, bounds: impl Into<Bounds<S>>) -> Self {
+        let bounds = bounds.into();
+        #[allow(clippy::let_unit_value)]
+        let () = Self::BRANCHING;
+        let mut store = SlotMap::new();
+        let id = TreeId::next();
+        let root_key = NodeId {
+            key: store.insert(CNNode::new(item, 0, bounds, None)),
+            tree: id,
+        };
+        Self {
+            store,
+            root_key,
+            id,
+        }
+    }
+
+    /// Returns the root's bounds.
+    #[inline]
+    pub fn bounds(&self) -> Bounds<S> {
+        self.store[self.root_key.key].get_bounds()
+    }
+
+    /// Returns the number of nodes in the tree.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns false, as a tree always has a root.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the column and row of the `i`th child of a node at `level`, and the number of
+    /// columns and rows its children form.
+    fn cell(i: usize, level: usize) -> ((usize, usize), (usize, usize)) {
+        match N {
+            2 if level.is_multiple_of(2) => ((i, 0), (2, 1)),
+            2 => ((0, i), (1, 2)),
+            4 => ((i & 1, i >> 1), (2, 2)),
+            _ => (
+                ((i & 1) | (i >> 1 & 2), (i >> 1 & 1) | (i >> 2 & 2)),
+                (4, 4),
+            ),
+        }
+    }
+
+    /// Returns the edges splitting `[min, max)` into `parts` of 1, 2 or 4 parts, or fails if a
+    /// part would be empty.
+    fn edges(min: S, max: S, parts: usize) -> Result<Vec<S>, Error> {
+        let half = |min: S, max: S| {
+            let middle = midpoint(min, max).ok_or(Error::UnitConversion)?;
+            if min < middle && middle < max {
+                Ok(middle)
+            } else {
+                Err(Error::CellTooSmall)
+            }
+        };
+        Ok(match parts {
+            1 => vec![min, max],
+            2 => vec![min, half(min, max)?, max],
+            _ => {
+                let middle = half(min, max)?;
+                vec![min, half(min, middle)?, middle, half(middle, max)?, max]
+            }
+        })
+    }
+
+    fn insert(&mut self, node: CNNode<T, NodeId, S, N>) -> NodeId {
+        NodeId {
+            key: self.store.insert(node),
+            tree: self.id,
+        }
+    }
+
+    /// Returns the first node in `chain` whose bounds satisfy `f`.
+    fn find_in_chain<F>(&self, chain: &[NodeId], f: F) -> Option<NodeId>
+    where
+        F: Fn(Bounds<S>) -> bool,
+    {
+        chain
+            .iter()
+            .copied()
+            .find(|&n| self.get_node(n).is_some_and(|n| f(n.get_bounds())))
+    }
+}
+
+impl<T, S, const N: usize> RegionQuadtree<T, N> for CNKTree<T, S, N>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Index = NodeId;
+    type Node = CNNode<T, NodeId, S, N>;
+
+    fn get_node(&self, index: Self::Index) -> Option<&Self::Node> {
+        if index.tree != self.id {
+            return None;
+        }
+        self.store.get(index.key)
+    }
+
+    fn get_node_mut(&mut self, index: Self::Index) -> Option<&mut Self::Node> {
+        if index.tree != self.id {
+            return None;
+        }
+        self.store.get_mut(index.key)
+    }
+
+    fn get_root(&self) -> Self::Index {
+        self.root_key
+    }
+
+    fn subdivide(
+        &mut self,
+        index: Self::Index,
+        items: [T; N],
+    ) -> Result<[Self::Index; N], SubdivideError<T, N>> {
+        let Some(node) = self.get_node(index) else {
+            return Err(SubdivideError {
+                items,
+                source: Error::InvalidIndex,
+            });
+        };
+        if node.has_children() {
+            return Err(SubdivideError {
+                items,
+                source: Error::AlreadySubdivided,
+            });
+        }
+        let level = node.level();
+        let bounds @ Bounds(left, top, right, bottom) = node.get_bounds();
+        let (_, (columns, rows)) = Self::cell(0, level);
+        let (xs, ys) = match Self::edges(left, right, columns)
+            .and_then(|xs| Ok((xs, Self::edges(top, bottom, rows)?)))
+        {
+            Ok(edges) => edges,
+            Err(source) => return Err(SubdivideError { items, source }),
+        };
+        let chains = Cardinality::ALL
+            .map(|direction| self.get_neighbors(index, direction).unwrap_or_default());
+        let [w_chain, n_chain, e_chain, s_chain] = &chains;
+
+        let child_bounds = |i: usize| {
+            let ((x, y), _) = Self::cell(i, level);
+            Bounds(xs[x], ys[y], xs[x + 1], ys[y + 1])
+        };
+        let mut items = items.into_iter();
+        let children: [NodeId; N] = std::array::from_fn(|i| {
+            let item = items.next().expect("there should be an item per child");
+            self.insert(CNNode::new(item, level + 1, child_bounds(i), Some(index)))
+        });
+        let sibling = |x: usize, y: usize| {
+            (0..N)
+                .find(|&j| Self::cell(j, level).0 == (x, y))
+                .map(|j| children[j])
+        };
+
+        // Children on the node's sides take their cardinal neighbors from its chains, the
+        // others from their siblings.
+        for (i, &child) in children.iter().enumerate() {
+            let ((x, y), _) = Self::cell(i, level);
+            let Bounds(l, t, r, b) = child_bounds(i);
+            let neighbors = [
+                match x {
+                    0 => self.find_in_chain(w_chain, |Bounds(_, nt, _, nb)| nt <= t && t < nb),
+                    _ => sibling(x - 1, y),
+                },
+                match y {
+                    0 => self.find_in_chain(n_chain, |Bounds(nl, _, nr, _)| nl <= l && l < nr),
+                    _ => sibling(x, y - 1),
+                },
+                if x + 1 == columns {
+                    self.find_in_chain(e_chain, |Bounds(_, nt, _, nb)| nt < b && b <= nb)
+                } else {
+                    sibling(x + 1, y)
+                },
+                if y + 1 == rows {
+                    self.find_in_chain(s_chain, |Bounds(nl, _, nr, _)| nl < r && r <= nr)
+                } else {
+                    sibling(x, y + 1)
+                },
+            ];
+            self.store[child.key].update_neighbors(neighbors);
+        }
+
+        // A neighbor's cardinal neighbor becomes the child beside the corner it starts at.
+        let child_where = |f: &dyn Fn(Bounds<S>) -> bool| {
+            (0..N).find(|&i| f(child_bounds(i))).map(|i| children[i])
+        };
+        for (direction, chain) in chains.iter().enumerate() {
+            let direction = Cardinality::try_from(direction).expect("direction should be valid");
+            let opposite = direction.opposite();
+            for &neighbor in chain {
+                let n = &self.store[neighbor.key];
+                if n.get_cardinal_neighbor_index(opposite) != Some(index) {
+                    continue;
+                }
+                let Bounds(nl, nt, nr, nb) = n.get_bounds();
+                let new_neighbor = match direction {
+                    Cardinality::West => {
+                        child_where(&|Bounds(l, t, _, b)| l == bounds.0 && t < nb && nb <= b)
+                    }
+                    Cardinality::North => {
+                        child_where(&|Bounds(l, t, r, _)| t == bounds.1 && l < nr && nr <= r)
+                    }
+                    Cardinality::East => {
+                        child_where(&|Bounds(_, t, r, b)| r == bounds.2 && t <= nt && nt < b)
+                    }
+                    Cardinality::South => {
+                        child_where(&|Bounds(l, _, r, b)| b == bounds.3 && l <= nl && nl < r)
+                    }
+                };
+                self.store[neighbor.key].update_neighbor(new_neighbor, opposite);
+            }
+        }
+
+        let parent = &mut self.store[index.key];
+        parent.update_neighbors([None; 4]);
+        parent.update_children(Some(children));
+        Ok(children)
+    }
+
+    fn pop_children(&mut self, index: Self::Index) -> Result<[T; N], PopChildrenError> {
+        let node = self.get_node(index).ok_or(PopChildrenError::InvalidIndex)?;
+        let children = node
+            .get_children_index()
+            .ok_or(PopChildrenError::NotSubdivided)?;
+        for child in children {
+            if self
+                .get_node(child)
+                .ok_or(Error::DanglingIndex)?
+                .has_children()
+            {
+                return Err(PopChildrenError::ChildrenSubdivided);
+            }
+        }
+
+        // The first child is at the top left corner and the last at the bottom right.
+        let (first, last) = (
+            &self.store[children[0].key],
+            &self.store[children[N - 1].key],
+        );
+        let parent_neighbors = [
+            first.get_cardinal_neighbor_index(Cardinality::West),
+            first.get_cardinal_neighbor_index(Cardinality::North),
+            last.get_cardinal_neighbor_index(Cardinality::East),
+            last.get_cardinal_neighbor_index(Cardinality::South),
+        ];
+
+        // Update neighbor nodes that point to child nodes to point to the parent.
+        let mut redirects = Vec::new();
+        for child in children {
+            for direction in [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ] {
+                for neighbor in self.get_neighbors(child, direction).unwrap_or_default() {
+                    let opposite = direction.opposite();
+                    let points_in = self.store[neighbor.key]
+                        .get_cardinal_neighbor_index(opposite)
+                        .is_some_and(|n| children.contains(&n));
+                    if points_in && !children.contains(&neighbor) {
+                        redirects.push((neighbor, opposite));
+                    }
+                }
+            }
+        }
+        for (neighbor, direction) in redirects {
+            self.store[neighbor.key].update_neighbor(Some(index), direction);
+        }
+
+        let parent = &mut self.store[index.key];
+        parent.update_neighbors(parent_neighbors);
+        parent.update_children(None);
+        Ok(children.map(|c| self.store.remove(c.key).unwrap().pop()))
+    }
+
+    fn point_locate(&self, point: Point<S>) -> Option<Self::Index> {
+        self.point_locate_path(point)?
+            .last()
+            .map(|&(index, _)| index)
+    }
+
+    /// Locations are positions among the children, so they're only meaningful when `N` is 4.
+    fn point_locate_path(&self, point: Point<S>) -> Option<Vec<(Self::Index, Option<Location>)>> {
+        let mut index = self.root_key;
+        let mut node = self.get_node(index)?;
+        if !node.point_in(point) {
+            return None;
+        }
+        let mut path = vec![(index, None)];
+        while let Some(children) = node.get_children_index() {
+            let position = children
+                .iter()
+                .position(|&c| self.get_node(c).is_some_and(|c| c.point_in(point)))?;
+            index = children[position];
+            node = self.get_node(index)?;
+            path.push((index, position.try_into().ok()));
+        }
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CNQuadtree;
+
+    /// Checks every leaf's cardinal neighbors and chains against point location.
+    fn assert_neighbors_consistent<T, const N: usize>(tree: &CNKTree<T, i32, N>) {
+        for leaf in tree.leaves_morton() {
+            let Bounds(l, t, r, b) = tree.get_node(leaf).unwrap().get_bounds();
+            let sides: [Vec<Point<i32>>; 4] = [
+                (t..b).map(|y| (l - 1, y)).collect(),
+                (l..r).map(|x| (x, t - 1)).collect(),
+                (t..b).rev().map(|y| (r, y)).collect(),
+                (l..r).rev().map(|x| (x, b)).collect(),
+            ];
+            for (direction, side) in sides.into_iter().enumerate() {
+                let mut expected: Vec<_> = side
+                    .into_iter()
+                    .filter_map(|p| tree.point_locate(p))
+                    .collect();
+                expected.dedup();
+                let direction = direction.try_into().unwrap();
+                assert_eq!(
+                    tree.get_node(leaf)
+                        .unwrap()
+                        .get_cardinal_neighbor_index(direction),
+                    expected.first().copied()
+                );
+                let neighbors = tree
+                    .get_neighbors(leaf, direction)
+                    .unwrap_or_default()
+                    .into_vec();
+                assert_eq!(neighbors, expected);
+            }
+        }
+    }
+
+    /// Subdivides and pops random leaves, checking the neighbors as it goes.
+    fn shuffle<const N: usize>(seed: u64) {
+        let mut seed = seed;
+        let mut next = |bound: i32| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % bound as u64) as i32
+        };
+        let mut tree = CNKTree::<_, i32, N>::new(0, (0, 0, 64, 64));
+        for round in 0..150 {
+            let leaf = tree.point_locate((next(64), next(64))).unwrap();
+            if round % 3 == 2 {
+                let parent = tree.get_node(leaf).unwrap().get_parent_index();
+                if let Some(parent) = parent {
+                    let _ = tree.pop_children(parent);
+                }
+            } else {
+                let _ = tree.subdivide(leaf, [0; N]);
+            }
+        }
+        assert!(tree.len() > 1 + N);
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn bintree() {
+        let mut tree = CNBintree::new(0, (0, 0, 8, 8));
+        let [west, east] = tree.subdivide(tree.get_root(), [1, 2]).unwrap();
+        assert_eq!(tree.get_node(west).unwrap().get_bounds(), (0, 0, 4, 8));
+        let [north, south] = tree.subdivide(east, [3, 4]).unwrap();
+        assert_eq!(tree.get_node(south).unwrap().get_bounds(), (4, 4, 8, 8));
+        assert_eq!(
+            tree.get_neighbors(west, Cardinality::East)
+                .unwrap()
+                .into_vec(),
+            vec![south, north]
+        );
+        assert_eq!(tree.point_locate((5, 6)), Some(south));
+        assert_eq!(tree.pop_children(east), Ok([3, 4]));
+        assert_eq!(
+            tree.get_neighbors(west, Cardinality::East)
+                .unwrap()
+                .into_vec(),
+            vec![east]
+        );
+        shuffle::<2>(3);
+    }
+
+    #[test]
+    fn fused_quadtree() {
+        let mut fused = CNFusedQuadtree::new(0, (0, 0, 16, 16));
+        let children = fused
+            .subdivide(fused.get_root(), std::array::from_fn(|i| i))
+            .unwrap();
+        let mut quadtree = CNQuadtree::new(0, (0, 0, 16, 16));
+        for child in quadtree.subdivide(quadtree.get_root(), [0; 4]).unwrap() {
+            quadtree.subdivide(child, [0; 4]).unwrap();
+        }
+        // Children follow the Morton order of the grandchildren they stand for.
+        let fused_bounds: Vec<_> = fused
+            .leaves_morton()
+            .map(|n| fused.get_node(n).unwrap().get_bounds())
+            .collect();
+        let quadtree_bounds: Vec<_> = quadtree
+            .leaves_morton()
+            .map(|n| quadtree.get_node(n).unwrap().get_bounds())
+            .collect();
+        assert_eq!(fused_bounds, quadtree_bounds);
+        assert_eq!(fused.get_node(children[5]).unwrap().level(), 1);
+        let grandchildren = fused.subdivide(children[0], [0; 16]).unwrap();
+        assert_eq!(
+            fused.get_node(grandchildren[15]).unwrap().get_bounds(),
+            (3, 3, 4, 4)
+        );
+        assert_eq!(
+            fused
+                .subdivide(grandchildren[0], [0; 16])
+                .unwrap_err()
+                .source,
+            Error::CellTooSmall
+        );
+        assert_eq!(fused.len(), 33);
+        shuffle::<16>(5);
+        shuffle::<4>(7);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::error::{PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use slotmap::{DefaultKey, SparseSecondaryMap};
+
+/// A tree holding items at its leaves only. Internal nodes take no space for an item, unlike a
+/// [`CNQuadtree`], where subdividing a leaf leaves its item on the new parent.
+///
+/// The structure is a `CNQuadtree<(), S>`, available through [`LeafOnlyItems::tree`] for
+/// neighbor queries and traversals. Items live in a separate map holding leaves only.
+#[derive(Clone, Debug)]
+pub struct LeafOnlyItems<T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: CNQuadtree<(), S>,
+    items: SparseSecondaryMap<DefaultKey, T>,
+}
+
+impl<T, S> LeafOnlyItems<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a tree of a single leaf holding `item`.
+    pub fn new(item: T, bounds: impl Into<Bounds<S>>) -> Self {
+        let bounds = bounds.into();
+        let tree = CNQuadtree::new((), bounds);
+        let mut items = SparseSecondaryMap::new();
+        items.insert(tree.get_root().key, item);
+        Self { tree, items }
+    }
+
+    /// Returns the tree's structure.
+    pub fn tree(&self) -> &CNQuadtree<(), S> {
+        &self.tree
+    }
+
+    /// Returns the item of a leaf, or None if the node is internal or doesn't exist.
+    pub fn item(&self, index: NodeId) -> Option<&T> {
+        self.tree.get_node(index)?;
+        self.items.get(index.key)
+    }
+
+    /// Returns the item of a leaf mutably, or None if the node is internal or doesn't exist.
+    pub fn item_mut(&mut self, index: NodeId) -> Option<&mut T> {
+        self.tree.get_node(index)?;
+        self.items.get_mut(index.key)
+    }
+
+    /// Returns the leaf containing the point and its item, or None if the point is outside the
+    /// tree's bounds.
+    pub fn point_locate(&self, point: Point<S>) -> Option<(NodeId, &T)> {
+        let leaf = self.tree.point_locate(point)?;
+        Some((leaf, self.items.get(leaf.key)?))
+    }
+
+    /// Returns an iterator over the leaves and their items in Morton order.
+    pub fn leaves(&self) -> impl Iterator<Item = (NodeId, &T)> + '_ {
+        self.tree
+            .leaves_morton()
+            .filter_map(|leaf| Some((leaf, self.items.get(leaf.key)?)))
+    }
+
+    /// Subdivides a leaf, giving its children `items` in the order NW, NE, SW, SE, and returns
+    /// the leaf's item, which the now internal node no longer holds.
+    pub fn subdivide(
+        &mut self,
+        index: NodeId,
+        items: [T; 4],
+    ) -> Result<([NodeId; 4], T), SubdivideError<T>> {
+        let children = match self.tree.subdivide(index, [(); 4]) {
+            Ok(children) => children,
+            Err(e) => {
+                return Err(SubdivideError {
+                    items,
+                    source: e.source,
+                })
+            }
+        };
+        for (child, item) in children.iter().zip(items) {
+            self.items.insert(child.key, item);
+        }
+        let item = self
+            .items
+            .remove(index.key)
+            .expect("a leaf being subdivided has an item");
+        Ok((children, item))
+    }
+
+    /// Removes the node's children, which must be leaves, gives the node `item`, and returns
+    /// the children's items.
+    pub fn pop_children(&mut self, index: NodeId, item: T) -> Result<[T; 4], PopChildrenError> {
+        let children = self
+            .tree
+            .get_node(index)
+            .ok_or(PopChildrenError::InvalidIndex)?
+            .get_children_index()
+            .ok_or(PopChildrenError::NotSubdivided)?;
+        self.tree.pop_children(index)?;
+        self.items.insert(index.key, item);
+        Ok(children.map(|child| {
+            self.items
+                .remove(child.key)
+                .expect("a popped leaf has an item")
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaf_only_items() {
+        let mut tree = LeafOnlyItems::new(String::from("root"), (0, 0, 8, 8));
+        let root = tree.tree().get_root();
+        let (children, item) = tree
+            .subdivide(root, ["nw", "ne", "sw", "se"].map(String::from))
+            .unwrap();
+        assert_eq!(item, "root");
+        assert_eq!(tree.item(root), None);
+        assert_eq!(tree.item(children[1]).unwrap(), "ne");
+        tree.item_mut(children[3]).unwrap().push('!');
+        assert_eq!(tree.point_locate((7, 7)).unwrap().1, "se!");
+        assert_eq!(tree.leaves().count(), 4);
+        assert_eq!(tree.items.len(), 4);
+
+        let (grandchildren, _) = tree.subdivide(children[0], Default::default()).unwrap();
+        let err = tree.subdivide(children[0], Default::default()).unwrap_err();
+        assert_eq!(err.source, crate::Error::AlreadySubdivided);
+        assert_eq!(
+            tree.pop_children(root, String::new()).unwrap_err(),
+            PopChildrenError::ChildrenSubdivided
+        );
+
+        let items = tree
+            .pop_children(children[0], String::from("merged"))
+            .unwrap();
+        assert_eq!(items, <[String; 4]>::default());
+        assert_eq!(tree.item(grandchildren[0]), None);
+        assert_eq!(tree.item(children[0]).unwrap(), "merged");
+        assert_eq!(tree.items.len(), 4);
+    }
+}
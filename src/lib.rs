@@ -1,5 +1,111 @@
+mod analytics;
+mod arena;
+#[cfg(feature = "bevy")]
+pub mod bevy;
+mod binary;
+mod boxed;
+mod budget;
+mod builder;
+mod checksum;
+mod clip;
+pub mod concurrent;
+mod contour;
+mod copy;
+mod corner_data;
+mod distance;
+mod edge_data;
+mod edges;
+mod entry;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fixed;
+mod flat;
+mod geo;
+mod heightmap;
+mod hierarchy;
+mod id;
+#[cfg(feature = "egui")]
+pub mod inspector;
+mod iter;
+mod journal;
+mod ktree;
+mod leaf_only;
+mod linear;
+mod literal;
 #[forbid(missing_docs, missing_doc_code_examples, unsafe_code)]
 mod location;
+mod metrics;
 mod node;
+mod pathfinding;
+mod persistent;
+mod points;
+mod polygon;
+mod polyline;
+mod progressive;
+mod pyramid;
+mod refine;
+mod render;
+mod replan;
+mod rle;
+mod sample;
+mod set_ops;
 mod slottree;
+mod stats;
+mod subscription;
+mod subtree;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod trace;
+mod transform;
 mod tree;
+mod view;
+mod visibility;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod zones;
+
+pub use analytics::Moments;
+pub use arena::ItemArena;
+pub use binary::{ItemCodec, LeBytes, FORMAT_VERSION, MAGIC};
+pub use boxed::AsAny;
+pub use budget::{Balance, Budget, Compress, PostOrderCursor, Progress, Rebuild};
+pub use builder::CNQuadtreeBuilder;
+pub use checksum::SubtreeChecksums;
+pub use clip::ClipView;
+pub use contour::Contour;
+pub use corner_data::CornerData;
+pub use edge_data::EdgeData;
+pub use edges::LeafEdge;
+pub use entry::Entry;
+pub use error::{Error, NeighborError, PopChildrenError, SubdivideError};
+pub use fixed::{Fixed, ParseFixedError};
+pub use flat::FlatArrays;
+pub use geo::{Equirectangular, GeoQuadtree, Projection, WebMercator};
+pub use heightmap::HeightPlane;
+pub use hierarchy::PortalGraph;
+pub use id::{IndexStatus, NodeId};
+pub use iter::{LeafIter, RegionIter};
+pub use journal::Journal;
+pub use ktree::{CNBintree, CNFusedQuadtree, CNKTree};
+pub use leaf_only::LeafOnlyItems;
+pub use linear::MAX_LINEAR_LEVEL;
+pub use literal::QuadtreeLiteral;
+pub use location::{Axis, Cardinality, Location};
+pub use metrics::Metrics;
+pub use node::{Bounds, CNNode, Point, RegionQuadtreeNode, SplitPolicy};
+pub use persistent::{PersistentQuadtree, Zipper};
+pub use points::{Bucket, CNPointQuadtree};
+pub use progressive::{ProgressiveDecoder, PROGRESSIVE_MAGIC, PROGRESSIVE_VERSION};
+pub use refine::{PriorityRefiner, Refinement, RefinementCriterion};
+pub use replan::Replanner;
+pub use rle::Run;
+pub use sample::LeafSampler;
+pub use slottree::CNQuadtree;
+pub use stats::Stats;
+pub use subscription::{SubscriptionId, Subscriptions};
+pub use subtree::{SubtreeMut, SubtreeView};
+pub use transform::Affine;
+pub use tree::{BoundsPredicate, Containment, Neighbors, RegionQuadtree, Visit, VisitFlow};
+pub use view::CNQuadtreeView;
+pub use zones::Zones;
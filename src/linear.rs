@@ -0,0 +1,188 @@
+use crate::error::Error;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashSet;
+
+/// Deepest level a locational code can describe.
+///
+/// A node's locational code is the path from the root to it. Each level takes two bits, most
+/// significant first, holding the child's position in the order NW, NE, SW, SE. Unused low bits
+/// are zero, so sorting codes sorts nodes in Morton order.
+pub const MAX_LINEAR_LEVEL: usize = 32;
+
+/// Appends the child's position at the given level to the code.
+fn push_digit(code: u64, level: usize, digit: usize) -> u64 {
+    code | (digit as u64) << (2 * (MAX_LINEAR_LEVEL - level))
+}
+
+/// Returns the child's position at the given level of the code.
+fn digit(code: u64, level: usize) -> usize {
+    (code >> (2 * (MAX_LINEAR_LEVEL - level)) & 0b11) as usize
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the leaves as a linear quadtree: a list of `(locational code, level, item)` sorted
+    /// by code. `f` is given each leaf's item and bounds. See [`MAX_LINEAR_LEVEL`] for the code
+    /// layout. Fails with [`Error::Overflow`] if the tree is deeper than [`MAX_LINEAR_LEVEL`].
+    pub fn to_linear<U, F>(&self, mut f: F) -> Result<Vec<(u64, usize, U)>, Error>
+    where
+        F: FnMut(&T, Bounds<S>) -> U,
+    {
+        let mut leaves = Vec::new();
+        // Children are pushed in reverse so leaves come out in code order.
+        let mut stack = vec![(self.get_root(), 0)];
+        while let Some((index, code)) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            match node.get_children_index() {
+                None => leaves.push((code, node.level(), f(node.get_item(), node.get_bounds()))),
+                Some(_) if node.level() >= MAX_LINEAR_LEVEL => return Err(Error::Overflow),
+                Some(children) => {
+                    for (digit, child) in children.into_iter().enumerate().rev() {
+                        stack.push((child, push_digit(code, node.level() + 1, digit)));
+                    }
+                }
+            }
+        }
+        Ok(leaves)
+    }
+
+    /// Builds a tree from a linear quadtree as returned by [`CNQuadtree::to_linear`]. The leaves
+    /// may be in any order but must cover the bounds exactly once. Internal nodes get the item
+    /// returned by `default`, which is given the node's bounds.
+    pub fn from_linear<I, D>(
+        bounds: impl Into<Bounds<S>>,
+        leaves: I,
+        mut default: D,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (u64, usize, T)>,
+        D: FnMut(Bounds<S>) -> T,
+    {
+        let bounds = bounds.into();
+        let mut tree = Self::new(default(bounds), bounds);
+        let mut assigned = HashSet::new();
+        for (code, level, item) in leaves {
+            if level > MAX_LINEAR_LEVEL || (level < MAX_LINEAR_LEVEL && code << (2 * level) != 0) {
+                return Err(Error::InvalidTiling);
+            }
+
+            let mut index = tree.get_root();
+            for depth in 1..=level {
+                if assigned.contains(&index) {
+                    return Err(Error::InvalidTiling);
+                }
+                let node = tree.get_node(index).ok_or(Error::DanglingIndex)?;
+                let children = match node.get_children_index() {
+                    Some(children) => children,
+                    None => {
+                        // The children's bounds aren't known until after the split.
+                        let bounds = node.get_bounds();
+                        let placeholder = [(); 4].map(|_| default(bounds));
+                        let children = tree.subdivide(index, placeholder).map_err(|e| e.source)?;
+                        for child in children {
+                            let node = tree.get_node_mut(child).ok_or(Error::DanglingIndex)?;
+                            *node.get_item_mut() = default(node.get_bounds());
+                        }
+                        children
+                    }
+                };
+                index = children[digit(code, depth)];
+            }
+
+            let node = tree.get_node_mut(index).ok_or(Error::DanglingIndex)?;
+            if node.has_children() || !assigned.insert(index) {
+                return Err(Error::InvalidTiling);
+            }
+            *node.get_item_mut() = item;
+        }
+
+        if tree.stats().leaves != assigned.len() {
+            return Err(Error::InvalidTiling);
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [_, ne, sw, _] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [_, _, ne_sw, _] = tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        tree.subdivide(ne_sw, [9, 10, 11, 12]).unwrap();
+        tree.subdivide(sw, [13, 14, 15, 16]).unwrap();
+
+        let linear = tree.to_linear(|item, _| *item).unwrap();
+        let codes: Vec<_> = linear
+            .iter()
+            .map(|&(code, level, _)| (code >> 58, level))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (0b000000, 1),
+                (0b010000, 2),
+                (0b010100, 2),
+                (0b011000, 3),
+                (0b011001, 3),
+                (0b011010, 3),
+                (0b011011, 3),
+                (0b011100, 2),
+                (0b100000, 2),
+                (0b100100, 2),
+                (0b101000, 2),
+                (0b101100, 2),
+                (0b110000, 1),
+            ]
+        );
+        assert!(linear.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let mut shuffled = linear.clone();
+        shuffled.reverse();
+        let rebuilt = CNQuadtree::from_linear((0, 0, 8, 8), shuffled, |_| 0).unwrap();
+        assert_eq!(rebuilt.to_linear(|item, _| *item).unwrap(), linear);
+    }
+
+    #[test]
+    fn rejects_bad_tilings() {
+        let bounds = (0, 0, 4, 4);
+        let single = CNQuadtree::from_linear(bounds, [(0, 0, 'a')], |_| '_').unwrap();
+        assert_eq!(
+            single.to_linear(|item, _| *item).unwrap(),
+            vec![(0, 0, 'a')]
+        );
+
+        let quarter = |digit: u64| (digit << 62, 1, 'x');
+        let gap = [quarter(0), quarter(1), quarter(2)];
+        assert_eq!(
+            CNQuadtree::from_linear(bounds, gap, |_| '_').unwrap_err(),
+            Error::InvalidTiling
+        );
+
+        let duplicate = [quarter(0), quarter(1), quarter(2), quarter(3), quarter(3)];
+        assert_eq!(
+            CNQuadtree::from_linear(bounds, duplicate, |_| '_').unwrap_err(),
+            Error::InvalidTiling
+        );
+
+        let overlap = [(0, 0, 'a'), quarter(0)];
+        assert_eq!(
+            CNQuadtree::from_linear(bounds, overlap, |_| '_').unwrap_err(),
+            Error::InvalidTiling
+        );
+
+        let stray_bits = [(1, 1, 'a')];
+        assert_eq!(
+            CNQuadtree::from_linear(bounds, stray_bits, |_| '_').unwrap_err(),
+            Error::InvalidTiling
+        );
+    }
+}
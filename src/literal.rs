@@ -0,0 +1,140 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{child_bounds, Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A tree's shape and leaf items as a plain recursive value, for writing trees literally and
+/// exchanging them with other tools. Branch children are in the order NW, NE, SW, SE.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QuadtreeLiteral<T> {
+    Leaf(T),
+    Branch(Box<[QuadtreeLiteral<T>; 4]>),
+}
+
+impl<T> QuadtreeLiteral<T> {
+    /// Returns a branch with the given children.
+    pub fn branch(
+        nw: QuadtreeLiteral<T>,
+        ne: QuadtreeLiteral<T>,
+        sw: QuadtreeLiteral<T>,
+        se: QuadtreeLiteral<T>,
+    ) -> Self {
+        Self::Branch(Box::new([nw, ne, sw, se]))
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a tree with the shape and leaf items of `literal`, with its cardinal neighbors
+    /// linked. Internal nodes get the item `internal` returns for their bounds. Fails if the
+    /// bounds can't be subdivided as deep as `literal`.
+    pub fn from_literal<F>(
+        bounds: impl Into<Bounds<S>>,
+        literal: QuadtreeLiteral<T>,
+        mut internal: F,
+    ) -> Result<Self, Error>
+    where
+        F: FnMut(Bounds<S>) -> T,
+    {
+        let bounds = bounds.into();
+        let (root_item, root_children) = match literal {
+            QuadtreeLiteral::Leaf(item) => return Ok(Self::new(item, bounds)),
+            QuadtreeLiteral::Branch(children) => (internal(bounds), children),
+        };
+        let mut tree = Self::new(root_item, bounds);
+        let mut stack = vec![(tree.get_root(), bounds, root_children)];
+        while let Some((index, bounds, children)) = stack.pop() {
+            let mut branches = Vec::new();
+            let mut items = Vec::with_capacity(4);
+            for (location, child) in children.into_iter().enumerate() {
+                match child {
+                    QuadtreeLiteral::Leaf(item) => items.push(item),
+                    QuadtreeLiteral::Branch(grandchildren) => {
+                        let child_bounds =
+                            child_bounds(bounds, location).ok_or(Error::UnitConversion)?;
+                        items.push(internal(child_bounds));
+                        branches.push((location, child_bounds, grandchildren));
+                    }
+                }
+            }
+            let Ok(items) = <[T; 4]>::try_from(items) else {
+                unreachable!("branches have four children");
+            };
+            let indices = tree.subdivide(index, items).map_err(|e| e.source)?;
+            stack.extend(
+                branches
+                    .into_iter()
+                    .map(|(location, bounds, children)| (indices[location], bounds, children)),
+            );
+        }
+        Ok(tree)
+    }
+
+    /// Returns the shape and leaf items of the tree.
+    pub fn to_literal(&self) -> QuadtreeLiteral<T>
+    where
+        T: Clone,
+    {
+        fn build<T, S>(tree: &CNQuadtree<T, S>, index: NodeId) -> QuadtreeLiteral<T>
+        where
+            T: Clone,
+            S: Copy
+                + Clone
+                + PartialOrd
+                + PartialEq
+                + NumAssign
+                + ToPrimitive
+                + NumOps
+                + FromPrimitive,
+        {
+            let node = tree.get_node(index).expect("children of a node exist");
+            match node.get_children_index() {
+                Some(children) => {
+                    QuadtreeLiteral::Branch(Box::new(children.map(|child| build(tree, child))))
+                }
+                None => QuadtreeLiteral::Leaf(node.get_item().clone()),
+            }
+        }
+        build(self, self.get_root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuadtreeLiteral::Leaf;
+    use super::*;
+    use crate::location::Cardinality;
+
+    #[test]
+    fn literal() {
+        let literal = QuadtreeLiteral::branch(
+            Leaf(1),
+            QuadtreeLiteral::branch(Leaf(2), Leaf(3), Leaf(4), Leaf(5)),
+            Leaf(6),
+            Leaf(7),
+        );
+        let tree = CNQuadtree::from_literal((0, 0, 8, 8), literal.clone(), |_| 0).unwrap();
+        assert_eq!(tree.leaves_morton().count(), 7);
+        let leaf = tree.point_locate((7, 3)).unwrap();
+        assert_eq!(*tree.get_node(leaf).unwrap().get_item(), 5);
+        let west = tree.point_locate((5, 3)).unwrap();
+        assert_eq!(
+            tree.get_node(leaf)
+                .unwrap()
+                .get_cardinal_neighbor_index(Cardinality::West),
+            Some(west)
+        );
+        assert_eq!(tree.to_literal(), literal);
+
+        assert_eq!(
+            CNQuadtree::<_, u32>::from_literal((0, 0, 1, 1), literal, |_| 0).err(),
+            Some(Error::CellTooSmall)
+        );
+        let leaf = CNQuadtree::<_, u32>::from_literal((0, 0, 1, 1), Leaf(9), |_| 0).unwrap();
+        assert_eq!(leaf.to_literal(), Leaf(9));
+    }
+}
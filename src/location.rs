@@ -1,13 +1,50 @@
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug)]
+/// A coordinate axis.
+pub enum Axis {
+    /// The axis West and East lie on.
+    X,
+    /// The axis North and South lie on.
+    Y,
+}
+
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug)]
 /// The four cardinal directions in the following order: West, North, East, and South.
 pub enum Cardinality {
+    /// Towards min x.
     West,
+    /// Towards min y.
     North,
+    /// Towards max x.
     East,
+    /// Towards max y.
     South,
 }
 
 impl Cardinality {
+    /// Every direction, in the order West, North, East, South.
+    pub const ALL: [Cardinality; 4] = [
+        Cardinality::West,
+        Cardinality::North,
+        Cardinality::East,
+        Cardinality::South,
+    ];
+
+    /// Returns an iterator over every direction. See [`Cardinality::ALL`].
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    #[inline]
+    /// Returns the axis the direction lies on.
+    pub fn axis(&self) -> Axis {
+        match self {
+            Cardinality::West | Cardinality::East => Axis::X,
+            Cardinality::North | Cardinality::South => Axis::Y,
+        }
+    }
+
     #[inline]
     /// Return the opposite direction of a cardinality.
     pub fn opposite(&self) -> Self {
@@ -40,12 +77,98 @@ impl TryFrom<usize> for Cardinality {
 /// The location of a quadtree child node in relation to its siblings.
 /// In the following order: NorthWest, NorthEast, SouthWest, SouthEast.
 pub enum Location {
+    /// The min x, min y quadrant.
     NorthWest,
+    /// The max x, min y quadrant.
     NorthEast,
+    /// The min x, max y quadrant.
     SouthWest,
+    /// The max x, max y quadrant.
     SouthEast,
 }
 
+impl Location {
+    /// Every location, in the order NorthWest, NorthEast, SouthWest, SouthEast.
+    pub const ALL: [Location; 4] = [
+        Location::NorthWest,
+        Location::NorthEast,
+        Location::SouthWest,
+        Location::SouthEast,
+    ];
+
+    /// Returns an iterator over every location. See [`Location::ALL`].
+    pub fn iter() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    #[inline]
+    /// Returns the two directions the location is towards, on the x-axis then the y-axis. For
+    /// example, NorthWest is towards West and North.
+    pub fn cardinal_components(&self) -> [Cardinality; 2] {
+        match self {
+            Location::NorthWest => [Cardinality::West, Cardinality::North],
+            Location::NorthEast => [Cardinality::East, Cardinality::North],
+            Location::SouthWest => [Cardinality::West, Cardinality::South],
+            Location::SouthEast => [Cardinality::East, Cardinality::South],
+        }
+    }
+
+    #[inline]
+    /// Returns the location whose directions are `x` and `y`, or None if they don't lie on the
+    /// x-axis and y-axis respectively.
+    pub fn from_cardinals(x: Cardinality, y: Cardinality) -> Option<Self> {
+        match (x, y) {
+            (Cardinality::West, Cardinality::North) => Some(Location::NorthWest),
+            (Cardinality::East, Cardinality::North) => Some(Location::NorthEast),
+            (Cardinality::West, Cardinality::South) => Some(Location::SouthWest),
+            (Cardinality::East, Cardinality::South) => Some(Location::SouthEast),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Returns true if the location is towards the direction, so that a child there shares its
+    /// parent's side in that direction.
+    pub fn is_towards(&self, direction: Cardinality) -> bool {
+        self.cardinal_components().contains(&direction)
+    }
+
+    #[inline]
+    /// Returns the sibling reflected across the axis perpendicular to `direction`, which is the
+    /// sibling adjacent to it in that direction or the opposite one. For example, NorthWest
+    /// mirrored to the East or West is NorthEast.
+    pub fn mirror(&self, direction: Cardinality) -> Self {
+        // Bit 0 of a location is its x half and bit 1 its y half.
+        let bit = match direction.axis() {
+            Axis::X => 1,
+            Axis::Y => 2,
+        };
+        (*self as usize ^ bit).try_into().unwrap()
+    }
+}
+
+impl Display for Cardinality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cardinality::West => "west",
+            Cardinality::North => "north",
+            Cardinality::East => "east",
+            Cardinality::South => "south",
+        })
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Location::NorthWest => "north-west",
+            Location::NorthEast => "north-east",
+            Location::SouthWest => "south-west",
+            Location::SouthEast => "south-east",
+        })
+    }
+}
+
 impl TryFrom<usize> for Location {
     type Error = String;
 
@@ -103,4 +226,32 @@ mod tests {
             assert_eq!(dir.next_neighbor(), next_neighbor);
         }
     }
+
+    #[test]
+    fn location_components() {
+        for location in Location::iter() {
+            let [x, y] = location.cardinal_components();
+            assert_eq!((x.axis(), y.axis()), (Axis::X, Axis::Y));
+            assert_eq!(Location::from_cardinals(x, y), Some(location));
+            assert!(location.is_towards(x) && !location.is_towards(x.opposite()));
+            for direction in Cardinality::iter() {
+                assert_eq!(location.mirror(direction).mirror(direction), location);
+            }
+        }
+        assert_eq!(
+            Location::NorthWest.mirror(Cardinality::East),
+            Location::NorthEast
+        );
+        assert_eq!(
+            Location::NorthEast.mirror(Cardinality::South),
+            Location::SouthEast
+        );
+        assert_eq!(
+            Location::from_cardinals(Cardinality::North, Cardinality::West),
+            None
+        );
+        assert_eq!(Cardinality::iter().count(), 4);
+        assert_eq!(Location::SouthWest.to_string(), "south-west");
+        assert_eq!(Cardinality::East.to_string(), "east");
+    }
 }
@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts of the operations performed on a tree since metrics were enabled. Returned by
+/// [`CNQuadtree::metrics`](crate::CNQuadtree::metrics).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Metrics {
+    /// Number of successful subdivisions.
+    pub subdivisions: u64,
+    /// Number of successful merges by `pop_children`.
+    pub merges: u64,
+    /// Number of neighbor chains walked while updating cardinal neighbors.
+    pub neighbor_chains: u64,
+    /// Total length of the neighbor chains walked.
+    pub neighbor_chain_nodes: u64,
+    /// Number of point locations by `point_locate` and `point_locate_path`.
+    pub point_locates: u64,
+    /// Total number of levels descended by point locations.
+    pub point_locate_depth: u64,
+}
+
+impl Metrics {
+    /// Returns the mean length of the neighbor chains walked, or 0 if none were.
+    pub fn average_neighbor_chain_len(&self) -> f64 {
+        ratio(self.neighbor_chain_nodes, self.neighbor_chains)
+    }
+
+    /// Returns the mean number of levels descended per point location, or 0 if there were none.
+    pub fn average_point_locate_depth(&self) -> f64 {
+        ratio(self.point_locate_depth, self.point_locates)
+    }
+}
+
+fn ratio(total: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+/// Live counters behind [`Metrics`]. Atomic so queries taking `&self` can count while the tree
+/// stays `Sync`.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    subdivisions: AtomicU64,
+    merges: AtomicU64,
+    neighbor_chains: AtomicU64,
+    neighbor_chain_nodes: AtomicU64,
+    point_locates: AtomicU64,
+    point_locate_depth: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn subdivision(&self) {
+        self.subdivisions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn merge(&self) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn neighbor_chain(&self, len: usize) {
+        self.neighbor_chains.fetch_add(1, Ordering::Relaxed);
+        self.neighbor_chain_nodes
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn point_locate(&self, depth: usize) {
+        self.point_locates.fetch_add(1, Ordering::Relaxed);
+        self.point_locate_depth
+            .fetch_add(depth as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            subdivisions: self.subdivisions.load(Ordering::Relaxed),
+            merges: self.merges.load(Ordering::Relaxed),
+            neighbor_chains: self.neighbor_chains.load(Ordering::Relaxed),
+            neighbor_chain_nodes: self.neighbor_chain_nodes.load(Ordering::Relaxed),
+            point_locates: self.point_locates.load(Ordering::Relaxed),
+            point_locate_depth: self.point_locate_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for Counters {
+    fn clone(&self) -> Self {
+        let metrics = self.snapshot();
+        Self {
+            subdivisions: metrics.subdivisions.into(),
+            merges: metrics.merges.into(),
+            neighbor_chains: metrics.neighbor_chains.into(),
+            neighbor_chain_nodes: metrics.neighbor_chain_nodes.into(),
+            point_locates: metrics.point_locates.into(),
+            point_locate_depth: metrics.point_locate_depth.into(),
+        }
+    }
+}
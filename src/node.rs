@@ -1,7 +1,101 @@
 use crate::location::{Cardinality, Location};
 use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::fmt::{self, Debug, Formatter};
 
-pub trait RegionQuadtreeNode<T>: PartialEq {
+/// Bounds of a region in the following order: min x, min y, max x, max y. Converts to and from
+/// a tuple in the same order, compares equal to one and is debug-formatted like one.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bounds<S>(pub S, pub S, pub S, pub S);
+/// A point in the following order: x, y.
+pub type Point<S> = (S, S);
+
+impl<S> Bounds<S>
+where
+    S: Copy + PartialOrd + NumOps + FromPrimitive,
+{
+    /// Returns the extent along the x-axis.
+    #[inline]
+    pub fn width(&self) -> S {
+        self.2 - self.0
+    }
+
+    /// Returns the extent along the y-axis.
+    #[inline]
+    pub fn height(&self) -> S {
+        self.3 - self.1
+    }
+
+    /// Returns the point the bounds are split at by default, rounded down for integers. Returns
+    /// None if the unit type can't represent two.
+    pub fn center(&self) -> Option<Point<S>> {
+        Some((midpoint(self.0, self.2)?, midpoint(self.1, self.3)?))
+    }
+
+    /// Returns the bounds of a quadrant split at [`Bounds::center`].
+    pub fn quadrant(&self, location: Location) -> Option<Self> {
+        let (x_middle, y_middle) = self.center()?;
+        let Bounds(left, top, right, bottom) = *self;
+        Some(match location {
+            Location::NorthWest => Bounds(left, top, x_middle, y_middle),
+            Location::NorthEast => Bounds(x_middle, top, right, y_middle),
+            Location::SouthWest => Bounds(left, y_middle, x_middle, bottom),
+            Location::SouthEast => Bounds(x_middle, y_middle, right, bottom),
+        })
+    }
+
+    /// Returns true if the bounds contain the point. Bounds are half-open, including the left
+    /// and top edges but not the right and bottom ones.
+    #[inline]
+    pub fn contains(&self, point: Point<S>) -> bool {
+        (self.0 <= point.0 && point.0 < self.2) && (self.1 <= point.1 && point.1 < self.3)
+    }
+
+    /// Returns true if the bounds overlap `other` with a non-zero area.
+    #[inline]
+    pub fn intersects(&self, other: Self) -> bool {
+        (self.0 < other.2 && other.0 < self.2) && (self.1 < other.3 && other.1 < self.3)
+    }
+
+    /// Returns true if `other` lies entirely within the bounds.
+    #[inline]
+    pub fn contains_rect(&self, other: Self) -> bool {
+        (self.0 <= other.0 && other.2 <= self.2) && (self.1 <= other.1 && other.3 <= self.3)
+    }
+}
+
+impl<S> From<(S, S, S, S)> for Bounds<S> {
+    #[inline]
+    fn from((left, top, right, bottom): (S, S, S, S)) -> Self {
+        Bounds(left, top, right, bottom)
+    }
+}
+
+impl<S> From<Bounds<S>> for (S, S, S, S) {
+    #[inline]
+    fn from(Bounds(left, top, right, bottom): Bounds<S>) -> Self {
+        (left, top, right, bottom)
+    }
+}
+
+impl<S: Debug> Debug for Bounds<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("")
+            .field(&self.0)
+            .field(&self.1)
+            .field(&self.2)
+            .field(&self.3)
+            .finish()
+    }
+}
+
+impl<S: PartialEq> PartialEq<(S, S, S, S)> for Bounds<S> {
+    fn eq(&self, other: &(S, S, S, S)) -> bool {
+        self.0 == other.0 && self.1 == other.1 && self.2 == other.2 && self.3 == other.3
+    }
+}
+
+/// A node of a tree whose internal nodes have `N` children, four for quadtrees.
+pub trait RegionQuadtreeNode<T, const N: usize = 4>: PartialEq {
     type Index: Clone;
     type Unit: Copy
         + Clone
@@ -19,11 +113,11 @@ pub trait RegionQuadtreeNode<T>: PartialEq {
         self.get_parent_index().is_some()
     }
     /// Return an array of child indices if it exists.
-    fn get_children_index(&self) -> Option<[Self::Index; 4]>;
-    /// Return a child index at the specified location if it exists.
+    fn get_children_index(&self) -> Option<[Self::Index; N]>;
+    /// Return a child index at the specified location if it exists. Locations are positions
+    /// among the children, so only the first `N` exist.
     fn get_child_index(&self, location: Location) -> Option<Self::Index> {
-        self.get_children_index()
-            .and_then(|children| Some(children[location as usize].clone()))
+        self.get_children_index()?.get(location as usize).cloned()
     }
     /// Returns true if the node has children.
     fn has_children(&self) -> bool {
@@ -60,15 +154,26 @@ pub trait RegionQuadtreeNode<T>: PartialEq {
             self.update_neighbor(new_neighbor, Cardinality::try_from(direction).unwrap());
         }
     }
-    fn update_children(&mut self, new_children: Option<[Self::Index; 4]>);
-    fn get_bounds(&self) -> (Self::Unit, Self::Unit, Self::Unit, Self::Unit);
-    fn point_in(&self, point: (Self::Unit, Self::Unit)) -> bool {
-        let bounds = self.get_bounds();
-        (bounds.0 <= point.0 && point.0 < bounds.2) && (bounds.1 <= point.1 && point.1 < bounds.3)
+    fn update_children(&mut self, new_children: Option<[Self::Index; N]>);
+    fn get_bounds(&self) -> Bounds<Self::Unit>;
+    /// Returns true if the node contains the point. Bounds are half-open, including the left and
+    /// top edges but not the right and bottom ones, so a point on an edge shared by two nodes is
+    /// in exactly one of them. Points with NaN coordinates are in no node.
+    fn point_in(&self, point: Point<Self::Unit>) -> bool {
+        self.get_bounds().contains(point)
+    }
+    /// Returns true if the node overlaps the region with a non-zero area.
+    fn intersects(&self, region: Bounds<Self::Unit>) -> bool {
+        self.get_bounds().intersects(region)
+    }
+    /// Returns true if the node lies entirely within the region.
+    fn inside(&self, region: Bounds<Self::Unit>) -> bool {
+        region.contains_rect(self.get_bounds())
     }
 }
 
-pub struct CNNode<T, I, S = u32>
+#[derive(Clone, Debug)]
+pub struct CNNode<T, I, S = u32, const N: usize = 4>
 where
     S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
     I: Copy + Clone,
@@ -76,16 +181,17 @@ where
     item: T,
     layer: usize,
     // min x, min y, max x, max y
-    bounds: (S, S, S, S),
+    bounds: Bounds<S>,
     parent: Option<I>,
     /// Cardinal neighbors in the following order: West, North, East, South.
     /// A neighbor is None if it's a border.
     neighbors: [Option<I>; 4],
-    /// Children in the following order: NorthWest, NorthEast, SouthWest, SouthEast.
-    children: Option<[I; 4]>,
+    /// Children in the following order: NorthWest, NorthEast, SouthWest, SouthEast. See
+    /// [`CNKTree`](crate::CNKTree) for other branching factors.
+    children: Option<[I; N]>,
 }
 
-impl<T, I, S> PartialEq for CNNode<T, I, S>
+impl<T, I, S, const N: usize> PartialEq for CNNode<T, I, S, N>
 where
     S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
     I: Copy + Clone,
@@ -95,7 +201,7 @@ where
     }
 }
 
-impl<T, S, I> RegionQuadtreeNode<T> for CNNode<T, I, S>
+impl<T, S, I, const N: usize> RegionQuadtreeNode<T, N> for CNNode<T, I, S, N>
 where
     S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
     I: Copy + Clone,
@@ -109,7 +215,7 @@ where
     }
 
     #[inline]
-    fn get_children_index(&self) -> Option<[Self::Index; 4]> {
+    fn get_children_index(&self) -> Option<[Self::Index; N]> {
         self.children
     }
 
@@ -143,22 +249,22 @@ where
     }
 
     #[inline]
-    fn update_children(&mut self, new_children: Option<[Self::Index; 4]>) {
+    fn update_children(&mut self, new_children: Option<[Self::Index; N]>) {
         self.children = new_children;
     }
 
     #[inline]
-    fn get_bounds(&self) -> (Self::Unit, Self::Unit, Self::Unit, Self::Unit) {
+    fn get_bounds(&self) -> Bounds<Self::Unit> {
         self.bounds
     }
 }
 
-impl<T, S, I> CNNode<T, I, S>
+impl<T, S, I, const N: usize> CNNode<T, I, S, N>
 where
     S: Copy + Clone + PartialOrd + NumAssign + ToPrimitive + NumOps + FromPrimitive,
     I: Copy + Clone,
 {
-    pub(crate) fn new(item: T, layer: usize, bounds: (S, S, S, S), parent: Option<I>) -> Self {
+    pub(crate) fn new(item: T, layer: usize, bounds: Bounds<S>, parent: Option<I>) -> Self {
         Self {
             item,
             layer,
@@ -172,4 +278,131 @@ where
     pub(crate) fn pop(self) -> T {
         self.item
     }
+
+    #[inline]
+    pub(crate) fn update_parent(&mut self, new_parent: Option<I>) {
+        self.parent = new_parent;
+    }
+
+    #[inline]
+    pub(crate) fn update_level(&mut self, layer: usize) {
+        self.layer = layer;
+    }
+
+    #[inline]
+    pub(crate) fn update_bounds(&mut self, bounds: Bounds<S>) {
+        self.bounds = bounds;
+    }
+}
+
+/// Chooses where [`CNQuadtree::subdivide`](crate::RegionQuadtree::subdivide) splits a node. For
+/// floating point coordinates, `Floor` and `Ceil` split at the same point.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SplitPolicy {
+    /// Splits at `min + (max - min) / 2`, rounding towards `min`, so the lower half is the
+    /// smaller one for odd integer extents.
+    #[default]
+    Floor,
+    /// Splits at `max - (max - min) / 2`, rounding towards `max`, so the upper half is the
+    /// smaller one for odd integer extents.
+    Ceil,
+    /// Splits a [`CNPointQuadtree`](crate::CNPointQuadtree) bucket at the median of its points'
+    /// coordinates, falling back to `Floor` if that would leave a half empty. Elsewhere it's the
+    /// same as `Floor`.
+    Median,
+}
+
+impl SplitPolicy {
+    /// Returns the split point of `[min, max)` without checking that both halves are nonempty.
+    pub(crate) fn split<S>(self, min: S, max: S) -> Option<S>
+    where
+        S: Copy + PartialOrd + NumOps + FromPrimitive,
+    {
+        match self {
+            SplitPolicy::Floor | SplitPolicy::Median => midpoint(min, max),
+            SplitPolicy::Ceil => {
+                let (floor, odd) = integer_midpoint(min, max)?;
+                Some(match floor {
+                    Some(floor) if odd => floor + S::from_i64(1)?,
+                    Some(floor) => floor,
+                    None => max - (max - min) / S::from_i64(2)?,
+                })
+            }
+        }
+    }
+}
+
+/// Returns the split point of [`SplitPolicy::Floor`], the default.
+pub(crate) fn midpoint<S>(min: S, max: S) -> Option<S>
+where
+    S: Copy + PartialOrd + NumOps + FromPrimitive,
+{
+    match integer_midpoint(min, max)? {
+        (Some(floor), _) => Some(floor),
+        (None, _) => Some(min + (max - min) / S::from_i64(2)?),
+    }
+}
+
+/// Returns `(min + max) / 2` rounded down for integer types, computed from halves so it can't
+/// overflow however far apart they are, and whether the sum is odd. The floor is None for
+/// types with fractions, whose midpoints don't round.
+fn integer_midpoint<S>(min: S, max: S) -> Option<(Option<S>, bool)>
+where
+    S: Copy + PartialOrd + NumOps + FromPrimitive,
+{
+    let (zero, one, two) = (S::from_i64(0)?, S::from_i64(1)?, S::from_i64(2)?);
+    if one / two != zero {
+        return Some((None, false));
+    }
+    // Division truncates towards zero, so odd negatives are one above their floored half.
+    let half = |v: S| {
+        if v % two < zero {
+            v / two - one
+        } else {
+            v / two
+        }
+    };
+    let (min_half, max_half) = (half(min), half(max));
+    let (min_odd, max_odd) = (min - min_half * two != zero, max - max_half * two != zero);
+    let floor = min_half + max_half;
+    Some(match (min_odd, max_odd) {
+        (true, true) => (Some(floor + one), false),
+        (false, false) => (Some(floor), false),
+        _ => (Some(floor), true),
+    })
+}
+
+/// Returns the bounds of the child at the location index, in the order NW, NE, SW, SE.
+pub(crate) fn child_bounds<S>(bounds: Bounds<S>, location: usize) -> Option<Bounds<S>>
+where
+    S: Copy + PartialOrd + NumOps + FromPrimitive,
+{
+    bounds.quadrant(Location::try_from(location).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_geometry() {
+        let bounds = Bounds::from((0, 0, 8, 5));
+        assert_eq!((bounds.width(), bounds.height()), (8, 5));
+        assert_eq!(bounds.center(), Some((4, 2)));
+        assert_eq!(
+            bounds.quadrant(Location::SouthEast),
+            Some(Bounds(4, 2, 8, 5))
+        );
+        assert_eq!(Bounds(-3, -3, 0, 0).center(), Some((-2, -2)));
+        assert_eq!(Bounds(0.0, 0.0, 1.0, 3.0).center(), Some((0.5, 1.5)));
+
+        assert!(bounds.contains((0, 4)) && !bounds.contains((8, 0)));
+        assert!(bounds.intersects(Bounds(7, 4, 9, 9)));
+        assert!(!bounds.intersects(Bounds(8, 0, 9, 5)));
+        assert!(bounds.contains_rect(Bounds(0, 0, 8, 5)));
+        assert!(!bounds.contains_rect(Bounds(1, 1, 9, 2)));
+
+        assert_eq!(bounds, (0, 0, 8, 5));
+        assert_eq!(<(i32, i32, i32, i32)>::from(bounds), (0, 0, 8, 5));
+    }
 }
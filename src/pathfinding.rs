@@ -0,0 +1,302 @@
+use crate::distance::Visit;
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the cheapest path of edge-adjacent leaves from `from` to `to`, both included,
+    /// and its cost, found by Dijkstra's algorithm. `cost` returns the cost per unit of length
+    /// of crossing a leaf, from its item, or None if the leaf can't be entered. A step between
+    /// adjacent leaves costs the distance between their centers times the mean of their costs.
+    /// Costs must not be negative. Unlike A*, no heuristic is needed, so any costs work.
+    ///
+    /// `cost` is called at most once per leaf. Returns None if either index isn't a leaf, an
+    /// end can't be entered, `to` can't be reached or a coordinate can't be converted to `f64`.
+    pub fn shortest_path<F>(&self, from: NodeId, to: NodeId, cost: F) -> Option<(Vec<NodeId>, f64)>
+    where
+        F: FnMut(&T) -> Option<f64>,
+    {
+        let mut leaf_cost = self.cached_leaf_cost(cost);
+        leaf_cost(from)?;
+        leaf_cost(to)?;
+        self.search(from, Some(to), None, &mut leaf_cost)?
+            .path_to(to)
+    }
+
+    /// Returns `cost` applied to the item of a leaf, called at most once per leaf, or None if
+    /// the index isn't a leaf.
+    pub(crate) fn cached_leaf_cost<'a, F>(
+        &'a self,
+        mut cost: F,
+    ) -> impl FnMut(NodeId) -> Option<f64> + 'a
+    where
+        F: FnMut(&T) -> Option<f64> + 'a,
+    {
+        let mut costs = HashMap::new();
+        move |index| {
+            *costs.entry(index).or_insert_with(|| {
+                self.get_node(index)
+                    .filter(|n| n.is_leaf())
+                    .and_then(|n| cost(n.get_item()))
+            })
+        }
+    }
+
+    /// Runs Dijkstra's algorithm from `from` over the leaves whose centers are within `within`,
+    /// or every leaf, with step costs as in [`CNQuadtree::shortest_path`]. Stops once `to` is
+    /// reached. Returns None if a coordinate can't be converted to `f64`.
+    pub(crate) fn search<F>(
+        &self,
+        from: NodeId,
+        to: Option<NodeId>,
+        within: Option<Bounds<f64>>,
+        leaf_cost: &mut F,
+    ) -> Option<Search>
+    where
+        F: FnMut(NodeId) -> Option<f64>,
+    {
+        let inside = |(x, y): Point<f64>| {
+            within.is_none_or(|Bounds(left, top, right, bottom)| {
+                left <= x && x < right && top <= y && y < bottom
+            })
+        };
+        let mut search = Search {
+            costs: HashMap::from([(from, 0.0)]),
+            previous: HashMap::new(),
+        };
+        let mut queue = BinaryHeap::from([Reverse(Visit(0.0, from))]);
+        while let Some(Reverse(Visit(total, leaf))) = queue.pop() {
+            if Some(leaf) == to {
+                break;
+            }
+            if search.costs.get(&leaf).is_some_and(|&c| c < total) {
+                continue;
+            }
+            let center = self.center_f64(leaf)?;
+            let Some(here) = leaf_cost(leaf) else {
+                continue;
+            };
+            for direction in Cardinality::ALL {
+                for neighbor in self.neighbors_iter(leaf, direction) {
+                    let Some(there) = leaf_cost(neighbor) else {
+                        continue;
+                    };
+                    let next_center = self.center_f64(neighbor)?;
+                    if !inside(next_center) {
+                        continue;
+                    }
+                    let length = (next_center.0 - center.0).hypot(next_center.1 - center.1);
+                    let next = total + length * (here + there) / 2.0;
+                    if search.costs.get(&neighbor).is_none_or(|&c| next < c) {
+                        search.costs.insert(neighbor, next);
+                        search.previous.insert(neighbor, leaf);
+                        queue.push(Reverse(Visit(next, neighbor)));
+                    }
+                }
+            }
+        }
+        Some(search)
+    }
+
+    /// Straightens a path of edge-adjacent leaves, such as one from
+    /// [`CNQuadtree::shortest_path`], into the shortest polyline from `start` to `goal` that
+    /// stays within the path's leaves, by pulling it taut through the edges consecutive leaves
+    /// share (the funnel algorithm). `start` should be in the first leaf and `goal` in the last.
+    /// Returns the polyline's points, `start` and `goal` included, turning only at corners of
+    /// the shared edges.
+    ///
+    /// Returns None if the path is empty, consecutive leaves don't share an edge or a coordinate
+    /// can't be converted to `f64`.
+    pub fn smooth_path(
+        &self,
+        path: &[NodeId],
+        start: Point<f64>,
+        goal: Point<f64>,
+    ) -> Option<Vec<Point<f64>>> {
+        if path.is_empty() {
+            return None;
+        }
+        let mut portals = vec![(start, start)];
+        for pair in path.windows(2) {
+            portals.push(self.portal(pair[0], pair[1])?);
+        }
+        portals.push((goal, goal));
+
+        // Whether `c` is strictly to the right or left of the ray from `a` through `b`, with y
+        // pointing down.
+        let cross = |a: Point<f64>, b: Point<f64>, c: Point<f64>| {
+            (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+        };
+        let right_of = |a, b, c| cross(a, b, c) > 0.0;
+        let left_of = |a, b, c| cross(a, b, c) < 0.0;
+
+        let mut points = vec![start];
+        let (mut apex, mut left, mut right) = (start, start, start);
+        let (mut left_index, mut right_index) = (0, 0);
+        let mut i = 1;
+        while i < portals.len() {
+            let (next_left, next_right) = portals[i];
+            if !right_of(apex, right, next_right) {
+                if apex == right || right_of(apex, left, next_right) {
+                    (right, right_index) = (next_right, i);
+                } else {
+                    // The right side crossed over the left, so the path turns at the left.
+                    apex = left;
+                    points.push(apex);
+                    (left, right) = (apex, apex);
+                    (left_index, right_index) = (left_index, left_index);
+                    i = left_index + 1;
+                    continue;
+                }
+            }
+            if !left_of(apex, left, next_left) {
+                if apex == left || left_of(apex, right, next_left) {
+                    (left, left_index) = (next_left, i);
+                } else {
+                    apex = right;
+                    points.push(apex);
+                    (left, right) = (apex, apex);
+                    (left_index, right_index) = (right_index, right_index);
+                    i = right_index + 1;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        if points.last() != Some(&goal) {
+            points.push(goal);
+        }
+        Some(points)
+    }
+
+    /// Returns the ends of the edge shared by two leaves, as seen moving from `from` to `to`:
+    /// on the left, then on the right. Returns None if they don't share an edge.
+    fn portal(&self, from: NodeId, to: NodeId) -> Option<(Point<f64>, Point<f64>)> {
+        let Bounds(left, top, right, bottom) = self.bounds_f64(from)?;
+        let Bounds(n_left, n_top, n_right, n_bottom) = self.bounds_f64(to)?;
+        let (low_x, high_x) = (left.max(n_left), right.min(n_right));
+        let (low_y, high_y) = (top.max(n_top), bottom.min(n_bottom));
+        // With y pointing down, the left hand points north when moving east.
+        let portal = if right == n_left && low_y < high_y {
+            ((right, low_y), (right, high_y))
+        } else if left == n_right && low_y < high_y {
+            ((left, high_y), (left, low_y))
+        } else if bottom == n_top && low_x < high_x {
+            ((high_x, bottom), (low_x, bottom))
+        } else if top == n_bottom && low_x < high_x {
+            ((low_x, top), (high_x, top))
+        } else {
+            return None;
+        };
+        Some(portal)
+    }
+}
+
+/// The leaves reached by [`CNQuadtree::search`].
+pub(crate) struct Search {
+    /// The cost of the cheapest path found to each leaf.
+    pub(crate) costs: HashMap<NodeId, f64>,
+    /// The leaf each leaf was reached from on that path.
+    previous: HashMap<NodeId, NodeId>,
+}
+
+impl Search {
+    /// Returns the path to a reached leaf, from the leaf the search started at, and its cost.
+    pub(crate) fn path_to(&self, to: NodeId) -> Option<(Vec<NodeId>, f64)> {
+        let cost = *self.costs.get(&to)?;
+        let mut path = vec![to];
+        while let Some(&step) = self.previous.get(path.last()?) {
+            path.push(step);
+        }
+        path.reverse();
+        Some((path, cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortest_path() {
+        // A 4 by 4 grid of unit cells: mud in the middle column, and a wall below it.
+        let mut tree = CNQuadtree::new(1.0, (0, 0, 4, 4));
+        tree.refine_to_levels(|_| 2, |_, &cost| [cost; 4]).unwrap();
+        let grid: Vec<Vec<NodeId>> = (0..4)
+            .map(|y| (0..4).map(|x| tree.point_locate((x, y)).unwrap()).collect())
+            .collect();
+        for (y, cost) in [(0, 9.0), (1, 9.0), (2, 9.0), (3, f64::INFINITY)] {
+            *tree.get_node_mut(grid[y][2]).unwrap().get_item_mut() = cost;
+        }
+        let passable = |&cost: &f64| cost.is_finite().then_some(cost);
+
+        // Straight through the mud costs 1 + 9 on the way in and out.
+        let (path, cost) = tree
+            .shortest_path(grid[0][0], grid[0][3], passable)
+            .unwrap();
+        assert_eq!(path, [grid[0][0], grid[0][1], grid[0][2], grid[0][3]]);
+        assert_eq!(cost, 1.0 + 5.0 + 5.0);
+
+        // The wall forces a detour through the mud, where it is thinnest.
+        *tree.get_node_mut(grid[1][2]).unwrap().get_item_mut() = 3.0;
+        let (path, cost) = tree
+            .shortest_path(grid[3][0], grid[3][3], passable)
+            .unwrap();
+        assert_eq!(path.len(), 8);
+        assert!(path.contains(&grid[1][2]));
+        assert_eq!(cost, 1.0 + 1.0 + 1.0 + 2.0 + 2.0 + 1.0 + 1.0);
+
+        let (path, cost) = tree
+            .shortest_path(grid[0][0], grid[0][0], passable)
+            .unwrap();
+        assert_eq!((path, cost), (vec![grid[0][0]], 0.0));
+        assert_eq!(tree.shortest_path(grid[0][0], grid[3][2], passable), None);
+        assert_eq!(
+            tree.shortest_path(tree.get_root(), grid[0][0], passable),
+            None
+        );
+    }
+
+    #[test]
+    fn smooth_path() {
+        let mut tree = CNQuadtree::new(1.0, (0, 0, 4, 4));
+        tree.refine_to_levels(|_| 2, |_, &cost| [cost; 4]).unwrap();
+        let cell = |x, y| tree.point_locate((x, y)).unwrap();
+
+        // Along the top row and down the east column, turning at the inner corner.
+        let path: Vec<_> = [(0, 0), (1, 0), (2, 0), (3, 0), (3, 1), (3, 2), (3, 3)]
+            .into_iter()
+            .map(|(x, y)| cell(x, y))
+            .collect();
+        assert_eq!(
+            tree.smooth_path(&path, (0.5, 0.5), (3.5, 3.5)),
+            Some(vec![(0.5, 0.5), (3.0, 1.0), (3.5, 3.5)])
+        );
+        // The same the other way round.
+        let reversed: Vec<_> = path.iter().rev().copied().collect();
+        assert_eq!(
+            tree.smooth_path(&reversed, (3.5, 3.5), (0.5, 0.5)),
+            Some(vec![(3.5, 3.5), (3.0, 1.0), (0.5, 0.5)])
+        );
+        // A zig-zag of cell centers with a clear line of sight becomes straight.
+        let stairs = [cell(0, 0), cell(1, 0), cell(1, 1), cell(2, 1), cell(2, 2)];
+        assert_eq!(
+            tree.smooth_path(&stairs, (0.2, 0.2), (2.8, 2.8)),
+            Some(vec![(0.2, 0.2), (2.8, 2.8)])
+        );
+
+        assert_eq!(tree.smooth_path(&[], (0.5, 0.5), (0.5, 0.5)), None);
+        assert_eq!(
+            tree.smooth_path(&[cell(0, 0), cell(2, 0)], (0.5, 0.5), (2.5, 0.5)),
+            None
+        );
+    }
+}
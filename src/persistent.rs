@@ -0,0 +1,345 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::location::Location;
+use crate::node::{child_bounds, Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::rc::Rc;
+
+/// An immutable quadtree whose versions share their unchanged subtrees. Cloning is `O(1)`, and
+/// an edit made through a [`Zipper`] copies only the nodes on the path to it, which makes
+/// speculative edits cheap.
+///
+/// Nodes don't store cardinal neighbors, as a shared subtree has different neighbors in each
+/// version. Convert to a [`CNQuadtree`] with [`PersistentQuadtree::to_tree`] for neighbor
+/// queries.
+#[derive(Debug)]
+pub struct PersistentQuadtree<T, S = u32> {
+    root: Rc<PersistentNode<T, S>>,
+}
+
+#[derive(Clone, Debug)]
+struct PersistentNode<T, S> {
+    bounds: Bounds<S>,
+    item: T,
+    children: Option<[Rc<PersistentNode<T, S>>; 4]>,
+}
+
+impl<T, S> Clone for PersistentQuadtree<T, S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: Rc::clone(&self.root),
+        }
+    }
+}
+
+impl<T, S> PersistentQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a tree of a single leaf.
+    pub fn new(item: T, bounds: impl Into<Bounds<S>>) -> Self {
+        let bounds = bounds.into();
+        Self {
+            root: Rc::new(PersistentNode {
+                bounds,
+                item,
+                children: None,
+            }),
+        }
+    }
+
+    /// Returns the root's bounds.
+    pub fn bounds(&self) -> Bounds<S> {
+        self.root.bounds
+    }
+
+    /// Returns the item of the leaf containing the point, or None if it's outside the bounds.
+    pub fn item_at(&self, point: Point<S>) -> Option<&T> {
+        let Bounds(left, top, right, bottom) = self.root.bounds;
+        if !(left <= point.0 && point.0 < right && top <= point.1 && point.1 < bottom) {
+            return None;
+        }
+        let mut node = &self.root;
+        while let Some(children) = &node.children {
+            node = children.iter().find(|child| {
+                let Bounds(left, top, right, bottom) = child.bounds;
+                left <= point.0 && point.0 < right && top <= point.1 && point.1 < bottom
+            })?;
+        }
+        Some(&node.item)
+    }
+
+    /// Returns the number of leaves.
+    pub fn leaf_count(&self) -> usize {
+        let mut count = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match &node.children {
+                Some(children) => stack.extend(children),
+                None => count += 1,
+            }
+        }
+        count
+    }
+
+    /// Returns a cursor at the root for editing a new version of the tree.
+    pub fn zipper(&self) -> Zipper<T, S> {
+        Zipper {
+            focus: Rc::clone(&self.root),
+            path: Vec::new(),
+        }
+    }
+
+    /// Returns a [`CNQuadtree`] with the same structure and items. Fails if the tree can't be
+    /// subdivided like this one.
+    pub fn to_tree(&self) -> Result<CNQuadtree<T, S>, Error>
+    where
+        T: Clone,
+    {
+        let mut tree = CNQuadtree::new(self.root.item.clone(), self.root.bounds);
+        let mut stack = vec![(tree.get_root(), &self.root)];
+        while let Some((index, node)) = stack.pop() {
+            if let Some(children) = &node.children {
+                let items = children.each_ref().map(|child| child.item.clone());
+                let indices = tree.subdivide(index, items).map_err(|e| e.source)?;
+                stack.extend(indices.into_iter().zip(children));
+            }
+        }
+        Ok(tree)
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    T: Clone,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a [`PersistentQuadtree`] with the same structure and items.
+    pub fn to_persistent(&self) -> PersistentQuadtree<T, S> {
+        fn build<T, S>(tree: &CNQuadtree<T, S>, index: NodeId) -> Rc<PersistentNode<T, S>>
+        where
+            T: Clone,
+            S: Copy
+                + Clone
+                + PartialOrd
+                + PartialEq
+                + NumAssign
+                + ToPrimitive
+                + NumOps
+                + FromPrimitive,
+        {
+            let node = tree.get_node(index).expect("children of a node exist");
+            Rc::new(PersistentNode {
+                bounds: node.get_bounds(),
+                item: node.get_item().clone(),
+                children: node
+                    .get_children_index()
+                    .map(|children| children.map(|child| build(tree, child))),
+            })
+        }
+        PersistentQuadtree {
+            root: build(self, self.get_root()),
+        }
+    }
+}
+
+/// A cursor into a [`PersistentQuadtree`] making edits around it. The edits form a new version,
+/// returned by [`Zipper::finish`], and never change the tree the zipper came from.
+#[derive(Clone, Debug)]
+pub struct Zipper<T, S = u32> {
+    focus: Rc<PersistentNode<T, S>>,
+    /// The ancestors of the focus, each with the location of the next node down.
+    path: Vec<(Rc<PersistentNode<T, S>>, Location)>,
+}
+
+impl<T, S> Zipper<T, S>
+where
+    T: Clone,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the focused node's bounds.
+    pub fn bounds(&self) -> Bounds<S> {
+        self.focus.bounds
+    }
+
+    /// Returns the focused node's item.
+    pub fn item(&self) -> &T {
+        &self.focus.item
+    }
+
+    /// Returns the focused node's level.
+    pub fn level(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Returns true if the focused node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.focus.children.is_none()
+    }
+
+    /// Moves to a child of the focused node. Returns false and stays if it's a leaf.
+    pub fn down(&mut self, location: Location) -> bool {
+        let Some(children) = &self.focus.children else {
+            return false;
+        };
+        let child = Rc::clone(&children[location as usize]);
+        let parent = std::mem::replace(&mut self.focus, child);
+        self.path.push((parent, location));
+        true
+    }
+
+    /// Moves to the parent of the focused node. Returns false and stays at the root.
+    pub fn up(&mut self) -> bool {
+        let Some((mut parent, location)) = self.path.pop() else {
+            return false;
+        };
+        let child = std::mem::replace(&mut self.focus, Rc::clone(&parent));
+        let children = parent.children.as_ref().expect("ancestors have children");
+        // Only copy the parent if the focus was edited.
+        if !Rc::ptr_eq(&children[location as usize], &child) {
+            Rc::make_mut(&mut parent)
+                .children
+                .as_mut()
+                .expect("ancestors have children")[location as usize] = child;
+            self.focus = parent;
+        }
+        true
+    }
+
+    /// Moves to the leaf containing the point, starting from the focused node's subtree and
+    /// climbing up until it contains the point. Returns false, leaving it at the root, if the
+    /// point is outside the tree.
+    pub fn seek(&mut self, point: Point<S>) -> bool {
+        let contains = |bounds: Bounds<S>| {
+            bounds.0 <= point.0 && point.0 < bounds.2 && bounds.1 <= point.1 && point.1 < bounds.3
+        };
+        while !contains(self.bounds()) {
+            if !self.up() {
+                return false;
+            }
+        }
+        while let Some(children) = &self.focus.children {
+            let Some(location) = (0..4).find(|&i| contains(children[i].bounds)) else {
+                break;
+            };
+            self.down(Location::try_from(location).expect("locations are 0 to 3"));
+        }
+        true
+    }
+
+    /// Replaces the focused node's item.
+    pub fn set_item(&mut self, item: T) -> T {
+        std::mem::replace(&mut Rc::make_mut(&mut self.focus).item, item)
+    }
+
+    /// Subdivides the focused leaf, giving its children `items` in the order NW, NE, SW, SE.
+    /// Fails with [`Error::AlreadySubdivided`] if it isn't a leaf, or [`Error::CellTooSmall`]
+    /// if a child would be empty.
+    pub fn subdivide(&mut self, items: [T; 4]) -> Result<(), Error> {
+        if !self.is_leaf() {
+            return Err(Error::AlreadySubdivided);
+        }
+        let bounds = self.bounds();
+        let mut all_bounds = [bounds; 4];
+        for (i, child) in all_bounds.iter_mut().enumerate() {
+            *child = child_bounds(bounds, i).ok_or(Error::UnitConversion)?;
+            if !(child.0 < child.2 && child.1 < child.3) {
+                return Err(Error::CellTooSmall);
+            }
+        }
+        let mut items = items.into_iter();
+        Rc::make_mut(&mut self.focus).children = Some(all_bounds.map(|bounds| {
+            Rc::new(PersistentNode {
+                bounds,
+                item: items.next().expect("four items"),
+                children: None,
+            })
+        }));
+        Ok(())
+    }
+
+    /// Removes every descendant of the focused node, turning it into a leaf.
+    pub fn collapse(&mut self) {
+        if !self.is_leaf() {
+            Rc::make_mut(&mut self.focus).children = None;
+        }
+    }
+
+    /// Replaces the focused subtree with the root of `subtree`, sharing it. Fails with
+    /// [`Error::BoundsMismatch`] if their bounds differ.
+    pub fn replace(&mut self, subtree: &PersistentQuadtree<T, S>) -> Result<(), Error> {
+        if subtree.bounds() != self.bounds() {
+            return Err(Error::BoundsMismatch);
+        }
+        self.focus = Rc::clone(&subtree.root);
+        Ok(())
+    }
+
+    /// Returns the edited version of the tree.
+    pub fn finish(mut self) -> PersistentQuadtree<T, S> {
+        while self.up() {}
+        PersistentQuadtree { root: self.focus }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zipper() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        let original = tree.to_persistent();
+
+        let mut zipper = original.zipper();
+        assert!(zipper.down(Location::SouthEast));
+        assert_eq!(zipper.set_item(9), 4);
+        assert!(zipper.up());
+        assert!(zipper.seek((1, 1)));
+        assert_eq!((zipper.item(), zipper.level()), (&5, 2));
+        assert!(!zipper.down(Location::NorthWest));
+        zipper.subdivide([10, 11, 12, 13]).unwrap();
+        assert!(zipper.seek((9, 1)));
+        assert_eq!(zipper.item(), &2);
+        let edited = zipper.finish();
+
+        // The original is untouched, and both share the subtree that wasn't edited.
+        assert_eq!(original.item_at((15, 15)), Some(&4));
+        assert_eq!(edited.item_at((15, 15)), Some(&9));
+        assert_eq!(original.leaf_count(), 7);
+        assert_eq!(edited.leaf_count(), 10);
+        let (Some(a), Some(b)) = (&original.root.children, &edited.root.children) else {
+            panic!("roots have children");
+        };
+        assert!(Rc::ptr_eq(&a[1], &b[1]) && Rc::ptr_eq(&a[2], &b[2]));
+        assert!(!Rc::ptr_eq(&a[0], &b[0]) && !Rc::ptr_eq(&a[3], &b[3]));
+
+        // Grafting the original's NW over the edited one's.
+        let nw = {
+            let mut zipper = original.zipper();
+            zipper.down(Location::NorthWest);
+            PersistentQuadtree {
+                root: Rc::clone(&zipper.focus),
+            }
+        };
+        let mut zipper = edited.zipper();
+        zipper.down(Location::NorthWest);
+        zipper.replace(&nw).unwrap();
+        assert_eq!(zipper.replace(&original).err(), Some(Error::BoundsMismatch));
+        let grafted = zipper.finish();
+        assert_eq!(grafted.leaf_count(), 7);
+
+        let converted = grafted.to_tree().unwrap();
+        assert_eq!(converted.leaves_morton().count(), 7);
+        let leaf = converted.point_locate((15, 15)).unwrap();
+        assert_eq!(*converted.get_node(leaf).unwrap().get_item(), 9);
+
+        let mut zipper = PersistentQuadtree::new(0, (0, 0, 1, 1)).zipper();
+        assert_eq!(zipper.subdivide([0; 4]), Err(Error::CellTooSmall));
+        assert!(!zipper.seek((2, 2)));
+    }
+}
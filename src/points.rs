@@ -0,0 +1,628 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode, SplitPolicy};
+use crate::slottree::CNQuadtree;
+use crate::transform::is_integer;
+use crate::tree::{Containment, RegionQuadtree};
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// The points stored in a leaf of a point quadtree, each with its payload.
+pub type Bucket<P, S> = Vec<(Point<S>, P)>;
+
+impl<P, S> CNQuadtree<Bucket<P, S>, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Bulk-loads a bucket PR quadtree. Leaves holding more than `capacity_per_leaf` points are
+    /// subdivided and their points moved into the children, until they fit, reach `max_depth`,
+    /// or are too small to split. Internal nodes hold empty buckets. The tree's maximum depth is
+    /// set to `max_depth`. Fails with [`Error::PointOutOfBounds`] if a point is outside `bounds`.
+    ///
+    /// Nodes are split without neighbor upkeep and linked once at the end, and the points are
+    /// partitioned in place and only moved into buckets once their leaves are known.
+    pub fn from_points<I>(
+        bounds: impl Into<Bounds<S>>,
+        max_depth: usize,
+        capacity_per_leaf: usize,
+        points: I,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (Point<S>, P)>,
+    {
+        let bounds = bounds.into();
+        let mut tree = Self::new(Vec::new(), bounds);
+        tree.set_max_depth(Some(max_depth));
+        let root = tree.get_root();
+        let mut points: Bucket<P, S> = points.into_iter().collect();
+        if points.iter().any(|&(point, _)| !bounds.contains(point)) {
+            return Err(Error::PointOutOfBounds);
+        }
+
+        // Each node's points are a range of `points`, which splitting reorders so that every
+        // child's points follow each other.
+        let mut leaves = Vec::new();
+        let mut stack = vec![(root, 0..points.len())];
+        while let Some((index, range)) = stack.pop() {
+            let level = tree.get_node(index).ok_or(Error::DanglingIndex)?.level();
+            if range.len() <= capacity_per_leaf || level >= max_depth {
+                leaves.push((index, range));
+                continue;
+            }
+            match tree.push_children(index, Default::default()) {
+                Ok(children) => {
+                    let ends = tree.partition_in_place(children, &mut points[range.clone()])?;
+                    let mut start = range.start;
+                    for (child, end) in children.into_iter().zip(ends) {
+                        stack.push((child, start..range.start + end));
+                        start = range.start + end;
+                    }
+                }
+                Err(Error::CellTooSmall) => leaves.push((index, range)),
+                Err(e) => return Err(e),
+            }
+        }
+        // A new tree splits with the default policy, whose neighbors the rebuild can follow.
+        tree.rebuild_neighbors()?;
+
+        // The leaves' ranges cover the points without overlapping, so in order they hand each
+        // point to its bucket with a single move.
+        leaves.sort_unstable_by_key(|(_, range)| range.start);
+        let mut points = points.into_iter();
+        for (index, range) in leaves {
+            tree.set_bucket(index, points.by_ref().take(range.len()).collect())?;
+        }
+
+        Ok(tree)
+    }
+
+    fn set_bucket(&mut self, index: NodeId, points: Bucket<P, S>) -> Result<(), Error> {
+        *self
+            .get_node_mut(index)
+            .ok_or(Error::DanglingIndex)?
+            .get_item_mut() = points;
+        Ok(())
+    }
+
+    /// Returns the median of the points to split a leaf at under [`SplitPolicy::Median`], for
+    /// each axis where it lies strictly inside the leaf. None under other policies.
+    fn bucket_median(&self, index: NodeId, points: &[(Point<S>, P)]) -> Option<Point<S>> {
+        if self.split_policy() != SplitPolicy::Median || points.is_empty() {
+            return None;
+        }
+        let Bounds(left, top, right, bottom) = self.get_node(index)?.get_bounds();
+        let median = |mut values: Vec<S>, min: S, max: S| {
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let median = values[values.len() / 2];
+            if min < median && median < max {
+                Some(median)
+            } else {
+                SplitPolicy::Floor.split(min, max)
+            }
+        };
+        Some((
+            median(points.iter().map(|(p, _)| p.0).collect(), left, right)?,
+            median(points.iter().map(|(p, _)| p.1).collect(), top, bottom)?,
+        ))
+    }
+
+    /// Subdivides a leaf with empty buckets at `middle`, or where the split policy chooses if
+    /// None or if `middle` leaves a child too small.
+    fn split_bucket(
+        &mut self,
+        index: NodeId,
+        middle: Option<Point<S>>,
+    ) -> Result<[NodeId; 4], Error> {
+        if let Some(middle) = middle {
+            match self.subdivide_at(index, middle, Default::default()) {
+                Err(e) if e.source == Error::CellTooSmall => {}
+                result => return result.map_err(|e| e.source),
+            }
+        }
+        self.subdivide(index, Default::default())
+            .map_err(|e| e.source)
+    }
+
+    /// Splits the points among the children containing them, in the children's order.
+    pub(crate) fn partition(
+        &self,
+        children: [NodeId; 4],
+        points: Bucket<P, S>,
+    ) -> Result<[Bucket<P, S>; 4], Error> {
+        let Bounds(_, _, x_middle, y_middle) = self
+            .get_node(children[0])
+            .ok_or(Error::DanglingIndex)?
+            .get_bounds();
+        let mut buckets: [Bucket<P, S>; 4] = Default::default();
+        for (point, payload) in points {
+            let east = point.0 >= x_middle;
+            let south = point.1 >= y_middle;
+            buckets[east as usize | ((south as usize) << 1)].push((point, payload));
+        }
+        Ok(buckets)
+    }
+
+    /// Reorders the points so that those of each child follow each other, in the children's
+    /// order, and returns where each child's points end.
+    fn partition_in_place(
+        &self,
+        children: [NodeId; 4],
+        points: &mut [(Point<S>, P)],
+    ) -> Result<[usize; 4], Error> {
+        let Bounds(_, _, x_middle, y_middle) = self
+            .get_node(children[0])
+            .ok_or(Error::DanglingIndex)?
+            .get_bounds();
+        let north = partition_by(points, |&(point, _)| point.1 < y_middle);
+        let (northern, southern) = points.split_at_mut(north);
+        let north_west = partition_by(northern, |&(point, _)| point.0 < x_middle);
+        let south_west = partition_by(southern, |&(point, _)| point.0 < x_middle);
+        Ok([
+            north_west,
+            north,
+            north + south_west,
+            north + southern.len(),
+        ])
+    }
+}
+
+/// Moves the elements satisfying `f` before the others and returns how many there are.
+fn partition_by<E, F>(elements: &mut [E], mut f: F) -> usize
+where
+    F: FnMut(&E) -> bool,
+{
+    let mut split = 0;
+    // Swapping unconditionally avoids a branch that random points mispredict half the time.
+    for i in 0..elements.len() {
+        let keep = f(&elements[i]);
+        elements.swap(split, i);
+        split += keep as usize;
+    }
+    split
+}
+
+/// A bucket PR quadtree. Leaves hold up to `capacity` points; inserting past that subdivides the
+/// leaf and moves its points into the children. Removing points merges siblings that fit in one
+/// bucket again. The underlying [`CNQuadtree`] keeps its cardinal neighbors, so neighbor
+/// queries on [`CNPointQuadtree::tree`] work as usual.
+#[derive(Clone, Debug)]
+pub struct CNPointQuadtree<P, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: CNQuadtree<Bucket<P, S>, S>,
+    capacity: usize,
+    len: usize,
+}
+
+impl<P, S> CNPointQuadtree<P, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// The number of points a leaf holds in trees collected from an iterator.
+    pub const DEFAULT_CAPACITY: usize = 8;
+
+    /// Returns an empty tree whose leaves hold up to `capacity` points.
+    pub fn new(bounds: impl Into<Bounds<S>>, capacity: usize) -> Self {
+        let bounds = bounds.into();
+        Self {
+            tree: CNQuadtree::new(Vec::new(), bounds),
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Bulk-loads a tree. See [`CNQuadtree::from_points`].
+    pub fn from_points<I>(
+        bounds: Bounds<S>,
+        max_depth: usize,
+        capacity: usize,
+        points: I,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (Point<S>, P)>,
+    {
+        let tree = CNQuadtree::from_points(bounds, max_depth, capacity, points)?;
+        let len = tree.buckets().map(|bucket| bucket.len()).sum();
+        Ok(Self {
+            tree,
+            capacity,
+            len,
+        })
+    }
+
+    /// Returns the underlying region quadtree.
+    #[inline]
+    pub fn tree(&self) -> &CNQuadtree<Bucket<P, S>, S> {
+        &self.tree
+    }
+
+    /// Sets where full buckets are split, such as at the median of their points with
+    /// [`SplitPolicy::Median`]. Existing leaves keep their bounds.
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.tree.set_split_policy(policy);
+    }
+
+    /// Returns the number of points a leaf holds before it's subdivided.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of points.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no points.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts a point and returns the leaf it ends up in. Leaves that can't be subdivided due to
+    /// the tree's minimum cell size or maximum depth hold more than `capacity` points. Fails with
+    /// [`Error::PointOutOfBounds`] if the point is outside the tree's bounds.
+    pub fn insert(&mut self, point: Point<S>, payload: P) -> Result<NodeId, Error> {
+        let mut index = self
+            .tree
+            .point_locate(point)
+            .ok_or(Error::PointOutOfBounds)?;
+        self.tree
+            .get_node_mut(index)
+            .ok_or(Error::DanglingIndex)?
+            .get_item_mut()
+            .push((point, payload));
+        self.len += 1;
+
+        loop {
+            let node = self.tree.get_node(index).ok_or(Error::DanglingIndex)?;
+            if node.get_item().len() <= self.capacity {
+                return Ok(index);
+            }
+            let middle = self.tree.bucket_median(index, node.get_item());
+            let children = match self.tree.split_bucket(index, middle) {
+                Ok(children) => children,
+                Err(Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes) => {
+                    return Ok(index)
+                }
+                Err(e) => return Err(e),
+            };
+            let points = std::mem::take(
+                self.tree
+                    .get_node_mut(index)
+                    .ok_or(Error::DanglingIndex)?
+                    .get_item_mut(),
+            );
+            let buckets = self.tree.partition(children, points)?;
+            for (child, bucket) in children.into_iter().zip(buckets) {
+                self.tree.set_bucket(child, bucket)?;
+            }
+            index = self.tree.point_locate(point).ok_or(Error::DanglingIndex)?;
+        }
+    }
+
+    /// Removes a point at the given position and returns its payload, or None if there is no
+    /// point there. Siblings whose points fit in one bucket afterwards are merged.
+    pub fn remove(&mut self, point: Point<S>) -> Option<P> {
+        self.remove_where(point, |_| true)
+    }
+
+    /// Like [`CNPointQuadtree::remove`], but only removes a point whose payload satisfies `f`.
+    /// Use it to pick one of several points sharing a position.
+    pub fn remove_where<F>(&mut self, point: Point<S>, mut f: F) -> Option<P>
+    where
+        F: FnMut(&P) -> bool,
+    {
+        let index = self.tree.point_locate(point)?;
+        let bucket = self.tree.get_node_mut(index)?.get_item_mut();
+        let position = bucket
+            .iter()
+            .position(|(p, payload)| *p == point && f(payload))?;
+        let (_, payload) = bucket.swap_remove(position);
+        self.len -= 1;
+        // The point is gone from the tree, so it's returned even if merging stops early.
+        self.merge_up(index);
+        Some(payload)
+    }
+
+    /// Merges the leaf's family into their parent while their points fit in one bucket, then
+    /// the parent's, and so on up the tree.
+    fn merge_up(&mut self, mut index: NodeId) -> Option<()> {
+        while let Some(parent) = self.tree.get_node(index)?.get_parent_index() {
+            let children = self.tree.get_node(parent)?.get_children_index()?;
+            let mut total = 0;
+            for child in children {
+                let child = self.tree.get_node(child)?;
+                if child.has_children() {
+                    return Some(());
+                }
+                total += child.get_item().len();
+            }
+            if total > self.capacity {
+                break;
+            }
+
+            let buckets = self.tree.pop_children(parent).ok()?;
+            let merged = self.tree.get_node_mut(parent)?.get_item_mut();
+            merged.extend(buckets.into_iter().flatten());
+            index = parent;
+        }
+        Some(())
+    }
+
+    /// Returns the points inside the region, along with their payloads.
+    pub fn query_region(
+        &self,
+        region: impl Into<Bounds<S>>,
+    ) -> impl Iterator<Item = &(Point<S>, P)> {
+        let region = region.into();
+        let Bounds(left, top, right, bottom) = region;
+        self.tree
+            .region_iter(region)
+            .filter_map(|(index, containment)| Some((self.tree.get_node(index)?, containment)))
+            .flat_map(move |(node, containment)| {
+                node.get_item().iter().filter(move |((x, y), _)| {
+                    containment == Containment::FullyInside
+                        || (left <= *x && *x < right && top <= *y && *y < bottom)
+                })
+            })
+    }
+
+    /// Returns every point along with its payload.
+    pub fn iter(&self) -> impl Iterator<Item = &(Point<S>, P)> {
+        self.tree.buckets().flatten()
+    }
+}
+
+/// Inserts every point like [`CNPointQuadtree::insert`].
+///
+/// # Panics
+///
+/// Panics if a point is outside the tree's bounds.
+impl<P, S> Extend<(Point<S>, P)> for CNPointQuadtree<P, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn extend<I: IntoIterator<Item = (Point<S>, P)>>(&mut self, points: I) {
+        for (point, payload) in points {
+            if let Err(e) = self.insert(point, payload) {
+                panic!("couldn't insert point: {e}");
+            }
+        }
+    }
+}
+
+/// Collects points into a tree whose leaves hold up to
+/// [`CNPointQuadtree::DEFAULT_CAPACITY`] points. The tree's bounds are the smallest ones
+/// containing every point, extended by one on the right and bottom to take in the points on
+/// those edges. Bounds can't extend past the largest value of the coordinate type, so they stop
+/// there and points with that coordinate are left out.
+impl<P, S> FromIterator<(Point<S>, P)> for CNPointQuadtree<P, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn from_iter<I: IntoIterator<Item = (Point<S>, P)>>(points: I) -> Self {
+        let points: Vec<_> = points.into_iter().collect();
+        let bounds = points.iter().fold(None, |bounds, &((x, y), _)| {
+            let Bounds(left, top, right, bottom) = bounds.unwrap_or(Bounds(x, y, x, y));
+            Some(Bounds(
+                if x < left { x } else { left },
+                if y < top { y } else { top },
+                if x > right { x } else { right },
+                if y > bottom { y } else { bottom },
+            ))
+        });
+        let Bounds(left, top, right, bottom) =
+            bounds.unwrap_or(Bounds(S::zero(), S::zero(), S::zero(), S::zero()));
+        let bounds = Bounds(left, top, one_past(right), one_past(bottom));
+        let mut tree = Self::new(bounds, Self::DEFAULT_CAPACITY);
+        tree.extend(
+            points
+                .into_iter()
+                .filter(|&(point, _)| bounds.contains(point)),
+        );
+        tree
+    }
+}
+
+/// Returns the value plus one, or the value itself if that would overflow an integer type.
+fn one_past<S>(value: S) -> S
+where
+    S: Copy + NumAssign + ToPrimitive + FromPrimitive,
+{
+    if !is_integer::<S>() {
+        return value + S::one();
+    }
+    let next = match value.to_i128() {
+        Some(value) => value.checked_add(1).and_then(S::from_i128),
+        None => value
+            .to_u128()
+            .and_then(|value| value.checked_add(1))
+            .and_then(S::from_u128),
+    };
+    next.unwrap_or(value)
+}
+
+/// Consumes the tree and yields every point along with its payload.
+impl<P, S> IntoIterator for CNPointQuadtree<P, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Item = (Point<S>, P);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Bucket<P, S>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tree
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+    }
+}
+
+impl<P, S> CNQuadtree<Bucket<P, S>, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the buckets of every node. Only leaves hold points.
+    fn buckets(&self) -> impl Iterator<Item = &Bucket<P, S>> {
+        self.level_order()
+            .into_iter()
+            .filter_map(|index| self.get_node(index).map(|n| n.get_item()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Cardinality;
+
+    #[test]
+    fn from_points() {
+        let points = (0..64).flat_map(|x| (0..4).map(move |y| ((x, y * 16), x * 4 + y)));
+        let tree = CNQuadtree::from_points((0, 0, 64, 64), 8, 4, points.clone()).unwrap();
+
+        let mut total = 0;
+        assert!(tree.all_leaves(None, |bounds, bucket| {
+            total += bucket.len();
+            let Bounds(l, t, r, b) = bounds;
+            bucket.len() <= 4
+                && bucket
+                    .iter()
+                    .all(|&((x, y), _)| l <= x && x < r && t <= y && y < b)
+        }));
+        assert_eq!(total, 256);
+        assert_eq!(tree.max_depth(), Some(8));
+
+        // Neighbors linked at the end match the ones kept while inserting point by point.
+        let mut rebuilt = CNPointQuadtree::new((0, 0, 64, 64), 4);
+        rebuilt.extend(points);
+        let rebuilt = rebuilt.tree();
+        for (leaf, rebuilt_leaf) in tree.leaves_morton().zip(rebuilt.leaves_morton()) {
+            for direction in Cardinality::ALL {
+                let bounds = |tree: &CNQuadtree<_, _>, leaf| {
+                    let neighbors = tree.get_neighbors(leaf, direction).unwrap();
+                    neighbors
+                        .iter()
+                        .map(|&n| tree.get_node(n).unwrap().get_bounds())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(bounds(&tree, leaf), bounds(rebuilt, rebuilt_leaf));
+            }
+        }
+        let leaf = tree.point_locate((5, 16)).unwrap();
+        assert!(tree
+            .get_node(leaf)
+            .unwrap()
+            .get_item()
+            .contains(&((5, 16), 21)));
+    }
+
+    #[test]
+    fn from_points_stops_at_limits() {
+        let duplicates = vec![((3, 3), ()); 10];
+        let tree = CNQuadtree::from_points((0, 0, 8, 8), 10, 2, duplicates).unwrap();
+        let leaf = tree.point_locate((3, 3)).unwrap();
+        assert_eq!(tree.get_node(leaf).unwrap().get_bounds(), (3, 3, 4, 4));
+        assert_eq!(tree.get_node(leaf).unwrap().get_item().len(), 10);
+
+        let shallow = CNQuadtree::from_points((0, 0, 8, 8), 1, 2, vec![((3, 3), ()); 10]).unwrap();
+        assert_eq!(shallow.stats().nodes, 5);
+
+        assert_eq!(
+            CNQuadtree::from_points((0, 0, 8, 8), 1, 2, [((8, 0), ())]).unwrap_err(),
+            Error::PointOutOfBounds
+        );
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let mut tree: CNPointQuadtree<char, i32> = [((-4, 2), 'a'), ((10, 7), 'b'), ((3, 3), 'c')]
+            .into_iter()
+            .collect();
+        assert_eq!(tree.tree().bounds(), (-4, 2, 11, 8));
+        assert_eq!(
+            tree.capacity(),
+            CNPointQuadtree::<char, i32>::DEFAULT_CAPACITY
+        );
+        tree.extend((0..10).map(|i| ((i, 5), 'd')));
+        assert_eq!(tree.len(), 13);
+        assert!(tree.tree()[tree.tree().get_root()].has_children());
+
+        let mut points: Vec<_> = tree.into_iter().collect();
+        points.sort();
+        assert_eq!(points.len(), 13);
+        assert_eq!(points[0], ((-4, 2), 'a'));
+        assert_eq!(points[12], ((10, 7), 'b'));
+    }
+
+    #[test]
+    fn collect_at_type_limits() {
+        let tree: CNPointQuadtree<char, u8> = [((0, 3), 'a'), ((u8::MAX, 7), 'b'), ((9, 9), 'c')]
+            .into_iter()
+            .collect();
+        assert_eq!(tree.tree().bounds(), (0, 3, u8::MAX, 10));
+        assert_eq!(tree.len(), 2);
+
+        let tree: CNPointQuadtree<(), u32> = [((u32::MAX, u32::MAX), ())].into_iter().collect();
+        assert_eq!(
+            tree.tree().bounds(),
+            (u32::MAX, u32::MAX, u32::MAX, u32::MAX)
+        );
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn median_splits() {
+        let mut tree = CNPointQuadtree::new((0, 0, 64, 64), 2);
+        tree.set_split_policy(SplitPolicy::Median);
+        for (i, point) in [(1, 1), (3, 2), (5, 3)].into_iter().enumerate() {
+            tree.insert(point, i).unwrap();
+        }
+        let leaf = tree.tree().point_locate((1, 1)).unwrap();
+        assert_eq!(
+            tree.tree().get_node(leaf).unwrap().get_bounds(),
+            (0, 0, 3, 2)
+        );
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.remove((5, 3)), Some(2));
+    }
+
+    #[test]
+    fn point_quadtree() {
+        let mut tree = CNPointQuadtree::new((0, 0, 32, 32), 3);
+        let points: Vec<_> = (0..100).map(|i| ((i % 10) * 3, (i / 10) * 3)).collect();
+        for (i, &point) in points.iter().enumerate() {
+            tree.insert(point, i).unwrap();
+        }
+        assert_eq!(tree.insert((32, 0), 0), Err(Error::PointOutOfBounds));
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.iter().count(), 100);
+        assert!(tree.tree().all_leaves(None, |_, bucket| bucket.len() <= 3));
+
+        let region = (4, 8, 20, 30);
+        let mut found: Vec<_> = tree.query_region(region).map(|&(_, i)| i).collect();
+        found.sort();
+        let expected: Vec<_> = (0..100)
+            .filter(|&i| {
+                let (x, y) = points[i];
+                (4..20).contains(&x) && (8..30).contains(&y)
+            })
+            .collect();
+        assert_eq!(found, expected);
+
+        for (i, &point) in points.iter().enumerate() {
+            assert_eq!(tree.remove(point), Some(i));
+        }
+        assert_eq!(tree.remove((0, 0)), None);
+        assert!(tree.is_empty());
+        assert_eq!(tree.tree().stats().nodes, 1);
+
+        tree.insert((3, 3), 1).unwrap();
+        tree.insert((3, 3), 2).unwrap();
+        assert_eq!(tree.remove_where((3, 3), |&p| p == 2), Some(2));
+        assert_eq!(tree.remove_where((3, 3), |&p| p == 2), None);
+        assert_eq!(tree.len(), 1);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::Error;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// How a cell lies relative to a polygon.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Coverage {
+    Outside,
+    Inside,
+    Boundary,
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Rasterizes a polygon into the tree, setting the cells it covers to `value`. Cells entirely
+    /// inside become single leaves, collapsing any descendants. Leaves crossed by the outline are
+    /// subdivided down to `max_level`, with children copying their parent's item, and leaves at
+    /// that level are painted if their center is inside. Cells outside are untouched.
+    ///
+    /// `polygon` lists the vertices, closed from the last back to the first. Self-intersecting
+    /// polygons are filled by the even-odd rule. Fails with [`Error::UnitConversion`] if a
+    /// coordinate can't be converted to `f64`.
+    pub fn paint_polygon(
+        &mut self,
+        polygon: &[Point<S>],
+        value: T,
+        max_level: usize,
+    ) -> Result<(), Error>
+    where
+        T: Clone,
+    {
+        let polygon = polygon
+            .iter()
+            .map(|&(x, y)| Some((x.to_f64()?, y.to_f64()?)))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::UnitConversion)?;
+        if polygon.len() < 3 {
+            return Ok(());
+        }
+
+        let mut stack = vec![self.get_root()];
+        while let Some(index) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            let level = node.level();
+            let bounds = self.bounds_f64(index).ok_or(Error::UnitConversion)?;
+            let paint = match coverage(&polygon, bounds) {
+                Coverage::Outside => false,
+                Coverage::Inside => {
+                    self.collapse(index)?;
+                    true
+                }
+                Coverage::Boundary => match node.get_children_index() {
+                    Some(children) => {
+                        stack.extend(children);
+                        continue;
+                    }
+                    None if level < max_level => {
+                        let items = [(); 4].map(|_| node.get_item().clone());
+                        match self.subdivide(index, items) {
+                            Ok(children) => {
+                                stack.extend(children);
+                                continue;
+                            }
+                            Err(e) => match e.source {
+                                Error::CellTooSmall
+                                | Error::MaxDepthExceeded
+                                | Error::OutOfNodes => {}
+                                e => return Err(e),
+                            },
+                        }
+                        contains(&polygon, center(bounds))
+                    }
+                    None => contains(&polygon, center(bounds)),
+                },
+            };
+            if paint {
+                let node = self.get_node_mut(index).ok_or(Error::DanglingIndex)?;
+                *node.get_item_mut() = value.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn center(Bounds(left, top, right, bottom): Bounds<f64>) -> Point<f64> {
+    ((left + right) / 2.0, (top + bottom) / 2.0)
+}
+
+/// Classifies a cell against a polygon. Edges running along the cell's border don't cross it.
+pub(crate) fn coverage(polygon: &[Point<f64>], bounds: Bounds<f64>) -> Coverage {
+    let edges = polygon.iter().zip(polygon.iter().cycle().skip(1));
+    if edges
+        .into_iter()
+        .any(|(&a, &b)| segment_crosses(a, b, bounds))
+    {
+        Coverage::Boundary
+    } else if contains(polygon, center(bounds)) {
+        Coverage::Inside
+    } else {
+        Coverage::Outside
+    }
+}
+
+/// Returns true if the point is inside the polygon by the even-odd rule.
+fn contains(polygon: &[Point<f64>], (x, y): Point<f64>) -> bool {
+    let mut inside = false;
+    for (&(x0, y0), &(x1, y1)) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Returns true if the segment passes through the rectangle's interior. The segment is clipped
+/// to the rectangle by Liang-Barsky; the clipped part is a chord, so its midpoint is interior
+/// unless it only runs along the border.
+fn segment_crosses(
+    (ax, ay): Point<f64>,
+    (bx, by): Point<f64>,
+    Bounds(left, top, right, bottom): Bounds<f64>,
+) -> bool {
+    let (dx, dy) = (bx - ax, by - ay);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-dx, ax - left),
+        (dx, right - ax),
+        (-dy, ay - top),
+        (dy, bottom - ay),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return false;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 > t1 {
+        return false;
+    }
+    let t = (t0 + t1) / 2.0;
+    let (x, y) = (ax + t * dx, ay + t * dy);
+    left < x && x < right && top < y && y < bottom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_polygon() {
+        let mut tree = CNQuadtree::new('.', (0, 0, 16, 16));
+        // Some existing structure inside the polygon is collapsed.
+        let [_, _, _, se] = tree.subdivide(tree.get_root(), ['.'; 4]).unwrap();
+        tree.subdivide(se, ['.'; 4]).unwrap();
+
+        let triangle = [(0, 0), (16, 0), (16, 16)];
+        tree.paint_polygon(&triangle, '#', 2).unwrap();
+        assert_eq!(
+            tree.render_ascii(8, 8, |&c| c),
+            "########\n\
+             ########\n\
+             ..######\n\
+             ..######\n\
+             ....####\n\
+             ....####\n\
+             ......##\n\
+             ......##\n"
+        );
+        // The NE quadrant is inside, so it stays a single leaf.
+        let ne = tree.point_locate((12, 2)).unwrap();
+        assert_eq!(tree.get_node(ne).unwrap().level(), 1);
+        // NW and SE are split once by the diagonal; SW only touches it at a corner.
+        assert_eq!(tree.stats().leaves, 1 + 4 + 1 + 4);
+
+        tree.paint_polygon(&[(0, 0), (1, 1)], '?', 4).unwrap();
+        assert!(tree.all_leaves(None, |_, &c| c != '?'));
+    }
+}
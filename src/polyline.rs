@@ -0,0 +1,267 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the leaves crossed by the path through `points`, in the order the path enters
+    /// them, stepping between leaves through cardinal neighbors. A leaf is listed again if the
+    /// path comes back to it, but never twice in a row. Paths through a corner shared by four
+    /// leaves cross one of the two leaves beside it. Returns None if a point is outside the
+    /// tree's bounds or `points` is empty.
+    pub fn locate_polyline(&self, points: &[Point<S>]) -> Option<Vec<NodeId>> {
+        let (&first, rest) = points.split_first()?;
+        let mut leaf = self.point_locate(first)?;
+        let mut result = vec![leaf];
+        let mut from = first;
+        for &to in rest {
+            self.walk_segment(leaf, from, to, |next| {
+                result.push(next);
+                true
+            })?;
+            // The walk may end beside the leaf holding the point if it lies on a boundary.
+            let end = self.relocate(*result.last()?, to)?;
+            if Some(&end) != result.last() {
+                result.push(end);
+            }
+            leaf = end;
+            from = to;
+        }
+        Some(result)
+    }
+
+    /// Returns whether the segment from `a` to `b` crosses a leaf whose item `blocks`, including
+    /// the leaves holding the endpoints. Stops at the first such leaf and doesn't allocate.
+    /// Returns None if an endpoint is outside the tree's bounds.
+    pub fn segment_blocked<F>(&self, a: Point<S>, b: Point<S>, mut blocks: F) -> Option<bool>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let start = self.point_locate(a)?;
+        let end = self.point_locate(b)?;
+        let mut blocked = blocks(self.get_node(start)?.get_item());
+        if !blocked {
+            self.walk_segment(start, a, b, |leaf| {
+                blocked = self
+                    .get_node(leaf)
+                    .is_some_and(|node| blocks(node.get_item()));
+                !blocked
+            })?;
+        }
+        // The walk stops short of the leaf holding `b` if `b` lies on its boundary.
+        if !blocked && end != start {
+            blocked = blocks(self.get_node(end)?.get_item());
+        }
+        Some(blocked)
+    }
+
+    /// Walks the leaves crossed by the segment from `a`, in leaf `from`, to `b`, calling `visit`
+    /// with each leaf entered after `from` until it returns false. Returns the last leaf
+    /// entered, or None if a coordinate can't be converted to `f64` or the walk leaves the
+    /// tree or fails to make progress.
+    pub(crate) fn walk_segment<F>(
+        &self,
+        from: NodeId,
+        a: Point<S>,
+        b: Point<S>,
+        mut visit: F,
+    ) -> Option<NodeId>
+    where
+        F: FnMut(NodeId) -> bool,
+    {
+        let (ax, ay) = (a.0.to_f64()?, a.1.to_f64()?);
+        let (dx, dy) = (b.0.to_f64()? - ax, b.1.to_f64()? - ay);
+        let mut leaf = from;
+        // A straight segment enters each leaf at most once, so a longer walk went astray.
+        for _ in 0..=self.capacity() {
+            let Bounds(left, top, right, bottom) = self.bounds_f64(leaf)?;
+            // Parameter along the segment at which it leaves the leaf through each axis.
+            let exit = |d: f64, min: f64, max: f64, start: f64| {
+                if d > 0.0 {
+                    (max - start) / d
+                } else if d < 0.0 {
+                    (min - start) / d
+                } else {
+                    f64::INFINITY
+                }
+            };
+            let tx = exit(dx, left, right, ax);
+            let ty = exit(dy, top, bottom, ay);
+            let t = tx.min(ty);
+            if t >= 1.0 {
+                return Some(leaf);
+            }
+
+            let (direction, along, forward) = if tx <= ty {
+                let direction = if dx > 0.0 {
+                    Cardinality::East
+                } else {
+                    Cardinality::West
+                };
+                (direction, ay + t * dy, dy >= 0.0)
+            } else {
+                let direction = if dy > 0.0 {
+                    Cardinality::South
+                } else {
+                    Cardinality::North
+                };
+                (direction, ax + t * dx, dx >= 0.0)
+            };
+            // Through a corner, the neighbor sharing it with this leaf is entered first.
+            let forward = forward != (tx == ty);
+            let range = |n: NodeId| {
+                let Bounds(l, t, r, b) = self.bounds_f64(n)?;
+                Some(match direction {
+                    Cardinality::West | Cardinality::East => (t, b),
+                    Cardinality::North | Cardinality::South => (l, r),
+                })
+            };
+            let contains = |&n: &NodeId| {
+                range(n).is_some_and(|(min, max)| {
+                    if forward {
+                        min <= along && along < max
+                    } else {
+                        min < along && along <= max
+                    }
+                })
+            };
+            // Rounding can put the crossing just outside the chain, so fall back to the
+            // nearest neighbor.
+            let distance = |&n: &NodeId| {
+                range(n).map_or(f64::INFINITY, |(min, max)| {
+                    (min - along).max(along - max).max(0.0)
+                })
+            };
+            let next = match self.neighbors_iter(leaf, direction).find(contains) {
+                Some(next) => next,
+                None => self
+                    .neighbors_iter(leaf, direction)
+                    .min_by(|a, b| distance(a).total_cmp(&distance(b)))?,
+            };
+            leaf = next;
+            if !visit(leaf) {
+                return Some(leaf);
+            }
+        }
+        None
+    }
+
+    /// Returns a node's bounds converted to `f64`.
+    pub(crate) fn bounds_f64(&self, index: NodeId) -> Option<Bounds<f64>> {
+        let Bounds(left, top, right, bottom) = self.get_node(index)?.get_bounds();
+        Some(Bounds(
+            left.to_f64()?,
+            top.to_f64()?,
+            right.to_f64()?,
+            bottom.to_f64()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_polyline() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let [ne_nw, ne_ne, ne_sw, ne_se] = tree.subdivide(ne, [0; 4]).unwrap();
+
+        // Straight across the top half, then down into SE and back west.
+        assert_eq!(
+            tree.locate_polyline(&[(1, 1), (7, 1), (7, 7), (1, 7)]),
+            Some(vec![nw, ne_nw, ne_ne, ne_se, se, sw])
+        );
+        // A diagonal through the center corner.
+        assert_eq!(
+            tree.locate_polyline(&[(1, 1), (7, 7)]),
+            Some(vec![nw, ne_sw, se])
+        );
+        // Leaving NE's SE child to the west.
+        assert_eq!(
+            tree.locate_polyline(&[(7, 3), (5, 3)]),
+            Some(vec![ne_se, ne_sw])
+        );
+        assert_eq!(tree.locate_polyline(&[(1, 1)]), Some(vec![nw]));
+        assert_eq!(tree.locate_polyline(&[(1, 1), (9, 1)]), None);
+        assert_eq!(tree.locate_polyline(&[]), None);
+    }
+
+    #[test]
+    fn segment_blocked() {
+        let mut tree = CNQuadtree::new(false, (0, 0, 8, 8));
+        let [_, ne, ..] = tree.subdivide(tree.get_root(), [false; 4]).unwrap();
+        let [_, _, _, ne_se] = tree.subdivide(ne, [false; 4]).unwrap();
+        *tree.get_node_mut(ne_se).unwrap().get_item_mut() = true;
+
+        let blocks = |&wall: &bool| wall;
+        assert_eq!(tree.segment_blocked((1, 1), (7, 1), blocks), Some(false));
+        assert_eq!(tree.segment_blocked((1, 3), (7, 3), blocks), Some(true));
+        assert_eq!(tree.segment_blocked((7, 7), (7, 1), blocks), Some(true));
+        // Through the center corner, beside the wall.
+        assert_eq!(tree.segment_blocked((1, 1), (7, 7), blocks), Some(false));
+        assert_eq!(tree.segment_blocked((7, 3), (7, 3), blocks), Some(true));
+        assert_eq!(tree.segment_blocked((1, 1), (9, 1), blocks), None);
+    }
+
+    #[test]
+    fn locate_polyline_random() {
+        let mut seed = 7u64;
+        let mut next = |bound: i32| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % bound as u64) as i32
+        };
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        for _ in 0..60 {
+            let leaf = tree.point_locate((next(64), next(64))).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+        for _ in 0..10 {
+            let leaf = tree.point_locate((next(64), next(64))).unwrap();
+            *tree.get_node_mut(leaf).unwrap().get_item_mut() = 1;
+        }
+        for leaf in tree.leaves_morton() {
+            for direction in [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ] {
+                assert_eq!(
+                    tree.neighbors_iter(leaf, direction).collect::<Vec<_>>(),
+                    tree.get_neighbors(leaf, direction)
+                        .unwrap_or_default()
+                        .into_vec()
+                );
+            }
+        }
+
+        for _ in 0..200 {
+            let (a, b) = ((next(64), next(64)), (next(64), next(64)));
+            let leaves = tree.locate_polyline(&[a, b]).unwrap();
+            assert_eq!(leaves.first(), tree.point_locate(a).as_ref());
+            assert_eq!(leaves.last(), tree.point_locate(b).as_ref());
+            let blocked = tree.segment_blocked(a, b, |&item| item != 0).unwrap();
+            assert_eq!(
+                blocked,
+                leaves
+                    .iter()
+                    .any(|&leaf| *tree.get_node(leaf).unwrap().get_item() != 0)
+            );
+            // Consecutive leaves touch.
+            for pair in leaves.windows(2) {
+                let Bounds(l0, t0, r0, b0) = tree.get_node(pair[0]).unwrap().get_bounds();
+                let Bounds(l1, t1, r1, b1) = tree.get_node(pair[1]).unwrap().get_bounds();
+                assert!(l0 <= r1 && l1 <= r0 && t0 <= b1 && t1 <= b0);
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::binary::{invalid_data, read_array, read_u64, read_unit, write_unit, ItemCodec};
+use crate::id::NodeId;
+use crate::node::{child_bounds, Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Write};
+
+/// Magic bytes at the start of every progressively encoded tree.
+pub const PROGRESSIVE_MAGIC: [u8; 4] = *b"CNQP";
+/// Version of the format written by [`CNQuadtree::save_progressive_to`].
+pub const PROGRESSIVE_VERSION: u16 = 1;
+
+// Layout of version 1, all integers little endian:
+//
+//   magic                  4 bytes
+//   version                u16
+//   min cell size          unit
+//   max depth              u64, u64::MAX if unlimited
+//   root bounds            4 units
+//   root item
+//   records                one per node in level order, each level in Morton order: a byte,
+//                          1 if the node has children and 0 if not, followed by the items of
+//                          its four children if it has them
+//
+// Units are encoded as in the format of `CNQuadtree::save_to`. Every record refines the tree
+// decoded so far, so a prefix of the stream is a coarser approximation of the whole.
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Writes the tree breadth-first, so that a [`ProgressiveDecoder`] can show progressively
+    /// finer approximations as bytes arrive. Internal nodes' items are written before their
+    /// children, so they stand in for their subtrees until those arrive. Like
+    /// [`CNQuadtree::save_to`], trees with splits other than
+    /// [`SplitPolicy::Floor`](crate::SplitPolicy::Floor)'s fail with
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn save_progressive_to<W, C>(&self, writer: &mut W, codec: &mut C) -> io::Result<()>
+    where
+        W: Write,
+        C: ItemCodec<T>,
+    {
+        let order = self.level_order();
+        for &index in &order {
+            let Some(node) = self.get_node(index) else {
+                continue;
+            };
+            if let Some([nw, ..]) = node.get_children_index() {
+                if self.get_node(nw).map(|n| n.get_bounds()) != child_bounds(node.get_bounds(), 0) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "tree isn't split at midpoints",
+                    ));
+                }
+            }
+        }
+
+        let dangling = || invalid_data("dangling index");
+        let root = self.get_node(self.get_root()).ok_or_else(dangling)?;
+        writer.write_all(&PROGRESSIVE_MAGIC)?;
+        writer.write_all(&PROGRESSIVE_VERSION.to_le_bytes())?;
+        write_unit(writer, self.min_cell_size())?;
+        let max_depth = self.max_depth().map_or(u64::MAX, |d| d as u64);
+        writer.write_all(&max_depth.to_le_bytes())?;
+        let Bounds(left, top, right, bottom) = root.get_bounds();
+        for unit in [left, top, right, bottom] {
+            write_unit(writer, unit)?;
+        }
+        codec.encode(root.get_item(), writer)?;
+
+        for index in order {
+            let node = self.get_node(index).ok_or_else(dangling)?;
+            match node.get_children_index() {
+                None => writer.write_all(&[0])?,
+                Some(children) => {
+                    writer.write_all(&[1])?;
+                    for child in children {
+                        let child = self.get_node(child).ok_or_else(dangling)?;
+                        codec.encode(child.get_item(), writer)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a tree written by [`CNQuadtree::save_progressive_to`] from bytes fed as they arrive.
+/// After each [`ProgressiveDecoder::feed`] the tree decoded so far is a complete, coarser
+/// approximation of the encoded one, with working neighbors, that can be displayed until finer
+/// levels arrive.
+///
+/// A record cut off by the end of the bytes fed so far is decoded again from its start once
+/// more bytes arrive, so the codec must not keep state between items.
+pub struct ProgressiveDecoder<T, S, C>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    codec: C,
+    /// Bytes fed but not decoded yet.
+    buffer: Vec<u8>,
+    tree: Option<CNQuadtree<T, S>>,
+    /// Minimum cell size and maximum depth, applied once the tree is complete.
+    settings: Option<(S, Option<usize>)>,
+    /// Nodes whose records are still to come, in stream order.
+    queue: VecDeque<NodeId>,
+}
+
+impl<T, S, C> ProgressiveDecoder<T, S, C>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    C: ItemCodec<T>,
+{
+    /// Returns a decoder that hasn't been fed any bytes.
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            buffer: Vec::new(),
+            tree: None,
+            settings: None,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Decodes as much of the stream as `bytes` completes, keeping any partial record for the
+    /// next call. Returns the number of nodes decoded so far. Fails with
+    /// [`io::ErrorKind::InvalidData`] if the stream is corrupt or continues past the tree, after
+    /// which the decoder shouldn't be fed again.
+    pub fn feed(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.extend_from_slice(bytes);
+        let mut rest = buffer.as_slice();
+        loop {
+            let mut record = rest;
+            match self.decode_next(&mut record) {
+                Ok(true) => rest = record,
+                Ok(false) => break,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let consumed = buffer.len() - rest.len();
+        buffer.drain(..consumed);
+        self.buffer = buffer;
+        if self.is_done() && !self.buffer.is_empty() {
+            return Err(invalid_data("bytes past the end of the tree"));
+        }
+        Ok(self.tree.as_ref().map_or(0, |tree| tree.node_count()))
+    }
+
+    /// Decodes the header or the next record from `reader`. Returns false if the tree is
+    /// complete.
+    fn decode_next(&mut self, reader: &mut &[u8]) -> io::Result<bool> {
+        let Some(tree) = &mut self.tree else {
+            if read_array::<4>(reader)? != PROGRESSIVE_MAGIC {
+                return Err(invalid_data("not a progressively encoded quadtree"));
+            }
+            if u16::from_le_bytes(read_array(reader)?) != PROGRESSIVE_VERSION {
+                return Err(invalid_data("unsupported format version"));
+            }
+            let min_cell_size = read_unit(reader)?;
+            let max_depth = match read_u64(reader)? {
+                u64::MAX => None,
+                d => Some(usize::try_from(d).map_err(|_| invalid_data("max depth is too large"))?),
+            };
+            let bounds = Bounds(
+                read_unit(reader)?,
+                read_unit(reader)?,
+                read_unit(reader)?,
+                read_unit(reader)?,
+            );
+            let tree = CNQuadtree::new(self.codec.decode(reader)?, bounds);
+            self.queue.push_back(tree.get_root());
+            self.settings = Some((min_cell_size, max_depth));
+            self.tree = Some(tree);
+            return Ok(true);
+        };
+        let Some(&index) = self.queue.front() else {
+            return Ok(false);
+        };
+
+        match read_array::<1>(reader)? {
+            [0] => {}
+            [1] => {
+                let items = [
+                    self.codec.decode(reader)?,
+                    self.codec.decode(reader)?,
+                    self.codec.decode(reader)?,
+                    self.codec.decode(reader)?,
+                ];
+                let children = tree
+                    .subdivide(index, items)
+                    .map_err(|e| invalid_data(&e.source.to_string()))?;
+                self.queue.extend(children);
+            }
+            _ => return Err(invalid_data("invalid node flag")),
+        }
+        self.queue.pop_front();
+        if self.queue.is_empty() {
+            if let Some((min_cell_size, max_depth)) = self.settings {
+                tree.set_min_cell_size(min_cell_size);
+                tree.set_max_depth(max_depth);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the tree decoded so far, or None until the header and root have arrived.
+    #[inline]
+    pub fn tree(&self) -> Option<&CNQuadtree<T, S>> {
+        self.tree.as_ref()
+    }
+
+    /// Returns true once every node has been decoded.
+    #[inline]
+    pub fn is_done(&self) -> bool {
+        self.tree.is_some() && self.queue.is_empty()
+    }
+
+    /// Returns the decoded tree. Fails with [`io::ErrorKind::UnexpectedEof`] if it isn't
+    /// complete.
+    pub fn finish(self) -> io::Result<CNQuadtree<T, S>> {
+        match self.tree {
+            Some(tree) if self.queue.is_empty() => Ok(tree),
+            _ => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "stream ended before the tree was complete",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::LeBytes;
+
+    #[test]
+    fn progressive_round_trip() {
+        let mut tree = CNQuadtree::<u16, i32>::new(0, (0, 0, 64, 64));
+        tree.set_max_depth(Some(5));
+        let [nw, _, _, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        let [se_nw, ..] = tree.subdivide(se, [9, 10, 11, 12]).unwrap();
+        tree.subdivide(se_nw, [13, 14, 15, 16]).unwrap();
+
+        let mut bytes = Vec::new();
+        tree.save_progressive_to(&mut bytes, &mut LeBytes).unwrap();
+
+        // Fed a byte at a time, the tree only ever gets finer.
+        let mut decoder = ProgressiveDecoder::<u16, i32, _>::new(LeBytes);
+        let mut counts = Vec::new();
+        for byte in &bytes {
+            let count = decoder.feed(&[*byte]).unwrap();
+            if counts.last() != Some(&count) {
+                counts.push(count);
+            }
+            // The root's item stands in for the south-east quadrant until it arrives.
+            if let Some(partial) = decoder.tree() {
+                let expected = if count == 1 { 0 } else { 4 };
+                assert_eq!(partial.item_at_level((40, 40), 1), Some(&expected));
+            }
+        }
+        assert_eq!(counts, [0, 1, 5, 9, 13, 17]);
+        assert!(decoder.is_done());
+        let decoded = decoder.finish().unwrap();
+        assert!(decoded == tree);
+        assert_eq!(decoded.max_depth(), Some(5));
+
+        let mut decoder = ProgressiveDecoder::<u16, i32, _>::new(LeBytes);
+        decoder.feed(&bytes[..bytes.len() - 1]).unwrap();
+        // Only the last leaf's flag is missing.
+        assert_eq!(decoder.tree().unwrap().node_count(), 17);
+        assert!(!decoder.is_done());
+        assert_eq!(
+            decoder.finish().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+
+        let mut decoder = ProgressiveDecoder::<u16, i32, _>::new(LeBytes);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decoder.feed(&trailing).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        let mut corrupt = bytes;
+        corrupt[0] = b'X';
+        let mut decoder = ProgressiveDecoder::<u16, i32, _>::new(LeBytes);
+        assert!(decoder.feed(&corrupt).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::Error;
+use crate::node::RegionQuadtreeNode;
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a raster for each level from 0 to `max_level`, the one at `level` being a
+    /// `2^level` by `2^level` grid of items in row-major order, top row first. Each cell takes
+    /// the item of the node at its level covering it, so coarser levels show the items internal
+    /// nodes hold, such as aggregates of their children, or the item of a shallower leaf. Cells
+    /// follow the tree's structure as in [`CNQuadtree::to_rle_rows`].
+    ///
+    /// The tree is walked once for all levels and every cell is written once. Fails with
+    /// [`Error::Overflow`] if a raster's size doesn't fit in a `usize`.
+    pub fn to_pyramid(&self, max_level: usize) -> Result<Vec<Vec<T>>, Error>
+    where
+        T: Clone,
+    {
+        let side = |level: usize| {
+            u32::try_from(level)
+                .ok()
+                .and_then(|level| 1usize.checked_shl(level))
+                .filter(|side| side.checked_mul(*side).is_some())
+                .ok_or(Error::Overflow)
+        };
+        side(max_level)?;
+        let mut pyramid: Vec<Vec<Option<T>>> = (0..=max_level)
+            .map(|level| Ok(vec![None; side(level)?.pow(2)]))
+            .collect::<Result<_, Error>>()?;
+
+        let mut stack = vec![(self.get_root(), 0, 0)];
+        while let Some((index, x, y)) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            let level = node.level();
+            let item = node.get_item();
+            pyramid[level][y * side(level)? + x] = Some(item.clone());
+            match node.get_children_index() {
+                Some(children) if level < max_level => {
+                    for (digit, child) in children.into_iter().enumerate() {
+                        stack.push((child, 2 * x + (digit & 1), 2 * y + (digit >> 1)));
+                    }
+                }
+                Some(_) => {}
+                // A leaf covers a block of every finer raster.
+                None => {
+                    for (finer, raster) in pyramid.iter_mut().enumerate().skip(level + 1) {
+                        let (size, side) = (1 << (finer - level), side(finer)?);
+                        for row in y * size..(y + 1) * size {
+                            for cell in &mut raster[row * side + x * size..][..size] {
+                                *cell = Some(item.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        pyramid
+            .into_iter()
+            .map(|raster| raster.into_iter().collect::<Option<Vec<T>>>())
+            .collect::<Option<_>>()
+            .ok_or(Error::InvalidTiling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_pyramid() {
+        // Internal nodes hold the sum of their children.
+        let mut tree = CNQuadtree::new(10, (0, 0, 8, 8));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [4, 1, 2, 3]).unwrap();
+        tree.subdivide(nw, [1, 1, 1, 1]).unwrap();
+
+        let pyramid = tree.to_pyramid(2).unwrap();
+        assert_eq!(pyramid.len(), 3);
+        assert_eq!(pyramid[0], [10]);
+        assert_eq!(pyramid[1], [4, 1, 2, 3]);
+        #[rustfmt::skip]
+        assert_eq!(pyramid[2], [
+            1, 1, 1, 1,
+            1, 1, 1, 1,
+            2, 2, 3, 3,
+            2, 2, 3, 3,
+        ]);
+
+        // Levels past the tree's depth repeat the leaves.
+        let pyramid = tree.to_pyramid(3).unwrap();
+        assert_eq!(pyramid[3].len(), 64);
+        assert_eq!(pyramid[3][63], 3);
+        assert_eq!(tree.to_pyramid(0).unwrap(), [vec![10]]);
+        assert_eq!(tree.to_pyramid(usize::MAX).unwrap_err(), Error::Overflow);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+
+/// What a [`RefinementCriterion`] wants done with a leaf.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Refinement {
+    /// Subdivide the leaf.
+    Refine,
+    /// Leave the leaf as is.
+    Keep,
+    /// Merge the leaf with its siblings. Only done if all four siblings are leaves that want to
+    /// be coarsened.
+    Coarsen,
+}
+
+/// Decides how leaves are adapted by [`CNQuadtree::refine_until_stable`].
+pub trait RefinementCriterion<T, S = u32> {
+    /// Returns what to do with a leaf.
+    fn evaluate(&self, bounds: Bounds<S>, item: &T) -> Refinement;
+
+    /// Returns the items of the children of a leaf being refined, in the order NW, NE, SW, SE.
+    fn split(&self, bounds: Bounds<S>, item: &T) -> [T; 4];
+
+    /// Combines the items of merged children into their parent's item. The parent keeps its
+    /// item from before it was subdivided by default.
+    fn merge(&self, bounds: Bounds<S>, item: &mut T, children: [T; 4]) {
+        let _ = (bounds, item, children);
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Adapts the tree to `criterion` in passes. Each pass evaluates every leaf once, subdivides
+    /// the leaves to refine and merges the families to coarsen; children created in a pass are
+    /// evaluated in the next. Leaves that can't be subdivided due to the tree's minimum cell size
+    /// or maximum depth are kept. Returns true if a pass left the tree unchanged within
+    /// `max_passes`, false if the criterion still wanted changes.
+    pub fn refine_until_stable<C>(
+        &mut self,
+        criterion: &C,
+        max_passes: usize,
+    ) -> Result<bool, Error>
+    where
+        C: RefinementCriterion<T, S> + ?Sized,
+    {
+        for _ in 0..max_passes {
+            if !self.refine_pass(criterion)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Runs one pass of [`CNQuadtree::refine_until_stable`]. Returns true if the tree changed.
+    fn refine_pass<C>(&mut self, criterion: &C) -> Result<bool, Error>
+    where
+        C: RefinementCriterion<T, S> + ?Sized,
+    {
+        let leaves: Vec<NodeId> = self.leaves_morton().collect();
+        let mut changed = false;
+        // Parents and how many of their children want to be coarsened. Siblings are adjacent
+        // in Morton order, so each family's votes are counted in one run.
+        let mut votes: Vec<(NodeId, usize)> = Vec::new();
+
+        for leaf in leaves {
+            let node = self.get_node(leaf).ok_or(Error::DanglingIndex)?;
+            let bounds = node.get_bounds();
+            match criterion.evaluate(bounds, node.get_item()) {
+                Refinement::Refine => {
+                    let items = criterion.split(bounds, node.get_item());
+                    match self.subdivide(leaf, items) {
+                        Ok(_) => changed = true,
+                        Err(e) => match e.source {
+                            Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes => {}
+                            e => return Err(e),
+                        },
+                    }
+                }
+                Refinement::Keep => {}
+                Refinement::Coarsen => {
+                    let Some(parent) = node.get_parent_index() else {
+                        continue;
+                    };
+                    match votes.last_mut() {
+                        Some((last, count)) if *last == parent => *count += 1,
+                        _ => votes.push((parent, 1)),
+                    }
+                }
+            }
+        }
+
+        for (parent, count) in votes {
+            if count < 4 {
+                continue;
+            }
+            let children = self.pop_children(parent)?;
+            let node = self.get_node_mut(parent).ok_or(Error::DanglingIndex)?;
+            let bounds = node.get_bounds();
+            criterion.merge(bounds, node.get_item_mut(), children);
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Subdivides the tree top-down until every leaf reaches the level `level_fn` returns for its
+    /// bounds, for example from an importance raster. Leaves are only refined, never merged, so
+    /// `level_fn` should return the finest level wanted anywhere within the bounds. `split`
+    /// returns the items of the children of a leaf being refined, in the order NW, NE, SW, SE.
+    /// Leaves that can't be subdivided due to the tree's minimum cell size, maximum depth or
+    /// node limit are kept. Returns how many leaves were subdivided.
+    ///
+    /// Neighbors are updated once at the end as in [`CNQuadtree::begin_batch`], or left for the
+    /// commit if the tree is already in a batch.
+    pub fn refine_to_levels<L, F>(&mut self, level_fn: L, mut split: F) -> Result<usize, Error>
+    where
+        L: Fn(&Bounds<S>) -> usize,
+        F: FnMut(Bounds<S>, &T) -> [T; 4],
+    {
+        let batched = self.is_batching();
+        self.begin_batch();
+        let mut refined = 0;
+        let mut stack: Vec<NodeId> = self.leaves_morton().collect();
+        let result = loop {
+            let Some(leaf) = stack.pop() else {
+                break Ok(refined);
+            };
+            let Some(node) = self.get_node(leaf) else {
+                break Err(Error::DanglingIndex);
+            };
+            let bounds = node.get_bounds();
+            if node.level() >= level_fn(&bounds) {
+                continue;
+            }
+            let items = split(bounds, node.get_item());
+            match self.subdivide(leaf, items) {
+                Ok(children) => {
+                    refined += 1;
+                    stack.extend(children);
+                }
+                Err(e) => match e.source {
+                    Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes => {}
+                    e => break Err(e),
+                },
+            }
+        };
+        if !batched {
+            self.commit();
+        }
+        result
+    }
+}
+
+/// Refines leaves in order of priority, a bounded number at a time, so refinement can be spread
+/// over frames. Priorities come from a closure over a leaf's bounds and item; leaves with no
+/// priority are never refined. Children created by a refinement are queued with their own
+/// priorities.
+///
+/// Priorities are checked again when a leaf reaches the front of the queue, so lowered
+/// priorities take effect on their own. Leaves whose priority rose, and nodes that became leaves
+/// outside the refiner, for example by [`RegionQuadtree::pop_children`], must be queued with
+/// [`PriorityRefiner::push`].
+pub struct PriorityRefiner<T, S, P, F> {
+    queue: BinaryHeap<Queued>,
+    priority: P,
+    split: F,
+    marker: PhantomData<fn(&T, Bounds<S>)>,
+}
+
+/// A queued leaf. Ordered by priority, then by id so the order is deterministic.
+#[derive(Copy, Clone, Debug)]
+struct Queued {
+    priority: f64,
+    leaf: NodeId,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .total_cmp(&other.priority)
+            .then_with(|| other.leaf.cmp(&self.leaf))
+    }
+}
+
+impl<T, S, P, F> PriorityRefiner<T, S, P, F>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    P: FnMut(Bounds<S>, &T) -> Option<f64>,
+    F: FnMut(Bounds<S>, &T) -> [T; 4],
+{
+    /// Returns a refiner with every leaf of `tree` queued. `split` returns the items of the
+    /// children of a leaf being refined, in the order NW, NE, SW, SE.
+    pub fn new(tree: &CNQuadtree<T, S>, priority: P, split: F) -> Self {
+        let mut refiner = Self {
+            queue: BinaryHeap::new(),
+            priority,
+            split,
+            marker: PhantomData,
+        };
+        for leaf in tree.leaves_morton() {
+            refiner.push(tree, leaf);
+        }
+        refiner
+    }
+
+    /// Returns the number of queued leaves, including ones that turn out to be stale.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns true if no leaf is queued.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues a leaf with its current priority. Does nothing if it isn't a leaf of `tree` or has
+    /// no priority.
+    pub fn push(&mut self, tree: &CNQuadtree<T, S>, leaf: NodeId) {
+        if let Some(priority) = self.evaluate(tree, leaf) {
+            self.queue.push(Queued { priority, leaf });
+        }
+    }
+
+    /// Subdivides up to `budget` leaves with the highest priority and returns how many were
+    /// subdivided. Leaves that can't be subdivided due to the tree's minimum cell size or
+    /// maximum depth are dropped from the queue without counting against the budget.
+    pub fn refine_n(&mut self, tree: &mut CNQuadtree<T, S>, budget: usize) -> Result<usize, Error> {
+        let mut refined = 0;
+        while refined < budget {
+            let Some(Queued { priority, leaf }) = self.queue.pop() else {
+                break;
+            };
+            match self.evaluate(tree, leaf) {
+                Some(current) if current == priority => {}
+                Some(current) => {
+                    self.queue.push(Queued {
+                        priority: current,
+                        leaf,
+                    });
+                    continue;
+                }
+                None => continue,
+            }
+
+            let node = tree.get_node(leaf).ok_or(Error::DanglingIndex)?;
+            let items = (self.split)(node.get_bounds(), node.get_item());
+            match tree.subdivide(leaf, items) {
+                Ok(children) => {
+                    refined += 1;
+                    for child in children {
+                        self.push(tree, child);
+                    }
+                }
+                Err(e) => match e.source {
+                    Error::CellTooSmall | Error::MaxDepthExceeded | Error::OutOfNodes => {}
+                    e => return Err(e),
+                },
+            }
+        }
+        Ok(refined)
+    }
+
+    /// Returns the leaf's priority, or None if it isn't a leaf of `tree` or has none.
+    fn evaluate(&mut self, tree: &CNQuadtree<T, S>, leaf: NodeId) -> Option<f64> {
+        let node = tree.get_node(leaf).filter(|node| node.is_leaf())?;
+        (self.priority)(node.get_bounds(), node.get_item()).filter(|p| !p.is_nan())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Refines cells overlapping a disc's border down to `size`, and coarsens the others.
+    struct Circle {
+        center: (i32, i32),
+        radius: i32,
+        size: i32,
+    }
+
+    impl RefinementCriterion<u32, i32> for Circle {
+        fn evaluate(&self, Bounds(l, t, r, b): Bounds<i32>, _: &u32) -> Refinement {
+            let (x, y) = self.center;
+            let nearest = (x.clamp(l, r) - x).pow(2) + (y.clamp(t, b) - y).pow(2);
+            let farthest =
+                (x - l).abs().max((x - r).abs()).pow(2) + (y - t).abs().max((y - b).abs()).pow(2);
+            let radius = self.radius * self.radius;
+            let crosses = nearest < radius && radius < farthest;
+            if crosses && r - l > self.size {
+                Refinement::Refine
+            } else if crosses {
+                Refinement::Keep
+            } else {
+                Refinement::Coarsen
+            }
+        }
+
+        fn split(&self, _: Bounds<i32>, &item: &u32) -> [u32; 4] {
+            [item + 1; 4]
+        }
+
+        fn merge(&self, _: Bounds<i32>, item: &mut u32, children: [u32; 4]) {
+            *item = children.iter().sum();
+        }
+    }
+
+    #[test]
+    fn refine_until_stable() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut circle = Circle {
+            center: (32, 32),
+            radius: 20,
+            size: 4,
+        };
+        assert!(tree.refine_until_stable(&circle, 10).unwrap());
+        assert!(tree.all_leaves(None, |bounds, _| {
+            circle.evaluate(bounds, &0) != Refinement::Refine
+        }));
+        let refined = tree.stats().nodes;
+        assert!(refined > 1);
+
+        assert!(!tree.refine_until_stable(&circle, 0).unwrap());
+
+        circle.radius = 0;
+        assert!(tree.refine_until_stable(&circle, 10).unwrap());
+        assert_eq!(tree.stats().nodes, 1);
+        assert_ne!(*tree.get_node(tree.get_root()).unwrap().get_item(), 0);
+    }
+
+    #[test]
+    fn refine_n() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        tree.set_max_depth(Some(3));
+        // Larger cells nearer the top-left corner come first, unless their item is set.
+        let priority = |Bounds(l, t, r, _): Bounds<i32>, &item: &u32| {
+            Some(f64::from(r - l - l - t) + f64::from(item) * 1000.0)
+        };
+        let mut refiner = PriorityRefiner::new(&tree, priority, |_, &item| [item + 1; 4]);
+
+        assert_eq!(refiner.refine_n(&mut tree, 2).unwrap(), 2);
+        assert_eq!(tree.stats().nodes, 9);
+        let nw = tree.point_locate((0, 0)).unwrap();
+        assert_eq!(tree.get_node(nw).unwrap().level(), 2);
+
+        let leaf = tree.point_locate((63, 63)).unwrap();
+        *tree.get_node_mut(leaf).unwrap().get_item_mut() = 5;
+        refiner.push(&tree, leaf);
+        assert_eq!(refiner.refine_n(&mut tree, 1).unwrap(), 1);
+        assert!(tree.get_node(leaf).unwrap().has_children());
+        assert_eq!(refiner.refine_n(&mut tree, 100).unwrap(), 21 - 3);
+        assert_eq!(refiner.refine_n(&mut tree, 100).unwrap(), 0);
+        assert!(refiner.is_empty());
+        assert_eq!(tree.stats().leaves, 64);
+    }
+
+    #[test]
+    fn refine_to_levels() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        tree.set_max_depth(Some(4));
+        // Finer towards the top-left corner, with a target past the maximum depth.
+        let level = |&Bounds(l, t, _, _): &Bounds<i32>| if l + t < 16 { 9 } else { 2 };
+        let refined = tree
+            .refine_to_levels(level, |_, &item| [item + 1; 4])
+            .unwrap();
+        assert!(!tree.is_batching());
+        assert_eq!(refined, tree.node_count() / 4);
+        assert_eq!(tree.height(), 4);
+        let corner = tree.point_locate((0, 0)).unwrap();
+        assert_eq!(tree.get_node(corner).unwrap().get_item(), &4);
+        let far = tree.point_locate((63, 63)).unwrap();
+        assert_eq!(tree.get_node(far).unwrap().level(), 2);
+
+        for leaf in tree.leaves_morton() {
+            let node = tree.get_node(leaf).unwrap();
+            let Bounds(l, t, r, b) = node.get_bounds();
+            assert_eq!(
+                node.get_cardinal_neighbors_index(),
+                [
+                    tree.point_locate((l - 1, t)),
+                    tree.point_locate((l, t - 1)),
+                    tree.point_locate((r, b - 1)),
+                    tree.point_locate((r - 1, b)),
+                ]
+            );
+        }
+        assert_eq!(tree.refine_to_levels(level, |_, _| [0; 4]), Ok(0));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::location::Location;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::fmt::{Debug, Display, Write};
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Renders the leaves as a `width` by `height` character grid, one line per row. Each
+    /// character is `glyph` of the item of the leaf containing the center of its cell. Cells
+    /// whose center can't be represented by the coordinate type are blank.
+    pub fn render_ascii<F>(&self, width: usize, height: usize, glyph: F) -> String
+    where
+        F: FnMut(&T) -> char,
+    {
+        self.render_ascii_within(self.bounds(), width, height, glyph)
+    }
+
+    /// Same as [`CNQuadtree::render_ascii`] but the grid spans `bounds` instead of the tree.
+    pub(crate) fn render_ascii_within<F>(
+        &self,
+        bounds: Bounds<S>,
+        width: usize,
+        height: usize,
+        mut glyph: F,
+    ) -> String
+    where
+        F: FnMut(&T) -> char,
+    {
+        let mut out = String::with_capacity((width + 1) * height);
+        let extent = {
+            let Bounds(left, top, right, bottom) = bounds;
+            left.to_f64()
+                .zip(top.to_f64())
+                .zip(right.to_f64().zip(bottom.to_f64()))
+        };
+        for row in 0..height {
+            for column in 0..width {
+                let item = extent.and_then(|((left, top), (right, bottom))| {
+                    let x = left + (column as f64 + 0.5) * (right - left) / width as f64;
+                    let y = top + (row as f64 + 0.5) * (bottom - top) / height as f64;
+                    let leaf = self.point_locate((S::from_f64(x)?, S::from_f64(y)?))?;
+                    self.get_node(leaf).map(|node| node.get_item())
+                });
+                out.push(item.map_or(' ', &mut glyph));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders the hierarchy as an indented tree, one node per line with its location among its
+    /// siblings, bounds and `label` of its item. Children are listed in the order NW, NE, SW,
+    /// SE.
+    pub fn render_tree<F, D>(&self, mut label: F) -> String
+    where
+        S: Debug,
+        F: FnMut(&T) -> D,
+        D: Display,
+    {
+        let mut out = String::new();
+        // Node, its location, the prefix of its line, and whether it is the last sibling.
+        let mut stack = vec![(self.get_root(), None, String::new(), true)];
+        while let Some((id, location, prefix, last)) = stack.pop() {
+            let Some(node) = self.get_node(id) else {
+                continue;
+            };
+            let (branch, indent) = match location {
+                None => ("", ""),
+                Some(_) if last => ("└── ", "    "),
+                Some(_) => ("├── ", "│   "),
+            };
+            let name = match location {
+                None => String::from("Root"),
+                Some(location) => format!("{:?}", location),
+            };
+            let _ = writeln!(
+                out,
+                "{}{}{} {:?}: {}",
+                prefix,
+                branch,
+                name,
+                node.get_bounds(),
+                label(node.get_item())
+            );
+            if let Some(children) = node.get_children_index() {
+                let prefix = format!("{}{}", prefix, indent);
+                for (i, child) in children.into_iter().enumerate().rev() {
+                    let location = Location::try_from(i).ok();
+                    stack.push((child, location, prefix.clone(), i == 3));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_ascii() {
+        let mut tree = CNQuadtree::new('.', (0, 0, 8, 8));
+        let children = tree
+            .subdivide(tree.get_root(), ['a', 'b', 'c', 'd'])
+            .unwrap();
+        tree.subdivide(children[3], ['1', '2', '3', '4']).unwrap();
+        assert_eq!(
+            tree.render_ascii(8, 4, |&c| c),
+            "aaaabbbb\n\
+             aaaabbbb\n\
+             cccc1122\n\
+             cccc3344\n"
+        );
+        assert_eq!(tree.render_ascii(2, 2, |&c| c), "ab\nc4\n");
+    }
+
+    #[test]
+    fn render_tree() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let children = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(children[0], [5, 6, 7, 8]).unwrap();
+        assert_eq!(
+            tree.render_tree(|&item| item),
+            "Root (0, 0, 8, 8): 0\n\
+             ├── NorthWest (0, 0, 4, 4): 1\n\
+             │   ├── NorthWest (0, 0, 2, 2): 5\n\
+             │   ├── NorthEast (2, 0, 4, 2): 6\n\
+             │   ├── SouthWest (0, 2, 2, 4): 7\n\
+             │   └── SouthEast (2, 2, 4, 4): 8\n\
+             ├── NorthEast (4, 0, 8, 4): 2\n\
+             ├── SouthWest (0, 4, 4, 8): 3\n\
+             └── SouthEast (4, 4, 8, 8): 4\n"
+        );
+    }
+}
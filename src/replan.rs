@@ -0,0 +1,339 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+/// An incremental path planner (D* Lite) between two leaves, which repairs its path after
+/// items or the tree's structure change instead of planning from scratch, such as for an agent
+/// moving through a map whose occupancy it keeps discovering.
+///
+/// Costs are as in [`CNQuadtree::shortest_path`], with `cost` passed to every call and only
+/// allowed to change for leaves within the regions reported to [`Replanner::update`], such as
+/// the dirty regions handed out by [`Subscriptions::take_dirty`](crate::Subscriptions::take_dirty).
+/// As the agent moves, [`Replanner::move_to`] sets the leaf the path starts from.
+#[derive(Clone, Debug)]
+pub struct Replanner {
+    start: NodeId,
+    goal: NodeId,
+    /// The cost per unit of length of the cheapest leaf, scaling the distance heuristic.
+    min_cost: f64,
+    /// The start when the heuristic was last offset, and the offset.
+    last: NodeId,
+    offset: f64,
+    /// The cost of the cheapest path to the goal from each leaf, as last settled.
+    g: HashMap<NodeId, f64>,
+    /// The cost of the cheapest path to the goal from each leaf, through its neighbors' `g`.
+    rhs: HashMap<NodeId, f64>,
+    /// The current key of each leaf in the queue, as the queue keeps outdated entries.
+    open: HashMap<NodeId, Key>,
+    queue: BinaryHeap<Reverse<(Key, NodeId)>>,
+}
+
+/// The priority of a leaf in the queue, compared lexicographically.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Key(f64, f64);
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then_with(|| self.1.total_cmp(&other.1))
+    }
+}
+
+impl Replanner {
+    /// Returns a planner for paths from `start` to `goal`. `min_cost` must be at most the cost
+    /// of any leaf that can be entered; the higher it is, the fewer leaves are searched, and 0
+    /// searches like Dijkstra's algorithm.
+    pub fn new(start: NodeId, goal: NodeId, min_cost: f64) -> Self {
+        let mut planner = Self {
+            start,
+            goal,
+            min_cost,
+            last: start,
+            offset: 0.0,
+            g: HashMap::new(),
+            rhs: HashMap::from([(goal, 0.0)]),
+            open: HashMap::new(),
+            queue: BinaryHeap::new(),
+        };
+        planner.push(goal, Key(0.0, 0.0));
+        planner
+    }
+
+    /// Returns the leaf paths start from.
+    pub fn start(&self) -> NodeId {
+        self.start
+    }
+
+    /// Returns the leaf paths lead to.
+    pub fn goal(&self) -> NodeId {
+        self.goal
+    }
+
+    /// Makes paths start from `start`, such as the leaf the agent moved into.
+    pub fn move_to<T, S>(&mut self, tree: &CNQuadtree<T, S>, start: NodeId)
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        self.offset += self.heuristic(tree, self.last, start);
+        (self.last, self.start) = (start, start);
+    }
+
+    /// Accounts for changes to the items or structure of the tree within `regions`, such as
+    /// the dirty regions of a [`Subscriptions`](crate::Subscriptions) subscription covering the
+    /// tree. Only the leaves overlapping the regions and their neighbors are updated; the
+    /// search resumes on the next call to [`Replanner::path`].
+    pub fn update<T, S, F>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        regions: impl IntoIterator<Item = Bounds<S>>,
+        cost: F,
+    ) where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(&T) -> Option<f64>,
+    {
+        // Forget leaves that were merged or subdivided.
+        let live = |index: &NodeId| tree.get_node(*index).is_some_and(|n| n.is_leaf());
+        self.g.retain(|index, _| live(index));
+        self.rhs.retain(|index, _| live(index));
+        self.open.retain(|index, _| live(index));
+
+        let mut leaf_cost = tree.cached_leaf_cost(cost);
+        for region in regions {
+            for leaf in tree.region_locate(region).unwrap_or_default() {
+                self.update_leaf(tree, leaf, &mut leaf_cost);
+                for direction in Cardinality::ALL {
+                    for neighbor in tree.neighbors_iter(leaf, direction) {
+                        self.update_leaf(tree, neighbor, &mut leaf_cost);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the cheapest path of edge-adjacent leaves from the start to the goal, both
+    /// included, and its cost, resuming the search from where the last call left it. Returns
+    /// None if the goal can't be reached or a coordinate can't be converted to `f64`.
+    pub fn path<T, S, F>(&mut self, tree: &CNQuadtree<T, S>, cost: F) -> Option<(Vec<NodeId>, f64)>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(&T) -> Option<f64>,
+    {
+        let mut leaf_cost = tree.cached_leaf_cost(cost);
+        leaf_cost(self.start)?;
+        while let Some(&Reverse((key, leaf))) = self.queue.peek() {
+            let start_key = self.key(tree, self.start);
+            if key >= start_key && self.rhs(self.start) == self.g(self.start) {
+                break;
+            }
+            self.queue.pop();
+            if self.open.get(&leaf) != Some(&key) {
+                continue;
+            }
+            let current = self.key(tree, leaf);
+            if key < current {
+                self.push(leaf, current);
+            } else if self.g(leaf) > self.rhs(leaf) {
+                self.g.insert(leaf, self.rhs(leaf));
+                self.open.remove(&leaf);
+                for direction in Cardinality::ALL {
+                    for neighbor in tree.neighbors_iter(leaf, direction) {
+                        self.update_leaf(tree, neighbor, &mut leaf_cost);
+                    }
+                }
+            } else {
+                self.g.remove(&leaf);
+                self.update_leaf(tree, leaf, &mut leaf_cost);
+                for direction in Cardinality::ALL {
+                    for neighbor in tree.neighbors_iter(leaf, direction) {
+                        self.update_leaf(tree, neighbor, &mut leaf_cost);
+                    }
+                }
+            }
+        }
+
+        // Follow the cheapest neighbors down to the goal.
+        let total = self.g(self.start);
+        if total == f64::INFINITY {
+            return None;
+        }
+        let mut path = vec![self.start];
+        let mut leaf = self.start;
+        while leaf != self.goal {
+            let (next, _) = self.cheapest_step(tree, leaf, &mut leaf_cost)?;
+            // The costs settled so far always lead downhill, but guard against a cycle.
+            if path.len() > self.g.len() {
+                return None;
+            }
+            path.push(next);
+            leaf = next;
+        }
+        Some((path, total))
+    }
+
+    /// Recomputes a leaf's `rhs` from its neighbors and queues it if it's inconsistent.
+    fn update_leaf<T, S, F>(&mut self, tree: &CNQuadtree<T, S>, leaf: NodeId, leaf_cost: &mut F)
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(NodeId) -> Option<f64>,
+    {
+        if leaf != self.goal {
+            match self.cheapest_step(tree, leaf, leaf_cost) {
+                Some((_, rhs)) => self.rhs.insert(leaf, rhs),
+                None => self.rhs.remove(&leaf),
+            };
+        }
+        if self.g(leaf) == self.rhs(leaf) {
+            self.open.remove(&leaf);
+        } else {
+            let key = self.key(tree, leaf);
+            self.push(leaf, key);
+        }
+    }
+
+    /// Returns the neighbor of a leaf the cheapest path to the goal continues to, and that
+    /// path's cost, or None if the goal can't be reached through any neighbor.
+    fn cheapest_step<T, S, F>(
+        &self,
+        tree: &CNQuadtree<T, S>,
+        leaf: NodeId,
+        leaf_cost: &mut F,
+    ) -> Option<(NodeId, f64)>
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+        F: FnMut(NodeId) -> Option<f64>,
+    {
+        let here = leaf_cost(leaf)?;
+        let center = tree.center_f64(leaf)?;
+        let mut best: Option<(NodeId, f64)> = None;
+        for direction in Cardinality::ALL {
+            for neighbor in tree.neighbors_iter(leaf, direction) {
+                let (Some(there), Some(next_center)) =
+                    (leaf_cost(neighbor), tree.center_f64(neighbor))
+                else {
+                    continue;
+                };
+                let length = (next_center.0 - center.0).hypot(next_center.1 - center.1);
+                let total = length * (here + there) / 2.0 + self.g(neighbor);
+                if total < f64::INFINITY && best.is_none_or(|(_, b)| total < b) {
+                    best = Some((neighbor, total));
+                }
+            }
+        }
+        best
+    }
+
+    fn key<T, S>(&self, tree: &CNQuadtree<T, S>, leaf: NodeId) -> Key
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        let cost = self.g(leaf).min(self.rhs(leaf));
+        Key(
+            cost + self.heuristic(tree, self.start, leaf) + self.offset,
+            cost,
+        )
+    }
+
+    /// Returns a lower bound of the cost of a path between two leaves.
+    fn heuristic<T, S>(&self, tree: &CNQuadtree<T, S>, a: NodeId, b: NodeId) -> f64
+    where
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        match (tree.center_f64(a), tree.center_f64(b)) {
+            (Some(a), Some(b)) => self.min_cost * (b.0 - a.0).hypot(b.1 - a.1),
+            _ => 0.0,
+        }
+    }
+
+    fn push(&mut self, leaf: NodeId, key: Key) {
+        self.open.insert(leaf, key);
+        self.queue.push(Reverse((key, leaf)));
+    }
+
+    fn g(&self, leaf: NodeId) -> f64 {
+        self.g.get(&leaf).copied().unwrap_or(f64::INFINITY)
+    }
+
+    fn rhs(&self, leaf: NodeId) -> f64 {
+        self.rhs.get(&leaf).copied().unwrap_or(f64::INFINITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Subscriptions;
+
+    #[test]
+    fn replanner() {
+        let mut tree = CNQuadtree::new(1.0, (0, 0, 8, 8));
+        tree.refine_to_levels(|_| 3, |_, &cost| [cost; 4]).unwrap();
+        let grid: Vec<Vec<NodeId>> = (0..8)
+            .map(|y| (0..8).map(|x| tree.point_locate((x, y)).unwrap()).collect())
+            .collect();
+        let cell = |x: usize, y: usize| grid[y][x];
+        let cost = |&cost: &f64| (cost < 10.0).then_some(cost);
+        // Costs are summed in a different order than by `shortest_path`.
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let mut subscriptions = Subscriptions::new((0, 0, 8, 8), 3);
+        subscriptions.subscribe((0, 0, 8, 8));
+
+        let mut planner = Replanner::new(cell(0, 4), cell(7, 4), 1.0);
+        let (path, total) = planner.path(&tree, cost).unwrap();
+        assert_eq!((path.len(), total), (8, 7.0));
+
+        // A wall across the row, open at the top.
+        for row in &grid[1..] {
+            subscriptions.set_item(&mut tree, row[4], 10.0);
+        }
+        for (_, regions) in subscriptions.take_dirty() {
+            planner.update(&tree, regions, cost);
+        }
+        let expected = tree.shortest_path(cell(0, 4), cell(7, 4), cost).unwrap();
+        let (path, total) = planner.path(&tree, cost).unwrap();
+        assert!(close(total, expected.1));
+        assert!(path.contains(&cell(4, 0)));
+        assert!(tree.smooth_path(&path, (0.5, 4.5), (7.5, 4.5)).is_some());
+
+        // After moving, closing the wall cuts the goal off.
+        planner.move_to(&tree, cell(1, 3));
+        let (path, total) = planner.path(&tree, cost).unwrap();
+        assert_eq!(path[0], cell(1, 3));
+        let expected = tree.shortest_path(cell(1, 3), cell(7, 4), cost).unwrap();
+        assert!(close(total, expected.1));
+        subscriptions.set_item(&mut tree, cell(4, 0), 10.0);
+        for (_, regions) in subscriptions.take_dirty() {
+            planner.update(&tree, regions, cost);
+        }
+        assert_eq!(planner.path(&tree, cost), None);
+
+        // Merging cells behind the wall and reopening it.
+        let parent = tree
+            .get_node(cell(6, 6))
+            .unwrap()
+            .get_parent_index()
+            .unwrap();
+        subscriptions.pop_children(&mut tree, parent).unwrap();
+        subscriptions.set_item(&mut tree, cell(4, 7), 1.0);
+        for (_, regions) in subscriptions.take_dirty() {
+            planner.update(&tree, regions, cost);
+        }
+        let expected = tree.shortest_path(cell(1, 3), cell(7, 4), cost).unwrap();
+        let (path, total) = planner.path(&tree, cost).unwrap();
+        assert!(close(total, expected.1));
+        assert!(path.contains(&cell(4, 7)));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::error::Error;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A run of equal cells in a scanline: the item and how many cells it spans.
+pub type Run<T> = (T, usize);
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the leaves as run-length encoded scanlines of a `2^level` by `2^level` grid, top
+    /// row first, with runs from left to right. Each cell takes the item of the leaf covering
+    /// it; a cell split further takes the item of its north-west-most leaf. Cells follow the
+    /// tree's structure, each level halving its parent, so they match the leaves' bounds under
+    /// even splits. Only the runs are built, never the full grid.
+    ///
+    /// Fails with [`Error::Overflow`] if the grid's side doesn't fit in a `usize`.
+    pub fn to_rle_rows(&self, level: usize) -> Result<Vec<Vec<Run<T>>>, Error>
+    where
+        T: Clone + PartialEq,
+    {
+        let side = 1usize
+            .checked_shl(level.try_into().map_err(|_| Error::Overflow)?)
+            .ok_or(Error::Overflow)?;
+        // Spans of each row as `(start, end, item)`, gathered in Morton order.
+        let mut spans: Vec<Vec<(usize, usize, &T)>> = vec![Vec::new(); side];
+        let mut stack = vec![(self.get_root(), 0, 0)];
+        while let Some((index, x, y)) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            match node.get_children_index() {
+                Some(children) if node.level() < level => {
+                    for (digit, child) in children.into_iter().enumerate().rev() {
+                        stack.push((child, 2 * x + (digit & 1), 2 * y + (digit >> 1)));
+                    }
+                }
+                _ => {
+                    let mut leaf = node;
+                    while let Some([nw, ..]) = leaf.get_children_index() {
+                        leaf = self.get_node(nw).ok_or(Error::DanglingIndex)?;
+                    }
+                    let size = side >> node.level();
+                    for row in &mut spans[y * size..(y + 1) * size] {
+                        row.push((x * size, (x + 1) * size, leaf.get_item()));
+                    }
+                }
+            }
+        }
+
+        Ok(spans
+            .into_iter()
+            .map(|mut row| {
+                row.sort_unstable_by_key(|&(start, ..)| start);
+                let mut runs: Vec<Run<T>> = Vec::new();
+                for (start, end, item) in row {
+                    match runs.last_mut() {
+                        Some((last, length)) if last == item => *length += end - start,
+                        _ => runs.push((item.clone(), end - start)),
+                    }
+                }
+                runs
+            })
+            .collect())
+    }
+
+    /// Builds a tree covering `bounds` from run-length encoded scanlines as returned by
+    /// [`CNQuadtree::to_rle_rows`], merging every square block of equal cells into one leaf.
+    /// Internal nodes get the item returned by `default`, which is given the node's bounds.
+    ///
+    /// There must be a power of two rows, each with runs adding up to the number of rows, or
+    /// this fails with [`Error::InvalidTiling`]. Splits follow the tree's default split policy,
+    /// so the bounds' sides should be multiples of the number of rows; subdivisions that fail
+    /// are returned as errors.
+    pub fn from_rle_rows<D>(
+        bounds: impl Into<Bounds<S>>,
+        rows: &[Vec<Run<T>>],
+        mut default: D,
+    ) -> Result<Self, Error>
+    where
+        T: Clone + PartialEq,
+        D: FnMut(Bounds<S>) -> T,
+    {
+        let side = rows.len();
+        if !side.is_power_of_two() {
+            return Err(Error::InvalidTiling);
+        }
+        // Runs of each row as `(end, item)`, so the run holding a cell is found by searching.
+        let mut ends: Vec<Vec<(usize, &T)>> = Vec::with_capacity(side);
+        for row in rows {
+            let mut end = 0;
+            let mut row_ends = Vec::with_capacity(row.len());
+            for (item, length) in row {
+                end += length;
+                row_ends.push((end, item));
+            }
+            if end != side {
+                return Err(Error::InvalidTiling);
+            }
+            ends.push(row_ends);
+        }
+        // Returns the item of the block at `(x, y)` with `size` cells per side, if they're equal.
+        let uniform = |x: usize, y: usize, size: usize| -> Option<&T> {
+            let mut item = None;
+            for row in &ends[y..y + size] {
+                let run = row.partition_point(|&(end, _)| end <= x);
+                let (end, run_item) = row[run];
+                if end < x + size || item.is_some_and(|item| item != run_item) {
+                    return None;
+                }
+                item = Some(run_item);
+            }
+            item
+        };
+
+        let bounds = bounds.into();
+        let root_item = uniform(0, 0, side).cloned();
+        let mut tree = Self::new(root_item.unwrap_or_else(|| default(bounds)), bounds);
+        tree.begin_batch();
+        let mut stack = Vec::new();
+        if uniform(0, 0, side).is_none() {
+            stack.push((tree.get_root(), 0, 0, side));
+        }
+        while let Some((index, x, y, size)) = stack.pop() {
+            // The children's bounds aren't known until after the split.
+            let node_bounds = tree
+                .get_node(index)
+                .ok_or(Error::DanglingIndex)?
+                .get_bounds();
+            let placeholder = [(); 4].map(|_| default(node_bounds));
+            let children = tree.subdivide(index, placeholder).map_err(|e| e.source)?;
+            let half = size / 2;
+            for (digit, child) in children.into_iter().enumerate() {
+                let (cx, cy) = (x + (digit & 1) * half, y + (digit >> 1) * half);
+                let node = tree.get_node_mut(child).ok_or(Error::DanglingIndex)?;
+                match uniform(cx, cy, half) {
+                    Some(item) => *node.get_item_mut() = item.clone(),
+                    None => {
+                        *node.get_item_mut() = default(node.get_bounds());
+                        stack.push((child, cx, cy, half));
+                    }
+                }
+            }
+        }
+        tree.commit();
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 1, 2, 2]).unwrap();
+        tree.subdivide(ne, [3, 1, 1, 1]).unwrap();
+        let [_, _, _, nw_se] = tree.subdivide(nw, [1, 1, 1, 4]).unwrap();
+        tree.subdivide(nw_se, [5, 6, 7, 8]).unwrap();
+
+        let rows = tree.to_rle_rows(2).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![(1, 2), (3, 1), (1, 1)],
+                vec![(1, 1), (5, 1), (1, 2)],
+                vec![(2, 4)],
+                vec![(2, 4)],
+            ]
+        );
+        assert_eq!(tree.to_rle_rows(0).unwrap(), vec![vec![(1, 1)]]);
+
+        let fine = tree.to_rle_rows(3).unwrap();
+        assert_eq!(fine[2], vec![(1, 2), (5, 1), (6, 1), (1, 4)]);
+        let rebuilt = CNQuadtree::from_rle_rows((0, 0, 8, 8), &fine, |_| 0).unwrap();
+        assert_eq!(rebuilt.to_rle_rows(3).unwrap(), fine);
+        // Uniform blocks become single leaves, giving back the original structure.
+        assert_eq!(rebuilt.leaf_count(), 13);
+        let leaf = rebuilt.point_locate((7, 3)).unwrap();
+        assert_eq!(rebuilt.get_node(leaf).unwrap().get_bounds(), (6, 2, 8, 4));
+
+        let uniform = CNQuadtree::from_rle_rows((0, 0, 8, 8), &[vec![(9, 1)]], |_| 0).unwrap();
+        assert_eq!(uniform.node_count(), 1);
+        assert_eq!(
+            CNQuadtree::from_rle_rows((0, 0, 8, 8), &[vec![(9, 2)], vec![(9, 2)]], |_| 0)
+                .unwrap()
+                .node_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_bad_rows() {
+        let bounds = (0, 0, 8, 8);
+        let three = vec![vec![(0, 3)]; 3];
+        assert_eq!(
+            CNQuadtree::from_rle_rows(bounds, &three, |_| 0).unwrap_err(),
+            Error::InvalidTiling
+        );
+        let short = vec![vec![(0, 2)], vec![(0, 1)]];
+        assert_eq!(
+            CNQuadtree::from_rle_rows(bounds, &short, |_| 0).unwrap_err(),
+            Error::InvalidTiling
+        );
+        assert_eq!(
+            CNQuadtree::<i32, i32>::from_rle_rows(bounds, &[], |_| 0).unwrap_err(),
+            Error::InvalidTiling
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashMap;
+
+/// Draws random leaves and points from a tree in `O(depth)` each, from the number and area of
+/// the leaves under every node. Made by [`CNQuadtree::sampler`].
+///
+/// Randomness comes from a closure returning numbers uniformly distributed in `[0, 1)`, such as
+/// `|| rng.random()` with the `rand` crate.
+pub struct LeafSampler<'a, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: &'a CNQuadtree<T, S>,
+    /// The number and total area of the sampled leaves under each node with any.
+    weights: HashMap<NodeId, (usize, f64)>,
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a sampler drawing from the leaves whose item satisfies `predicate`. Returns None
+    /// if a coordinate can't be converted to `f64`.
+    pub fn sampler<F>(&self, mut predicate: F) -> Option<LeafSampler<'_, T, S>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut weights = HashMap::new();
+        // Children come after their parents, so going backwards sums them first.
+        for index in self.level_order().into_iter().rev() {
+            let node = self.get_node(index)?;
+            let weight = match node.get_children_index() {
+                Some(children) => children
+                    .iter()
+                    .filter_map(|child| weights.get(child))
+                    .fold((0, 0.0), |(leaves, area), &(l, a)| (leaves + l, area + a)),
+                None if predicate(node.get_item()) => {
+                    let Bounds(left, top, right, bottom) = self.bounds_f64(index)?;
+                    (1, (right - left) * (bottom - top))
+                }
+                None => continue,
+            };
+            if weight.0 > 0 {
+                weights.insert(index, weight);
+            }
+        }
+        Some(LeafSampler {
+            tree: self,
+            weights,
+        })
+    }
+}
+
+impl<T, S> LeafSampler<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the number of sampled leaves.
+    pub fn len(&self) -> usize {
+        self.weight(self.tree.get_root()).0
+    }
+
+    /// Returns true if there are no leaves to sample.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total area of the sampled leaves.
+    pub fn area(&self) -> f64 {
+        self.weight(self.tree.get_root()).1
+    }
+
+    /// Returns a leaf drawn uniformly from the sampled leaves, or None if there are none.
+    pub fn sample_leaf<R>(&self, mut rng: R) -> Option<NodeId>
+    where
+        R: FnMut() -> f64,
+    {
+        let target = rng() * self.len() as f64;
+        self.descend(target, |&(leaves, _)| leaves as f64)
+            .map(|(leaf, _)| leaf)
+    }
+
+    /// Returns a point drawn uniformly from the area of the sampled leaves, or None if they have
+    /// no area.
+    pub fn sample_point<R>(&self, mut rng: R) -> Option<Point<f64>>
+    where
+        R: FnMut() -> f64,
+    {
+        let target = rng() * self.area();
+        let (leaf, _) = self.descend(target, |&(_, area)| area)?;
+        let Bounds(left, top, right, bottom) = self.tree.bounds_f64(leaf)?;
+        Some((left + rng() * (right - left), top + rng() * (bottom - top)))
+    }
+
+    fn weight(&self, index: NodeId) -> (usize, f64) {
+        self.weights.get(&index).copied().unwrap_or((0, 0.0))
+    }
+
+    /// Walks down from the root to the leaf at `target` along the cumulative measure of the
+    /// leaves. Returns the leaf and what's left of `target` within it.
+    fn descend<M>(&self, mut target: f64, measure: M) -> Option<(NodeId, f64)>
+    where
+        M: Fn(&(usize, f64)) -> f64,
+    {
+        let mut index = self.tree.get_root();
+        if measure(&self.weight(index)) <= 0.0 {
+            return None;
+        }
+        while let Some(children) = self.tree.get_node(index)?.get_children_index() {
+            // Falls back on the last child with any weight in case rounding overshoots.
+            let mut chosen = None;
+            for child in children {
+                let Some(weight) = self.weights.get(&child) else {
+                    continue;
+                };
+                let m = measure(weight);
+                if m <= 0.0 {
+                    continue;
+                }
+                chosen = Some(child);
+                if target < m {
+                    break;
+                }
+                target -= m;
+            }
+            index = chosen?;
+        }
+        Some((index, target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cycles through evenly spaced numbers in `[0, 1)`.
+    fn stepper(steps: u32) -> impl FnMut() -> f64 {
+        let mut i = 0;
+        move || {
+            i = (i + 1) % steps;
+            f64::from(i) / f64::from(steps)
+        }
+    }
+
+    #[test]
+    fn sampler() {
+        let mut tree = CNQuadtree::new(true, (0, 0, 8, 8));
+        let [nw, ..] = tree
+            .subdivide(tree.get_root(), [true, true, true, false])
+            .unwrap();
+        let nw_children = tree.subdivide(nw, [true; 4]).unwrap();
+
+        let sampler = tree.sampler(|&item| item).unwrap();
+        assert_eq!(sampler.len(), 6);
+        assert_eq!(sampler.area(), 48.0);
+
+        let mut leaves: HashMap<NodeId, usize> = HashMap::new();
+        let mut rng = stepper(600);
+        for _ in 0..600 {
+            *leaves
+                .entry(sampler.sample_leaf(&mut rng).unwrap())
+                .or_default() += 1;
+        }
+        assert_eq!(leaves.len(), 6);
+        assert!(leaves.values().all(|&count| count == 100));
+
+        let mut rng = stepper(97);
+        let mut in_nw = 0;
+        for _ in 0..480 {
+            let (x, y) = sampler.sample_point(&mut rng).unwrap();
+            assert!(!(x >= 4.0 && y >= 4.0));
+            if x < 4.0 && y < 4.0 {
+                in_nw += 1;
+            }
+        }
+        // NW is a third of the sampled area.
+        assert!((150..=170).contains(&in_nw));
+        assert!(nw_children.iter().all(|child| leaves.contains_key(child)));
+
+        let empty = tree.sampler(|_| false).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.sample_leaf(|| 0.5), None);
+        assert_eq!(empty.sample_point(|| 0.5), None);
+    }
+}
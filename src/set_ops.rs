@@ -0,0 +1,231 @@
+use crate::error::Error;
+use crate::node::RegionQuadtreeNode;
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A set operation on regions, where cells holding `T::default()` are outside the region.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum SetOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// What a leaf of the other tree does to the part of this tree it covers.
+enum Cover<T> {
+    /// The part is unchanged.
+    Keep,
+    /// The part becomes a single leaf with the item.
+    Replace(T),
+    /// Each of the part's leaves is combined with the other leaf's item.
+    Combine,
+}
+
+impl SetOperation {
+    /// Returns the item of a cell holding `a` in this tree and `b` in the other.
+    fn apply<T: Clone + Default + PartialEq>(self, a: &T, b: &T) -> T {
+        let empty = T::default();
+        match self {
+            Self::Union if *a == empty => b.clone(),
+            Self::Intersection if *b == empty => empty,
+            Self::Difference if *b != empty => empty,
+            _ => a.clone(),
+        }
+    }
+
+    /// Returns what a leaf of the other tree holding `b` does.
+    fn cover<T: Default + PartialEq>(self, b: &T) -> Cover<T> {
+        let empty = T::default();
+        match self {
+            Self::Union if *b == empty => Cover::Keep,
+            Self::Union => Cover::Combine,
+            Self::Intersection if *b == empty => Cover::Replace(empty),
+            Self::Difference if *b != empty => Cover::Replace(empty),
+            _ => Cover::Keep,
+        }
+    }
+
+    /// Returns true if a leaf of this tree holding `a` is unchanged whatever it's combined with.
+    fn keeps<T: Default + PartialEq>(self, a: &T) -> bool {
+        match self {
+            Self::Union => *a != T::default(),
+            Self::Intersection | Self::Difference => *a == T::default(),
+        }
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    T: Clone + Default + PartialEq,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Adds the region of `other` to the tree's. Cells holding `T::default()`, such as `false`
+    /// for masks or an unlabeled cell, are outside a region; any other item is inside. Cells
+    /// inside both regions keep the tree's item, and cells only inside `other`'s take its item.
+    ///
+    /// Trees are combined node by node without rasterizing, subdividing leaves where `other` is
+    /// finer, and the result is compressed with [`CNQuadtree::compress`]. Both trees must have
+    /// the same bounds, or it fails with [`Error::BoundsMismatch`]. Fails if a leaf can't be
+    /// subdivided like `other`'s, for example because of the tree's maximum depth, leaving the
+    /// operation partly done.
+    pub fn union_with(&mut self, other: &CNQuadtree<T, S>) -> Result<(), Error> {
+        self.set_operation(other, SetOperation::Union)
+    }
+
+    /// Keeps only the part of the tree's region that is also in `other`'s. Cells in both keep
+    /// the tree's item. Otherwise like [`CNQuadtree::union_with`].
+    pub fn intersect_with(&mut self, other: &CNQuadtree<T, S>) -> Result<(), Error> {
+        self.set_operation(other, SetOperation::Intersection)
+    }
+
+    /// Removes the region of `other` from the tree's, leaving `T::default()` where it was.
+    /// Otherwise like [`CNQuadtree::union_with`].
+    pub fn subtract_with(&mut self, other: &CNQuadtree<T, S>) -> Result<(), Error> {
+        self.set_operation(other, SetOperation::Difference)
+    }
+
+    fn set_operation(
+        &mut self,
+        other: &CNQuadtree<T, S>,
+        operation: SetOperation,
+    ) -> Result<(), Error> {
+        if self.bounds() != other.bounds() {
+            return Err(Error::BoundsMismatch);
+        }
+        // Pairs of a node and the node of `other` covering it.
+        let mut stack = vec![(self.get_root(), other.get_root())];
+        while let Some((index, source)) = stack.pop() {
+            let node = self.get_node(index).ok_or(Error::InvalidIndex)?;
+            let source_node = other.get_node(source).ok_or(Error::InvalidIndex)?;
+            // A leaf of `other` larger than the node covers it, as if subdivided with its item.
+            let source_children = if source_node.get_bounds() == node.get_bounds() {
+                source_node.get_children_index()
+            } else {
+                None
+            };
+
+            let Some(source_children) = source_children else {
+                let b = source_node.get_item();
+                match operation.cover(b) {
+                    Cover::Keep => {}
+                    Cover::Replace(item) => {
+                        self.collapse(index)?;
+                        if let Some(node) = self.get_node_mut(index) {
+                            *node.get_item_mut() = item;
+                        }
+                    }
+                    Cover::Combine => match node.get_children_index() {
+                        Some(children) => stack.extend(children.map(|child| (child, source))),
+                        None => {
+                            let item = operation.apply(node.get_item(), b);
+                            if let Some(node) = self.get_node_mut(index) {
+                                *node.get_item_mut() = item;
+                            }
+                        }
+                    },
+                }
+                continue;
+            };
+
+            let children = match node.get_children_index() {
+                Some(children) => children,
+                None if operation.keeps(node.get_item()) => continue,
+                None => {
+                    let item = node.get_item();
+                    let items = std::array::from_fn(|_| item.clone());
+                    self.subdivide(index, items).map_err(|e| e.source)?
+                }
+            };
+            stack.extend(children.into_iter().zip(source_children));
+        }
+        self.compress()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Bounds;
+
+    /// Returns a mask over `(0, 0, 16, 16)` set within `region`, split down to 4 by 4 cells.
+    fn mask(region: Bounds<i32>) -> CNQuadtree<bool, i32> {
+        let mut tree = CNQuadtree::new(false, (0, 0, 16, 16));
+        tree.set_min_cell_size(4);
+        tree.refine_to_levels(
+            |bounds| if region.intersects(*bounds) { 2 } else { 0 },
+            |_, _| [false; 4],
+        )
+        .unwrap();
+        let leaves: Vec<_> = tree.leaves_morton().collect();
+        for leaf in leaves {
+            let node = tree.get_node_mut(leaf).unwrap();
+            *node.get_item_mut() = region.contains_rect(node.get_bounds());
+        }
+        tree.compress().unwrap();
+        tree
+    }
+
+    /// Asserts the trees have the same leaves, ignoring internal nodes' items.
+    fn assert_same_leaves(a: &CNQuadtree<bool, i32>, b: &CNQuadtree<bool, i32>) {
+        let leaves = |tree: &CNQuadtree<bool, i32>| -> Vec<_> {
+            tree.leaves_morton()
+                .map(|leaf| {
+                    let node = tree.get_node(leaf).unwrap();
+                    (node.get_bounds(), *node.get_item())
+                })
+                .collect()
+        };
+        assert_eq!(leaves(a), leaves(b));
+    }
+
+    #[test]
+    fn masks() {
+        let left = mask(Bounds(0, 0, 8, 12));
+        let top = mask(Bounds(0, 0, 16, 4));
+
+        let mut union = left.clone();
+        union.union_with(&top).unwrap();
+        assert_eq!(union.region_area(|&set| set), Some(96.0 + 32.0));
+        // The filled north-west quadrant merges into one leaf, as does the empty south-east.
+        assert_eq!(union.leaf_count(), 10);
+
+        let mut intersection = left.clone();
+        intersection.intersect_with(&top).unwrap();
+        assert_eq!(intersection.region_area(|&set| set), Some(32.0));
+        assert_same_leaves(&intersection, &mask(Bounds(0, 0, 8, 4)));
+
+        let mut difference = left.clone();
+        difference.subtract_with(&top).unwrap();
+        assert_same_leaves(&difference, &mask(Bounds(0, 4, 8, 12)));
+
+        // Removing a region from itself leaves nothing, in a single leaf.
+        difference.subtract_with(&difference.clone()).unwrap();
+        assert_eq!(difference.node_count(), 1);
+        assert!(!difference[difference.get_root()].get_item());
+
+        assert_eq!(
+            union.union_with(&CNQuadtree::new(true, (0, 0, 8, 8))),
+            Err(Error::BoundsMismatch)
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let mut a = CNQuadtree::new(0, (0, 0, 8, 8));
+        a.subdivide(a.get_root(), [1, 0, 0, 2]).unwrap();
+        let mut b = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ..] = b.subdivide(b.get_root(), [0, 3, 0, 0]).unwrap();
+        b.subdivide(nw, [0, 4, 0, 0]).unwrap();
+
+        let mut union = a.clone();
+        union.union_with(&b).unwrap();
+        let labels: Vec<i32> = union.into_iter().collect();
+        assert_eq!(labels, [1, 3, 0, 2]);
+
+        let mut union = b.clone();
+        union.union_with(&a).unwrap();
+        let labels: Vec<i32> = union.into_iter().collect();
+        assert_eq!(labels, [1, 4, 1, 1, 3, 0, 2]);
+    }
+}
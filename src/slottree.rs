@@ -1,409 +1,3119 @@
-use crate::location::Cardinality;
-use crate::node::{CNNode, RegionQuadtreeNode};
-use crate::tree::{find_cardinal_neighbor, RegionQuadtree, SubdivideError, SubdivideErrorEnum};
+use crate::error::{Error, PopChildrenError, SubdivideError};
+use crate::flat::FlatArrays;
+use crate::id::{IndexStatus, NodeId, TreeId};
+use crate::location::{Cardinality, Location};
+use crate::metrics::{Counters, Metrics};
+use crate::node::{Bounds, CNNode, Point, RegionQuadtreeNode, SplitPolicy};
+use crate::stats::Stats;
+use crate::trace::{debug_event, enter_span, trace_event};
+use crate::transform::Affine;
+use crate::tree::{advances_along_side, reaches_side_end, RegionQuadtree};
 use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
-use slotmap::{DefaultKey, SlotMap};
+use slotmap::{DefaultKey, SecondaryMap, SlotMap};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, IndexMut};
+
+/// Mapping from the keys of one store to the ids of another tree.
+type KeyMap = SecondaryMap<DefaultKey, NodeId>;
+
+/// A validated subdivision of a leaf.
+struct Split<S> {
+    level: usize,
+    bounds: Bounds<S>,
+    middle: Point<S>,
+    /// Neighbor chains of the leaf in the following order: West, North, East, South.
+    neighbors: [Vec<NodeId>; 4],
+}
 
 pub struct CNQuadtree<T, S = u32>
 where
     S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
 {
-    store: SlotMap<DefaultKey, CNNode<T, DefaultKey, S>>,
-    root_key: DefaultKey,
+    store: SlotMap<DefaultKey, CNNode<T, NodeId, S>>,
+    root_key: NodeId,
+    /// Tag of the ids of this tree's nodes.
+    id: TreeId,
     layers: Vec<usize>,
+    /// Smallest width or height a child may have after a subdivision.
+    min_cell_size: S,
+    /// Deepest level a node may be created at. None if unlimited.
+    max_depth: Option<usize>,
+    /// Most nodes the tree may hold, with room for all of them reserved. None if unlimited.
+    node_limit: Option<usize>,
+    /// Where subdivisions split nodes.
+    split_policy: SplitPolicy,
+    /// Whether neighbor updates are deferred until [`CNQuadtree::commit`].
+    batching: bool,
+    /// Operation counters. None unless metrics are enabled.
+    metrics: Option<Box<Counters>>,
+    /// Ids of removed nodes. None unless tombstones are enabled.
+    tombstones: Option<HashSet<NodeId>>,
+    /// Transform from tree to world coordinates and its inverse. None if they're the same.
+    world: Option<[Affine; 2]>,
 }
 
 impl<T, S> CNQuadtree<T, S>
 where
     S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
 {
-    pub fn new(item: T, bounds: (S, S, S, S)) -> Self {
-        let root_node = CNNode::<T, DefaultKey, S>::new(item, 0, bounds, None);
+    pub fn new(item: T, bounds: impl Into<Bounds<S>>) -> Self {
+        let bounds = bounds.into();
+        Self::with_capacity(item, bounds, 0)
+    }
 
-        let mut store = SlotMap::new();
-        let root_key = store.insert(root_node);
+    /// Returns a tree with room for at least `capacity` nodes before reallocating.
+    pub fn with_capacity(item: T, bounds: impl Into<Bounds<S>>, capacity: usize) -> Self {
+        let bounds = bounds.into();
+        let root_node = CNNode::<T, NodeId, S>::new(item, 0, bounds, None);
+
+        let mut store = SlotMap::with_capacity(capacity);
+        let id = TreeId::next();
+        let root_key = NodeId {
+            key: store.insert(root_node),
+            tree: id,
+        };
 
         Self {
             store,
             root_key,
+            id,
             layers: vec![1],
+            min_cell_size: S::zero(),
+            max_depth: None,
+            node_limit: None,
+            split_policy: SplitPolicy::Floor,
+            batching: false,
+            metrics: None,
+            tombstones: None,
+            world: None,
         }
     }
 
-    fn get_children_cardinal_neighbors(
-        &self,
-        cardinal_neighbor: Option<DefaultKey>,
-        parent_layer: usize,
-        cardinality: Cardinality,
-    ) -> (Option<DefaultKey>, Option<DefaultKey>) {
-        match cardinal_neighbor {
-            None => (None, None),
-            Some(inherited_neighbor) => (
-                Some(inherited_neighbor),
-                find_cardinal_neighbor::<CNQuadtree<T, S>, T>(
-                    &self,
-                    parent_layer + 1,
-                    cardinality,
-                    inherited_neighbor,
-                ),
-            ),
-        }
-    }
-
-    fn get_and_update_children_neighbors(
-        &mut self,
-        first_child: DefaultKey,
-        second_child: DefaultKey,
-        parent: DefaultKey,
-        cardinality: Cardinality,
-    ) -> Option<DefaultKey> {
-        let mut neighbors = self.get_neighbors(first_child, cardinality)?;
-        let mut other_neighbors = self.get_neighbors(second_child, cardinality)?;
-        neighbors.append(&mut other_neighbors);
-
-        for neighbor in neighbors.iter() {
-            self.get_node_mut(*neighbor)
-                .unwrap()
-                .update_neighbor(Some(parent), cardinality.opposite());
-        }
+    /// Returns the root's bounds.
+    #[inline]
+    pub fn bounds(&self) -> Bounds<S> {
+        self.store[self.root_key.key].get_bounds()
+    }
 
-        Some(neighbors[0])
+    /// Returns the number of nodes the tree can hold without reallocating.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.store.capacity()
     }
 
-    fn update_neighbors_to_children(
-        &mut self,
-        neighbors: Option<Vec<DefaultKey>>,
-        first_child: DefaultKey,
-        second_child: DefaultKey,
-        second_child_cardinal_neighbor: Option<DefaultKey>,
-        cardinality: Cardinality,
-    ) {
-        match neighbors {
-            None => {}
-            Some(neighbors) => {
-                let mut new_neighbor = Some(first_child);
-                for neighbor in neighbors {
-                    if neighbor == second_child_cardinal_neighbor.unwrap() {
-                        new_neighbor = Some(second_child);
-                    }
-                    self.get_node_mut(neighbor)
-                        .unwrap()
-                        .update_neighbor(new_neighbor.clone(), cardinality);
+    /// Reserves room for at least `additional` more nodes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.store.reserve(additional);
+    }
+
+    /// Removes every node and replaces the root with a leaf holding `item`, keeping the bounds,
+    /// settings and allocated capacity so the tree can be rebuilt without reallocating. Every
+    /// index is invalidated; returns the new root's index.
+    pub fn clear(&mut self, item: T) -> NodeId {
+        let bounds = self.bounds();
+        if let Some(tombstones) = &mut self.tombstones {
+            let id = self.id;
+            tombstones.extend(self.store.keys().map(|key| NodeId { key, tree: id }));
+        }
+        self.store.clear();
+        self.root_key = NodeId {
+            key: self.store.insert(CNNode::new(item, 0, bounds, None)),
+            tree: self.id,
+        };
+        self.layers.clear();
+        self.layers.push(1);
+        self.root_key
+    }
+
+    /// Makes the node the root, removing every node outside its subtree. Nodes within keep
+    /// their indices and are moved up to levels counted from the new root, and cardinal
+    /// neighbors that pointed outside are cleared. The maximum depth is counted from the new
+    /// root too, so nodes can't be split finer than before. Takes time linear in the number of
+    /// nodes. Fails with [`Error::InvalidIndex`] if the index is invalid.
+    pub fn make_root(&mut self, index: NodeId) -> Result<(), Error> {
+        let offset = self.get_node(index).ok_or(Error::InvalidIndex)?.level();
+        if offset == 0 {
+            return Ok(());
+        }
+
+        let mut subtree = HashSet::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            subtree.insert(index);
+            stack.extend(
+                self.store[index.key]
+                    .get_children_index()
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+        let id = self.id;
+        let outside: Vec<_> = self
+            .store
+            .keys()
+            .map(|key| NodeId { key, tree: id })
+            .filter(|index| !subtree.contains(index))
+            .collect();
+        for index in outside {
+            self.remove(index);
+        }
+
+        self.layers.clear();
+        for &index in &subtree {
+            let node = &mut self.store[index.key];
+            let level = node.level() - offset;
+            node.update_level(level);
+            for direction in Cardinality::ALL {
+                if node
+                    .get_cardinal_neighbor_index(direction)
+                    .is_some_and(|neighbor| !subtree.contains(&neighbor))
+                {
+                    node.update_neighbor(None, direction);
                 }
             }
+            if self.layers.len() <= level {
+                self.layers.resize(level + 1, 0);
+            }
+            self.layers[level] += 1;
         }
+        self.store[index.key].update_parent(None);
+        self.root_key = index;
+        self.max_depth = self.max_depth.map(|depth| depth.saturating_sub(offset));
+        Ok(())
     }
 
-    #[inline]
-    fn get_max_level(&self) -> usize {
-        self.layers
-            .iter()
-            .enumerate()
-            .filter_map(|(layer, &num)| if num > 0 { Some(layer) } else { None })
-            .max()
-            .unwrap()
+    /// Moves every node into a store sized to fit the tree, or the node limit if one is set,
+    /// releasing the memory left behind by removed nodes. Same as [`CNQuadtree::defragment`].
+    pub fn shrink_to_fit(&mut self) -> HashMap<NodeId, NodeId> {
+        self.defragment()
     }
-}
 
-impl<T, S> RegionQuadtree<T> for CNQuadtree<T, S>
-where
-    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
-{
-    type Index = DefaultKey;
-    type Node = CNNode<T, DefaultKey, S>;
+    /// Rewrites the store so nodes are laid out in depth-first NW, NE, SW, SE order, which is the
+    /// Morton order of their cells with parents before children. This restores locality lost to
+    /// repeated subdivides and pops. Every index changes; returns the mapping from old to new
+    /// indices.
+    pub fn defragment(&mut self) -> HashMap<NodeId, NodeId> {
+        let order = self.depth_first_keys();
+        self.relayout(order)
+    }
 
-    fn get_node(&self, index: Self::Index) -> Option<&Self::Node> {
-        self.store.get(index)
+    /// Returns the leaf containing the point like [`RegionQuadtree::point_locate`], except that a
+    /// coordinate within `tolerance` below a split line counts as on it, and so lands past it.
+    /// Use it for points computed with rounding error, such as `0.1 + 0.2` for a split at 0.3.
+    /// Points are still located in exactly one leaf, but it may not contain them. Returns None if
+    /// the point is outside the tree's bounds.
+    pub fn point_locate_snapped(&self, point: Point<S>, tolerance: S) -> Option<NodeId> {
+        self.descend(point, tolerance, |_, _| {})
     }
 
-    fn get_node_mut(&mut self, index: Self::Index) -> Option<&mut Self::Node> {
-        self.store.get_mut(index)
+    /// Returns the node containing the point at `level`, or the leaf containing it if the tree
+    /// is shallower there. Returns None if the point is outside the tree's bounds.
+    pub fn node_at_level(&self, point: Point<S>, level: usize) -> Option<NodeId> {
+        let mut index = self.root_key;
+        let mut node = self.get_node(index)?;
+        if !node.point_in(point) {
+            return None;
+        }
+        while node.level() < level {
+            let Some(children) = node.get_children_index() else {
+                break;
+            };
+            let (position, child) = self.child_containing(children, point, S::zero())?;
+            index = children[position];
+            node = child;
+        }
+        Some(index)
     }
 
-    fn get_root(&self) -> Self::Index {
-        self.root_key
+    /// Returns the item of the node containing the point at `level`, like a mipmap read. Internal
+    /// nodes keep items of their own, such as those combined by
+    /// [`RefinementCriterion::merge`](crate::RefinementCriterion::merge) or the planes of
+    /// [`CNQuadtree::from_heightmap`], so this reads a coarser value even where finer leaves
+    /// exist. Returns the leaf's item if the tree is shallower there, or None if the point is
+    /// outside the tree's bounds.
+    pub fn item_at_level(&self, point: Point<S>, level: usize) -> Option<&T> {
+        Some(self.get_node(self.node_at_level(point, level)?)?.get_item())
     }
 
-    fn subdivide(
-        &mut self,
-        index: Self::Index,
-        items: [T; 4],
-    ) -> Result<[Self::Index; 4], SubdivideError<T>> {
-        let (parent_layer, bounds) = match self.get_node(index) {
-            Some(x) if !x.has_children() => (x.level(), x.get_bounds()),
-            Some(x) if x.has_children() => {
-                return Err(SubdivideError {
-                    items,
-                    source: SubdivideErrorEnum::AlreadySubdivided,
-                })
+    /// Locates many points at once. Returns the leaf containing each point, in the same order, or
+    /// None for points outside the tree's bounds. Points are partitioned down the tree together,
+    /// so the nodes shared by nearby points are visited once.
+    pub fn point_locate_batch(&self, points: &[Point<S>]) -> Vec<Option<NodeId>> {
+        enter_span!("point_locate_batch", points = points.len());
+        let mut result = vec![None; points.len()];
+        let Some(root) = self.get_node(self.root_key) else {
+            return result;
+        };
+        let mut queries: Vec<usize> = (0..points.len())
+            .filter(|&i| root.point_in(points[i]))
+            .collect();
+        let mut scratch = vec![0; queries.len()];
+
+        // Each entry is a node and the range of `queries` holding the points inside it.
+        let mut stack = vec![(self.root_key, 0, queries.len())];
+        while let Some((index, start, end)) = stack.pop() {
+            let Some(children) = self.get_node(index).and_then(|n| n.get_children_index()) else {
+                for &i in &queries[start..end] {
+                    result[i] = Some(index);
+                }
+                continue;
+            };
+
+            // Counting sort of the range by the child containing each point.
+            let Some(middle) = self.middle_of(children) else {
+                continue;
+            };
+            let mut counts = [0; 4];
+            let mut locations = Vec::with_capacity(end - start);
+            for &i in &queries[start..end] {
+                let location = child_at(middle, points[i], S::zero());
+                counts[location] += 1;
+                locations.push(location);
             }
-            _ => {
-                return Err(SubdivideError {
-                    items,
-                    source: SubdivideErrorEnum::InvalidIndex,
-                })
+            let mut offsets = [start; 4];
+            for location in 1..4 {
+                offsets[location] = offsets[location - 1] + counts[location - 1];
             }
-        };
+            let mut next = offsets;
+            for (&i, &location) in queries[start..end].iter().zip(&locations) {
+                scratch[next[location]] = i;
+                next[location] += 1;
+            }
+            queries[start..end].copy_from_slice(&scratch[start..end]);
+            for location in 0..4 {
+                let (child_start, child_end) = (offsets[location], next[location]);
+                if child_start < child_end {
+                    stack.push((children[location], child_start, child_end));
+                }
+            }
+        }
 
-        let [nw_item, ne_item, sw_item, se_item] = items;
-        let (left, top, right, bottom) = bounds;
+        result
+    }
 
-        let x_middle = (left + right) / S::from_i64(2).unwrap();
-        let y_middle = (top + bottom) / S::from_i64(2).unwrap();
+    /// Returns the number of nodes, internal ones included.
+    #[inline]
+    pub fn node_count(&self) -> usize {
+        self.store.len()
+    }
 
-        // Get neighbors.
-        let w_neighbors = self.get_neighbors(index, Cardinality::West);
-        let n_neighbors = self.get_neighbors(index, Cardinality::North);
-        let e_neighbors = self.get_neighbors(index, Cardinality::East);
-        let s_neighbors = self.get_neighbors(index, Cardinality::South);
+    /// Returns the number of leaves.
+    #[inline]
+    pub fn leaf_count(&self) -> usize {
+        // Every subdivision turns a leaf into an internal node and adds four leaves.
+        (self.store.len() * 3 + 1) / 4
+    }
 
-        // Get inherited and calculated non-sibling cardinal neighbors.
-        let (ne_n_neighbor, nw_n_neighbor) = self.get_children_cardinal_neighbors(
-            n_neighbors.as_ref().and_then(|n| n.first().cloned()),
-            parent_layer,
-            Cardinality::North,
-        );
-        let (sw_w_neighbor, nw_w_neighbor) = self.get_children_cardinal_neighbors(
-            w_neighbors.as_ref().and_then(|n| n.first().cloned()),
-            parent_layer,
-            Cardinality::West,
-        );
-        let (sw_s_neighbor, se_s_neighbor) = self.get_children_cardinal_neighbors(
-            s_neighbors.as_ref().and_then(|n| n.first().cloned()),
-            parent_layer,
-            Cardinality::South,
-        );
-        let (ne_e_neighbor, se_e_neighbor) = self.get_children_cardinal_neighbors(
-            e_neighbors.as_ref().and_then(|n| n.first().cloned()),
-            parent_layer,
-            Cardinality::East,
-        );
+    /// Returns the level of the deepest leaf, 0 if the root is a leaf.
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.get_max_level()
+    }
 
-        // Create child nodes.
-        let nw_node = CNNode::<T, DefaultKey, S>::new(
-            nw_item,
-            parent_layer + 1,
-            (left, top, x_middle, y_middle),
-            Some(index),
-        );
-        let ne_node = CNNode::<T, DefaultKey, S>::new(
-            ne_item,
-            parent_layer + 1,
-            (x_middle, top, right, y_middle),
-            Some(index),
-        );
-        let sw_node = CNNode::<T, DefaultKey, S>::new(
-            sw_item,
-            parent_layer + 1,
-            (left, y_middle, x_middle, bottom),
-            Some(index),
-        );
-        let se_node = CNNode::<T, DefaultKey, S>::new(
-            se_item,
-            parent_layer + 1,
-            (x_middle, y_middle, right, bottom),
-            Some(index),
-        );
+    /// Returns the number of nodes at each level, starting with the root's. The last level is
+    /// the deepest one with nodes.
+    #[inline]
+    pub fn level_counts(&self) -> &[usize] {
+        &self.layers
+    }
 
-        // Insert child nodes.
-        let nw_key = self.store.insert(nw_node);
-        let ne_key = self.store.insert(ne_node);
-        let sw_key = self.store.insert(sw_node);
-        let se_key = self.store.insert(se_node);
+    /// Returns the tree's node counts and memory footprint.
+    pub fn stats(&self) -> Stats {
+        let levels = self.get_max_level() + 1;
+        let mut nodes_per_level = self.layers.clone();
+        nodes_per_level.truncate(levels);
+        let mut leaves_per_level = vec![0; levels];
+        let mut leaf_area = 0.0;
+        for node in self.store.values().filter(|n| n.is_leaf()) {
+            leaves_per_level[node.level()] += 1;
+            let Bounds(left, top, right, bottom) = node.get_bounds();
+            leaf_area += ((right - left).to_f64().unwrap_or(f64::NAN))
+                * ((bottom - top).to_f64().unwrap_or(f64::NAN));
+        }
+        let leaves: usize = leaves_per_level.iter().sum();
 
-        // Update child node neighbors.
-        self.get_node_mut(nw_key).unwrap().update_neighbors([
-            nw_w_neighbor,
-            nw_n_neighbor,
-            Some(ne_key),
-            Some(sw_key),
-        ]);
-        self.store.get_mut(ne_key).unwrap().update_neighbors([
-            Some(nw_key),
-            ne_n_neighbor,
-            ne_e_neighbor,
-            Some(se_key),
-        ]);
-        self.store.get_mut(sw_key).unwrap().update_neighbors([
-            sw_w_neighbor,
-            Some(nw_key),
-            Some(se_key),
-            sw_s_neighbor,
-        ]);
-        self.store.get_mut(se_key).unwrap().update_neighbors([
-            Some(sw_key),
-            Some(ne_key),
-            se_e_neighbor,
-            se_s_neighbor,
-        ]);
+        // Each slot stores its node and a version number.
+        let slot_size = std::mem::size_of::<CNNode<T, NodeId, S>>() + std::mem::size_of::<u32>();
+        let estimated_bytes = std::mem::size_of::<Self>()
+            + (self.store.capacity() + 1) * slot_size
+            + self.layers.capacity() * std::mem::size_of::<usize>();
 
-        // Update neighbor nodes to point to child nodes.
-        self.update_neighbors_to_children(
-            w_neighbors,
-            nw_key,
-            sw_key,
-            sw_w_neighbor,
-            Cardinality::West,
-        );
-        self.update_neighbors_to_children(
-            n_neighbors,
-            nw_key,
-            ne_key,
-            ne_n_neighbor,
-            Cardinality::North,
-        );
-        self.update_neighbors_to_children(
-            e_neighbors,
-            se_key,
-            ne_key,
-            se_e_neighbor,
-            Cardinality::East,
-        );
-        self.update_neighbors_to_children(
-            s_neighbors,
-            sw_key,
-            se_key,
-            sw_s_neighbor,
-            Cardinality::South,
-        );
+        Stats {
+            nodes: self.store.len(),
+            leaves,
+            nodes_per_level,
+            leaves_per_level,
+            average_leaf_area: leaf_area / leaves as f64,
+            fill_ratio: self.store.len() as f64 / self.store.capacity() as f64,
+            estimated_bytes,
+        }
+    }
 
-        // Update parent.
-        let parent = self.get_node_mut(index).unwrap();
-        parent.update_neighbors([None, None, None, None]);
-        parent.update_children(Some([nw_key, ne_key, sw_key, se_key]));
+    /// Returns the deepest level a node may be created at. None if unlimited.
+    #[inline]
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
 
-        if self.layers.len() <= parent_layer + 1 {
-            self.layers.resize(parent_layer + 2, 0);
+    /// Sets the deepest level a node may be created at. Subdividing a node at that level fails
+    /// with [`Error::MaxDepthExceeded`]. Existing deeper nodes are kept.
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    /// Returns the most nodes the tree may hold. None if unlimited.
+    #[inline]
+    pub fn node_limit(&self) -> Option<usize> {
+        self.node_limit
+    }
+
+    /// Caps the number of nodes and reserves room for all of them, so the store never
+    /// reallocates while the limit is set. Subdividing past the limit fails with
+    /// [`Error::OutOfNodes`], and popping children frees their slots for reuse. Existing nodes
+    /// are kept even if there are more of them.
+    pub fn set_node_limit(&mut self, limit: Option<usize>) {
+        self.node_limit = limit;
+        if let Some(limit) = limit {
+            self.store.reserve(limit.saturating_sub(self.store.len()));
         }
-        self.layers[parent_layer + 1] += 4;
+    }
+
+    /// Returns where subdivisions split nodes.
+    #[inline]
+    pub fn split_policy(&self) -> SplitPolicy {
+        self.split_policy
+    }
 
-        Ok([nw_key, ne_key, sw_key, se_key])
+    /// Sets where subdivisions split nodes. Existing nodes keep their bounds. Trees with splits
+    /// other than [`SplitPolicy::Floor`]'s can't be saved with [`CNQuadtree::save_to`].
+    pub fn set_split_policy(&mut self, policy: SplitPolicy) {
+        self.split_policy = policy;
     }
 
-    fn pop_children(&mut self, index: Self::Index) -> Option<[T; 4]> {
-        let (parent_layer, children) = match self.get_node(index) {
-            Some(n) if n.has_children() => (n.level(), n.get_children_index().unwrap()),
-            _ => return None,
-        };
+    /// Starts a batch: until [`CNQuadtree::commit`], subdividing and popping children skip
+    /// updating the cardinal neighbors, which makes bulk refinement much cheaper. Neighbors are
+    /// stale during the batch, so anything that follows them, such as
+    /// [`RegionQuadtree::get_neighbors`], gives wrong results until the commit.
+    pub fn begin_batch(&mut self) {
+        self.batching = true;
+    }
 
-        // Check if children has children.
-        for child in children.iter() {
-            if self.get_node(*child).unwrap().has_children() {
-                return None;
-            }
+    /// Ends a batch started with [`CNQuadtree::begin_batch`] and recomputes the cardinal
+    /// neighbors of every leaf. Does nothing outside a batch.
+    pub fn commit(&mut self) {
+        if !self.batching {
+            return;
         }
+        enter_span!("commit", nodes = self.store.len());
+        self.batching = false;
+        let leaves: Vec<_> = self
+            .store
+            .iter()
+            .filter(|(_, node)| node.is_leaf())
+            .map(|(key, _)| NodeId { key, tree: self.id })
+            .collect();
+        self.relink(leaves);
+    }
 
-        let [nw_key, ne_key, sw_key, se_key] = children;
+    /// Returns true between [`CNQuadtree::begin_batch`] and [`CNQuadtree::commit`].
+    #[inline]
+    pub fn is_batching(&self) -> bool {
+        self.batching
+    }
+
+    /// Starts or stops counting operations. Enabling resets the counts; disabling discards them.
+    /// Counting is off by default.
+    pub fn set_metrics_enabled(&mut self, enabled: bool) {
+        self.metrics = enabled.then(Box::default);
+    }
 
-        let w_cneighbor =
-            self.get_and_update_children_neighbors(nw_key, sw_key, index, Cardinality::West);
-        let n_cneighbor =
-            self.get_and_update_children_neighbors(nw_key, ne_key, index, Cardinality::North);
-        let e_cneighbor =
-            self.get_and_update_children_neighbors(se_key, ne_key, index, Cardinality::East);
-        let s_cneighbor =
-            self.get_and_update_children_neighbors(se_key, sw_key, index, Cardinality::South);
+    /// Returns the operation counts since metrics were enabled, or None if they're disabled.
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics.as_ref().map(|counters| counters.snapshot())
+    }
 
-        {
-            let parent = self.get_node_mut(index).unwrap();
-            parent.update_neighbors([w_cneighbor, n_cneighbor, e_cneighbor, s_cneighbor]);
-            parent.update_children(None);
+    /// Starts or stops keeping the ids of removed nodes, so [`CNQuadtree::index_status`] can
+    /// tell removed ids from ones the tree never handed out. Every removal adds an id until
+    /// tombstones are disabled, which discards them. Off by default.
+    pub fn set_tombstones_enabled(&mut self, enabled: bool) {
+        self.tombstones = enabled.then(HashSet::new);
+    }
+
+    /// Places the tree in a world whose coordinates `transform` maps tree coordinates to, for
+    /// the queries taking world coordinates such as [`CNQuadtree::point_locate_world`]. The tree
+    /// itself stays axis-aligned. None makes world and tree coordinates the same, which is the
+    /// default. Fails with [`Error::SingularTransform`] if the transform can't be inverted.
+    pub fn set_world_transform(&mut self, transform: Option<Affine>) -> Result<(), Error> {
+        self.world = match transform {
+            Some(transform) => Some([
+                transform,
+                transform.inverse().ok_or(Error::SingularTransform)?,
+            ]),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Returns the transform from tree to world coordinates, or None if they're the same.
+    #[inline]
+    pub fn world_transform(&self) -> Option<Affine> {
+        self.world.map(|[to_world, _]| to_world)
+    }
+
+    /// Returns the world coordinates of a point given in tree coordinates.
+    pub fn tree_to_world(&self, point: Point<f64>) -> Point<f64> {
+        self.world
+            .map_or(point, |[to_world, _]| to_world.apply(point))
+    }
+
+    /// Returns the tree coordinates of a point given in world coordinates.
+    pub fn world_to_tree(&self, point: Point<f64>) -> Point<f64> {
+        self.world
+            .map_or(point, |[_, to_tree]| to_tree.apply(point))
+    }
+
+    /// Returns true if the id finds a node of this tree.
+    #[inline]
+    pub fn is_live(&self, index: NodeId) -> bool {
+        self.get_node(index).is_some()
+    }
+
+    /// Returns whether the id finds a node, and if not, why. Without tombstones, every id of
+    /// this tree that doesn't find a node is taken to be removed, and every other id to never
+    /// have existed; ids from before [`CNQuadtree::defragment`] count as another tree's. With
+    /// them, only ids removed since they were enabled count as removed. Telling recycled ids
+    /// from removed ones takes time linear in the number of nodes.
+    pub fn index_status(&self, index: NodeId) -> IndexStatus {
+        if self.is_live(index) {
+            return IndexStatus::Live;
+        }
+        let removed = match &self.tombstones {
+            Some(tombstones) => tombstones.contains(&index),
+            None => index.tree == self.id,
+        };
+        if !removed {
+            return IndexStatus::NeverExisted;
         }
+        let slot = |key: DefaultKey| slotmap::Key::data(&key).as_ffi() as u32;
+        if index.tree == self.id && self.store.keys().any(|key| slot(key) == slot(index.key)) {
+            IndexStatus::Recycled
+        } else {
+            IndexStatus::Removed
+        }
+    }
 
-        self.layers[parent_layer + 1] -= 4;
+    /// Returns the smallest width or height a child may have after a subdivision.
+    #[inline]
+    pub fn min_cell_size(&self) -> S {
+        self.min_cell_size
+    }
 
-        let children = [
-            self.store.remove(nw_key).unwrap().pop(),
-            self.store.remove(ne_key).unwrap().pop(),
-            self.store.remove(sw_key).unwrap().pop(),
-            self.store.remove(se_key).unwrap().pop(),
-        ];
+    /// Sets the smallest width or height a child may have after a subdivision. Subdividing a
+    /// node whose children would be smaller fails with [`Error::CellTooSmall`]. Children with
+    /// zero width or height are always rejected.
+    pub fn set_min_cell_size(&mut self, size: S) {
+        self.min_cell_size = size;
+    }
 
-        Some(children)
+    /// Returns a copy of the tree with the same structure and neighbors, with every item
+    /// transformed by `f`. `f` is given the node's item and bounds.
+    pub fn map_items<U, F>(&self, mut f: F) -> CNQuadtree<U, S>
+    where
+        F: FnMut(&T, Bounds<S>) -> U,
+    {
+        match self.try_map_items(|item, bounds| Ok::<U, Infallible>(f(item, bounds))) {
+            Ok(tree) => tree,
+            Err(e) => match e {},
+        }
     }
 
-    fn point_locate(
-        &self,
-        point: (
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-        ),
-    ) -> Option<Self::Index> {
-        let mut index = self.root_key;
+    /// Clones the tree like [`Clone::clone`], and returns the mapping from the ids of this tree
+    /// to the ids of the same nodes in the clone.
+    pub fn clone_with_ids(&self) -> (Self, HashMap<NodeId, NodeId>)
+    where
+        T: Clone,
+    {
+        let mut store = self.store.clone();
+        let id = TreeId::next();
+        let keys: KeyMap = store
+            .keys()
+            .map(|key| (key, NodeId { key, tree: id }))
+            .collect();
+        for node in store.values_mut() {
+            remap_links(node, &keys);
+        }
+        let tree = Self {
+            store,
+            root_key: keys[self.root_key.key],
+            id,
+            layers: self.layers.clone(),
+            min_cell_size: self.min_cell_size,
+            max_depth: self.max_depth,
+            node_limit: self.node_limit,
+            split_policy: self.split_policy,
+            batching: self.batching,
+            metrics: self.metrics.clone(),
+            tombstones: self.tombstones.as_ref().map(|_| HashSet::new()),
+            world: self.world,
+        };
+        let ids = keys
+            .into_iter()
+            .map(|(key, new)| (NodeId { key, tree: self.id }, new))
+            .collect();
+        (tree, ids)
+    }
 
-        let mut node = self.get_node(index)?;
-        if !node.point_in(point) {
-            return None;
+    /// Fallible version of [`CNQuadtree::map_items`]. Returns the first error returned by `f`.
+    pub fn try_map_items<U, E, F>(&self, f: F) -> Result<CNQuadtree<U, S>, E>
+    where
+        F: FnMut(&T, Bounds<S>) -> Result<U, E>,
+    {
+        self.try_map_store(f).map(|(tree, _)| tree)
+    }
+
+    /// Flattens the tree into parallel arrays with dense `u32` indices. `f` is given each node's
+    /// item and bounds and returns its payload. Fails with [`Error::Overflow`] if the tree has
+    /// too many nodes to index with `u32`.
+    pub fn to_flat_arrays<P, F>(&self, mut f: F) -> Result<FlatArrays<S, P>, Error>
+    where
+        F: FnMut(&T, Bounds<S>) -> P,
+    {
+        if self.store.len() >= FlatArrays::<S, P>::NONE as usize {
+            return Err(Error::Overflow);
         }
-        if !node.has_children() {
-            return Some(index);
+
+        // Breadth-first, so siblings get consecutive indices.
+        let order = self.level_order();
+        let mut dense = SecondaryMap::with_capacity(order.len());
+        for (i, &key) in order.iter().enumerate() {
+            dense.insert(key.key, i as u32);
         }
 
-        let (left, top, right, bottom) = node.get_bounds();
-        let width: f32 = (right - left).to_f32().unwrap();
-        let height: f32 = (bottom - top).to_f32().unwrap();
+        let to_dense = |key: Option<NodeId>| key.map_or(FlatArrays::<S, P>::NONE, |k| dense[k.key]);
+        let mut flat = FlatArrays {
+            bounds: Vec::with_capacity(order.len()),
+            levels: Vec::with_capacity(order.len()),
+            parents: Vec::with_capacity(order.len()),
+            first_child: Vec::with_capacity(order.len()),
+            neighbors: Vec::with_capacity(order.len()),
+            items: Vec::with_capacity(order.len()),
+        };
+        for key in order {
+            let node = &self.store[key.key];
+            let Bounds(left, top, right, bottom) = node.get_bounds();
+            flat.bounds.push([left, top, right, bottom]);
+            flat.levels.push(node.level() as u32);
+            flat.parents.push(to_dense(node.get_parent_index()));
+            flat.first_child
+                .push(to_dense(node.get_children_index().map(|[nw, ..]| nw)));
+            flat.neighbors
+                .push(node.get_cardinal_neighbors_index().map(to_dense));
+            flat.items.push(f(node.get_item(), node.get_bounds()));
+        }
 
-        // Converting point to [0, 1)x[0, 1) form
-        let x: f32 = (point.0 - left).to_f32().unwrap() / width;
-        let y: f32 = (point.1 - top).to_f32().unwrap() / height;
+        Ok(flat)
+    }
 
-        let x_loc_code = (x * 2f32.powi(self.get_max_level() as i32)) as usize;
-        let y_loc_code = (y * 2f32.powi(self.get_max_level() as i32)) as usize;
+    /// Copies the tree into a new store in depth-first order with every item transformed by
+    /// `f`. Returns the new tree and the mapping from old to new keys.
+    fn try_map_store<U, E, F>(&self, mut f: F) -> Result<(CNQuadtree<U, S>, KeyMap), E>
+    where
+        F: FnMut(&T, Bounds<S>) -> Result<U, E>,
+    {
+        let mut store = SlotMap::with_capacity(self.store.len());
+        let mut keys = KeyMap::with_capacity(self.store.len());
+        let id = TreeId::next();
 
-        // Current level = root level = max_level
-        // So root's children's level is max_level - 1
-        let mut next_level = self.get_max_level() - 1;
+        let mut stack = vec![self.root_key];
+        while let Some(old_key) = stack.pop() {
+            let node = &self.store[old_key.key];
+            let item = f(node.get_item(), node.get_bounds())?;
+            let mut new_node = CNNode::new(
+                item,
+                node.level(),
+                node.get_bounds(),
+                node.get_parent_index(),
+            );
+            new_node.update_children(node.get_children_index());
+            new_node.update_neighbors(node.get_cardinal_neighbors_index());
+            let key = store.insert(new_node);
+            keys.insert(old_key.key, NodeId { key, tree: id });
+            if let Some(children) = node.get_children_index() {
+                stack.extend(children.into_iter().rev());
+            }
+        }
 
-        while node.has_children() {
-            let child_branch_bit = 1 << next_level;
-            let child_index = ((x_loc_code & child_branch_bit) >> next_level)
-                + ((y_loc_code & child_branch_bit) >> (next_level - 1));
-            index = node.get_children_index().unwrap()[child_index];
-            node = self.get_node(index).unwrap();
-            next_level -= 1;
+        for node in store.values_mut() {
+            remap_links(node, &keys);
         }
 
-        debug_assert!(node.point_in(point));
+        let tree = CNQuadtree {
+            store,
+            root_key: keys[self.root_key.key],
+            id,
+            layers: self.layers.clone(),
+            min_cell_size: self.min_cell_size,
+            max_depth: self.max_depth,
+            node_limit: self.node_limit,
+            split_policy: self.split_policy,
+            batching: self.batching,
+            metrics: self.metrics.clone(),
+            tombstones: self.tombstones.as_ref().map(|_| HashSet::new()),
+            world: self.world,
+        };
+        Ok((tree, keys))
+    }
 
-        Some(index)
+    /// Removes the node from the store, keeping its id if tombstones are enabled.
+    fn remove(&mut self, index: NodeId) -> Option<CNNode<T, NodeId, S>> {
+        let node = self.store.remove(index.key)?;
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.insert(index);
+        }
+        Some(node)
     }
 
-    fn region_locate(
-        &self,
-        region: (
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-        ),
-    ) -> Option<Vec<Self::Index>> {
-        todo!()
+    /// Inserts the node into the store and returns its id.
+    fn insert(&mut self, node: CNNode<T, NodeId, S>) -> NodeId {
+        NodeId {
+            key: self.store.insert(node),
+            tree: self.id,
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns every key in the tree in depth-first NW, NE, SW, SE order.
+    fn depth_first_keys(&self) -> Vec<NodeId> {
+        let mut keys = Vec::with_capacity(self.store.len());
+        let mut stack = vec![self.root_key];
+        while let Some(key) = stack.pop() {
+            keys.push(key);
+            if let Some(children) = self.store[key.key].get_children_index() {
+                stack.extend(children.into_iter().rev());
+            }
+        }
+        keys
+    }
 
-    #[test]
-    fn basic_subdivide() {
-        let mut tree = CNQuadtree::new("root".to_string(), (0, 0, 100, 100));
-        let root = tree.get_root();
+    /// Returns every key in the tree level by level, each level in NW, NE, SW, SE (Morton) order.
+    pub(crate) fn level_order(&self) -> Vec<NodeId> {
+        let mut keys = Vec::with_capacity(self.store.len());
+        let mut queue = VecDeque::from([self.root_key]);
+        while let Some(key) = queue.pop_front() {
+            keys.push(key);
+            if let Some(children) = self.store[key.key].get_children_index() {
+                queue.extend(children);
+            }
+        }
+        keys
+    }
 
-        let child_items = [
-            "nw".to_string(),
-            "ne".to_string(),
-            "sw".to_string(),
-            "se".to_string(),
-        ];
+    /// Recomputes every cardinal neighbor from the parent and child links alone, for trees whose
+    /// neighbors were imported, lost or corrupted. Children must be in NW, NE, SW, SE order.
+    /// Fails with [`Error::DanglingIndex`] if a child doesn't exist, leaving the neighbors
+    /// unchanged. Assumes nodes line up with their neighbors at the same level, as they do under
+    /// [`SplitPolicy::Floor`] and [`SplitPolicy::Ceil`] but not after median or
+    /// [`CNQuadtree::subdivide_at`] splits. Use [`Rebuild`](crate::Rebuild) to spread the work
+    /// over several calls, for any splits.
+    pub fn rebuild_neighbors(&mut self) -> Result<(), Error> {
+        enter_span!("rebuild_neighbors", nodes = self.store.len());
+        // The neighbor of each node at its level or coarser, top-down like Samet's method: a
+        // child's neighbor is either a sibling or a child of its parent's neighbor.
+        // They're carried along with the queued nodes rather than kept for every node.
+        let mut leaves = Vec::new();
+        let mut queue = VecDeque::from([(self.root_key, [None; 4])]);
+        while let Some((index, parent_adjacent)) = queue.pop_front() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            let Some(children) = node.get_children_index() else {
+                leaves.push((index, parent_adjacent));
+                continue;
+            };
+            for (location, &child) in children.iter().enumerate() {
+                let mut child_adjacent = [None; 4];
+                for (direction, neighbor) in child_adjacent.iter_mut().enumerate() {
+                    // The sibling on that side, if any, else the child of the parent's neighbor
+                    // on the far side of it.
+                    let (sibling, across) = match direction {
+                        0 => (location % 2 == 1, location ^ 1),
+                        1 => (location >= 2, location ^ 2),
+                        2 => (location % 2 == 0, location ^ 1),
+                        _ => (location < 2, location ^ 2),
+                    };
+                    *neighbor = if sibling {
+                        Some(children[across])
+                    } else {
+                        match parent_adjacent[direction] {
+                            None => None,
+                            Some(n) => {
+                                let n_node = self.get_node(n).ok_or(Error::DanglingIndex)?;
+                                Some(n_node.get_children_index().map_or(n, |c| c[across]))
+                            }
+                        }
+                    };
+                }
+                queue.push_back((child, child_adjacent));
+            }
+        }
 
-        let children = tree.subdivide(root, child_items.clone());
-        assert!(children.is_ok());
-        for (i, child) in children.unwrap().into_iter().enumerate() {
-            assert_eq!(tree.get_node(child).unwrap().get_item(), &child_items[i]);
+        // Each leaf's cardinal neighbor is the leaf beside the corner it starts at: down the
+        // equal-or-larger neighbor through NE children for west and south, SW for north and
+        // east.
+        for (_, leaf_neighbors) in &mut leaves {
+            for (direction, neighbor) in leaf_neighbors.iter_mut().enumerate() {
+                let corner = if direction % 3 == 0 {
+                    Location::NorthEast
+                } else {
+                    Location::SouthWest
+                };
+                while let Some(n) = *neighbor {
+                    let n_node = self.get_node(n).ok_or(Error::DanglingIndex)?;
+                    match n_node.get_children_index() {
+                        Some(children) => *neighbor = Some(children[corner as usize]),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        for node in self.store.values_mut() {
+            node.update_neighbors([None; 4]);
+        }
+        for (leaf, leaf_neighbors) in leaves {
+            self.store[leaf.key].update_neighbors(leaf_neighbors);
         }
+        Ok(())
     }
 
-    #[test]
-    fn point_locate() {}
+    /// Returns the leaf a node with the given bounds has as its cardinal neighbor at
+    /// `direction`: the one beside its top-left corner for west and north, and beside its
+    /// bottom-right corner for east and south. Found by descending from the root, so it doesn't
+    /// depend on any neighbor links. Returns None at the border.
+    pub(crate) fn corner_leaf(&self, bounds: Bounds<S>, direction: Cardinality) -> Option<NodeId> {
+        let Bounds(left, top, right, bottom) = bounds;
+        // Whether a node holds the corner's neighbor. Coordinates on the corner itself belong
+        // to the side facing the node, so their comparisons are flipped.
+        let holds = |Bounds(l, t, r, b): Bounds<S>| match direction {
+            Cardinality::West => l < left && left <= r && t <= top && top < b,
+            Cardinality::North => l <= left && left < r && t < top && top <= b,
+            Cardinality::East => l <= right && right < r && t < bottom && bottom <= b,
+            Cardinality::South => l < right && right <= r && t <= bottom && bottom < b,
+        };
+
+        let mut index = self.root_key;
+        let mut node = self.get_node(index)?;
+        if !holds(node.get_bounds()) {
+            return None;
+        }
+        while let Some(children) = node.get_children_index() {
+            index = children.into_iter().find(|&c| {
+                self.get_node(c)
+                    .is_some_and(|child| holds(child.get_bounds()))
+            })?;
+            node = self.get_node(index)?;
+        }
+        Some(index)
+    }
+
+    /// Recomputes the cardinal neighbors of the given leaves with [`CNQuadtree::corner_leaf`].
+    /// Unlike [`CNQuadtree::rebuild_neighbors`] this works for any splits. Indices that aren't
+    /// leaves are skipped.
+    pub(crate) fn relink<I>(&mut self, leaves: I)
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        for leaf in leaves {
+            let Some(bounds) = self
+                .get_node(leaf)
+                .filter(|n| n.is_leaf())
+                .map(|n| n.get_bounds())
+            else {
+                continue;
+            };
+            let neighbors = Cardinality::ALL.map(|direction| self.corner_leaf(bounds, direction));
+            self.store[leaf.key].update_neighbors(neighbors);
+        }
+    }
+
+    /// Moves `children`, taken from a node of `from`, and their descendants under the leaf
+    /// `to_index` in `to`, keeping their relative levels. Returns the moved leaves, or the leaf
+    /// itself if there are no children. The moved nodes get new indices and no neighbors.
+    fn transplant(
+        from: &mut Self,
+        children: Option<[NodeId; 4]>,
+        to: &mut Self,
+        to_index: NodeId,
+    ) -> Vec<NodeId> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![(children, to_index)];
+        while let Some((children, parent)) = stack.pop() {
+            let Some(children) = children else {
+                leaves.push(parent);
+                continue;
+            };
+            let from_level = from.store[children[0].key].level();
+            let level = to.store[parent.key].level() + 1;
+            let moved = children.map(|child| {
+                let node = from.remove(child).unwrap();
+                let grandchildren = node.get_children_index();
+                let bounds = node.get_bounds();
+                let index = to.insert(CNNode::new(node.pop(), level, bounds, Some(parent)));
+                stack.push((grandchildren, index));
+                index
+            });
+            to.store[parent.key].update_children(Some(moved));
+
+            from.layers[from_level] -= 4;
+            if to.layers.len() <= level {
+                to.layers.resize(level + 1, 0);
+            }
+            to.layers[level] += 4;
+        }
+        while from.layers.len() > 1 && from.layers.last() == Some(&0) {
+            from.layers.pop();
+        }
+        leaves
+    }
+
+    /// Removes the subtree rooted at `index` and returns it as a tree of its own, with the same
+    /// settings and levels counted from its root, along with the subtree root's parent and
+    /// level. The parent keeps the stale index until [`CNQuadtree::reattach`]. `extra_nodes`
+    /// limits how many nodes the subtree may gain if the tree has a node limit.
+    pub(crate) fn detach(
+        &mut self,
+        index: NodeId,
+        extra_nodes: usize,
+    ) -> Option<(Option<NodeId>, usize, Self)> {
+        self.get_node(index)?;
+        let root = self.remove(index)?;
+        let (parent, level, bounds) = (root.get_parent_index(), root.level(), root.get_bounds());
+        let children = root.get_children_index();
+
+        let mut part = Self::new(root.pop(), bounds);
+        part.min_cell_size = self.min_cell_size;
+        part.split_policy = self.split_policy;
+        part.batching = self.batching;
+        part.max_depth = self.max_depth.map(|depth| depth.saturating_sub(level));
+        let part_root = part.root_key;
+        Self::transplant(self, children, &mut part, part_root);
+        part.node_limit = self.node_limit.map(|_| part.store.len() + extra_nodes);
+        Some((parent, level, part))
+    }
+
+    /// Puts a tree returned by [`CNQuadtree::detach`] back as the child of `parent` at
+    /// `location`, or as the root if there's no parent, at `level`. Returns the new index of its
+    /// root and its leaves, whose neighbors are left for [`CNQuadtree::relink`].
+    pub(crate) fn reattach(
+        &mut self,
+        parent: Option<NodeId>,
+        location: usize,
+        level: usize,
+        mut part: Self,
+    ) -> (NodeId, Vec<NodeId>) {
+        let root = part.store.remove(part.root_key.key).unwrap();
+        let children = root.get_children_index();
+        let bounds = root.get_bounds();
+        let index = self.insert(CNNode::new(root.pop(), level, bounds, parent));
+        match parent.and_then(|p| self.store.get_mut(p.key)) {
+            Some(parent) => {
+                if let Some(mut siblings) = parent.get_children_index() {
+                    siblings[location] = index;
+                    parent.update_children(Some(siblings));
+                }
+            }
+            None => self.root_key = index,
+        }
+        let leaves = Self::transplant(&mut part, children, self, index);
+        (index, leaves)
+    }
+
+    /// Moves the nodes into a new store in the given order, which must list every node exactly
+    /// once. The tree gets a new tag, so old ids no longer find nodes. Returns the mapping from
+    /// old to new ids.
+    fn relayout(&mut self, order: Vec<NodeId>) -> HashMap<NodeId, NodeId> {
+        let mut old_store = std::mem::take(&mut self.store);
+        let mut keys = KeyMap::with_capacity(order.len());
+        // Keep the room reserved for a node limit.
+        self.store = SlotMap::with_capacity(order.len().max(self.node_limit.unwrap_or(0)));
+        let old_id = std::mem::replace(&mut self.id, TreeId::next());
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.extend(&order);
+        }
+        for old_key in order {
+            if let Some(node) = old_store.remove(old_key.key) {
+                let key = self.store.insert(node);
+                keys.insert(old_key.key, NodeId { key, tree: self.id });
+            }
+        }
+
+        for node in self.store.values_mut() {
+            remap_links(node, &keys);
+        }
+        self.root_key = keys[self.root_key.key];
+        keys.into_iter()
+            .map(|(key, new)| (NodeId { key, tree: old_id }, new))
+            .collect()
+    }
+
+    /// Iterates over the same neighbors as [`RegionQuadtree::get_neighbors`] without allocating.
+    /// Yields nothing if the node is at the border or doesn't exist, and stops early where
+    /// `get_neighbors` would report a broken chain.
+    pub(crate) fn neighbors_iter(
+        &self,
+        index: NodeId,
+        direction: Cardinality,
+    ) -> impl Iterator<Item = NodeId> + '_ {
+        let node = self.get_node(index);
+        let mut next = node
+            .and_then(|n| n.get_cardinal_neighbor_index(direction))
+            .filter(|&n| self.get_node(n).is_some());
+        std::iter::from_fn(move || {
+            let current = next?;
+            let neighbor = self.get_node(current)?;
+            next = node
+                .filter(|node| {
+                    !reaches_side_end(neighbor.get_bounds(), node.get_bounds(), direction)
+                })
+                .and_then(|_| neighbor.get_cardinal_neighbor_index(direction.next_neighbor()))
+                .filter(|&n| {
+                    self.get_node(n).is_some_and(|next| {
+                        advances_along_side(neighbor.get_bounds(), next.get_bounds(), direction)
+                    })
+                });
+            Some(current)
+        })
+    }
+
+    /// Returns the neighbor chain of a node at the given direction. The chain is empty if the
+    /// node is at the border.
+    fn neighbor_chain(&self, index: NodeId, direction: Cardinality) -> Result<Vec<NodeId>, Error> {
+        let chain = self.get_neighbors(index, direction)?.into_vec();
+        if let Some(metrics) = &self.metrics {
+            metrics.neighbor_chain(chain.len());
+        }
+        Ok(chain)
+    }
+
+    /// Returns the neighbor chains of both nodes at the given direction.
+    fn joined_neighbor_chain(
+        &self,
+        first: NodeId,
+        second: NodeId,
+        direction: Cardinality,
+    ) -> Result<Vec<NodeId>, Error> {
+        let mut neighbors = self.neighbor_chain(first, direction)?;
+        neighbors.extend(self.neighbor_chain(second, direction)?);
+        Ok(neighbors)
+    }
+
+    /// Returns the first node in the neighbor chain whose bounds satisfy `f`.
+    fn find_in_chain<F>(&self, chain: &[NodeId], f: F) -> Option<NodeId>
+    where
+        F: Fn(Bounds<S>) -> bool,
+    {
+        chain
+            .iter()
+            .copied()
+            .find(|&n| self.get_node(n).is_some_and(|n| f(n.get_bounds())))
+    }
+
+    /// Redirects the cardinal neighbor at the given direction of each node in the chain from any
+    /// node in `from` to the node returned by `to`. `to` is given the chain node's bounds.
+    fn redirect_neighbors<F>(
+        &mut self,
+        chain: &[NodeId],
+        from: &[NodeId],
+        direction: Cardinality,
+        to: F,
+    ) where
+        F: Fn(Bounds<S>) -> NodeId,
+    {
+        for &neighbor in chain {
+            let Some(node) = self.get_node_mut(neighbor) else {
+                continue;
+            };
+            match node.get_cardinal_neighbor_index(direction) {
+                Some(n) if from.contains(&n) => {
+                    let new_neighbor = to(node.get_bounds());
+                    trace_event!(
+                        node = ?neighbor,
+                        ?direction,
+                        from = ?n,
+                        to = ?new_neighbor,
+                        "update neighbor"
+                    );
+                    node.update_neighbor(Some(new_neighbor), direction);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Checks that the node can be subdivided and gathers everything the subdivision needs
+    /// without modifying the tree.
+    fn prepare_split(&self, index: NodeId, middle: Option<Point<S>>) -> Result<Split<S>, Error> {
+        let node = self.get_node(index).ok_or(Error::InvalidIndex)?;
+        if node.has_children() {
+            return Err(Error::AlreadySubdivided);
+        }
+        if self.max_depth.is_some_and(|max| node.level() >= max) {
+            return Err(Error::MaxDepthExceeded);
+        }
+        if self
+            .node_limit
+            .is_some_and(|max| self.store.len() + 4 > max)
+        {
+            return Err(Error::OutOfNodes);
+        }
+
+        let bounds = node.get_bounds();
+        let Bounds(left, top, right, bottom) = bounds;
+        let middle = (
+            self.split_point(left, right, middle.map(|m| m.0))?,
+            self.split_point(top, bottom, middle.map(|m| m.1))?,
+        );
+
+        if self.batching {
+            return Ok(Split {
+                level: node.level(),
+                bounds,
+                middle,
+                neighbors: Default::default(),
+            });
+        }
+        Ok(Split {
+            level: node.level(),
+            bounds,
+            middle,
+            neighbors: [
+                self.neighbor_chain(index, Cardinality::West)?,
+                self.neighbor_chain(index, Cardinality::North)?,
+                self.neighbor_chain(index, Cardinality::East)?,
+                self.neighbor_chain(index, Cardinality::South)?,
+            ],
+        })
+    }
+
+    /// Subdivides a leaf at the given split point instead of where the split policy chooses,
+    /// for data-driven splits. Fails with [`Error::CellTooSmall`] if `middle` isn't strictly
+    /// inside the leaf or leaves a child smaller than the minimum cell size, and otherwise like
+    /// [`RegionQuadtree::subdivide`].
+    pub fn subdivide_at(
+        &mut self,
+        index: NodeId,
+        middle: Point<S>,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>> {
+        self.split_node(index, Some(middle), items)
+    }
+
+    /// Subdivides a leaf like [`RegionQuadtree::subdivide`], with each child's item made by
+    /// `item` from the child's location and bounds and the leaf's item, such as for boxed trait
+    /// objects built per child. `item` is only called once the subdivision can't fail.
+    pub fn subdivide_with<F>(&mut self, index: NodeId, mut item: F) -> Result<[NodeId; 4], Error>
+    where
+        F: FnMut(Location, Bounds<S>, &T) -> T,
+    {
+        let split = match self.prepare_split(index, None) {
+            Ok(split) => split,
+            Err(source) => {
+                debug_event!(node = ?index, error = %source, "subdivide failed");
+                return Err(source);
+            }
+        };
+        let parent = self.get_node(index).ok_or(Error::InvalidIndex)?.get_item();
+        let Bounds(left, top, right, bottom) = split.bounds;
+        let (x_middle, y_middle) = split.middle;
+        let items = Location::ALL.map(|location| {
+            let bounds = match location {
+                Location::NorthWest => Bounds(left, top, x_middle, y_middle),
+                Location::NorthEast => Bounds(x_middle, top, right, y_middle),
+                Location::SouthWest => Bounds(left, y_middle, x_middle, bottom),
+                Location::SouthEast => Bounds(x_middle, y_middle, right, bottom),
+            };
+            item(location, bounds, parent)
+        });
+        Ok(self.apply_split(index, split, items))
+    }
+
+    /// Subdivides a leaf at `middle`, or where the split policy chooses if None.
+    fn split_node(
+        &mut self,
+        index: NodeId,
+        middle: Option<Point<S>>,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>> {
+        match self.prepare_split(index, middle) {
+            Ok(split) => Ok(self.apply_split(index, split, items)),
+            Err(source) => {
+                debug_event!(node = ?index, error = %source, "subdivide failed");
+                Err(SubdivideError { items, source })
+            }
+        }
+    }
+
+    /// Subdivides a leaf as prepared by [`CNQuadtree::prepare_split`].
+    fn apply_split(&mut self, index: NodeId, split: Split<S>, items: [T; 4]) -> [NodeId; 4] {
+        let Split {
+            level: parent_layer,
+            bounds,
+            middle: (x_middle, y_middle),
+            neighbors: [w_neighbors, n_neighbors, e_neighbors, s_neighbors],
+        } = split;
+
+        let [nw_item, ne_item, sw_item, se_item] = items;
+        let Bounds(left, top, right, bottom) = bounds;
+        enter_span!(
+            "subdivide",
+            node = ?index,
+            level = parent_layer,
+            left = left.to_f64(),
+            top = top.to_f64(),
+            right = right.to_f64(),
+            bottom = bottom.to_f64(),
+        );
+
+        // Inherited cardinal neighbors share a corner with the parent. The others are the
+        // neighbors touching the corner on the splitting line.
+        let nw_w_neighbor = w_neighbors.first().copied();
+        let nw_n_neighbor = n_neighbors.first().copied();
+        let se_e_neighbor = e_neighbors.first().copied();
+        let se_s_neighbor = s_neighbors.first().copied();
+        let sw_w_neighbor = self.find_in_chain(&w_neighbors, |Bounds(_, t, _, b)| {
+            t <= y_middle && y_middle < b
+        });
+        let ne_n_neighbor = self.find_in_chain(&n_neighbors, |Bounds(l, _, r, _)| {
+            l <= x_middle && x_middle < r
+        });
+        let ne_e_neighbor = self.find_in_chain(&e_neighbors, |Bounds(_, t, _, b)| {
+            t < y_middle && y_middle <= b
+        });
+        let sw_s_neighbor = self.find_in_chain(&s_neighbors, |Bounds(l, _, r, _)| {
+            l < x_middle && x_middle <= r
+        });
+
+        // Create child nodes.
+        let nw_node = CNNode::<T, NodeId, S>::new(
+            nw_item,
+            parent_layer + 1,
+            Bounds(left, top, x_middle, y_middle),
+            Some(index),
+        );
+        let ne_node = CNNode::<T, NodeId, S>::new(
+            ne_item,
+            parent_layer + 1,
+            Bounds(x_middle, top, right, y_middle),
+            Some(index),
+        );
+        let sw_node = CNNode::<T, NodeId, S>::new(
+            sw_item,
+            parent_layer + 1,
+            Bounds(left, y_middle, x_middle, bottom),
+            Some(index),
+        );
+        let se_node = CNNode::<T, NodeId, S>::new(
+            se_item,
+            parent_layer + 1,
+            Bounds(x_middle, y_middle, right, bottom),
+            Some(index),
+        );
+
+        // Insert child nodes.
+        let nw_key = self.insert(nw_node);
+        let ne_key = self.insert(ne_node);
+        let sw_key = self.insert(sw_node);
+        let se_key = self.insert(se_node);
+
+        // Update child node neighbors.
+        self.store[nw_key.key].update_neighbors([
+            nw_w_neighbor,
+            nw_n_neighbor,
+            Some(ne_key),
+            Some(sw_key),
+        ]);
+        self.store[ne_key.key].update_neighbors([
+            Some(nw_key),
+            ne_n_neighbor,
+            ne_e_neighbor,
+            Some(se_key),
+        ]);
+        self.store[sw_key.key].update_neighbors([
+            sw_w_neighbor,
+            Some(nw_key),
+            Some(se_key),
+            sw_s_neighbor,
+        ]);
+        self.store[se_key.key].update_neighbors([
+            Some(sw_key),
+            Some(ne_key),
+            se_e_neighbor,
+            se_s_neighbor,
+        ]);
+
+        // Update neighbor nodes to point to child nodes. A neighbor's cardinal neighbor is the
+        // child touching the same corner it touched on the parent.
+        self.redirect_neighbors(
+            &w_neighbors,
+            &[index],
+            Cardinality::East,
+            |Bounds(_, _, _, b)| {
+                if b <= y_middle {
+                    nw_key
+                } else {
+                    sw_key
+                }
+            },
+        );
+        self.redirect_neighbors(
+            &n_neighbors,
+            &[index],
+            Cardinality::South,
+            |Bounds(_, _, r, _)| {
+                if r <= x_middle {
+                    nw_key
+                } else {
+                    ne_key
+                }
+            },
+        );
+        self.redirect_neighbors(
+            &e_neighbors,
+            &[index],
+            Cardinality::West,
+            |Bounds(_, t, _, _)| {
+                if t < y_middle {
+                    ne_key
+                } else {
+                    se_key
+                }
+            },
+        );
+        self.redirect_neighbors(
+            &s_neighbors,
+            &[index],
+            Cardinality::North,
+            |Bounds(l, _, _, _)| {
+                if l < x_middle {
+                    sw_key
+                } else {
+                    se_key
+                }
+            },
+        );
+
+        // Update parent.
+        let parent = &mut self.store[index.key];
+        parent.update_neighbors([None, None, None, None]);
+        parent.update_children(Some([nw_key, ne_key, sw_key, se_key]));
+
+        if self.layers.len() <= parent_layer + 1 {
+            self.layers.resize(parent_layer + 2, 0);
+        }
+        self.layers[parent_layer + 1] += 4;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.subdivision();
+        }
+        trace_event!(children = ?[nw_key, ne_key, sw_key, se_key], "subdivided");
+        [nw_key, ne_key, sw_key, se_key]
+    }
+
+    /// Subdivides a leaf where the split policy chooses without linking any cardinal neighbors,
+    /// for building a whole tree before a single [`CNQuadtree::rebuild_neighbors`]. Skips the
+    /// depth and node limits, which the caller sets afterwards.
+    pub(crate) fn push_children(
+        &mut self,
+        index: NodeId,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], Error> {
+        let node = self.get_node(index).ok_or(Error::InvalidIndex)?;
+        if node.has_children() {
+            return Err(Error::AlreadySubdivided);
+        }
+        let level = node.level();
+        let Bounds(left, top, right, bottom) = node.get_bounds();
+        let x_middle = self.split_point(left, right, None)?;
+        let y_middle = self.split_point(top, bottom, None)?;
+
+        let mut items = items.into_iter();
+        let children = Location::ALL.map(|location| {
+            let bounds = match location {
+                Location::NorthWest => Bounds(left, top, x_middle, y_middle),
+                Location::NorthEast => Bounds(x_middle, top, right, y_middle),
+                Location::SouthWest => Bounds(left, y_middle, x_middle, bottom),
+                Location::SouthEast => Bounds(x_middle, y_middle, right, bottom),
+            };
+            let item = items.next().expect("one item per location");
+            self.insert(CNNode::new(item, level + 1, bounds, Some(index)))
+        });
+        self.store[index.key].update_children(Some(children));
+
+        if self.layers.len() <= level + 1 {
+            self.layers.resize(level + 2, 0);
+        }
+        self.layers[level + 1] += 4;
+        Ok(children)
+    }
+
+    /// Returns `middle`, or the split point of `[min, max)` chosen by the split policy if None.
+    /// Fails if either half would be smaller than the minimum cell size or empty.
+    fn split_point(&self, min: S, max: S, middle: Option<S>) -> Result<S, Error> {
+        // Splitting never computes the extent, but widths are computed elsewhere, so extents
+        // that don't fit in S are rejected. They're checked in f64 when the coordinates convert
+        // to finite floats; types that don't, such as big integers, have no range to overflow.
+        let extent = max
+            .to_f64()
+            .zip(min.to_f64())
+            .map(|(max, min)| max - min)
+            .filter(|extent| extent.is_finite());
+        if extent.is_some_and(|extent| S::from_f64(extent).is_none()) {
+            return Err(Error::Overflow);
+        }
+
+        let middle = match middle {
+            Some(middle) => middle,
+            None => self
+                .split_policy
+                .split(min, max)
+                .ok_or(Error::UnitConversion)?,
+        };
+
+        // Checked before subtracting, which would underflow unsigned units outside the range.
+        if !(min < middle && middle < max) {
+            return Err(Error::CellTooSmall);
+        }
+        let (lower, upper) = (middle - min, max - middle);
+        if lower < self.min_cell_size || upper < self.min_cell_size {
+            return Err(Error::CellTooSmall);
+        }
+
+        Ok(middle)
+    }
+
+    /// Descends from the root to the leaf containing the point, calling `visit` with each node's
+    /// index and its location among its siblings (None for the root). Coordinates within
+    /// `tolerance` below a split line count as on it.
+    fn descend<F>(&self, point: Point<S>, tolerance: S, visit: F) -> Option<NodeId>
+    where
+        F: FnMut(NodeId, Option<Location>),
+    {
+        self.descend_from(self.root_key, point, tolerance, visit)
+    }
+
+    /// Same as [`CNQuadtree::descend`] but from the node at `index`, which `visit` is given
+    /// without a location.
+    pub(crate) fn descend_from<F>(
+        &self,
+        mut index: NodeId,
+        point: Point<S>,
+        tolerance: S,
+        mut visit: F,
+    ) -> Option<NodeId>
+    where
+        F: FnMut(NodeId, Option<Location>),
+    {
+        let mut node = self.get_node(index)?;
+        if !node.point_in(point) {
+            return None;
+        }
+        visit(index, None);
+
+        while let Some(children) = node.get_children_index() {
+            let (position, child) = self.child_containing(children, point, tolerance)?;
+            index = children[position];
+            node = child;
+            visit(index, position.try_into().ok());
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.point_locate(node.level());
+        }
+
+        Some(index)
+    }
+
+    /// Returns the position among the children of the one containing the point, assuming the
+    /// parent contains it, with the child found there. The split lines are the north west
+    /// child's right and bottom edges, and points on them go to the children past them.
+    /// Coordinates within `tolerance` below a split line are moved onto it first.
+    ///
+    /// Descents call this at every level, so the children are looked up in the store directly,
+    /// as they're known to be in this tree, and the north west child is looked up once and
+    /// reused if it's the one containing the point.
+    ///
+    /// Location codes aren't cached per mutation. Random descents are dominated by cache misses
+    /// on the nodes themselves rather than by key resolution, and a table rebuilt lazily from
+    /// `&self` would need interior mutability that shared readers such as
+    /// [`ShardedQuadtree`](crate::concurrent::ShardedQuadtree) would contend on.
+    #[inline]
+    fn child_containing(
+        &self,
+        children: [NodeId; 4],
+        point: Point<S>,
+        tolerance: S,
+    ) -> Option<(usize, &CNNode<T, NodeId, S>)> {
+        let nw = self.store.get(children[0].key)?;
+        let Bounds(_, _, x_middle, y_middle) = nw.get_bounds();
+        match child_at((x_middle, y_middle), point, tolerance) {
+            0 => Some((0, nw)),
+            position => Some((position, self.store.get(children[position].key)?)),
+        }
+    }
+
+    /// Returns the split point of the node with these children, its north west child's bottom
+    /// right corner.
+    #[inline]
+    fn middle_of(&self, children: [NodeId; 4]) -> Option<Point<S>> {
+        let Bounds(_, _, x_middle, y_middle) = self.store.get(children[0].key)?.get_bounds();
+        Some((x_middle, y_middle))
+    }
+
+    #[inline]
+    fn get_max_level(&self) -> usize {
+        self.layers.len() - 1
+    }
+}
+
+/// Rewrites the node's parent, children and neighbors through the key mapping.
+fn remap_links<T, S>(node: &mut CNNode<T, NodeId, S>, keys: &KeyMap)
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    node.update_parent(node.get_parent_index().map(|p| keys[p.key]));
+    node.update_children(node.get_children_index().map(|c| c.map(|c| keys[c.key])));
+    node.update_neighbors(
+        node.get_cardinal_neighbors_index()
+            .map(|n| n.map(|n| keys[n.key])),
+    );
+}
+
+impl<T, S> RegionQuadtree<T> for CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Index = NodeId;
+    type Node = CNNode<T, NodeId, S>;
+
+    fn get_node(&self, index: Self::Index) -> Option<&Self::Node> {
+        if index.tree != self.id {
+            return None;
+        }
+        self.store.get(index.key)
+    }
+
+    fn get_node_mut(&mut self, index: Self::Index) -> Option<&mut Self::Node> {
+        if index.tree != self.id {
+            return None;
+        }
+        self.store.get_mut(index.key)
+    }
+
+    fn get_root(&self) -> Self::Index {
+        self.root_key
+    }
+
+    fn subdivide(
+        &mut self,
+        index: Self::Index,
+        items: [T; 4],
+    ) -> Result<[Self::Index; 4], SubdivideError<T>> {
+        self.split_node(index, None, items)
+    }
+
+    fn pop_children(&mut self, index: Self::Index) -> Result<[T; 4], PopChildrenError> {
+        let node = self.get_node(index).ok_or(PopChildrenError::InvalidIndex)?;
+        let parent_layer = node.level();
+        enter_span!(
+            "pop_children",
+            node = ?index,
+            level = parent_layer,
+            left = node.get_bounds().0.to_f64(),
+            top = node.get_bounds().1.to_f64(),
+            right = node.get_bounds().2.to_f64(),
+            bottom = node.get_bounds().3.to_f64(),
+        );
+        let children = node
+            .get_children_index()
+            .ok_or(PopChildrenError::NotSubdivided)?;
+
+        // Check if children has children.
+        for child in children {
+            if self
+                .get_node(child)
+                .ok_or(Error::DanglingIndex)?
+                .has_children()
+            {
+                debug_event!(node = ?index, "pop_children failed: children are subdivided");
+                return Err(PopChildrenError::ChildrenSubdivided);
+            }
+        }
+
+        let [nw_key, ne_key, sw_key, se_key] = children;
+
+        let (w_neighbors, n_neighbors, e_neighbors, s_neighbors) = if self.batching {
+            Default::default()
+        } else {
+            (
+                self.joined_neighbor_chain(nw_key, sw_key, Cardinality::West)?,
+                self.joined_neighbor_chain(nw_key, ne_key, Cardinality::North)?,
+                self.joined_neighbor_chain(se_key, ne_key, Cardinality::East)?,
+                self.joined_neighbor_chain(se_key, sw_key, Cardinality::South)?,
+            )
+        };
+
+        let (nw, se) = (&self.store[nw_key.key], &self.store[se_key.key]);
+        let parent_neighbors = [
+            nw.get_cardinal_neighbor_index(Cardinality::West),
+            nw.get_cardinal_neighbor_index(Cardinality::North),
+            se.get_cardinal_neighbor_index(Cardinality::East),
+            se.get_cardinal_neighbor_index(Cardinality::South),
+        ];
+
+        // Update neighbor nodes that point to child nodes to point to the parent.
+        self.redirect_neighbors(&w_neighbors, &children, Cardinality::East, |_| index);
+        self.redirect_neighbors(&n_neighbors, &children, Cardinality::South, |_| index);
+        self.redirect_neighbors(&e_neighbors, &children, Cardinality::West, |_| index);
+        self.redirect_neighbors(&s_neighbors, &children, Cardinality::North, |_| index);
+
+        let parent = &mut self.store[index.key];
+        parent.update_neighbors(parent_neighbors);
+        parent.update_children(None);
+
+        if let Some(count) = self.layers.get_mut(parent_layer + 1) {
+            *count = count.saturating_sub(4);
+        }
+        while self.layers.len() > 1 && self.layers.last() == Some(&0) {
+            self.layers.pop();
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.merge();
+        }
+        trace_event!(children = ?children, "popped children");
+        Ok(children.map(|c| self.remove(c).unwrap().pop()))
+    }
+
+    fn point_locate(&self, point: Point<S>) -> Option<Self::Index> {
+        let leaf = self.descend(point, S::zero(), |_, _| {});
+        trace_event!(
+            x = point.0.to_f64(),
+            y = point.1.to_f64(),
+            leaf = ?leaf,
+            level = leaf.and_then(|l| self.get_node(l)).map(|n| n.level()),
+            "point_locate"
+        );
+        leaf
+    }
+
+    fn point_locate_path(&self, point: Point<S>) -> Option<Vec<(Self::Index, Option<Location>)>> {
+        let mut path = Vec::with_capacity(self.get_max_level() + 1);
+        self.descend(point, S::zero(), |index, location| {
+            path.push((index, location))
+        })?;
+        Some(path)
+    }
+}
+
+/// Returns the position among the children split at `middle` of the one containing the point,
+/// as for [`CNQuadtree::child_containing`].
+#[inline]
+fn child_at<S>(middle: Point<S>, point: Point<S>, tolerance: S) -> usize
+where
+    S: Copy + PartialOrd + NumOps + NumAssign,
+{
+    // Compared without subtracting when there's no tolerance, so the result is exactly the
+    // comparison `point_in` makes.
+    let past = |value: S, middle: S| {
+        value >= middle || (tolerance > S::zero() && middle - value <= tolerance)
+    };
+    // Children are ordered NW, NE, SW, SE so the x bit is the low bit of the child index.
+    past(point.0, middle.0) as usize | (past(point.1, middle.1) as usize) << 1
+}
+
+/// Returns the node at the index like [`RegionQuadtree::get_node`].
+///
+/// # Panics
+///
+/// Panics if the index is invalid.
+impl<T, S> Index<NodeId> for CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Output = CNNode<T, NodeId, S>;
+
+    fn index(&self, index: NodeId) -> &Self::Output {
+        self.get_node(index).expect("node index is invalid")
+    }
+}
+
+/// Returns the node at the index like [`RegionQuadtree::get_node_mut`].
+///
+/// # Panics
+///
+/// Panics if the index is invalid.
+impl<T, S> IndexMut<NodeId> for CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn index_mut(&mut self, index: NodeId) -> &mut Self::Output {
+        self.get_node_mut(index).expect("node index is invalid")
+    }
+}
+
+/// Consumes the tree and yields the items of its leaves in Morton order. Items of internal
+/// nodes are dropped.
+impl<T, S> IntoIterator for CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let leaves: Vec<_> = self.leaves_morton().collect();
+        leaves
+            .into_iter()
+            .filter_map(|leaf| self.store.remove(leaf.key))
+            .map(|node| node.pop())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+/// Clones every node under a tree tag of its own, so indices of the original tree are rejected by
+/// the clone and must be re-resolved, for example with the mapping from
+/// [`CNQuadtree::clone_with_ids`].
+impl<T, S> Clone for CNQuadtree<T, S>
+where
+    T: Clone,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a copy of the tree with a tag of its own, so ids of one don't find nodes in the
+    /// other. Use [`CNQuadtree::clone_with_ids`] to carry ids across.
+    fn clone(&self) -> Self {
+        self.clone_with_ids().0
+    }
+}
+
+/// Prints the hierarchy depth-first with one node per line, indented by level, as
+/// `location bounds level: item`.
+impl<T, S> Debug for CNQuadtree<T, S>
+where
+    T: Debug,
+    S: Debug
+        + Copy
+        + Clone
+        + PartialOrd
+        + PartialEq
+        + NumAssign
+        + ToPrimitive
+        + NumOps
+        + FromPrimitive,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "CNQuadtree {{")?;
+
+        let mut stack = vec![(self.root_key, None)];
+        while let Some((key, location)) = stack.pop() {
+            let node = &self.store[key.key];
+            let indent = "    ".repeat(node.level() + 1);
+            let location = match location {
+                None => String::from("Root"),
+                Some(l) => format!("{:?}", l),
+            };
+            writeln!(
+                f,
+                "{}{} {:?} level {}: {:?}",
+                indent,
+                location,
+                node.get_bounds(),
+                node.level(),
+                node.get_item()
+            )?;
+
+            if let Some(children) = node.get_children_index() {
+                for (i, child) in children.into_iter().enumerate().rev() {
+                    stack.push((child, Some(Location::try_from(i).unwrap())));
+                }
+            }
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// Trees are equal if they have the same structure, bounds and items, regardless of keys.
+impl<T, S> PartialEq for CNQuadtree<T, S>
+where
+    T: PartialEq,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn eq(&self, other: &Self) -> bool {
+        if self.store.len() != other.store.len() {
+            return false;
+        }
+
+        let mut stack = vec![(self.root_key, other.root_key)];
+        while let Some((a, b)) = stack.pop() {
+            let (a, b) = (&self.store[a.key], &other.store[b.key]);
+            if a.get_bounds() != b.get_bounds() || a.get_item() != b.get_item() {
+                return false;
+            }
+            match (a.get_children_index(), b.get_children_index()) {
+                (None, None) => {}
+                (Some(a), Some(b)) => stack.extend(a.into_iter().zip(b)),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+impl<T, S> Eq for CNQuadtree<T, S>
+where
+    T: Eq,
+    S: Eq
+        + Copy
+        + Clone
+        + PartialOrd
+        + PartialEq
+        + NumAssign
+        + ToPrimitive
+        + NumOps
+        + FromPrimitive,
+{
+}
+
+/// Hashes the structure, bounds and items in depth-first order, consistent with [`PartialEq`].
+impl<T, S> Hash for CNQuadtree<T, S>
+where
+    T: Hash,
+    S: Hash
+        + Copy
+        + Clone
+        + PartialOrd
+        + PartialEq
+        + NumAssign
+        + ToPrimitive
+        + NumOps
+        + FromPrimitive,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.store.len().hash(state);
+
+        let mut stack = vec![self.root_key];
+        while let Some(key) = stack.pop() {
+            let node = &self.store[key.key];
+            node.get_bounds().hash(state);
+            node.get_item().hash(state);
+            let children = node.get_children_index();
+            children.is_some().hash(state);
+            if let Some(children) = children {
+                stack.extend(children);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::CNQuadtreeBuilder;
+    use crate::error::NeighborError;
+    use crate::node::midpoint;
+    use crate::tree::{Containment, Visit, VisitFlow};
+
+    /// Asserts that every leaf's cardinal neighbors are the leaves touching its corners, that its
+    /// neighbor chains list every adjacent leaf in order, and that internal nodes have none.
+    fn assert_neighbors_consistent<T>(tree: &CNQuadtree<T, i32>) {
+        for (key, node) in tree.store.iter() {
+            let index = NodeId { key, tree: tree.id };
+            if node.has_children() {
+                assert_eq!(node.get_cardinal_neighbors_index(), [None; 4]);
+                continue;
+            }
+
+            let Bounds(l, t, r, b) = node.get_bounds();
+            let expected = [
+                tree.point_locate((l - 1, t)),
+                tree.point_locate((l, t - 1)),
+                tree.point_locate((r, b - 1)),
+                tree.point_locate((r - 1, b)),
+            ];
+            assert_eq!(node.get_cardinal_neighbors_index(), expected);
+
+            let sides: [Vec<Point<i32>>; 4] = [
+                (t..b).map(|y| (l - 1, y)).collect(),
+                (l..r).map(|x| (x, t - 1)).collect(),
+                (t..b).rev().map(|y| (r, y)).collect(),
+                (l..r).rev().map(|x| (x, b)).collect(),
+            ];
+            for (direction, side) in sides.into_iter().enumerate() {
+                let mut expected: Vec<_> = side
+                    .into_iter()
+                    .filter_map(|p| tree.point_locate(p))
+                    .collect();
+                expected.dedup();
+                let neighbors = tree
+                    .get_neighbors(index, direction.try_into().unwrap())
+                    .unwrap_or_default()
+                    .into_vec();
+                assert_eq!(neighbors, expected);
+            }
+        }
+    }
+
+    /// A small linear congruential generator so tests are deterministic without extra
+    /// dependencies.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: i32) -> i32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % bound as u64) as i32
+        }
+    }
+
+    #[test]
+    fn basic_subdivide() {
+        let mut tree = CNQuadtree::new("root".to_string(), (0, 0, 100, 100));
+        let root = tree.get_root();
+
+        let child_items = [
+            "nw".to_string(),
+            "ne".to_string(),
+            "sw".to_string(),
+            "se".to_string(),
+        ];
+
+        let children = tree.subdivide(root, child_items.clone());
+        assert!(children.is_ok());
+        for (i, child) in children.unwrap().into_iter().enumerate() {
+            assert_eq!(tree.get_node(child).unwrap().get_item(), &child_items[i]);
+        }
+    }
+
+    #[test]
+    fn neighbors_across_levels() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let [a, _, c, _] = tree.subdivide(ne, [0; 4]).unwrap();
+        let [c_nw, _, c_sw, _] = tree.subdivide(c, [0; 4]).unwrap();
+        assert_neighbors_consistent(&tree);
+
+        let neighbors =
+            |index, direction| tree.get_neighbors(index, direction).map(|n| n.into_vec());
+        // Same level, two levels finer spread over a finer sibling, and coarser.
+        assert_eq!(neighbors(sw, Cardinality::North), Ok(vec![nw]));
+        assert_eq!(neighbors(nw, Cardinality::East), Ok(vec![c_sw, c_nw, a]));
+        assert_eq!(neighbors(c_nw, Cardinality::West), Ok(vec![nw]));
+        assert_eq!(neighbors(c_sw, Cardinality::South), Ok(vec![se]));
+        // Borders have no neighbors, while internal and missing nodes are errors.
+        assert_eq!(neighbors(nw, Cardinality::West), Ok(vec![]));
+        assert_eq!(
+            neighbors(ne, Cardinality::West),
+            Err(NeighborError::NotLeaf)
+        );
+        tree.pop_children(c).unwrap();
+        assert_eq!(
+            tree.get_neighbors(c_nw, Cardinality::West),
+            Err(NeighborError::InvalidIndex)
+        );
+
+        // A chain that stops short of the side's end is corrupted, not truncated.
+        let [_, _, c_sw, _] = tree.subdivide(c, [0; 4]).unwrap();
+        tree.get_node_mut(c_sw)
+            .unwrap()
+            .update_neighbor(None, Cardinality::North);
+        assert_eq!(
+            tree.get_neighbors(nw, Cardinality::East),
+            Err(NeighborError::Corrupted(Error::BrokenChain))
+        );
+    }
+
+    /// A read-only tree that counts the nodes looked up through it.
+    struct CountingLookups<'a> {
+        tree: &'a CNQuadtree<i32, i32>,
+        lookups: std::cell::Cell<usize>,
+    }
+
+    impl RegionQuadtree<i32> for CountingLookups<'_> {
+        type Index = NodeId;
+        type Node = CNNode<i32, NodeId, i32>;
+
+        fn get_node(&self, index: NodeId) -> Option<&Self::Node> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.tree.get_node(index)
+        }
+
+        fn get_node_mut(&mut self, _: NodeId) -> Option<&mut Self::Node> {
+            None
+        }
+
+        fn get_root(&self) -> NodeId {
+            self.tree.get_root()
+        }
+
+        fn subdivide(
+            &mut self,
+            _: NodeId,
+            items: [i32; 4],
+        ) -> Result<[NodeId; 4], SubdivideError<i32>> {
+            Err(SubdivideError {
+                items,
+                source: Error::InvalidIndex,
+            })
+        }
+
+        fn pop_children(&mut self, _: NodeId) -> Result<[i32; 4], PopChildrenError> {
+            Err(PopChildrenError::InvalidIndex)
+        }
+
+        fn point_locate(&self, point: Point<i32>) -> Option<NodeId> {
+            self.tree.point_locate(point)
+        }
+
+        fn point_locate_path(&self, point: Point<i32>) -> Option<Vec<(NodeId, Option<Location>)>> {
+            self.tree.point_locate_path(point)
+        }
+    }
+
+    #[test]
+    fn neighbor_chain_worst_case() {
+        // A level 1 leaf facing a column of leaves at level 12 along its east side.
+        const DEPTH: usize = 12;
+        let half = 1 << DEPTH;
+        let mut tree = CNQuadtree::new(0, (0, 0, 2 * half, 2 * half));
+        tree.refine_to_levels(
+            |b| {
+                if b.0 <= half && b.2 > half && b.1 < half {
+                    DEPTH
+                } else {
+                    0
+                }
+            },
+            |_, _| [0; 4],
+        )
+        .unwrap();
+        let nw = tree.point_locate((0, 0)).unwrap();
+        assert_eq!(tree[nw].level(), 1);
+
+        let counting = CountingLookups {
+            tree: &tree,
+            lookups: Default::default(),
+        };
+        let column = counting.get_neighbors(nw, Cardinality::East).unwrap();
+        assert_eq!(column.len(), 1 << (DEPTH - 1));
+        assert_eq!(counting.lookups.get(), column.len() + 1);
+        assert!(tree
+            .neighbors_iter(nw, Cardinality::East)
+            .eq(column.iter().copied()));
+
+        // The other way round, each deep leaf has a single neighbor.
+        for &leaf in &column {
+            assert_eq!(tree[leaf].level(), DEPTH);
+            counting.lookups.set(0);
+            let neighbors = counting.get_neighbors(leaf, Cardinality::West).unwrap();
+            assert_eq!(neighbors.into_vec(), [nw]);
+            assert_eq!(counting.lookups.get(), 2);
+        }
+
+        // A chain that loops back is reported instead of followed.
+        let (first, second) = (column[0], column[1]);
+        tree.get_node_mut(second)
+            .unwrap()
+            .update_neighbor(Some(first), Cardinality::North);
+        assert_eq!(
+            tree.get_neighbors(nw, Cardinality::East),
+            Err(NeighborError::Corrupted(Error::BrokenChain))
+        );
+        assert_eq!(tree.neighbors_iter(nw, Cardinality::East).count(), 2);
+    }
+
+    #[test]
+    fn index_and_into_iter() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 4, 4));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        tree[nw].update_neighbor(None, Cardinality::North);
+        *tree[nw].get_item_mut() = 9;
+        assert_eq!(*tree[nw].get_item(), 9);
+        assert_eq!(tree[ne].level(), 1);
+        assert_eq!(tree.into_iter().collect::<Vec<_>>(), [9, 5, 6, 7, 8, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "node index is invalid")]
+    fn index_panics() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 4, 4));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.pop_children(tree.get_root()).unwrap();
+        let _ = &tree[nw];
+    }
+
+    #[test]
+    fn counts() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        assert_eq!(
+            (tree.node_count(), tree.leaf_count(), tree.height()),
+            (1, 1, 0)
+        );
+        let [nw, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let [nw_nw, ..] = tree.subdivide(nw, [0; 4]).unwrap();
+        tree.subdivide(nw_nw, [0; 4]).unwrap();
+        assert_eq!(
+            (tree.node_count(), tree.leaf_count(), tree.height()),
+            (13, 10, 3)
+        );
+        assert_eq!(tree.level_counts(), [1, 4, 4, 4]);
+        assert_eq!(tree.leaf_count(), tree.leaves_morton().count());
+
+        tree.pop_children(nw_nw).unwrap();
+        assert_eq!(
+            (tree.node_count(), tree.leaf_count(), tree.height()),
+            (9, 7, 2)
+        );
+        assert_eq!(tree.level_counts(), [1, 4, 4]);
+        tree.collapse(tree.get_root()).unwrap();
+        assert_eq!(tree.level_counts(), [1]);
+        assert_eq!(tree.height(), 0);
+    }
+
+    #[test]
+    fn index_status() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        tree.set_tombstones_enabled(true);
+        let root = tree.get_root();
+        let children = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        assert!(tree.is_live(children[0]));
+        assert_eq!(tree.index_status(children[0]), IndexStatus::Live);
+
+        tree.pop_children(root).unwrap();
+        assert!(!tree.is_live(children[0]));
+        assert_eq!(tree.index_status(children[0]), IndexStatus::Removed);
+
+        // The new children reuse the old ones' slots.
+        tree.subdivide(root, [5, 6, 7, 8]).unwrap();
+        assert!(children
+            .iter()
+            .all(|&child| tree.index_status(child) == IndexStatus::Recycled));
+
+        let other = CNQuadtree::new(0, (0, 0, 8, 8));
+        assert_eq!(
+            tree.index_status(other.get_root()),
+            IndexStatus::NeverExisted
+        );
+
+        let root = tree.clear(0);
+        assert_eq!(tree.index_status(root), IndexStatus::Live);
+        tree.set_tombstones_enabled(false);
+        let [nw, ..] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        tree.pop_children(root).unwrap();
+        assert_eq!(tree.index_status(nw), IndexStatus::Removed);
+    }
+
+    #[test]
+    fn make_root() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        tree.set_max_depth(Some(4));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        let [_, _, _, se] = tree.subdivide(nw, [9, 10, 11, 12]).unwrap();
+        let grandchildren = tree.subdivide(se, [13, 14, 15, 16]).unwrap();
+
+        tree.make_root(nw).unwrap();
+        assert_eq!(tree.get_root(), nw);
+        assert_eq!(tree.bounds(), (0, 0, 8, 8));
+        assert_eq!(tree.node_count(), 9);
+        assert_eq!(tree.level_counts(), [1, 4, 4]);
+        assert_eq!(tree.max_depth(), Some(3));
+        assert_eq!(tree[grandchildren[0]].level(), 2);
+        assert!(!tree.is_live(ne));
+        assert_neighbors_consistent(&tree);
+        assert_eq!(tree.point_locate((5, 5)), Some(grandchildren[0]));
+        assert_eq!(tree.point_locate((9, 5)), None);
+
+        assert_eq!(tree.make_root(ne), Err(Error::InvalidIndex));
+        tree.make_root(nw).unwrap();
+        assert_eq!(tree.node_count(), 9);
+    }
+
+    #[test]
+    fn clear() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        tree.set_max_depth(Some(2));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.subdivide(nw, [0; 4]).unwrap();
+        let capacity = tree.capacity();
+
+        let root = tree.clear(7);
+        assert_eq!(root, tree.get_root());
+        assert!(tree.get_node(nw).is_none());
+        assert_eq!((tree.node_count(), tree.height()), (1, 0));
+        assert_eq!(*tree[root].get_item(), 7);
+        assert_eq!(tree.bounds(), (0, 0, 8, 8));
+        assert_eq!(tree.capacity(), capacity);
+        assert_eq!(tree.max_depth(), Some(2));
+
+        let [nw, ..] = tree.subdivide(root, [0; 4]).unwrap();
+        assert_eq!(tree.point_locate((1, 1)), Some(nw));
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn node_limit() {
+        let mut tree = CNQuadtreeBuilder::new((0, 0, 64, 64))
+            .node_limit(9)
+            .build(0);
+        let capacity = tree.capacity();
+        assert!(capacity >= 9);
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.subdivide(nw, [0; 4]).unwrap();
+        let err = tree.subdivide(ne, [1; 4]).unwrap_err();
+        assert_eq!((err.source, err.items), (Error::OutOfNodes, [1; 4]));
+        assert_neighbors_consistent(&tree);
+
+        // Popped slots are reused without growing the store.
+        tree.pop_children(nw).unwrap();
+        tree.subdivide(ne, [1; 4]).unwrap();
+        assert_eq!(tree.capacity(), capacity);
+        tree.defragment();
+        assert_eq!(tree.capacity(), capacity);
+    }
+
+    #[test]
+    fn split_mut() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(se, [5, 6, 7, 8]).unwrap();
+
+        let (counts, [nw, ne]) = tree
+            .split_mut([nw, ne], |[mut a, mut b]| {
+                std::thread::scope(|scope| {
+                    let a = scope.spawn(move || {
+                        let root = a.get_root();
+                        let [_, _, _, a_se] = a.subdivide(root, [9; 4]).unwrap();
+                        a.subdivide(a_se, [10; 4]).unwrap();
+                        a.node_count()
+                    });
+                    let b = scope.spawn(move || {
+                        let root = b.get_root();
+                        b.subdivide(root, [11; 4]).unwrap();
+                        b.node_count()
+                    });
+                    (a.join().unwrap(), b.join().unwrap())
+                })
+            })
+            .unwrap();
+        assert_eq!(counts, (9, 5));
+        assert_eq!(tree.node_count(), 21);
+        assert_eq!(tree.height(), 3);
+        assert_eq!(tree.get_node(nw).unwrap().get_bounds(), (0, 0, 32, 32));
+        assert_eq!(tree.get_node(ne).unwrap().level(), 1);
+        assert_eq!(tree[tree.point_locate((20, 20)).unwrap()].get_item(), &10);
+        assert_eq!(
+            tree.get_node(tree.point_locate((40, 40)).unwrap())
+                .unwrap()
+                .level(),
+            2
+        );
+        assert_neighbors_consistent(&tree);
+
+        assert_eq!(
+            tree.split_mut([sw, sw], |_| ()).unwrap_err(),
+            Error::OverlappingSubtrees
+        );
+        assert_eq!(
+            tree.split_mut([tree.get_root(), sw], |_| ()).unwrap_err(),
+            Error::OverlappingSubtrees
+        );
+        tree.pop_children(ne).unwrap();
+        assert_eq!(tree.split_mut([nw, se], |_| ()).map(|_| ()), Ok(()));
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn batch() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        tree.begin_batch();
+        assert!(tree.is_batching());
+        let mut frontier = vec![tree.get_root()];
+        for _ in 0..3 {
+            frontier = frontier
+                .into_iter()
+                .flat_map(|leaf| tree.subdivide(leaf, [0; 4]).unwrap())
+                .collect();
+        }
+        let [nw, ..] = tree.subdivide(frontier[0], [1; 4]).unwrap();
+        tree.subdivide(nw, [2; 4]).unwrap();
+        tree.pop_children(nw).unwrap();
+        tree.commit();
+        assert!(!tree.is_batching());
+        assert_eq!(tree.node_count(), 1 + 4 + 16 + 64 + 4);
+        assert_neighbors_consistent(&tree);
+
+        // Outside a batch commit does nothing.
+        tree.commit();
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn subdivide_errors() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        let [nw, ..] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+
+        let err = tree.subdivide(root, [5, 6, 7, 8]).unwrap_err();
+        assert_eq!(err.source, Error::AlreadySubdivided);
+        assert_eq!(err.items, [5, 6, 7, 8]);
+
+        tree.pop_children(root).unwrap();
+        let err = tree.subdivide(nw, [5, 6, 7, 8]).unwrap_err();
+        assert_eq!(err.source, Error::InvalidIndex);
+        assert_eq!(err.to_string(), "node index is invalid");
+        assert_eq!(
+            tree.pop_children(root),
+            Err(PopChildrenError::NotSubdivided)
+        );
+    }
+
+    #[test]
+    fn subdivide_degenerate_and_extreme_bounds() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 3, 2));
+        let root = tree.get_root();
+        let [nw, ne, ..] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        assert_eq!(tree.get_node(nw).unwrap().get_bounds(), (0, 0, 1, 1));
+        assert_eq!(tree.get_node(ne).unwrap().get_bounds(), (1, 0, 3, 1));
+
+        let err = tree.subdivide(nw, [5, 6, 7, 8]).unwrap_err();
+        assert_eq!(err.source, Error::CellTooSmall);
+        let err = tree.subdivide(ne, [5, 6, 7, 8]).unwrap_err();
+        assert_eq!(err.source, Error::CellTooSmall);
+
+        let mut tree = CNQuadtree::new(0u8, (200u8, 200, 255, 255));
+        let root = tree.get_root();
+        let [nw, ..] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            tree.get_node(nw).unwrap().get_bounds(),
+            (200, 200, 227, 227)
+        );
+
+        let mut tree = CNQuadtree::new(0i8, (-128i8, -128, 127, 127));
+        let err = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap_err();
+        assert_eq!(err.source, Error::Overflow);
+
+        let mut tree = CNQuadtree::new(0, (-64, -64, 64, 64));
+        tree.set_min_cell_size(32);
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [nw_nw, ..] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        assert_eq!(
+            tree.get_node(nw_nw).unwrap().get_bounds(),
+            (-64, -64, -32, -32)
+        );
+        let err = tree.subdivide(nw_nw, [9, 10, 11, 12]).unwrap_err();
+        assert_eq!(err.source, Error::CellTooSmall);
+    }
+
+    #[test]
+    fn subdivide_max_depth() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        tree.set_max_depth(Some(2));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [nw_nw, ..] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+
+        let err = tree.subdivide(nw_nw, [9, 10, 11, 12]).unwrap_err();
+        assert_eq!(err.source, Error::MaxDepthExceeded);
+        assert_eq!(err.items, [9, 10, 11, 12]);
+
+        tree.set_max_depth(None);
+        assert!(tree.subdivide(nw_nw, [9, 10, 11, 12]).is_ok());
+    }
+
+    #[test]
+    fn pop_children_errors() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        let [nw, ..] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+
+        assert_eq!(
+            tree.pop_children(root),
+            Err(PopChildrenError::ChildrenSubdivided)
+        );
+        assert_eq!(tree.pop_children(nw), Ok([5, 6, 7, 8]));
+        assert_eq!(tree.pop_children(nw), Err(PopChildrenError::NotSubdivided));
+        assert_eq!(tree.pop_children(root), Ok([1, 2, 3, 4]));
+        assert_eq!(tree.pop_children(nw), Err(PopChildrenError::InvalidIndex));
+        assert_eq!(
+            Error::from(PopChildrenError::InvalidIndex),
+            Error::InvalidIndex
+        );
+    }
+
+    #[test]
+    fn point_locate() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        assert_eq!(tree.point_locate((50, 50)), Some(root));
+        assert_eq!(tree.point_locate((100, 50)), None);
+
+        let [nw, ne, sw, se] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        assert_eq!(tree.point_locate((10, 10)), Some(nw));
+        assert_eq!(tree.point_locate((60, 10)), Some(ne));
+        assert_eq!(tree.point_locate((10, 60)), Some(sw));
+        assert_eq!(tree.point_locate((99, 99)), Some(se));
+
+        let [_, se_ne, _, _] = tree.subdivide(se, [5, 6, 7, 8]).unwrap();
+        assert_eq!(tree.point_locate((80, 60)), Some(se_ne));
+        assert_eq!(tree.point_locate((60, 10)), Some(ne));
+    }
+
+    #[test]
+    fn point_locate_odd_extents() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 5, 7));
+        let [_, _, _, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(se, [5, 6, 7, 8]).unwrap();
+        for x in 0..5 {
+            for y in 0..7 {
+                let leaf = tree.point_locate((x, y)).unwrap();
+                assert!(tree.get_node(leaf).unwrap().point_in((x, y)));
+            }
+        }
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn metrics() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        assert_eq!(tree.metrics(), None);
+        tree.set_metrics_enabled(true);
+
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.subdivide(nw, [0; 4]).unwrap();
+        tree.point_locate((1, 1));
+        tree.point_locate((5, 1));
+        tree.pop_children(nw).unwrap();
+        let _ = tree.subdivide(ne, [0; 4]);
+        let _ = tree.pop_children(ne);
+
+        let metrics = tree.metrics().unwrap();
+        assert_eq!(metrics.subdivisions, 3);
+        assert_eq!(metrics.merges, 2);
+        assert_eq!(metrics.point_locates, 2);
+        assert_eq!(metrics.average_point_locate_depth(), 1.5);
+        // Four chains per subdivision and eight per merge.
+        assert_eq!(metrics.neighbor_chains, 28);
+        assert!(metrics.neighbor_chain_nodes > 0);
+        assert_eq!(tree.clone().metrics(), Some(metrics));
+
+        tree.set_metrics_enabled(false);
+        assert_eq!(tree.metrics(), None);
+    }
+
+    #[test]
+    fn item_at_level() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [_, _, _, nw_se] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+
+        assert_eq!(tree.item_at_level((20, 20), 0), Some(&0));
+        assert_eq!(tree.item_at_level((20, 20), 1), Some(&1));
+        assert_eq!(tree.item_at_level((20, 20), 2), Some(&8));
+        assert_eq!(tree.node_at_level((20, 20), 5), Some(nw_se));
+        assert_eq!(tree.item_at_level((40, 40), 3), Some(&4));
+        assert_eq!(tree.item_at_level((64, 0), 0), None);
+    }
+
+    #[test]
+    fn point_locate_batch() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 37, 29));
+        let mut rng = Lcg(3);
+        for _ in 0..80 {
+            let leaf = tree.point_locate((rng.next(37), rng.next(29))).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+
+        let points: Vec<_> = (0..500)
+            .map(|_| (rng.next(41) - 2, rng.next(33) - 2))
+            .collect();
+        let expected: Vec<_> = points.iter().map(|&p| tree.point_locate(p)).collect();
+        assert_eq!(tree.point_locate_batch(&points), expected);
+        assert!(expected.contains(&None));
+        assert_eq!(tree.point_locate_batch(&[]), vec![]);
+    }
+
+    #[test]
+    fn relocate() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 48));
+        let mut rng = Lcg(9);
+        for _ in 0..120 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(48))).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+
+        let mut point = (30, 20);
+        let mut leaf = tree.point_locate(point).unwrap();
+        for _ in 0..300 {
+            let next = (rng.next(64), rng.next(48));
+            let moved = (
+                (point.0 + (next.0 - 32) / 4).clamp(0, 63),
+                (point.1 + (next.1 - 24) / 4).clamp(0, 47),
+            );
+            assert_eq!(tree.relocate(leaf, next), tree.point_locate(next));
+            assert_eq!(tree.relocate(leaf, moved), tree.point_locate(moved));
+            point = moved;
+            leaf = tree.relocate(leaf, point).unwrap();
+        }
+
+        assert_eq!(tree.relocate(leaf, (64, 0)), None);
+        assert_eq!(
+            tree.relocate(tree.get_root(), (5, 5)),
+            tree.point_locate((5, 5))
+        );
+    }
+
+    #[test]
+    fn point_locate_path() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        assert_eq!(tree.point_locate_path((50, 50)), Some(vec![(root, None)]));
+        assert_eq!(tree.point_locate_path((50, 100)), None);
+
+        let [_, _, sw, _] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        let [_, sw_ne, _, _] = tree.subdivide(sw, [5, 6, 7, 8]).unwrap();
+        assert_eq!(
+            tree.point_locate_path((30, 60)),
+            Some(vec![
+                (root, None),
+                (sw, Some(Location::SouthWest)),
+                (sw_ne, Some(Location::NorthEast)),
+            ])
+        );
+    }
+
+    #[test]
+    fn region_locate() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        assert_eq!(tree.region_locate((10, 10, 20, 20)), Some(vec![root]));
+        assert_eq!(tree.region_locate((100, 0, 120, 20)), None);
+
+        let [nw, ne, sw, se] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        let [se_nw, se_ne, se_sw, se_se] = tree.subdivide(se, [5, 6, 7, 8]).unwrap();
+
+        assert_eq!(tree.region_locate((0, 0, 50, 50)), Some(vec![nw]));
+        assert_eq!(
+            tree.region_locate((40, 40, 70, 70)),
+            Some(vec![nw, ne, sw, se_nw])
+        );
+        assert_eq!(
+            tree.region_locate((60, 60, 80, 80)),
+            Some(vec![se_nw, se_ne, se_sw, se_se])
+        );
+        assert_eq!(
+            tree.region_locate_classified((0, 50, 75, 100)),
+            Some(vec![
+                (sw, Containment::FullyInside),
+                (se_nw, Containment::FullyInside),
+                (se_sw, Containment::FullyInside),
+            ])
+        );
+        assert_eq!(
+            tree.region_locate_classified((25, 25, 100, 75)),
+            Some(vec![
+                (nw, Containment::PartiallyOverlapping),
+                (ne, Containment::PartiallyOverlapping),
+                (sw, Containment::PartiallyOverlapping),
+                (se_nw, Containment::FullyInside),
+                (se_ne, Containment::FullyInside),
+            ])
+        );
+    }
+
+    #[test]
+    fn region_iter_short_circuits() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        let [nw, _, _, _] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+
+        let mut iter = tree.region_iter((0, 0, 100, 100));
+        assert_eq!(iter.next(), Some((nw, Containment::FullyInside)));
+        assert_eq!(iter.count(), 3);
+        assert_eq!(tree.region_iter((200, 200, 300, 300)).next(), None);
+    }
+
+    #[test]
+    fn accept() {
+        /// Counts nodes overlapping a window and stops at a given item.
+        struct Counter {
+            window: Bounds<i32>,
+            stop_at: i32,
+            visited: Vec<i32>,
+        }
+
+        impl Visit<NodeId, CNNode<i32, NodeId, i32>> for Counter {
+            fn visit(&mut self, _: NodeId, node: &CNNode<i32, NodeId, i32>) -> VisitFlow {
+                if !self.window.intersects(node.get_bounds()) {
+                    return VisitFlow::Skip;
+                }
+                self.visited.push(*node.get_item());
+                if *node.get_item() == self.stop_at {
+                    VisitFlow::Stop
+                } else {
+                    VisitFlow::Descend
+                }
+            }
+        }
+
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let [nw, _, _, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        tree.subdivide(se, [9, 10, 11, 12]).unwrap();
+
+        let mut counter = Counter {
+            window: Bounds(0, 0, 50, 100),
+            stop_at: -1,
+            visited: Vec::new(),
+        };
+        assert!(tree.accept(&mut counter));
+        assert_eq!(counter.visited, [0, 1, 5, 6, 7, 8, 3]);
+
+        counter.visited.clear();
+        counter.stop_at = 6;
+        assert!(!tree.accept(&mut counter));
+        assert_eq!(counter.visited, [0, 1, 5, 6]);
+
+        let mut leaves = 0;
+        tree.accept(&mut |_, node: &CNNode<i32, NodeId, i32>| {
+            leaves += usize::from(node.is_leaf());
+            VisitFlow::Descend
+        });
+        assert_eq!(leaves, tree.leaf_count());
+    }
+
+    #[test]
+    fn find_leaf() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        let [_, ne, _, se] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        let [_, _, _, se_se] = tree.subdivide(se, [5, 6, 7, 8]).unwrap();
+
+        assert_eq!(tree.find_leaf(None, |_, &item| item % 2 == 0), Some(ne));
+        assert_eq!(tree.find_leaf(None, |_, &item| item > 8), None);
+
+        let east_half = |bounds: Bounds<i32>| bounds.2 > 50;
+        assert_eq!(
+            tree.find_leaf(Some(&east_half), |_, &item| item > 7),
+            Some(se_se)
+        );
+        assert!(tree.any_leaf(Some(&east_half), |_, &item| item == 6));
+        assert!(!tree.any_leaf(Some(&east_half), |_, &item| item == 1));
+        assert!(tree.all_leaves(Some(&east_half), |bounds, _| bounds.2 > 50));
+        assert!(!tree.all_leaves(None, |bounds, _| bounds.2 > 50));
+    }
+
+    #[test]
+    fn leaf_curves() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 16, 16));
+        let mut rng = Lcg(5);
+        for _ in 0..60 {
+            let leaf = tree.point_locate((rng.next(16), rng.next(16))).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+        let leaves = tree.stats().leaves;
+
+        let morton: Vec<_> = tree.leaves_morton().collect();
+        let mut expected = Vec::new();
+        tree.all_leaves(None, |bounds, _| {
+            expected.push(tree.point_locate((bounds.0, bounds.1)).unwrap());
+            true
+        });
+        assert_eq!(morton, expected);
+
+        let hilbert: Vec<_> = tree.leaves_hilbert().collect();
+        assert_eq!(hilbert.len(), leaves);
+        let first = tree.get_node(hilbert[0]).unwrap().get_bounds();
+        assert_eq!((first.0, first.1), (0, 0));
+        let last = tree.get_node(hilbert[leaves - 1]).unwrap().get_bounds();
+        assert_eq!((last.2, last.1), (16, 0));
+        for pair in hilbert.windows(2) {
+            let Bounds(l0, t0, r0, b0) = tree.get_node(pair[0]).unwrap().get_bounds();
+            let Bounds(l1, t1, r1, b1) = tree.get_node(pair[1]).unwrap().get_bounds();
+            let x_touch = (r0 == l1 || r1 == l0) && t0.max(t1) < b0.min(b1);
+            let y_touch = (b0 == t1 || b1 == t0) && l0.max(l1) < r0.min(r1);
+            assert!(x_touch || y_touch);
+        }
+    }
+
+    #[test]
+    fn retain_refined() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 100, 100));
+        let root = tree.get_root();
+        let [nw, ne, sw, se] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        tree.subdivide(nw, [10, 11, 12, 13]).unwrap();
+        let [se_nw, ..] = tree.subdivide(se, [0, 14, 15, 16]).unwrap();
+        let [_, _, _, se_nw_se] = tree.subdivide(se_nw, [17, 18, 19, 20]).unwrap();
+
+        // Only the 0 in se_nw's subtree is kept.
+        *tree.get_node_mut(se_nw_se).unwrap().get_item_mut() = 0;
+        tree.retain_refined(|&item| item == 0, |bounds| bounds.0);
+
+        assert!(tree.get_node(nw).unwrap().is_leaf());
+        assert_eq!(*tree.get_node(nw).unwrap().get_item(), 0);
+        assert!(tree.get_node(ne).unwrap().is_leaf());
+        assert!(tree.get_node(sw).unwrap().is_leaf());
+        assert!(tree.get_node(se).unwrap().has_children());
+        assert!(tree.get_node(se_nw).unwrap().has_children());
+        assert_eq!(tree.layers, vec![1, 4, 4, 4]);
+
+        tree.retain_refined(|_| false, |_| 42);
+        assert!(tree.get_node(root).unwrap().is_leaf());
+        assert_eq!(*tree.get_node(root).unwrap().get_item(), 42);
+        assert_eq!(tree.store.len(), 1);
+    }
+
+    #[test]
+    fn neighbors_after_random_edits() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut rng = Lcg(7);
+
+        for _ in 0..300 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(64))).unwrap();
+            let node = tree.get_node(leaf).unwrap();
+            let Bounds(l, _, r, _) = node.get_bounds();
+
+            if rng.next(3) > 0 && r - l > 1 {
+                tree.subdivide(leaf, [1, 2, 3, 4]).unwrap();
+            } else if let Some(parent) = node.get_parent_index() {
+                let _ = tree.pop_children(parent);
+            }
+            assert_neighbors_consistent(&tree);
+        }
+
+        assert!(tree.get_max_level() > 2);
+    }
+
+    #[test]
+    fn get_equal_or_larger_neighbor() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let root = tree.get_root();
+        let [nw, ne, sw, _] = tree.subdivide(root, [1, 2, 3, 4]).unwrap();
+        let [ne_nw, _, ne_sw, _] = tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        tree.subdivide(ne_sw, [9, 10, 11, 12]).unwrap();
+
+        // NW's east cardinal neighbor is NE's NW child, but NE is the neighbor at its level.
+        assert_eq!(tree.get_neighbors(nw, Cardinality::East).unwrap().len(), 3);
+        assert_eq!(
+            tree.get_equal_or_larger_neighbor(nw, Cardinality::East),
+            Some(ne)
+        );
+        assert_eq!(
+            tree.get_equal_or_larger_neighbor(ne_nw, Cardinality::West),
+            Some(nw)
+        );
+        assert_eq!(
+            tree.get_equal_or_larger_neighbor(sw, Cardinality::North),
+            Some(nw)
+        );
+        assert_eq!(
+            tree.get_equal_or_larger_neighbor(nw, Cardinality::West),
+            None
+        );
+        assert_eq!(
+            tree.get_equal_or_larger_neighbor(root, Cardinality::East),
+            None
+        );
+    }
+
+    #[test]
+    fn rebuild_neighbors() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut rng = Lcg(11);
+        for _ in 0..200 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(64))).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+        let expected: Vec<_> = tree
+            .store
+            .values()
+            .map(|node| node.get_cardinal_neighbors_index())
+            .collect();
+
+        let keys: Vec<_> = tree.store.keys().collect();
+        for (i, node) in tree.store.values_mut().enumerate() {
+            let wrong = NodeId {
+                key: keys[(i * 7) % keys.len()],
+                tree: tree.id,
+            };
+            node.update_neighbors([Some(wrong), None, Some(wrong), None]);
+        }
+        tree.rebuild_neighbors().unwrap();
+        assert_neighbors_consistent(&tree);
+        let rebuilt: Vec<_> = tree
+            .store
+            .values()
+            .map(|node| node.get_cardinal_neighbors_index())
+            .collect();
+        assert_eq!(rebuilt, expected);
+    }
+
+    #[test]
+    fn map_items() {
+        let mut tree = CNQuadtree::new(1, (0, 0, 64, 64));
+        let root = tree.get_root();
+        let [_, ne, ..] = tree.subdivide(root, [2, 3, 4, 5]).unwrap();
+        tree.subdivide(ne, [6, 7, 8, 9]).unwrap();
+
+        let mapped = tree.map_items(|&item, Bounds(l, t, _, _)| format!("{}@{},{}", item, l, t));
+        assert_neighbors_consistent(&mapped);
+        assert_eq!(mapped.layers, tree.layers);
+        let leaf = mapped.point_locate((50, 20)).unwrap();
+        assert_eq!(mapped.get_node(leaf).unwrap().get_item(), "9@48,16");
+        let root = mapped.get_root();
+        assert_eq!(mapped.get_node(root).unwrap().get_item(), "1@0,0");
+
+        let failed = tree.try_map_items(|&item, _| if item < 7 { Ok(item) } else { Err(item) });
+        assert_eq!(failed.err(), Some(7));
+    }
+
+    #[test]
+    fn structural_equality() {
+        fn hash_of<T: Hash>(value: &T) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let mut a = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut b = CNQuadtree::new(0, (0, 0, 64, 64));
+        assert!(a == b);
+
+        // Build the same shape in a different order so the keys differ.
+        let [a_nw, _, _, a_se] = a.subdivide(a.get_root(), [1, 2, 3, 4]).unwrap();
+        a.subdivide(a_nw, [5, 6, 7, 8]).unwrap();
+        a.subdivide(a_se, [9, 10, 11, 12]).unwrap();
+        let [b_nw, _, _, b_se] = b.subdivide(b.get_root(), [1, 2, 3, 4]).unwrap();
+        b.subdivide(b_se, [9, 10, 11, 12]).unwrap();
+        b.subdivide(b_nw, [5, 6, 7, 8]).unwrap();
+        assert!(a == b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        *b.get_node_mut(b_se).unwrap().get_item_mut() = 0;
+        assert!(a != b);
+
+        *b.get_node_mut(b_se).unwrap().get_item_mut() = 4;
+        b.pop_children(b_se).unwrap();
+        assert!(a != b);
+        assert_ne!(hash_of(&a), hash_of(&b));
+        assert!(a != CNQuadtree::new(0, (0, 0, 32, 32)));
+    }
+
+    #[test]
+    fn clones_have_their_own_ids() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+
+        let (mut branch, ids) = tree.clone_with_ids();
+        assert!(branch == tree);
+        assert!(branch.get_node(nw).is_none());
+        let [branch_nw, ..] = branch.subdivide(ids[&nw], [5, 6, 7, 8]).unwrap();
+        *branch.get_node_mut(ids[&nw]).unwrap().get_item_mut() = 9;
+        // Both trees hand out the same slots, but their ids don't mix.
+        let [tree_nw, ..] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        assert!(tree.get_node(branch_nw).is_none());
+        assert!(branch.get_node(tree_nw).is_none());
+        tree.pop_children(nw).unwrap();
+
+        assert!(tree.get_node(nw).unwrap().is_leaf());
+        assert_eq!(*tree.get_node(nw).unwrap().get_item(), 1);
+        assert_neighbors_consistent(&tree);
+        assert_neighbors_consistent(&branch);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut tree = CNQuadtree::with_capacity(0, (0, 0, 64, 64), 16);
+        assert!(tree.capacity() >= 16);
+        tree.reserve(1000);
+        assert!(tree.capacity() >= 1001);
+
+        let mut index = tree.get_root();
+        for _ in 0..5 {
+            index = tree.subdivide(index, [1, 2, 3, 4]).unwrap()[3];
+        }
+        tree.collapse(tree.get_root()).unwrap();
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let root = tree.get_root();
+        let before = tree.clone();
+
+        let keys = tree.shrink_to_fit();
+        assert_eq!(tree.capacity(), 5);
+        assert_eq!(keys.len(), 5);
+        assert!(tree == before);
+        assert_eq!(
+            tree.get_node(keys[&nw]).unwrap().get_bounds(),
+            (0, 0, 32, 32)
+        );
+        assert_eq!(tree.get_root(), keys[&root]);
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn defragment() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut rng = Lcg(11);
+        for _ in 0..200 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(64))).unwrap();
+            match tree.get_node(leaf).unwrap().get_parent_index() {
+                Some(parent) if rng.next(3) == 0 => {
+                    let _ = tree.pop_children(parent);
+                }
+                _ => {
+                    let _ = tree.subdivide(leaf, [1, 2, 3, 4]);
+                }
+            }
+        }
+        let (before, ids) = tree.clone_with_ids();
+        let old_keys = tree.depth_first_keys();
+
+        let keys = tree.defragment();
+        assert!(tree == before);
+        assert_eq!(
+            tree.store.keys().collect::<Vec<_>>(),
+            tree.depth_first_keys()
+                .into_iter()
+                .map(|id| id.key)
+                .collect::<Vec<_>>()
+        );
+        for old_key in old_keys {
+            assert_eq!(
+                tree.get_node(keys[&old_key]).unwrap().get_bounds(),
+                before.get_node(ids[&old_key]).unwrap().get_bounds()
+            );
+        }
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn to_flat_arrays() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 4, 4));
+        let [_, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+
+        let flat = tree.to_flat_arrays(|item, _| *item as f32).unwrap();
+        const NONE: u32 = FlatArrays::<i32, f32>::NONE;
+        assert_eq!(flat.len(), 9);
+        assert_eq!(
+            flat.items,
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]
+        );
+        assert_eq!(flat.levels, vec![0, 1, 1, 1, 1, 2, 2, 2, 2]);
+        assert_eq!(flat.parents, vec![NONE, 0, 0, 0, 0, 2, 2, 2, 2]);
+        assert_eq!(
+            flat.first_child,
+            vec![1, NONE, 5, NONE, NONE, NONE, NONE, NONE, NONE]
+        );
+        assert_eq!(flat.bounds[5], [2, 0, 3, 1]);
+
+        assert_eq!(flat.neighbors[0], [NONE; 4]);
+        assert_eq!(flat.neighbors[1], [NONE, NONE, 7, 3]);
+        assert_eq!(flat.neighbors[4], [3, 7, NONE, NONE]);
+        assert_eq!(flat.neighbors[5], [1, NONE, 6, 7]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut tree = CNQuadtree::with_capacity(0, (0, 0, 64, 64), 20);
+        let [_, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [nw, ..] = tree.subdivide(ne, [5, 6, 7, 8]).unwrap();
+        tree.subdivide(nw, [9, 10, 11, 12]).unwrap();
+        tree.pop_children(nw).unwrap();
+
+        let stats = tree.stats();
+        assert_eq!(stats.nodes, 9);
+        assert_eq!(stats.leaves, 7);
+        assert_eq!(stats.nodes_per_level, vec![1, 4, 4]);
+        assert_eq!(stats.leaves_per_level, vec![0, 3, 4]);
+        assert_eq!(stats.average_leaf_area, 64.0 * 64.0 / 7.0);
+        assert_eq!(stats.fill_ratio, 9.0 / tree.capacity() as f64);
+        assert!(stats.estimated_bytes >= 20 * std::mem::size_of::<CNNode<i32, NodeId, i32>>());
+    }
+
+    #[test]
+    fn ids_are_tied_to_their_tree() {
+        let mut a = CNQuadtree::new(0, (0, 0, 8, 8));
+        let mut b = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [a_nw, ..] = a.subdivide(a.get_root(), [1, 2, 3, 4]).unwrap();
+        b.subdivide(b.get_root(), [5, 6, 7, 8]).unwrap();
+        assert!(b.get_node(a_nw).is_none());
+        assert!(b.get_node_mut(a.get_root()).is_none());
+        assert!(b.subdivide(a_nw, [0; 4]).is_err());
+
+        let (clone, ids) = a.clone_with_ids();
+        assert!(clone.get_node(a_nw).is_none());
+        assert_eq!(*clone.get_node(ids[&a_nw]).unwrap().get_item(), 1);
+
+        let keys = a.defragment();
+        assert!(a.get_node(a_nw).is_none());
+        assert_eq!(*a.get_node(keys[&a_nw]).unwrap().get_item(), 1);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<X: Send + Sync>() {}
+        assert_send_sync::<CNQuadtree<String, f64>>();
+        assert_send_sync::<NodeId>();
+        assert_send_sync::<crate::CNPointQuadtree<String, f64>>();
+        assert_send_sync::<crate::concurrent::ShardedQuadtree<String, f64>>();
+        assert_send_sync::<crate::CNQuadtreeView<'static, f64>>();
+    }
+
+    #[test]
+    fn wide_integer_units() {
+        let edge = 1i128 << 100;
+        let mut tree = CNQuadtree::new(0u8, (-edge, -edge, edge, edge));
+        let point = ((1 << 90) + 12345, 7 - (1 << 99));
+        let mut leaf = tree.get_root();
+        while let Ok(children) = tree.subdivide(leaf, [0; 4]) {
+            leaf = tree.point_locate(point).unwrap();
+            assert!(children.contains(&leaf));
+        }
+        assert_eq!(tree.get_max_level(), 101);
+        let Bounds(l, t, r, b) = tree.get_node(leaf).unwrap().get_bounds();
+        assert_eq!((l, t, r - l, b - t), (point.0, point.1, 1, 1));
+        let west = tree
+            .get_neighbors(leaf, Cardinality::West)
+            .unwrap()
+            .into_vec();
+        assert_eq!(west, vec![tree.point_locate((l - 1, t)).unwrap()]);
+
+        // Midpoints are computed without the extent, which would overflow here.
+        assert_eq!(midpoint(i64::MIN, i64::MAX), Some(-1));
+        assert_eq!(SplitPolicy::Ceil.split(i64::MIN, i64::MAX), Some(0));
+        assert_eq!(midpoint(u64::MAX - 3, u64::MAX), Some(u64::MAX - 2));
+        assert_eq!(midpoint(-7, 0), Some(-4));
+        assert_eq!(SplitPolicy::Ceil.split(-7, 0), Some(-3));
+    }
+
+    #[test]
+    fn split_policy() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 3, 2));
+        tree.set_split_policy(SplitPolicy::Ceil);
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        assert_eq!(tree.get_node(nw).unwrap().get_bounds(), (0, 0, 2, 1));
+        assert_eq!(tree.get_node(se).unwrap().get_bounds(), (2, 1, 3, 2));
+        assert_eq!(
+            tree.get_neighbors(ne, Cardinality::West)
+                .unwrap()
+                .into_vec(),
+            vec![nw]
+        );
+        assert_eq!(
+            tree.get_neighbors(sw, Cardinality::East)
+                .unwrap()
+                .into_vec(),
+            vec![se]
+        );
+
+        let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+        let [nw, ..] = tree.subdivide_at(tree.get_root(), (2, 5), [0; 4]).unwrap();
+        assert_eq!(tree.get_node(nw).unwrap().get_bounds(), (0, 0, 2, 5));
+        let leaf = tree.point_locate((1, 6)).unwrap();
+        assert_eq!(tree.get_node(leaf).unwrap().get_bounds(), (0, 5, 2, 8));
+        assert_eq!(
+            tree.subdivide_at(nw, (0, 2), [0; 4]).unwrap_err().source,
+            Error::CellTooSmall
+        );
+        assert_eq!(
+            tree.subdivide_at(nw, (1, 5), [0; 4]).unwrap_err().source,
+            Error::CellTooSmall
+        );
+        tree.subdivide_at(nw, (1, 4), [0; 4]).unwrap();
+        assert_neighbors_consistent(&tree);
+
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let mut rng = Lcg(5);
+        for _ in 0..200 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(64))).unwrap();
+            let Bounds(l, t, r, b) = tree.get_node(leaf).unwrap().get_bounds();
+            if r - l > 1 && b - t > 1 {
+                let middle = (l + 1 + rng.next(r - l - 1), t + 1 + rng.next(b - t - 1));
+                tree.subdivide_at(leaf, middle, [0; 4]).unwrap();
+            }
+        }
+        assert_neighbors_consistent(&tree);
+        for _ in 0..50 {
+            let leaf = tree.point_locate((rng.next(64), rng.next(64))).unwrap();
+            let parent = tree.get_node(leaf).unwrap().get_parent_index().unwrap();
+            let _ = tree.pop_children(parent);
+        }
+        assert_neighbors_consistent(&tree);
+    }
+
+    #[test]
+    fn float_point_locate() {
+        let mut tree = CNQuadtree::<u8, f32>::new(0, (0.1, 0.2, 0.7, 1.3));
+        let mut rng = Lcg(3);
+        for _ in 0..100 {
+            let point = (
+                0.1 + rng.next(600) as f32 / 1000.0,
+                0.2 + rng.next(1100) as f32 / 1000.0,
+            );
+            let leaf = tree.point_locate(point).unwrap();
+            let _ = tree.subdivide(leaf, [0; 4]);
+        }
+        // Points on every edge and a step past it land in exactly the leaf containing them.
+        let leaves: Vec<_> = tree.leaves_morton().collect();
+        let step = |v: f32| f32::from_bits(v.to_bits() + 1);
+        for &leaf in &leaves {
+            let Bounds(l, t, r, b) = tree.get_node(leaf).unwrap().get_bounds();
+            for point in [(l, t), (r, b), (step(l), step(t)), (r, t), (l, b)] {
+                let containing: Vec<_> = leaves
+                    .iter()
+                    .filter(|&&n| tree.get_node(n).unwrap().point_in(point))
+                    .collect();
+                match tree.point_locate(point) {
+                    Some(found) => assert_eq!(containing, [&found]),
+                    None => assert!(containing.is_empty()),
+                }
+            }
+        }
+        assert_eq!(tree.point_locate((f32::NAN, 0.5)), None);
+        assert_eq!(tree.point_locate((f32::INFINITY, 0.5)), None);
+
+        let mut tree = CNQuadtree::<u8, f32>::new(0, (0.0, 0.0, 0.6, 0.6));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        let almost = 0.1 + 0.2 - 1e-7;
+        assert_eq!(tree.point_locate((almost, 0.1)), Some(nw));
+        assert_eq!(tree.point_locate_snapped((almost, 0.1), 1e-6), Some(ne));
+        assert_eq!(tree.point_locate_snapped((0.29, 0.1), 1e-6), Some(nw));
+        assert_eq!(tree.point_locate_snapped((0.6, 0.1), 1e-6), None);
+    }
+
+    #[test]
+    fn debug_hierarchy() {
+        let mut tree = CNQuadtree::new("root", (0, 0, 4, 4));
+        let [_, ne, ..] = tree
+            .subdivide(tree.get_root(), ["nw", "ne", "sw", "se"])
+            .unwrap();
+        tree.subdivide(ne, ["a", "b", "c", "d"]).unwrap();
+
+        let expected = r#"CNQuadtree {
+    Root (0, 0, 4, 4) level 0: "root"
+        NorthWest (0, 0, 2, 2) level 1: "nw"
+        NorthEast (2, 0, 4, 2) level 1: "ne"
+            NorthWest (2, 0, 3, 1) level 2: "a"
+            NorthEast (3, 0, 4, 1) level 2: "b"
+            SouthWest (2, 1, 3, 2) level 2: "c"
+            SouthEast (3, 1, 4, 2) level 2: "d"
+        SouthWest (0, 2, 2, 4) level 1: "sw"
+        SouthEast (2, 2, 4, 4) level 1: "se"
+}"#;
+        assert_eq!(format!("{:?}", tree), expected);
+    }
 }
@@ -0,0 +1,20 @@
+/// A snapshot of a tree's structure and memory footprint. Returned by
+/// [`CNQuadtree::stats`](crate::CNQuadtree::stats).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// Number of nodes, internal and leaf.
+    pub nodes: usize,
+    /// Number of leaves.
+    pub leaves: usize,
+    /// Number of nodes at each level, starting from the root.
+    pub nodes_per_level: Vec<usize>,
+    /// Number of leaves at each level, starting from the root.
+    pub leaves_per_level: Vec<usize>,
+    /// Mean area of the leaves.
+    pub average_leaf_area: f64,
+    /// Ratio of nodes to the store's capacity.
+    pub fill_ratio: f64,
+    /// Estimated bytes held by the tree's own allocations. Heap memory owned by items is not
+    /// included.
+    pub estimated_bytes: usize,
+}
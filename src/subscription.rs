@@ -0,0 +1,267 @@
+use crate::error::{PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::node::{child_bounds, Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::{BTreeMap, HashMap};
+
+/// Identifies a subscription of a [`Subscriptions`] registry.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SubscriptionId(u64);
+
+/// The subscriptions stored at a node of the index.
+type Entries<S> = Vec<(SubscriptionId, Bounds<S>)>;
+
+/// A registry of regions of interest, reporting which ones each mutation of a tree affects.
+///
+/// Mutations made through [`Subscriptions::subdivide`], [`Subscriptions::pop_children`] and
+/// [`Subscriptions::set_item`] mark the subscriptions overlapping the mutated node as dirty.
+/// Mutations made directly on the tree can be reported with [`Subscriptions::notify`].
+/// [`Subscriptions::take_dirty`] hands out the dirty regions of each subscription.
+///
+/// Subscriptions are indexed by a quadtree of their own, each stored at the smallest node
+/// containing it, so finding the ones overlapping a region doesn't scan all of them.
+pub struct Subscriptions<S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    index: CNQuadtree<Entries<S>, S>,
+    /// The region of each subscription and the index node holding it.
+    regions: HashMap<SubscriptionId, (Bounds<S>, NodeId)>,
+    dirty: BTreeMap<SubscriptionId, Vec<Bounds<S>>>,
+    next: u64,
+}
+
+impl<S> Subscriptions<S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an empty registry for regions within `bounds`. Subscriptions are indexed down to
+    /// `max_depth` levels.
+    pub fn new(bounds: impl Into<Bounds<S>>, max_depth: usize) -> Self {
+        let bounds = bounds.into();
+        let mut index = CNQuadtree::new(Vec::new(), bounds);
+        index.set_max_depth(Some(max_depth));
+        Self {
+            index,
+            regions: HashMap::new(),
+            dirty: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// Returns the number of subscriptions.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Returns true if there are no subscriptions.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Registers a region of interest. Parts of it outside the registry's bounds are never
+    /// affected.
+    pub fn subscribe(&mut self, region: impl Into<Bounds<S>>) -> SubscriptionId {
+        let region = region.into();
+        let id = SubscriptionId(self.next);
+        self.next += 1;
+
+        let mut node = self.index.get_root();
+        while let Some(bounds) = self.index.get_node(node).map(|n| n.get_bounds()) {
+            let fits = |child: Bounds<S>| {
+                child.0 <= region.0
+                    && region.2 <= child.2
+                    && child.1 <= region.1
+                    && region.3 <= child.3
+            };
+            let Some(location) = (0..4).find(|&i| child_bounds(bounds, i).is_some_and(fits)) else {
+                break;
+            };
+            let children = match self
+                .index
+                .get_node(node)
+                .and_then(|n| n.get_children_index())
+            {
+                Some(children) => children,
+                None => match self.index.subdivide(node, Default::default()) {
+                    Ok(children) => children,
+                    Err(_) => break,
+                },
+            };
+            node = children[location];
+        }
+
+        if let Some(entries) = self.index.get_node_mut(node) {
+            entries.get_item_mut().push((id, region));
+        }
+        self.regions.insert(id, (region, node));
+        id
+    }
+
+    /// Removes a subscription and returns its region. Its pending dirty regions are dropped.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<Bounds<S>> {
+        let (region, node) = self.regions.remove(&id)?;
+        if let Some(entries) = self.index.get_node_mut(node) {
+            entries.get_item_mut().retain(|&(other, _)| other != id);
+        }
+        self.dirty.remove(&id);
+        Some(region)
+    }
+
+    /// Returns a subscription's region.
+    pub fn region(&self, id: SubscriptionId) -> Option<Bounds<S>> {
+        self.regions.get(&id).map(|&(region, _)| region)
+    }
+
+    /// Returns the subscriptions whose region overlaps `region` with a non-zero area, in
+    /// subscription order.
+    pub fn affected_by(&self, region: impl Into<Bounds<S>>) -> Vec<SubscriptionId> {
+        let region = region.into();
+        let mut result = Vec::new();
+        let mut stack = vec![self.index.get_root()];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.index.get_node(index) else {
+                continue;
+            };
+            if !node.intersects(region) {
+                continue;
+            }
+            result.extend(
+                node.get_item()
+                    .iter()
+                    .filter(|&&(_, other)| overlaps(region, other))
+                    .map(|&(id, _)| id),
+            );
+            stack.extend(node.get_children_index().into_iter().flatten());
+        }
+        result.sort_unstable();
+        result
+    }
+
+    /// Marks the subscriptions overlapping `region` as dirty and returns them.
+    pub fn notify(&mut self, region: impl Into<Bounds<S>>) -> Vec<SubscriptionId> {
+        let region = region.into();
+        let affected = self.affected_by(region);
+        for &id in &affected {
+            self.dirty.entry(id).or_default().push(region);
+        }
+        affected
+    }
+
+    /// Returns the dirty subscriptions with the regions reported for each since the last call,
+    /// in subscription order, and clears them.
+    pub fn take_dirty(&mut self) -> Vec<(SubscriptionId, Vec<Bounds<S>>)> {
+        std::mem::take(&mut self.dirty).into_iter().collect()
+    }
+
+    /// Subdivides a leaf of `tree` and notifies the subscriptions overlapping it.
+    pub fn subdivide<T>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        index: NodeId,
+        items: [T; 4],
+    ) -> Result<[NodeId; 4], SubdivideError<T>> {
+        let children = tree.subdivide(index, items)?;
+        if let Some(node) = tree.get_node(index) {
+            self.notify(node.get_bounds());
+        }
+        Ok(children)
+    }
+
+    /// Pops the children of a node of `tree` and notifies the subscriptions overlapping it.
+    pub fn pop_children<T>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        index: NodeId,
+    ) -> Result<[T; 4], PopChildrenError> {
+        let items = tree.pop_children(index)?;
+        if let Some(node) = tree.get_node(index) {
+            self.notify(node.get_bounds());
+        }
+        Ok(items)
+    }
+
+    /// Replaces the item of a node of `tree`, notifies the subscriptions overlapping it, and
+    /// returns the old item. Returns None if the node doesn't exist.
+    pub fn set_item<T>(
+        &mut self,
+        tree: &mut CNQuadtree<T, S>,
+        index: NodeId,
+        item: T,
+    ) -> Option<T> {
+        let node = tree.get_node_mut(index)?;
+        let old = std::mem::replace(node.get_item_mut(), item);
+        let bounds = node.get_bounds();
+        self.notify(bounds);
+        Some(old)
+    }
+}
+
+/// Returns true if the regions overlap with a non-zero area.
+fn overlaps<S: PartialOrd>(a: Bounds<S>, b: Bounds<S>) -> bool {
+    a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions() {
+        let mut subscriptions = Subscriptions::new((0, 0, 64, 64), 4);
+        let small = subscriptions.subscribe((1, 1, 3, 3));
+        let straddling = subscriptions.subscribe((30, 30, 34, 34));
+        let all = subscriptions.subscribe((0, 0, 64, 64));
+        let gone = subscriptions.subscribe((40, 40, 41, 41));
+        assert_eq!(
+            subscriptions.unsubscribe(gone),
+            Some(Bounds(40, 40, 41, 41))
+        );
+        assert_eq!(subscriptions.len(), 3);
+        // The small region was indexed deep, the straddling one at the root.
+        assert!(subscriptions.index.stats().nodes > 1);
+
+        assert_eq!(subscriptions.affected_by((0, 0, 2, 2)), vec![small, all]);
+        assert_eq!(
+            subscriptions.affected_by((32, 0, 64, 32)),
+            vec![straddling, all]
+        );
+        assert_eq!(subscriptions.affected_by((3, 3, 30, 30)), vec![all]);
+
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let root = tree.get_root();
+        let [nw, ne, ..] = subscriptions
+            .subdivide(&mut tree, root, [1, 2, 3, 4])
+            .unwrap();
+        assert_eq!(subscriptions.set_item(&mut tree, ne, 5), Some(2));
+        subscriptions.subdivide(&mut tree, nw, [0; 4]).unwrap();
+        assert_eq!(
+            subscriptions.take_dirty(),
+            vec![
+                (small, vec![Bounds(0, 0, 64, 64), Bounds(0, 0, 32, 32)]),
+                (
+                    straddling,
+                    vec![
+                        Bounds(0, 0, 64, 64),
+                        Bounds(32, 0, 64, 32),
+                        Bounds(0, 0, 32, 32)
+                    ]
+                ),
+                (
+                    all,
+                    vec![
+                        Bounds(0, 0, 64, 64),
+                        Bounds(32, 0, 64, 32),
+                        Bounds(0, 0, 32, 32)
+                    ]
+                ),
+            ]
+        );
+        assert!(subscriptions.take_dirty().is_empty());
+
+        subscriptions.pop_children(&mut tree, nw).unwrap();
+        assert_eq!(subscriptions.take_dirty().len(), 3);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::error::{Error, NeighborError, PopChildrenError, SubdivideError};
+use crate::id::NodeId;
+use crate::location::{Cardinality, Location};
+use crate::node::{Bounds, CNNode, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::{Neighbors, RegionQuadtree};
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+
+/// A subtree detached by [`CNQuadtree::split_mut`] for independent editing. It derefs to a tree
+/// rooted at the subtree's root, with the same bounds, so everything a [`CNQuadtree`] offers
+/// works within it. Its indices, levels and neighbors are its own: levels start at 0 at the
+/// subtree's root, and neighbors end at the subtree's border.
+pub struct SubtreeMut<'a, T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: &'a mut CNQuadtree<T, S>,
+}
+
+impl<T, S> Deref for SubtreeMut<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Target = CNQuadtree<T, S>;
+
+    fn deref(&self) -> &Self::Target {
+        self.tree
+    }
+}
+
+impl<T, S> DerefMut for SubtreeMut<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.tree
+    }
+}
+
+/// A read-only view of the subtree rooted at a node, returned by [`CNQuadtree::subtree_view`].
+/// It's a [`RegionQuadtree`] of its own whose root is that node: indices outside the subtree
+/// are invalid, queries only find nodes within it, and leaves at its border have no neighbors
+/// on that side. Nodes keep the tree's levels and indices.
+///
+/// Nodes' cardinal neighbors may still point outside the subtree. Methods that modify the tree
+/// fail as if every index were invalid.
+pub struct SubtreeView<'a, T, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    tree: &'a CNQuadtree<T, S>,
+    root: NodeId,
+    level: usize,
+    bounds: Bounds<S>,
+}
+
+impl<T, S> Clone for SubtreeView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, S> Copy for SubtreeView<'_, T, S> where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive
+{
+}
+
+impl<T, S> SubtreeView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the subtree root's bounds.
+    #[inline]
+    pub fn bounds(&self) -> Bounds<S> {
+        self.bounds
+    }
+
+    /// Returns true if the node is within the subtree. Nodes at the root's level or deeper that
+    /// lie within its bounds can only be its descendants, since each level's nodes are disjoint.
+    pub fn contains(&self, index: NodeId) -> bool {
+        self.tree.get_node(index).is_some_and(|node| {
+            node.level() >= self.level && self.bounds.contains_rect(node.get_bounds())
+        })
+    }
+
+    /// Returns true if the node lies against the subtree's border on the given side.
+    fn at_border(&self, bounds: Bounds<S>, direction: Cardinality) -> bool {
+        match direction {
+            Cardinality::West => bounds.0 == self.bounds.0,
+            Cardinality::North => bounds.1 == self.bounds.1,
+            Cardinality::East => bounds.2 == self.bounds.2,
+            Cardinality::South => bounds.3 == self.bounds.3,
+        }
+    }
+}
+
+impl<T, S> RegionQuadtree<T> for SubtreeView<'_, T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    type Index = NodeId;
+    type Node = CNNode<T, NodeId, S>;
+
+    fn get_node(&self, index: Self::Index) -> Option<&Self::Node> {
+        self.tree.get_node(index).filter(|_| self.contains(index))
+    }
+
+    /// Always returns None, as the view is read-only.
+    fn get_node_mut(&mut self, _: Self::Index) -> Option<&mut Self::Node> {
+        None
+    }
+
+    fn get_root(&self) -> Self::Index {
+        self.root
+    }
+
+    fn get_neighbors(
+        &self,
+        index: Self::Index,
+        direction: Cardinality,
+    ) -> Result<Neighbors<Self::Index>, NeighborError> {
+        let node = self.get_node(index).ok_or(NeighborError::InvalidIndex)?;
+        if node.is_leaf() && self.at_border(node.get_bounds(), direction) {
+            return Ok(Neighbors::new());
+        }
+        self.tree.get_neighbors(index, direction)
+    }
+
+    fn get_equal_or_larger_neighbor(
+        &self,
+        index: Self::Index,
+        direction: Cardinality,
+    ) -> Option<Self::Index> {
+        if self.at_border(self.get_node(index)?.get_bounds(), direction) {
+            return None;
+        }
+        self.tree.get_equal_or_larger_neighbor(index, direction)
+    }
+
+    /// Always fails with [`Error::InvalidIndex`], as the view is read-only.
+    fn subdivide(
+        &mut self,
+        _: Self::Index,
+        items: [T; 4],
+    ) -> Result<[Self::Index; 4], SubdivideError<T>> {
+        Err(SubdivideError {
+            items,
+            source: Error::InvalidIndex,
+        })
+    }
+
+    /// Always fails with [`PopChildrenError::InvalidIndex`], as the view is read-only.
+    fn pop_children(&mut self, _: Self::Index) -> Result<[T; 4], PopChildrenError> {
+        Err(PopChildrenError::InvalidIndex)
+    }
+
+    fn point_locate(&self, point: Point<S>) -> Option<Self::Index> {
+        self.tree
+            .descend_from(self.root, point, S::zero(), |_, _| {})
+    }
+
+    fn point_locate_path(&self, point: Point<S>) -> Option<Vec<(Self::Index, Option<Location>)>> {
+        let mut path = Vec::new();
+        self.tree
+            .descend_from(self.root, point, S::zero(), |index, location| {
+                path.push((index, location))
+            })?;
+        Some(path)
+    }
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a read-only view of the subtree rooted at `index`, or None if the index is
+    /// invalid.
+    pub fn subtree_view(&self, index: NodeId) -> Option<SubtreeView<'_, T, S>> {
+        let node = self.get_node(index)?;
+        Some(SubtreeView {
+            tree: self,
+            root: index,
+            level: node.level(),
+            bounds: node.get_bounds(),
+        })
+    }
+
+    /// Detaches the subtrees rooted at `indices` and hands them to `f` as disjoint
+    /// [`SubtreeMut`]s, which can be edited on different threads, for example with rayon or
+    /// [`std::thread::scope`]. Afterwards they're put back and the cardinal neighbors along their
+    /// borders are recomputed. Returns what `f` returns and the subtrees' new root indices.
+    ///
+    /// Every index within the subtrees changes, their roots' included. Each subtree keeps the
+    /// tree's settings, with the maximum depth counted from its root, and an even share of the
+    /// nodes left under the node limit. Fails with [`Error::InvalidIndex`] if an index is
+    /// invalid, or [`Error::OverlappingSubtrees`] if a subtree contains another. Subtrees of a
+    /// tree in a batch are in a batch too.
+    pub fn split_mut<const K: usize, R, F>(
+        &mut self,
+        indices: [NodeId; K],
+        f: F,
+    ) -> Result<(R, [NodeId; K]), Error>
+    where
+        F: FnOnce([SubtreeMut<'_, T, S>; K]) -> R,
+    {
+        let roots: HashSet<_> = indices.iter().copied().collect();
+        for &index in &indices {
+            let mut ancestor = self
+                .get_node(index)
+                .ok_or(Error::InvalidIndex)?
+                .get_parent_index();
+            while let Some(a) = ancestor {
+                if roots.contains(&a) {
+                    return Err(Error::OverlappingSubtrees);
+                }
+                ancestor = self.get_node(a).and_then(|n| n.get_parent_index());
+            }
+        }
+        if roots.len() < K {
+            return Err(Error::OverlappingSubtrees);
+        }
+
+        // Leaves outside the subtrees whose neighbors may point into them. In a batch the
+        // neighbors are stale and fixed by the commit instead.
+        let mut relinked = Vec::new();
+        if !self.is_batching() {
+            for &index in &indices {
+                let leaves: HashSet<_> = self.subtree_leaves(index).into_iter().collect();
+                for &leaf in &leaves {
+                    for direction in Cardinality::ALL {
+                        relinked.extend(
+                            self.neighbors_iter(leaf, direction)
+                                .filter(|n| !leaves.contains(n)),
+                        );
+                    }
+                }
+            }
+        }
+
+        let extra_nodes = self.node_limit().map_or(0, |limit| {
+            limit.saturating_sub(self.node_count()) / K.max(1)
+        });
+        let mut origins = Vec::with_capacity(K);
+        let mut parts = Vec::with_capacity(K);
+        for index in indices {
+            let parent = self.get_node(index).and_then(|n| n.get_parent_index());
+            let location = parent
+                .and_then(|p| self.get_node(p)?.get_children_index())
+                .and_then(|children| children.iter().position(|&c| c == index))
+                .unwrap_or(0);
+            let (parent, level, part) =
+                self.detach(index, extra_nodes).ok_or(Error::InvalidIndex)?;
+            origins.push((parent, location, level));
+            parts.push(part);
+        }
+
+        let mut views = parts.iter_mut().map(|tree| SubtreeMut { tree });
+        let result = f(std::array::from_fn(|_| views.next().unwrap()));
+
+        let mut new_roots = [self.get_root(); K];
+        for (i, ((parent, location, level), part)) in origins.into_iter().zip(parts).enumerate() {
+            let (root, leaves) = self.reattach(parent, location, level, part);
+            new_roots[i] = root;
+            relinked.extend(leaves);
+        }
+        if !self.is_batching() {
+            self.relink(relinked);
+        }
+        Ok((result, new_roots))
+    }
+
+    /// Returns the leaves of the subtree rooted at `index`.
+    fn subtree_leaves(&self, index: NodeId) -> Vec<NodeId> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![index];
+        while let Some(index) = stack.pop() {
+            match self.get_node(index).and_then(|n| n.get_children_index()) {
+                None => leaves.push(index),
+                Some(children) => stack.extend(children),
+            }
+        }
+        leaves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtree_view() {
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [nw, ne, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [_, nw_ne, _, nw_se] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+        let [nw_se_nw, ..] = tree.subdivide(nw_se, [9; 4]).unwrap();
+
+        let mut view = tree.subtree_view(nw).unwrap();
+        assert_eq!(view.get_root(), nw);
+        assert_eq!(view.bounds(), (0, 0, 32, 32));
+        assert!(view.get_node(nw_se_nw).is_some());
+        assert!(view.get_node(ne).is_none());
+        assert!(view.get_node(tree.get_root()).is_none());
+        assert_eq!(view.point_locate((16, 16)), Some(nw_se_nw));
+        assert_eq!(view.point_locate((40, 0)), None);
+        assert_eq!(view.point_locate_path((16, 16)).unwrap().len(), 3);
+        assert_eq!(view.leaves_morton().count(), 7);
+        assert_eq!(view.region_locate((20, 0, 64, 8)), Some(vec![nw_ne]));
+
+        // Neighbors stop at the subtree's border.
+        assert!(view
+            .get_neighbors(nw_ne, Cardinality::East)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            tree.get_neighbors(nw_ne, Cardinality::East).unwrap().len(),
+            1
+        );
+        assert_eq!(
+            view.get_neighbors(nw_ne, Cardinality::South).unwrap().len(),
+            2
+        );
+        assert_eq!(
+            view.get_equal_or_larger_neighbor(nw_ne, Cardinality::East),
+            None
+        );
+        assert_eq!(
+            view.get_neighbors(ne, Cardinality::West),
+            Err(NeighborError::InvalidIndex)
+        );
+
+        assert!(view.get_node_mut(nw_ne).is_none());
+        assert_eq!(
+            view.subdivide(nw_ne, [0; 4]).unwrap_err().source,
+            Error::InvalidIndex
+        );
+        assert_eq!(
+            view.pop_children(nw_se),
+            Err(PopChildrenError::InvalidIndex)
+        );
+        assert!(tree.get_node(nw_se).unwrap().has_children());
+    }
+}
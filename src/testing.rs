@@ -0,0 +1,406 @@
+//! Tools for fuzzing the structure of a [`CNQuadtree`]: random operation sequences, a checker
+//! for the cardinal neighbor invariants and shrinking of failing sequences. Enabled by the
+//! `testing` feature.
+//!
+//! ```
+//! use cnquadtree::testing::OpSequence;
+//! use cnquadtree::CNQuadtree;
+//!
+//! for seed in 0..8 {
+//!     let ops = OpSequence::random(seed, 100);
+//!     let mut tree = CNQuadtree::<u32, i32>::new(0, (0, 0, 64, 64));
+//!     if let Err(failure) = ops.run(&mut tree) {
+//!         let smallest = ops.shrink(|ops| {
+//!             ops.run(&mut CNQuadtree::<u32, i32>::new(0, (0, 0, 64, 64))).is_err()
+//!         });
+//!         panic!("{failure} with {smallest:?}");
+//!     }
+//! }
+//! ```
+
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use thiserror::Error;
+
+/// An operation on a tree. Nodes are picked by position rather than index, wrapping around
+/// the number of candidates, so every operation applies to any tree and sequences stay valid
+/// when shrunk.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Op {
+    /// Subdivides the leaf at this position in Morton order, with children holding copies of
+    /// its item. Subdivisions the tree's settings forbid are skipped.
+    Subdivide(usize),
+    /// Pops the children of the node at this position among those whose children are all
+    /// leaves, in level order. Skipped if the tree is a single leaf.
+    Pop(usize),
+    /// Sets the item of the leaf at this position in Morton order.
+    Paint(usize, u32),
+    /// Locates the point at these fractions of the tree's width and height, in 65536ths, and
+    /// checks the leaf found contains it.
+    Query(u16, u16),
+}
+
+/// An invariant of the tree that doesn't hold at a node.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("{reason} at {index:?}")]
+pub struct Violation {
+    /// The node the invariant doesn't hold at.
+    pub index: NodeId,
+    /// What's wrong at the node.
+    pub reason: String,
+}
+
+/// An operation after which an invariant didn't hold.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("step {step} ({op:?}): {violation}")]
+pub struct Failure {
+    /// The position of the operation in the sequence.
+    pub step: usize,
+    /// The operation that was run.
+    pub op: Op,
+    /// The first invariant that didn't hold after it.
+    pub violation: Violation,
+}
+
+/// A sequence of operations to run against a tree, checking its invariants after each.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OpSequence {
+    /// The operations, in the order they're run.
+    pub ops: Vec<Op>,
+}
+
+impl OpSequence {
+    /// Returns `len` operations drawn from a generator seeded with `seed`, so the same seed
+    /// always gives the same sequence. Subdivisions are the most common, so trees grow deep.
+    pub fn random(seed: u64, len: usize) -> Self {
+        let mut state = seed;
+        // SplitMix64, which is good enough for picking operations.
+        let mut next = move || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let ops = (0..len)
+            .map(|_| {
+                let (kind, value) = (next() % 10, next());
+                let position = (value >> 32) as usize;
+                match kind {
+                    0..=3 => Op::Subdivide(position),
+                    4 | 5 => Op::Pop(position),
+                    6 | 7 => Op::Paint(position, value as u32),
+                    _ => Op::Query(value as u16, (value >> 16) as u16),
+                }
+            })
+            .collect();
+        Self { ops }
+    }
+
+    /// Applies the operations to `tree` in order, checking [`check_invariants`] after each.
+    /// Returns the first operation after which an invariant didn't hold.
+    pub fn run<T, S>(&self, tree: &mut CNQuadtree<T, S>) -> Result<(), Failure>
+    where
+        T: Clone + From<u32>,
+        S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+    {
+        for (step, &op) in self.ops.iter().enumerate() {
+            apply(tree, op)
+                .and_then(|_| check_invariants(tree))
+                .map_err(|violation| Failure {
+                    step,
+                    op,
+                    violation,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Returns a smaller sequence for which `fails` still returns true, by removing runs of
+    /// operations and then moving positions to zero while it keeps failing. `fails` should run
+    /// the sequence against a fresh tree. Returns the sequence unchanged if nothing can be
+    /// removed.
+    pub fn shrink<F>(&self, mut fails: F) -> Self
+    where
+        F: FnMut(&Self) -> bool,
+    {
+        let mut ops = self.ops.clone();
+        let mut chunk = (ops.len() / 2).max(1);
+        loop {
+            let mut removed = false;
+            let mut start = 0;
+            while start < ops.len() {
+                let mut candidate = ops.clone();
+                candidate.drain(start..(start + chunk).min(ops.len()));
+                if fails(&Self {
+                    ops: candidate.clone(),
+                }) {
+                    ops = candidate;
+                    removed = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            if !removed {
+                if chunk == 1 {
+                    break;
+                }
+                chunk /= 2;
+            }
+        }
+
+        for i in 0..ops.len() {
+            let simpler = match ops[i] {
+                Op::Subdivide(_) => Op::Subdivide(0),
+                Op::Pop(_) => Op::Pop(0),
+                Op::Paint(_, item) => Op::Paint(0, item),
+                Op::Query(..) => Op::Query(0, 0),
+            };
+            if simpler == ops[i] {
+                continue;
+            }
+            let mut candidate = ops.clone();
+            candidate[i] = simpler;
+            if fails(&Self {
+                ops: candidate.clone(),
+            }) {
+                ops = candidate;
+            }
+        }
+        Self { ops }
+    }
+}
+
+/// Applies one operation, checking a query's result.
+fn apply<T, S>(tree: &mut CNQuadtree<T, S>, op: Op) -> Result<(), Violation>
+where
+    T: Clone + From<u32>,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    let root = tree.get_root();
+    let violation = |index, reason: &str| Violation {
+        index,
+        reason: reason.to_owned(),
+    };
+    let pick = |candidates: Vec<NodeId>, position: usize| {
+        (!candidates.is_empty()).then(|| candidates[position % candidates.len()])
+    };
+    match op {
+        Op::Subdivide(position) => {
+            let Some(leaf) = pick(tree.leaves_morton().collect(), position) else {
+                return Err(violation(root, "tree has no leaves"));
+            };
+            let item = tree[leaf].get_item().clone();
+            // Subdivisions the tree's settings forbid are expected to fail.
+            let _ = tree.subdivide(leaf, std::array::from_fn(|_| item.clone()));
+        }
+        Op::Pop(position) => {
+            let twigs = tree
+                .level_order()
+                .into_iter()
+                .filter(|&index| {
+                    tree[index]
+                        .get_children_index()
+                        .is_some_and(|children| children.iter().all(|&c| tree[c].is_leaf()))
+                })
+                .collect();
+            if let Some(twig) = pick(twigs, position) {
+                tree.pop_children(twig)
+                    .map_err(|e| violation(twig, &format!("pop failed: {e}")))?;
+            }
+        }
+        Op::Paint(position, item) => {
+            let Some(leaf) = pick(tree.leaves_morton().collect(), position) else {
+                return Err(violation(root, "tree has no leaves"));
+            };
+            if let Some(node) = tree.get_node_mut(leaf) {
+                *node.get_item_mut() = item.into();
+            }
+        }
+        Op::Query(x, y) => {
+            let Bounds(l, t, r, b) = tree.bounds();
+            let along = |start: S, end: S, fraction: u16| {
+                let (start, end) = (start.to_f64()?, end.to_f64()?);
+                S::from_f64(start + (end - start) * f64::from(fraction) / 65536.0)
+            };
+            let (Some(x), Some(y)) = (along(l, r, x), along(t, b, y)) else {
+                return Ok(());
+            };
+            let found = tree
+                .point_locate((x, y))
+                .ok_or_else(|| violation(root, "point inside the tree wasn't located"))?;
+            if !tree[found].is_leaf() || !tree[found].get_bounds().contains((x, y)) {
+                return Err(violation(
+                    found,
+                    "located a node that isn't the leaf at the point",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks the structural invariants of a tree outside a batch: parents and children link to
+/// each other, levels increase by one, children tile their parent, internal nodes have no
+/// cardinal neighbors, and every leaf's neighbor chains are leaves lying against its sides,
+/// in order and without gaps, covering each side not on the tree's border. Also checks that
+/// every node is reachable from the root.
+pub fn check_invariants<T, S>(tree: &CNQuadtree<T, S>) -> Result<(), Violation>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    let fail = |index, reason: &str| {
+        Err(Violation {
+            index,
+            reason: reason.to_owned(),
+        })
+    };
+    let root = tree.get_root();
+    let Some(root_node) = tree.get_node(root) else {
+        return fail(root, "root doesn't exist");
+    };
+    if root_node.get_parent_index().is_some() || root_node.level() != 0 {
+        return fail(root, "root has a parent or a level other than 0");
+    }
+    let border = root_node.get_bounds();
+
+    let mut reached = 0;
+    let mut stack = vec![root];
+    while let Some(index) = stack.pop() {
+        reached += 1;
+        let Some(node) = tree.get_node(index) else {
+            return fail(index, "node doesn't exist");
+        };
+        let bounds = node.get_bounds();
+
+        if let Some(children) = node.get_children_index() {
+            if node.get_cardinal_neighbors_index() != [None; 4] {
+                return fail(index, "internal node has cardinal neighbors");
+            }
+            let Some(nodes) = children
+                .iter()
+                .map(|&child| tree.get_node(child))
+                .collect::<Option<Vec<_>>>()
+            else {
+                return fail(index, "child doesn't exist");
+            };
+            if nodes
+                .iter()
+                .any(|child| child.get_parent_index() != Some(index))
+            {
+                return fail(index, "child doesn't link back to its parent");
+            }
+            if nodes.iter().any(|child| child.level() != node.level() + 1) {
+                return fail(index, "child isn't one level below its parent");
+            }
+            let Bounds(l, t, r, b) = bounds;
+            let Bounds(_, _, x, y) = nodes[0].get_bounds();
+            let tiles = [
+                Bounds(l, t, x, y),
+                Bounds(x, t, r, y),
+                Bounds(l, y, x, b),
+                Bounds(x, y, r, b),
+            ];
+            if nodes
+                .iter()
+                .zip(tiles)
+                .any(|(c, tile)| c.get_bounds() != tile)
+            {
+                return fail(index, "children don't tile their parent");
+            }
+            stack.extend(children);
+            continue;
+        }
+
+        for direction in Cardinality::ALL {
+            let on_border = match direction {
+                Cardinality::West => bounds.0 == border.0,
+                Cardinality::North => bounds.1 == border.1,
+                Cardinality::East => bounds.2 == border.2,
+                Cardinality::South => bounds.3 == border.3,
+            };
+            let chain = match tree.get_neighbors(index, direction) {
+                Ok(chain) => chain,
+                Err(e) => return fail(index, &format!("{direction} neighbors: {e}")),
+            };
+            if on_border != chain.is_empty() {
+                return fail(
+                    index,
+                    &format!("{direction} neighbors don't match the border"),
+                );
+            }
+            let mut previous: Option<Bounds<S>> = None;
+            for &neighbor in &chain {
+                let Some(neighbor) = tree.get_node(neighbor).filter(|n| n.is_leaf()) else {
+                    return fail(index, &format!("{direction} neighbor isn't a leaf"));
+                };
+                let n = neighbor.get_bounds();
+                let (against, starts) = match direction {
+                    Cardinality::West => (n.2 == bounds.0, n.1 <= bounds.1 && bounds.1 < n.3),
+                    Cardinality::North => (n.3 == bounds.1, n.0 <= bounds.0 && bounds.0 < n.2),
+                    Cardinality::East => (n.0 == bounds.2, n.1 < bounds.3 && bounds.3 <= n.3),
+                    Cardinality::South => (n.1 == bounds.3, n.0 < bounds.2 && bounds.2 <= n.2),
+                };
+                let continues = match previous {
+                    None => starts,
+                    Some(p) => match direction {
+                        Cardinality::West => n.1 == p.3,
+                        Cardinality::North => n.0 == p.2,
+                        Cardinality::East => n.3 == p.1,
+                        Cardinality::South => n.2 == p.0,
+                    },
+                };
+                if !against || !continues {
+                    return fail(index, &format!("{direction} neighbors don't line its side"));
+                }
+                previous = Some(n);
+            }
+        }
+    }
+
+    if reached != tree.node_count() {
+        return fail(root, "tree has nodes unreachable from the root");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_sequences() {
+        for seed in 0..16 {
+            let mut tree = CNQuadtree::<u32, i32>::new(0, (0, 0, 64, 64));
+            tree.set_max_depth(Some(5));
+            OpSequence::random(seed, 200).run(&mut tree).unwrap();
+        }
+
+        let mut tree = CNQuadtree::<u32, i32>::new(0, (0, 0, 64, 64));
+        let [nw, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        tree.get_node_mut(nw)
+            .unwrap()
+            .update_neighbor(None, Cardinality::East);
+        let violation = check_invariants(&tree).unwrap_err();
+        assert_eq!(violation.index, nw);
+    }
+
+    #[test]
+    fn shrink() {
+        // Pretend that any two subdivisions break the tree.
+        let fails = |ops: &OpSequence| {
+            ops.ops
+                .iter()
+                .filter(|op| matches!(op, Op::Subdivide(_)))
+                .count()
+                >= 2
+        };
+        let ops = OpSequence::random(7, 50);
+        assert!(fails(&ops));
+        assert_eq!(ops.shrink(fails).ops, [Op::Subdivide(0), Op::Subdivide(0)]);
+    }
+}
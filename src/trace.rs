@@ -0,0 +1,75 @@
+//! Instrumentation emitted through `tracing` when the `tracing` feature is enabled. Without it,
+//! the macros expand to nothing and their arguments aren't evaluated.
+
+/// Emits a trace-level event.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "cnquadtree", $($arg)*);
+    };
+}
+
+/// Emits a debug-level event.
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "cnquadtree", $($arg)*);
+    };
+}
+
+/// Enters a trace-level span until the end of the enclosing block.
+macro_rules! enter_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(target: "cnquadtree", $($arg)*).entered();
+    };
+}
+
+pub(crate) use {debug_event, enter_span, trace_event};
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::slottree::CNQuadtree;
+    use crate::tree::RegionQuadtree;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Counts the crate's spans and events.
+    #[derive(Clone, Default)]
+    struct Counter {
+        spans: Arc<AtomicUsize>,
+        events: Arc<AtomicUsize>,
+    }
+
+    impl Subscriber for Counter {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "cnquadtree"
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(self.spans.fetch_add(1, Ordering::Relaxed) as u64 + 1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {
+            self.events.fetch_add(1, Ordering::Relaxed);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn instrumentation() {
+        let counter = Counter::default();
+        tracing::subscriber::with_default(counter.clone(), || {
+            let mut tree = CNQuadtree::new(0, (0, 0, 8, 8));
+            let children = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+            tree.subdivide(children[0], [0; 4]).unwrap();
+            tree.point_locate((1, 1));
+        });
+        assert_eq!(counter.spans.load(Ordering::Relaxed), 2);
+        // Two subdivisions, the neighbor redirected from NE and SW by the second, and the query.
+        assert_eq!(counter.events.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::error::Error;
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::polygon::{coverage, Coverage};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A 2D affine transform, mapping `(x, y)` to
+/// `(m[0][0] * x + m[0][1] * y + m[0][2], m[1][0] * x + m[1][1] * y + m[1][2])`. Combines
+/// rotations, scalings, shears and translations, such as the placement of a tree in a world
+/// set with [`CNQuadtree::set_world_transform`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Affine {
+    pub m: [[f64; 3]; 2],
+}
+
+impl Affine {
+    /// The transform leaving points where they are.
+    pub const IDENTITY: Self = Self {
+        m: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    };
+
+    /// Returns a transform moving points by `(dx, dy)`.
+    pub fn translation(dx: f64, dy: f64) -> Self {
+        Self {
+            m: [[1.0, 0.0, dx], [0.0, 1.0, dy]],
+        }
+    }
+
+    /// Returns a transform scaling points about the origin.
+    pub fn scaling(sx: f64, sy: f64) -> Self {
+        Self {
+            m: [[sx, 0.0, 0.0], [0.0, sy, 0.0]],
+        }
+    }
+
+    /// Returns a transform rotating points about the origin by `radians`, from the x axis
+    /// towards the y axis.
+    pub fn rotation(radians: f64) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self {
+            m: [[cos, -sin, 0.0], [sin, cos, 0.0]],
+        }
+    }
+
+    /// Returns the transform applying this one and then `next`.
+    pub fn then(&self, next: &Self) -> Self {
+        let (a, b) = (&next.m, &self.m);
+        let row = |i: usize| {
+            [
+                a[i][0] * b[0][0] + a[i][1] * b[1][0],
+                a[i][0] * b[0][1] + a[i][1] * b[1][1],
+                a[i][0] * b[0][2] + a[i][1] * b[1][2] + a[i][2],
+            ]
+        };
+        Self {
+            m: [row(0), row(1)],
+        }
+    }
+
+    /// Returns the transform undoing this one, or None if it collapses the plane.
+    pub fn inverse(&self) -> Option<Self> {
+        let [[a, b, c], [d, e, f]] = self.m;
+        let determinant = a * e - b * d;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        let (a, b, d, e) = (
+            e / determinant,
+            -b / determinant,
+            -d / determinant,
+            a / determinant,
+        );
+        Some(Self {
+            m: [[a, b, -(a * c + b * f)], [d, e, -(d * c + e * f)]],
+        })
+    }
+
+    /// Returns the transformed point.
+    #[inline]
+    pub fn apply(&self, (x, y): Point<f64>) -> Point<f64> {
+        let [[a, b, c], [d, e, f]] = self.m;
+        (a * x + b * y + c, d * x + e * y + f)
+    }
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// Returns true if the coordinate type is an integer type, which can't hold a half.
+pub(crate) fn is_integer<S: Copy + ToPrimitive + FromPrimitive>() -> bool {
+    S::from_f64(0.5).and_then(|half| half.to_f64()) != Some(0.5)
+}
+
+/// Returns the value as an integer if the coordinate type is an integer type.
+fn as_integer<S: Copy + ToPrimitive + FromPrimitive>(value: S) -> Option<i128> {
+    if !is_integer::<S>() {
+        return None;
+    }
+    value.to_i128()
+}
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Moves every node by `(dx, dy)`, for example to re-center the origin of a streaming
+    /// world. Indices, levels and neighbors are kept. Fails with [`Error::Overflow`] if a
+    /// coordinate would leave the range of an integer coordinate type, leaving the tree as it
+    /// was.
+    pub fn translate(&mut self, dx: S, dy: S) -> Result<(), Error> {
+        let shift = |value: S, by: S| match (as_integer(value), as_integer(by)) {
+            (Some(value), Some(by)) => value
+                .checked_add(by)
+                .and_then(S::from_i128)
+                .ok_or(Error::Overflow),
+            _ => Ok(value + by),
+        };
+        self.map_bounds(|Bounds(l, t, r, b)| {
+            Ok(Bounds(
+                shift(l, dx)?,
+                shift(t, dy)?,
+                shift(r, dx)?,
+                shift(b, dy)?,
+            ))
+        })
+    }
+
+    /// Scales every node's bounds and the minimum cell size by `factor` about the origin.
+    /// Indices, levels and neighbors are kept. Fails with [`Error::UnitConversion`] if the
+    /// factor isn't positive and finite or a scaled integer coordinate wouldn't be a whole
+    /// number, and with [`Error::Overflow`] if it wouldn't fit the coordinate type, leaving the
+    /// tree as it was.
+    pub fn scale(&mut self, factor: f64) -> Result<(), Error> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return Err(Error::UnitConversion);
+        }
+        let whole_factor = (factor.fract() == 0.0).then_some(factor as i128);
+        let scale = |value: S| -> Result<S, Error> {
+            match (as_integer(value), whole_factor) {
+                (Some(value), Some(factor)) => value
+                    .checked_mul(factor)
+                    .and_then(S::from_i128)
+                    .ok_or(Error::Overflow),
+                (integer, _) => {
+                    let scaled = value.to_f64().ok_or(Error::UnitConversion)? * factor;
+                    if integer.is_some() && scaled.fract() != 0.0 {
+                        return Err(Error::UnitConversion);
+                    }
+                    S::from_f64(scaled).ok_or(Error::Overflow)
+                }
+            }
+        };
+        let min_cell_size = scale(self.min_cell_size())?;
+        self.map_bounds(|Bounds(l, t, r, b)| {
+            Ok(Bounds(scale(l)?, scale(t)?, scale(r)?, scale(b)?))
+        })?;
+        self.set_min_cell_size(min_cell_size);
+        Ok(())
+    }
+
+    /// Returns the leaf containing a point given in world coordinates, as placed by
+    /// [`CNQuadtree::set_world_transform`]. Integer coordinates are rounded down, so a leaf
+    /// covers the world area its cells map to. Returns None if the point is outside the tree.
+    pub fn point_locate_world(&self, point: Point<f64>) -> Option<NodeId> {
+        let (x, y) = self.world_to_tree(point);
+        let unit = |value: f64| match as_integer(S::zero()) {
+            Some(_) => S::from_f64(value.floor()),
+            None => S::from_f64(value),
+        };
+        self.point_locate((unit(x)?, unit(y)?))
+    }
+
+    /// Returns the leaves overlapping a rectangle given in world coordinates, which may be
+    /// rotated or sheared in tree coordinates, in Morton order. Leaves only touching its
+    /// outline aren't included. Returns None if it doesn't overlap the tree, like
+    /// [`RegionQuadtree::region_locate`]. Fails with [`Error::UnitConversion`] if bounds can't be
+    /// converted to `f64`.
+    pub fn region_locate_world(
+        &self,
+        Bounds(left, top, right, bottom): Bounds<f64>,
+    ) -> Result<Option<Vec<NodeId>>, Error> {
+        let quad = [(left, top), (right, top), (right, bottom), (left, bottom)]
+            .map(|corner| self.world_to_tree(corner));
+        let mut leaves = Vec::new();
+        let mut stack = vec![self.get_root()];
+        while let Some(index) = stack.pop() {
+            let bounds = self.bounds_f64(index).ok_or(Error::UnitConversion)?;
+            if coverage(&quad, bounds) == Coverage::Outside {
+                continue;
+            }
+            match self
+                .get_node(index)
+                .ok_or(Error::DanglingIndex)?
+                .get_children_index()
+            {
+                Some(children) => stack.extend(children.into_iter().rev()),
+                None => leaves.push(index),
+            }
+        }
+        Ok((!leaves.is_empty()).then_some(leaves))
+    }
+
+    /// Replaces every node's bounds with what `f` returns for them, once all have succeeded.
+    fn map_bounds<F>(&mut self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(Bounds<S>) -> Result<Bounds<S>, Error>,
+    {
+        let mut moved = Vec::with_capacity(self.node_count());
+        for index in self.level_order() {
+            let node = self.get_node(index).ok_or(Error::DanglingIndex)?;
+            moved.push((index, f(node.get_bounds())?));
+        }
+        for (index, bounds) in moved {
+            if let Some(node) = self.get_node_mut(index) {
+                node.update_bounds(bounds);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_and_scale() {
+        let mut tree = CNQuadtree::<u8, i32>::new(0, (0, 0, 8, 8));
+        tree.set_min_cell_size(2);
+        let [nw, ..] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        let [_, _, _, nw_se] = tree.subdivide(nw, [5, 6, 7, 8]).unwrap();
+
+        tree.translate(-100, 50).unwrap();
+        assert_eq!(tree.bounds(), (-100, 50, -92, 58));
+        assert_eq!(tree[nw_se].get_bounds(), (-98, 52, -96, 54));
+        assert_eq!(tree.point_locate((-97, 53)), Some(nw_se));
+
+        tree.scale(3.0).unwrap();
+        assert_eq!(tree[nw_se].get_bounds(), (-294, 156, -288, 162));
+        assert_eq!(tree.min_cell_size(), 6);
+        tree.scale(0.5).unwrap();
+        assert_eq!(tree[nw_se].get_bounds(), (-147, 78, -144, 81));
+
+        // Failures leave the tree untouched.
+        assert_eq!(tree.scale(0.5), Err(Error::UnitConversion));
+        assert_eq!(tree.translate(0, i32::MAX), Err(Error::Overflow));
+        assert_eq!(tree.scale(-1.0), Err(Error::UnitConversion));
+        assert_eq!(tree[nw_se].get_bounds(), (-147, 78, -144, 81));
+
+        let mut tree = CNQuadtree::<u8, f64>::new(0, (0.0, 0.0, 1.0, 1.0));
+        tree.scale(0.25).unwrap();
+        tree.translate(0.5, -0.5).unwrap();
+        assert_eq!(tree.bounds(), (0.5, -0.5, 0.75, -0.25));
+    }
+
+    #[test]
+    fn world_transform() {
+        let mut tree = CNQuadtree::<u8, i32>::new(0, (0, 0, 8, 8));
+        let [nw, ne, sw, se] = tree.subdivide(tree.get_root(), [1, 2, 3, 4]).unwrap();
+        assert_eq!(tree.point_locate_world((5.5, 1.0)), Some(ne));
+
+        // A world where the tree is rotated a quarter turn, doubled and moved to (100, 100).
+        let placement = Affine::scaling(2.0, 2.0)
+            .then(&Affine::rotation(std::f64::consts::FRAC_PI_2))
+            .then(&Affine::translation(100.0, 100.0));
+        tree.set_world_transform(Some(placement)).unwrap();
+        let (x, y) = tree.tree_to_world((8.0, 0.0));
+        assert!((x - 100.0).abs() < 1e-9 && (y - 116.0).abs() < 1e-9);
+        let (x, y) = tree.world_to_tree((97.0, 110.0));
+        assert!((x - 5.0).abs() < 1e-9 && (y - 1.5).abs() < 1e-9);
+        assert_eq!(tree.point_locate_world((97.0, 110.0)), Some(ne));
+        assert_eq!(tree.point_locate_world((90.0, 101.0)), Some(sw));
+        assert_eq!(tree.point_locate_world((101.0, 101.0)), None);
+
+        // The world rectangle covers the west halves of the NW and SW quadrants.
+        let leaves = tree
+            .region_locate_world(Bounds(84.0, 100.0, 100.0, 104.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(leaves, [nw, sw]);
+        let all = tree
+            .region_locate_world(Bounds(50.0, 50.0, 150.0, 150.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(all, [nw, ne, sw, se]);
+        assert_eq!(
+            tree.region_locate_world(Bounds(0.0, 0.0, 10.0, 10.0)),
+            Ok(None)
+        );
+
+        assert_eq!(
+            tree.set_world_transform(Some(Affine::scaling(1.0, 0.0))),
+            Err(Error::SingularTransform)
+        );
+        tree.set_world_transform(None).unwrap();
+        assert_eq!(tree.world_transform(), None);
+    }
+}
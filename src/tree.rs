@@ -1,12 +1,25 @@
+use crate::entry::Entry;
+use crate::error::{Error, NeighborError, PopChildrenError, SubdivideError};
+use crate::iter::{LeafIter, RegionIter};
 use crate::location::{Cardinality, Location};
-use crate::node::RegionQuadtreeNode;
-use std::fmt::Debug;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use smallvec::SmallVec;
 
-use thiserror::Error;
+/// The neighbors of a node at one side. Most sides have few enough to stay inline.
+pub type Neighbors<I> = SmallVec<[I; 4]>;
 
-pub trait RegionQuadtree<T> {
+/// The coordinate type of a tree's nodes.
+pub(crate) type Unit<T, Q, const N: usize = 4> =
+    <<Q as RegionQuadtree<T, N>>::Node as RegionQuadtreeNode<T, N>>::Unit;
+
+/// A predicate over a node's bounds, used to skip subtrees during traversal.
+pub type BoundsPredicate<'a, S> = &'a dyn Fn(Bounds<S>) -> bool;
+
+/// A region tree whose internal nodes have `N` children, four for quadtrees. Methods that name
+/// quadrants, such as [`RegionQuadtree::leaves_hilbert`], only make sense for quadtrees.
+pub trait RegionQuadtree<T, const N: usize = 4> {
     type Index: Clone;
-    type Node: RegionQuadtreeNode<T, Index = Self::Index>;
+    type Node: RegionQuadtreeNode<T, N, Index = Self::Index>;
 
     /// Returns a shared ref to the node if index is valid. Otherwise, returns None.
     fn get_node(&self, index: Self::Index) -> Option<&Self::Node>;
@@ -18,62 +31,86 @@ pub trait RegionQuadtree<T> {
         &mut self,
         index: Self::Index,
         f: fn(&mut Self::Node) -> O,
-    ) -> Option<[O; 4]> {
+    ) -> Option<[O; N]> {
         let children_index = self.get_node(index)?.get_children_index()?;
-        Some(children_index.map(|c| f(self.get_node_mut(c).expect("failed to get child node"))))
+        if children_index
+            .iter()
+            .any(|c| self.get_node(c.clone()).is_none())
+        {
+            return None;
+        }
+        Some(children_index.map(|c| f(self.get_node_mut(c).unwrap())))
     }
+    /// Returns the leaves adjacent to a leaf at the given direction, ordered along the side:
+    /// top to bottom on the west, left to right on the north, and backwards on the east and
+    /// south. Neighbors may be larger, equal or smaller than the leaf, whatever their levels.
+    /// The list is empty if the leaf is at the border on that side.
+    ///
+    /// A call looks up `k + 1` nodes for `k` neighbors, the leaf included, however many levels
+    /// apart the leaf and its neighbors are: each neighbor links to the next along the side, so
+    /// nothing is searched. This holds for corrupted trees too, as a chain that doesn't move
+    /// along the side is reported rather than followed.
+    ///
+    /// Fails with [`NeighborError::NotLeaf`] for internal nodes, which don't keep neighbors, and
+    /// with [`NeighborError::Corrupted`] if a neighbor doesn't exist, the chain stops before
+    /// covering the leaf's side or a neighbor doesn't start further along the side than the
+    /// previous one.
     fn get_neighbors(
         &self,
         index: Self::Index,
         direction: Cardinality,
-    ) -> Option<Vec<Self::Index>> {
-        let node = self.get_node(index)?;
-        let first_neighbor_index = node.get_cardinal_neighbor_index(direction)?;
-        let first_neighbor = self.get_node(first_neighbor_index.clone())?;
-
-        // Allocate 2^(first_neighbor.layer - node.layer) if first_neighbor is further down the tree
-        // Otherwise only allocate one slot.
-        let capacity = {
-            if node.level() < first_neighbor.level() {
-                2_usize.pow((first_neighbor.level() - node.level()) as u32)
-            } else {
-                1
-            }
+    ) -> Result<Neighbors<Self::Index>, NeighborError> {
+        let node = self.get_node(index).ok_or(NeighborError::InvalidIndex)?;
+        if node.has_children() {
+            return Err(NeighborError::NotLeaf);
+        }
+        let mut result = Neighbors::new();
+        let Some(mut neighbor_index) = node.get_cardinal_neighbor_index(direction) else {
+            return Ok(result);
         };
+        let mut neighbor = self
+            .get_node(neighbor_index.clone())
+            .ok_or(Error::DanglingIndex)?;
 
-        let mut result = Vec::with_capacity(capacity);
-
-        let mut neighbor = first_neighbor;
-        let mut neighbor_index = first_neighbor_index;
-        // While succeeding neighbor is still the cardinal neighbor of node in the opposite direction
+        // Follow the chain until a neighbor reaches the far end of the node's side.
         loop {
-            result.push(neighbor_index.clone());
-            neighbor_index = match neighbor.get_cardinal_neighbor_index(direction.next_neighbor()) {
-                None => break,
-                Some(n) => n,
-            };
-            neighbor = match self.get_node(neighbor_index.clone()) {
-                None => break,
-                Some(n) => n,
-            };
-
-            // neighbor's cardinal neighbor in the opposite direction of the given direction
-            let opposite_side_index =
-                match neighbor.get_cardinal_neighbor_index(direction.opposite()) {
-                    None => break,
-                    Some(i) => i,
-                };
-            let opposite_side = match self.get_node(opposite_side_index) {
-                None => break,
-                Some(x) => x,
-            };
-
-            if neighbor.level() >= node.level() || node != opposite_side {
-                break;
+            result.push(neighbor_index);
+            if reaches_side_end(neighbor.get_bounds(), node.get_bounds(), direction) {
+                return Ok(result);
+            }
+            neighbor_index = neighbor
+                .get_cardinal_neighbor_index(direction.next_neighbor())
+                .ok_or(Error::BrokenChain)?;
+            let next = self
+                .get_node(neighbor_index.clone())
+                .ok_or(Error::DanglingIndex)?;
+            if !advances_along_side(neighbor.get_bounds(), next.get_bounds(), direction) {
+                return Err(Error::BrokenChain.into());
             }
+            neighbor = next;
+        }
+    }
+    /// Returns the single neighbor at the given direction whose level is the node's or coarser,
+    /// or None if the node is at the border. Unlike [`RegionQuadtree::get_neighbors`], which
+    /// lists the leaves along the whole side, this follows the cardinal neighbor and climbs up
+    /// to the node's level, so the result spans at least the node's side and may have children.
+    /// Off-center splits can leave a coarser neighbor shorter than the node's side.
+    fn get_equal_or_larger_neighbor(
+        &self,
+        index: Self::Index,
+        direction: Cardinality,
+    ) -> Option<Self::Index> {
+        let level = self.get_node(index.clone())?.level();
+        let mut neighbor_index = self
+            .get_node(index)?
+            .get_cardinal_neighbor_index(direction)?;
+        loop {
+            let neighbor = self.get_node(neighbor_index.clone())?;
+            if neighbor.level() <= level {
+                return Some(neighbor_index);
+            }
+            neighbor_index = neighbor.get_parent_index()?;
         }
-
-        Some(result)
     }
     fn map_neighbors<O>(
         &mut self,
@@ -81,104 +118,392 @@ pub trait RegionQuadtree<T> {
         f: fn(&mut Self::Node) -> O,
         direction: Cardinality,
     ) -> Option<Vec<O>> {
-        let neighbors = self.get_neighbors(index, direction)?;
-        Some(
-            neighbors
-                .into_iter()
-                .map(|n| f(self.get_node_mut(n).expect("failed to get neighbor node")))
-                .collect(),
-        )
+        let neighbors = self.get_neighbors(index, direction).ok()?;
+        neighbors
+            .into_iter()
+            .map(|n| self.get_node_mut(n).map(f))
+            .collect()
     }
     fn subdivide(
         &mut self,
         index: Self::Index,
-        items: [T; 4],
-    ) -> Result<[Self::Index; 4], SubdivideError<T>>;
-    fn pop_children(&mut self, index: Self::Index) -> Option<[T; 4]>;
+        items: [T; N],
+    ) -> Result<[Self::Index; N], SubdivideError<T, N>>;
+    /// Removes the node's children and returns their items. The children must be leaves.
+    fn pop_children(&mut self, index: Self::Index) -> Result<[T; N], PopChildrenError>;
+    /// Removes every descendant of the node, turning it into a leaf.
+    fn collapse(&mut self, index: Self::Index) -> Result<(), PopChildrenError> {
+        // Internal nodes in pre-order, so popping in reverse handles children before parents.
+        let mut internal = Vec::new();
+        let mut stack = vec![index];
+        while let Some(i) = stack.pop() {
+            let node = self
+                .get_node(i.clone())
+                .ok_or(PopChildrenError::InvalidIndex)?;
+            if let Some(children) = node.get_children_index() {
+                stack.extend(children);
+                internal.push(i);
+            }
+        }
+
+        for i in internal.into_iter().rev() {
+            self.pop_children(i)?;
+        }
+
+        Ok(())
+    }
+    /// Collapses every maximal subtree whose leaves all fail `keep` into a single leaf holding
+    /// the item returned by `default`. `default` is given the collapsed node's bounds.
+    fn retain_refined<K, D>(&mut self, mut keep: K, mut default: D)
+    where
+        Self: Sized,
+        K: FnMut(&T) -> bool,
+        D: FnMut(Bounds<Unit<T, Self, N>>) -> T,
+    {
+        /// Returns true if every leaf under the node fails `keep`. Collapses failing children
+        /// of nodes that don't uniformly fail.
+        fn visit<T, Q, K, D, const N: usize>(
+            tree: &mut Q,
+            index: Q::Index,
+            keep: &mut K,
+            default: &mut D,
+        ) -> bool
+        where
+            Q: RegionQuadtree<T, N>,
+            K: FnMut(&T) -> bool,
+            D: FnMut(Bounds<Unit<T, Q, N>>) -> T,
+        {
+            let children = match tree.get_node(index.clone()) {
+                None => return false,
+                Some(n) => match n.get_children_index() {
+                    None => return !keep(n.get_item()),
+                    Some(c) => c,
+                },
+            };
+
+            let failing = children
+                .clone()
+                .map(|child| visit(tree, child, keep, default));
+            if failing.iter().all(|&f| f) {
+                return true;
+            }
+
+            for (child, failing) in children.into_iter().zip(failing) {
+                if failing {
+                    collapse_into(tree, child, default);
+                }
+            }
+            false
+        }
+
+        fn collapse_into<T, Q, D, const N: usize>(tree: &mut Q, index: Q::Index, default: &mut D)
+        where
+            Q: RegionQuadtree<T, N>,
+            D: FnMut(Bounds<Unit<T, Q, N>>) -> T,
+        {
+            let is_internal = tree
+                .get_node(index.clone())
+                .is_some_and(|n| n.has_children());
+            if is_internal && tree.collapse(index.clone()).is_ok() {
+                if let Some(node) = tree.get_node_mut(index) {
+                    *node.get_item_mut() = default(node.get_bounds());
+                }
+            }
+        }
+
+        let root = self.get_root();
+        if visit(self, root.clone(), &mut keep, &mut default) {
+            collapse_into(self, root, &mut default);
+        }
+    }
+    /// Returns the node's position among its siblings as a location, which is only meaningful
+    /// for quadtrees. None for the root.
     fn location_among_siblings(&self, index: Self::Index) -> Option<Location> {
         let node = self.get_node(index)?;
         let parent = self.get_node(node.get_parent_index()?)?;
         let children = parent.get_children_index()?;
-        Some(
-            children
-                .into_iter()
-                .position(|x| self.get_node(x.clone()) == Some(node))?
-                .try_into()
-                .unwrap(),
-        )
+        children
+            .into_iter()
+            .position(|x| self.get_node(x.clone()) == Some(node))?
+            .try_into()
+            .ok()
     }
     fn point_locate(
         &self,
-        point: (
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-        ),
+        point: Point<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>,
     ) -> Option<Self::Index>;
+    /// Returns the leaf containing the point by walking cardinal neighbors from `from`, which is
+    /// faster than [`RegionQuadtree::point_locate`] when the point is near `from`. Falls back to
+    /// descending from the root if `from` isn't a leaf. Returns None if the point is outside the
+    /// tree's bounds.
+    fn relocate(
+        &self,
+        from: Self::Index,
+        point: Point<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>,
+    ) -> Option<Self::Index> {
+        if !self.get_node(self.get_root())?.point_in(point) {
+            return None;
+        }
+        if !self.get_node(from.clone())?.is_leaf() {
+            return self.point_locate(point);
+        }
+
+        // Each step crosses the side the point lies past. The other coordinate, clamped to the
+        // current leaf, picks the neighbor, so the walk never moves away from the point.
+        let (x, y) = point;
+        let mut index = from;
+        loop {
+            let node = self.get_node(index.clone())?;
+            let Bounds(left, top, right, bottom) = node.get_bounds();
+            let (direction, before, after, along) = if x < left {
+                (Cardinality::West, y < top, y >= bottom, y)
+            } else if x >= right {
+                (Cardinality::East, y >= bottom, y < top, y)
+            } else if y < top {
+                (Cardinality::North, x < left, x >= right, x)
+            } else if y >= bottom {
+                (Cardinality::South, x >= right, x < left, x)
+            } else {
+                return Some(index);
+            };
+
+            // Chains run top to bottom on the west, left to right on the north, and backwards on
+            // the other sides.
+            let chain = self.get_neighbors(index, direction).ok()?;
+            index = if before {
+                chain.first()?.clone()
+            } else if after {
+                chain.last()?.clone()
+            } else {
+                chain.into_iter().find(|n| {
+                    self.get_node(n.clone()).is_some_and(|n| {
+                        let Bounds(l, t, r, b) = n.get_bounds();
+                        match direction {
+                            Cardinality::West | Cardinality::East => t <= along && along < b,
+                            Cardinality::North | Cardinality::South => l <= along && along < r,
+                        }
+                    })
+                })?
+            };
+        }
+    }
+    /// Returns the chain of nodes from the root to the leaf containing the point, each paired
+    /// with its location among its siblings (None for the root). Returns None if the point is
+    /// outside the tree's bounds.
+    fn point_locate_path(
+        &self,
+        point: Point<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>,
+    ) -> Option<Vec<(Self::Index, Option<Location>)>>;
+    /// Returns the leaves overlapping the region. Returns None if the region is outside the
+    /// tree's bounds.
     fn region_locate(
         &self,
-        region: (
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-            <Self::Node as RegionQuadtreeNode<T>>::Unit,
-        ),
-    ) -> Option<Vec<Self::Index>>;
+        region: impl Into<Bounds<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>>,
+    ) -> Option<Vec<Self::Index>>
+    where
+        Self: Sized,
+    {
+        self.region_locate_classified(region)
+            .map(|leaves| leaves.into_iter().map(|(index, _)| index).collect())
+    }
+    /// Returns the leaves overlapping the region, each tagged with whether it lies entirely
+    /// within the region. Returns None if the region is outside the tree's bounds.
+    fn region_locate_classified(
+        &self,
+        region: impl Into<Bounds<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>>,
+    ) -> Option<Vec<(Self::Index, Containment)>>
+    where
+        Self: Sized,
+    {
+        let result: Vec<_> = self.region_iter(region).collect();
+        if result.is_empty() {
+            None
+        } else {
+            Some(result)
+        }
+    }
+    /// Returns a lazy iterator over the leaves overlapping the region, each tagged with whether
+    /// it lies entirely within the region.
+    fn region_iter(
+        &self,
+        region: impl Into<Bounds<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>>,
+    ) -> RegionIter<'_, T, Self, N>
+    where
+        Self: Sized,
+    {
+        RegionIter::new(self, region.into())
+    }
+    /// Returns an iterator over the leaves in Morton (Z) order, which is depth-first NW, NE, SW,
+    /// SE order.
+    fn leaves_morton(&self) -> LeafIter<'_, T, Self, N>
+    where
+        Self: Sized,
+    {
+        LeafIter::morton(self)
+    }
+    /// Returns an iterator over the leaves in Hilbert curve order. Consecutive leaves always
+    /// share an edge, which gives better locality than Morton order. Trees other than quadtrees
+    /// get Morton order.
+    fn leaves_hilbert(&self) -> LeafIter<'_, T, Self, N>
+    where
+        Self: Sized,
+    {
+        LeafIter::hilbert(self)
+    }
+    /// Returns the first leaf, in depth-first NW, NE, SW, SE order, for which `f` returns true.
+    /// `f` is given the leaf's bounds and item. If `descend` is given, subtrees whose bounds it
+    /// returns false for are skipped.
+    fn find_leaf<F>(
+        &self,
+        descend: Option<BoundsPredicate<'_, Unit<T, Self, N>>>,
+        mut f: F,
+    ) -> Option<Self::Index>
+    where
+        Self: Sized,
+        F: FnMut(Bounds<Unit<T, Self, N>>, &T) -> bool,
+    {
+        let mut stack = vec![self.get_root()];
+        while let Some(index) = stack.pop() {
+            let node = self.get_node(index.clone())?;
+            let bounds = node.get_bounds();
+            if descend.is_some_and(|d| !d(bounds)) {
+                continue;
+            }
+
+            match node.get_children_index() {
+                None if f(bounds, node.get_item()) => return Some(index),
+                None => {}
+                Some(children) => stack.extend(children.into_iter().rev()),
+            }
+        }
+
+        None
+    }
+    /// Returns true if `f` returns true for any leaf. See [`RegionQuadtree::find_leaf`].
+    fn any_leaf<F>(&self, descend: Option<BoundsPredicate<'_, Unit<T, Self, N>>>, f: F) -> bool
+    where
+        Self: Sized,
+        F: FnMut(Bounds<Unit<T, Self, N>>, &T) -> bool,
+    {
+        self.find_leaf(descend, f).is_some()
+    }
+    /// Returns true if `f` returns true for every leaf that isn't skipped. See
+    /// [`RegionQuadtree::find_leaf`].
+    fn all_leaves<F>(
+        &self,
+        descend: Option<BoundsPredicate<'_, Unit<T, Self, N>>>,
+        mut f: F,
+    ) -> bool
+    where
+        Self: Sized,
+        F: FnMut(Bounds<Unit<T, Self, N>>, &T) -> bool,
+    {
+        self.find_leaf(descend, |bounds, item| !f(bounds, item))
+            .is_none()
+    }
+    /// Walks the tree depth-first in NW, NE, SW, SE order, calling the visitor on every node
+    /// before its children. The visitor decides whether to descend into a node's children, skip
+    /// them, or stop. Returns false if the visitor stopped the traversal.
+    fn accept<V>(&self, visitor: &mut V) -> bool
+    where
+        Self: Sized,
+        V: Visit<Self::Index, Self::Node> + ?Sized,
+    {
+        let mut stack = vec![self.get_root()];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.get_node(index.clone()) else {
+                continue;
+            };
+            match visitor.visit(index, node) {
+                VisitFlow::Descend => {
+                    if let Some(children) = node.get_children_index() {
+                        stack.extend(children.into_iter().rev());
+                    }
+                }
+                VisitFlow::Skip => {}
+                VisitFlow::Stop => return false,
+            }
+        }
+        true
+    }
+    /// Returns an entry to the leaf containing the point. Returns None if the point is outside
+    /// the tree's bounds.
+    fn entry_at(
+        &mut self,
+        point: Point<<Self::Node as RegionQuadtreeNode<T, N>>::Unit>,
+    ) -> Option<Entry<'_, T, Self, N>>
+    where
+        Self: Sized,
+    {
+        let index = self.point_locate(point)?;
+        Some(Entry::new(self, index, point))
+    }
 }
 
-/// Error type for quadtree subdivision.
-/// `items` are the passed items to the subdivision function.
-#[derive(Debug, Error)]
-pub struct SubdivideError<T> {
-    pub items: [T; 4],
-    pub source: SubdivideErrorEnum,
+/// How a leaf returned by a region query relates to the queried region.
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum Containment {
+    FullyInside,
+    PartiallyOverlapping,
 }
 
-/// Enum error type for quadtree subdivision.
-#[derive(Debug, Error, Copy, Clone)]
-pub enum SubdivideErrorEnum {
-    #[error("node index is invalid")]
-    InvalidIndex,
-    #[error("node is already subdivided")]
-    AlreadySubdivided,
+/// What a [`Visit`] wants done after visiting a node.
+#[derive(Eq, PartialEq, Copy, Clone, Hash, Debug)]
+pub enum VisitFlow {
+    /// Visit the node's children, if it has any.
+    Descend,
+    /// Skip the node's children and carry on with the rest of the tree.
+    Skip,
+    /// End the traversal.
+    Stop,
 }
 
-pub fn find_cardinal_neighbor<T, U>(
-    tree: &T,
-    child_layer: usize,
-    direction: Cardinality,
-    inherited_neighbor: T::Index,
-) -> Option<T::Index>
+/// A traversal policy for [`RegionQuadtree::accept`], such as pruning by bounds, stopping early
+/// or collecting statistics, written once and usable with any tree. Closures taking the index
+/// and node are visitors too.
+pub trait Visit<I, Node: ?Sized> {
+    /// Visits a node and returns how the traversal continues.
+    fn visit(&mut self, index: I, node: &Node) -> VisitFlow;
+}
+
+impl<I, Node, F> Visit<I, Node> for F
 where
-    T: RegionQuadtree<U>,
+    Node: ?Sized,
+    F: FnMut(I, &Node) -> VisitFlow,
 {
-    // TODO: Rewrite using bitwise operations
-    let mut layers = vec![0_usize];
-    let mut current_neighbor = tree.get_node(inherited_neighbor.clone())?;
-    let mut current_neighbor_index = inherited_neighbor.clone();
-
-    while layers[0] != 0 {
-        let index = current_neighbor.level().saturating_sub(child_layer);
-        if index >= layers.len() {
-            layers.resize(index + 1, 0);
-        }
-
-        layers[index] += 1;
-
-        for index in (0..layers.len()).rev() {
-            if layers[index] >= 2 && index != 0 {
-                layers[index] = 0;
-                layers[index - 1] += 1;
-            }
-        }
-
-        current_neighbor_index =
-            current_neighbor.get_cardinal_neighbor_index(direction.next_neighbor())?;
-        current_neighbor = tree.get_node(current_neighbor_index.clone())?;
+    fn visit(&mut self, index: I, node: &Node) -> VisitFlow {
+        self(index, node)
     }
+}
 
-    // Return the next neighbor
-    current_neighbor_index =
-        current_neighbor.get_cardinal_neighbor_index(direction.next_neighbor())?;
+/// Returns whether a neighbor at `direction` of a node extends to or past the end of the node's
+/// side where its neighbor chain stops: the bottom for west, the right for north, the top for
+/// east and the left for south.
+pub(crate) fn reaches_side_end<U: PartialOrd>(
+    neighbor: Bounds<U>,
+    node: Bounds<U>,
+    direction: Cardinality,
+) -> bool {
+    match direction {
+        Cardinality::West => neighbor.3 >= node.3,
+        Cardinality::North => neighbor.2 >= node.2,
+        Cardinality::East => neighbor.1 <= node.1,
+        Cardinality::South => neighbor.0 <= node.0,
+    }
+}
 
-    Some(current_neighbor_index)
+/// Returns whether `next`, the neighbor following `previous` in a chain at `direction`, starts
+/// further along the side than `previous`, as every well-formed chain does. Chains that fail
+/// this would revisit nodes instead of ending.
+pub(crate) fn advances_along_side<U: PartialOrd>(
+    previous: Bounds<U>,
+    next: Bounds<U>,
+    direction: Cardinality,
+) -> bool {
+    match direction {
+        Cardinality::West => next.1 > previous.1,
+        Cardinality::North => next.0 > previous.0,
+        Cardinality::East => next.3 < previous.3,
+        Cardinality::South => next.2 < previous.2,
+    }
 }
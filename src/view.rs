@@ -0,0 +1,425 @@
+use crate::binary::{invalid_data, Header, ItemCodec};
+use crate::location::Cardinality;
+use crate::node::{child_bounds, midpoint, Bounds, Point};
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::io;
+
+/// A read-only tree borrowed from a buffer in the binary format written by
+/// [`CNQuadtree::save_to`](crate::CNQuadtree::save_to), such as a memory-mapped file. Nothing is
+/// deserialized up front; queries read the buffer directly.
+///
+/// Nodes are identified by their position in the encoding, with the root at 0. Items must be
+/// encoded with a fixed size. Cardinal neighbors aren't stored, so neighbor queries descend from
+/// the root. A buffer with a corrupted rank directory yields wrong results, but never panics or
+/// loops forever.
+#[derive(Copy, Clone, Debug)]
+pub struct CNQuadtreeView<'a, S> {
+    bounds: Bounds<S>,
+    len: usize,
+    topology: &'a [u8],
+    ranks: &'a [u8],
+    items: &'a [u8],
+    item_size: usize,
+}
+
+impl<'a, S> CNQuadtreeView<'a, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns a view of the encoded tree whose items each take `item_size` bytes.
+    pub fn new(bytes: &'a [u8], item_size: usize) -> io::Result<Self> {
+        let mut rest = bytes;
+        let header = Header::<S>::read(&mut rest)?;
+        let len = header.len;
+        if len == 0 {
+            return Err(invalid_data("tree has no nodes"));
+        }
+        let words_len = len.div_ceil(64) * 8;
+        let items_len = len
+            .checked_mul(item_size)
+            .ok_or_else(|| invalid_data("items are too large"))?;
+        if Some(rest.len()) != items_len.checked_add(2 * words_len) {
+            return Err(invalid_data("buffer length doesn't match the node count"));
+        }
+        let (topology, rest) = rest.split_at(words_len);
+        let (ranks, items) = rest.split_at(words_len);
+
+        let view = Self {
+            bounds: header.bounds,
+            len,
+            topology,
+            ranks,
+            items,
+            item_size,
+        };
+        // Check the totals without reading every word.
+        let last = len.div_ceil(64) - 1;
+        let internal = usize::try_from(view.rank_word(last))
+            .ok()
+            .and_then(|rank| rank.checked_add(view.topology_word(last).count_ones() as usize));
+        if internal.and_then(|i| i.checked_mul(4)?.checked_add(1)) != Some(len) {
+            return Err(invalid_data("node count doesn't match topology"));
+        }
+        if !len.is_multiple_of(64) && view.topology_word(last) >> (len % 64) != 0 {
+            return Err(invalid_data("topology has bits past the last node"));
+        }
+
+        Ok(view)
+    }
+
+    /// Returns the number of nodes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the view has no nodes. Always false, as every tree has a root.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root node.
+    #[inline]
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    /// Returns true if the node has children. Returns false for invalid nodes.
+    pub fn has_children(&self, node: usize) -> bool {
+        node < self.len && self.topology_word(node / 64) & (1 << (node % 64)) != 0
+    }
+
+    /// Returns the node's children in the order NW, NE, SW, SE, or None if it's a leaf.
+    pub fn children(&self, node: usize) -> Option<[usize; 4]> {
+        if !self.has_children(node) {
+            return None;
+        }
+        let word = self.topology_word(node / 64) & ((1 << (node % 64)) - 1);
+        let rank = usize::try_from(self.rank_word(node / 64))
+            .ok()?
+            .checked_add(word.count_ones() as usize)?;
+        let first = rank.checked_mul(4)?.checked_add(1)?;
+        // Children always follow their parent in level order.
+        if first <= node || first.checked_add(3)? >= self.len {
+            return None;
+        }
+        Some([first, first + 1, first + 2, first + 3])
+    }
+
+    /// Returns the node's parent, or None for the root and invalid nodes.
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        if node == 0 || node >= self.len {
+            return None;
+        }
+        let rank = ((node - 1) / 4) as u64;
+
+        // The last word with at most `rank` set bits before it holds the parent.
+        let (mut low, mut high) = (0, self.len.div_ceil(64));
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.rank_word(mid) <= rank {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let word = low.checked_sub(1)?;
+        let mut bits = self.topology_word(word);
+        for _ in 0..rank - self.rank_word(word) {
+            bits &= bits.checked_sub(1)?;
+        }
+        let parent = word * 64 + bits.trailing_zeros() as usize;
+        (bits != 0 && parent < node).then_some(parent)
+    }
+
+    /// Returns the chain of nodes from the root to the node.
+    pub fn path(&self, node: usize) -> Option<Vec<usize>> {
+        if node >= self.len {
+            return None;
+        }
+        let mut path = vec![node];
+        let mut current = node;
+        while let Some(parent) = self.parent(current) {
+            path.push(parent);
+            current = parent;
+        }
+        if current != 0 {
+            return None;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Returns the node's level. The root is at level 0.
+    pub fn level(&self, node: usize) -> Option<usize> {
+        self.path(node).map(|p| p.len() - 1)
+    }
+
+    /// Returns the node's bounds.
+    pub fn bounds(&self, node: usize) -> Option<Bounds<S>> {
+        let path = self.path(node)?;
+        let mut bounds = self.bounds;
+        for pair in path.windows(2) {
+            let location = pair[1].checked_sub(self.children(pair[0])?[0])?;
+            bounds = child_bounds(bounds, location)?;
+        }
+        Some(bounds)
+    }
+
+    /// Returns the node's encoded item.
+    pub fn item_bytes(&self, node: usize) -> Option<&'a [u8]> {
+        let start = node.checked_mul(self.item_size)?;
+        self.items.get(start..start.checked_add(self.item_size)?)
+    }
+
+    /// Decodes the node's item.
+    pub fn item<T, C: ItemCodec<T>>(&self, node: usize, codec: &mut C) -> io::Result<T> {
+        let mut bytes = self
+            .item_bytes(node)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid node"))?;
+        codec.decode(&mut bytes)
+    }
+
+    /// Returns the leaves in level order.
+    pub fn leaves(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|&n| !self.has_children(n))
+    }
+
+    /// Returns the leaf containing the point, or None if the point is outside the tree's bounds.
+    pub fn point_locate(&self, point: Point<S>) -> Option<usize> {
+        self.locate(point, (false, false))
+    }
+
+    /// Returns the node's cardinal neighbor at the given direction. Only leaves have cardinal
+    /// neighbors.
+    pub fn cardinal_neighbor(&self, node: usize, direction: Cardinality) -> Option<usize> {
+        if self.has_children(node) {
+            return None;
+        }
+        let Bounds(left, top, right, bottom) = self.bounds(node)?;
+        match direction {
+            Cardinality::West => self.locate((left, top), (true, false)),
+            Cardinality::North => self.locate((left, top), (false, true)),
+            Cardinality::East => self.locate((right, bottom), (false, true)),
+            Cardinality::South => self.locate((right, bottom), (true, false)),
+        }
+    }
+
+    /// Returns every leaf adjacent to the leaf at the given direction, in the same order as
+    /// [`RegionQuadtree::get_neighbors`](crate::RegionQuadtree::get_neighbors). Returns None if
+    /// a corrupted buffer stops the walk along the side from moving forward.
+    pub fn neighbors(&self, node: usize, direction: Cardinality) -> Option<Vec<usize>> {
+        let mut neighbor = self.cardinal_neighbor(node, direction)?;
+        let Bounds(left, top, right, bottom) = self.bounds(node)?;
+        let mut result = Vec::new();
+        let mut reached = None;
+        loop {
+            result.push(neighbor);
+            let Bounds(l, t, r, b) = self.bounds(neighbor)?;
+            // The far edge of each neighbor along the side, which only moves forward in a valid
+            // tree: down for west, right for north, up for east and left for south.
+            let (edge, forward) = match direction {
+                Cardinality::West => (b, true),
+                Cardinality::North => (r, true),
+                Cardinality::East => (t, false),
+                Cardinality::South => (l, false),
+            };
+            let stalled = |reached: S| {
+                if forward {
+                    edge <= reached
+                } else {
+                    edge >= reached
+                }
+            };
+            if reached.is_some_and(stalled) {
+                return None;
+            }
+            reached = Some(edge);
+            let next = match direction {
+                Cardinality::West if b < bottom => self.locate((left, b), (true, false)),
+                Cardinality::North if r < right => self.locate((r, top), (false, true)),
+                Cardinality::East if t > top => self.locate((right, t), (false, true)),
+                Cardinality::South if l > left => self.locate((l, bottom), (true, false)),
+                _ => break,
+            };
+            neighbor = next?;
+        }
+        Some(result)
+    }
+
+    /// Descends to the leaf containing the point. An axis flagged in `from_below` is located
+    /// just below the coordinate instead of at it, so its ranges are `(min, max]`.
+    fn locate(&self, point: Point<S>, from_below: (bool, bool)) -> Option<usize> {
+        let contains = |min: S, max: S, value: S, from_below: bool| {
+            if from_below {
+                min < value && value <= max
+            } else {
+                min <= value && value < max
+            }
+        };
+
+        let Bounds(mut left, mut top, mut right, mut bottom) = self.bounds;
+        if !contains(left, right, point.0, from_below.0)
+            || !contains(top, bottom, point.1, from_below.1)
+        {
+            return None;
+        }
+
+        let mut node = 0;
+        while let Some(children) = self.children(node) {
+            let (x_middle, y_middle) = (midpoint(left, right)?, midpoint(top, bottom)?);
+            let east = !contains(left, x_middle, point.0, from_below.0);
+            let south = !contains(top, y_middle, point.1, from_below.1);
+            if east {
+                left = x_middle;
+            } else {
+                right = x_middle;
+            }
+            if south {
+                top = y_middle;
+            } else {
+                bottom = y_middle;
+            }
+            node = children[east as usize | ((south as usize) << 1)];
+        }
+        Some(node)
+    }
+
+    fn topology_word(&self, word: usize) -> u64 {
+        read_word(self.topology, word)
+    }
+
+    fn rank_word(&self, word: usize) -> u64 {
+        read_word(self.ranks, word)
+    }
+}
+
+fn read_word(words: &[u8], word: usize) -> u64 {
+    words
+        .get(word * 8..word * 8 + 8)
+        .and_then(|w| w.try_into().ok())
+        .map_or(0, u64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binary::LeBytes;
+    use crate::node::RegionQuadtreeNode;
+    use crate::slottree::CNQuadtree;
+    use crate::tree::RegionQuadtree;
+
+    #[test]
+    fn matches_tree() {
+        let mut tree = CNQuadtree::<u32, i32>::new(0, (0, 0, 64, 64));
+        let mut seed = 1u64;
+        for i in 0..150 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let point = ((seed >> 33) as i32 % 64, (seed >> 45) as i32 % 64);
+            let leaf = tree.point_locate(point).unwrap();
+            let _ = tree.subdivide(leaf, [i, i + 1, i + 2, i + 3]);
+        }
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+
+        let view = CNQuadtreeView::<i32>::new(&bytes, 4).unwrap();
+        assert_eq!(view.len(), tree.stats().nodes);
+        assert_eq!(view.leaves().count(), tree.stats().leaves);
+        let bounds_of = |index| tree.get_node(index).unwrap().get_bounds();
+        for x in 0..64 {
+            for y in 0..64 {
+                let leaf = view.point_locate((x, y)).unwrap();
+                let expected = tree.point_locate((x, y)).unwrap();
+                assert_eq!(view.bounds(leaf), Some(bounds_of(expected)));
+                assert_eq!(
+                    view.level(leaf),
+                    Some(tree.get_node(expected).unwrap().level())
+                );
+                let item: u32 = view.item(leaf, &mut LeBytes).unwrap();
+                assert_eq!(item, *tree.get_node(expected).unwrap().get_item());
+
+                for direction in 0..4 {
+                    let direction = Cardinality::try_from(direction).unwrap();
+                    let from_view: Vec<_> = view
+                        .neighbors(leaf, direction)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|n| view.bounds(n).unwrap())
+                        .collect();
+                    let from_tree: Vec<_> = tree
+                        .get_neighbors(expected, direction)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(bounds_of)
+                        .collect();
+                    assert_eq!(from_view, from_tree);
+                }
+            }
+        }
+        assert_eq!(view.point_locate((64, 0)), None);
+        assert_eq!(view.parent(0), None);
+        assert_eq!(view.children(0), Some([1, 2, 3, 4]));
+        assert_eq!(view.parent(4), Some(0));
+    }
+
+    #[test]
+    fn rejects_mismatched_buffer() {
+        let tree = CNQuadtree::<u16, u32>::new(0, (0, 0, 4, 4));
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+
+        assert!(CNQuadtreeView::<u32>::new(&bytes, 2).is_ok());
+        assert!(CNQuadtreeView::<u32>::new(&bytes, 4).is_err());
+        assert!(CNQuadtreeView::<u32>::new(&bytes[..bytes.len() - 1], 2).is_err());
+
+        // A header claiming no nodes, with nothing after it.
+        let header_len = bytes.len() - 16 - 2;
+        let mut forged = bytes[..header_len].to_vec();
+        forged[header_len - 8..].copy_from_slice(&0u64.to_le_bytes());
+        assert!(CNQuadtreeView::<u32>::new(&forged, 2).is_err());
+    }
+
+    #[test]
+    fn survives_forged_ranks() {
+        let mut tree = CNQuadtree::<u16, u32>::new(0, (0, 0, 64, 64));
+        let children = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        for child in children {
+            for grandchild in tree.subdivide(child, [0; 4]).unwrap() {
+                tree.subdivide(grandchild, [0; 4]).unwrap();
+            }
+        }
+        let mut bytes = Vec::new();
+        tree.save_to(&mut bytes, &mut LeBytes).unwrap();
+        let view = CNQuadtreeView::<u32>::new(&bytes, 2).unwrap();
+        assert_eq!(view.len(), 85);
+        assert_eq!(view.item_bytes(usize::MAX / 2), None);
+
+        // Only the last rank word is checked up front, so a huge first one gets through.
+        let ranks = bytes.len() - 85 * 2 - 16;
+        bytes[ranks..ranks + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        let view = CNQuadtreeView::<u32>::new(&bytes, 2).unwrap();
+        assert_eq!(view.children(0), None);
+        assert_eq!(view.point_locate((1, 1)), Some(0));
+        assert_eq!(view.bounds(5), None);
+
+        // Forged ranks that make children and parents disagree must not stall neighbor walks.
+        for word in 0..2 {
+            for rank in [0, 1, 3, 5, 9, 20, 21, 40, u64::MAX / 2] {
+                let mut forged = bytes.clone();
+                let start = ranks + word * 8;
+                forged[start..start + 8].copy_from_slice(&rank.to_le_bytes());
+                let Ok(view) = CNQuadtreeView::<u32>::new(&forged, 2) else {
+                    continue;
+                };
+                for node in 0..view.len() {
+                    for direction in Cardinality::ALL {
+                        let _ = view.neighbors(node, direction);
+                    }
+                }
+            }
+        }
+    }
+}
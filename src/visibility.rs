@@ -0,0 +1,221 @@
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// Sorted, disjoint closed intervals of angles in `[-PI, PI]`.
+type Angles = Vec<(f64, f64)>;
+
+impl<T, S> CNQuadtree<T, S>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns the leaves seen from `origin`, where leaves whose item `blocks` are opaque. A leaf
+    /// is seen if a ray from `origin` reaches its interior crossing only transparent leaves, so
+    /// opaque leaves facing `origin` are seen too. The leaf holding `origin` is always seen.
+    ///
+    /// Sweeps the angles of the rays reaching each leaf outwards through the edges it shares
+    /// with its cardinal neighbors. Rays grazing an edge or passing exactly through a corner
+    /// don't count. Returns an empty set if `origin` is outside the tree's bounds or a
+    /// coordinate can't be converted to `f64`.
+    pub fn visible_from<F>(&self, origin: Point<S>, mut blocks: F) -> HashSet<NodeId>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let (Some(start), Some(ox), Some(oy)) = (
+            self.point_locate(origin),
+            origin.0.to_f64(),
+            origin.1.to_f64(),
+        ) else {
+            return HashSet::new();
+        };
+        let mut rays: HashMap<NodeId, Angles> = HashMap::from([(start, vec![(-PI, PI)])]);
+        let mut queue = VecDeque::from([start]);
+        let mut opaque = HashMap::new();
+        while let Some(leaf) = queue.pop_front() {
+            let Some(node) = self.get_node(leaf) else {
+                continue;
+            };
+            if *opaque
+                .entry(leaf)
+                .or_insert_with(|| blocks(node.get_item()))
+            {
+                continue;
+            }
+            let Some(Bounds(left, top, right, bottom)) = self.bounds_f64(leaf) else {
+                return HashSet::new();
+            };
+            for direction in [
+                Cardinality::West,
+                Cardinality::North,
+                Cardinality::East,
+                Cardinality::South,
+            ] {
+                for neighbor in self.get_neighbors(leaf, direction).unwrap_or_default() {
+                    let Some(Bounds(n_left, n_top, n_right, n_bottom)) = self.bounds_f64(neighbor)
+                    else {
+                        return HashSet::new();
+                    };
+                    // The shared edge as its line, its extent along the line, and the direction
+                    // of rays crossing it outwards.
+                    let (line, from, to, vertical, outwards) = match direction {
+                        Cardinality::West => (left, top.max(n_top), bottom.min(n_bottom), true, PI),
+                        Cardinality::East => {
+                            (right, top.max(n_top), bottom.min(n_bottom), true, 0.0)
+                        }
+                        Cardinality::North => {
+                            (top, left.max(n_left), right.min(n_right), false, -FRAC_PI_2)
+                        }
+                        Cardinality::South => (
+                            bottom,
+                            left.max(n_left),
+                            right.min(n_right),
+                            false,
+                            FRAC_PI_2,
+                        ),
+                    };
+                    let edge = edge_angles((ox, oy), line, from, to, vertical, outwards);
+                    let reaching = intersect(&rays[&leaf], &edge);
+                    if reaching.is_empty() {
+                        continue;
+                    }
+                    let current = rays.entry(neighbor).or_default();
+                    let merged = union(current, &reaching);
+                    if merged != *current {
+                        *current = merged;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+        }
+        rays.into_keys().collect()
+    }
+}
+
+/// Returns the angles of rays from `origin` crossing an edge in the direction `outwards`. The
+/// edge lies on the line `x = line` from `y = from` to `y = to` if `vertical`, or on `y = line`
+/// from `x = from` to `x = to` otherwise.
+fn edge_angles(
+    origin: (f64, f64),
+    line: f64,
+    from: f64,
+    to: f64,
+    vertical: bool,
+    outwards: f64,
+) -> Angles {
+    let (across, along) = if vertical {
+        origin
+    } else {
+        (origin.1, origin.0)
+    };
+    // Signed distance from the origin to the line, positive if rays cross it outwards.
+    let ahead = if outwards.cos() + outwards.sin() > 0.0 {
+        line - across
+    } else {
+        across - line
+    };
+    if ahead < 0.0 || from >= to {
+        return Vec::new();
+    }
+    if ahead == 0.0 {
+        // The origin lies on the line, so only rays leaving from the edge itself cross it.
+        return if from <= along && along <= to {
+            normalize(outwards - FRAC_PI_2, outwards + FRAC_PI_2)
+        } else {
+            Vec::new()
+        };
+    }
+    let angle = |t: f64| {
+        if vertical {
+            (t - origin.1).atan2(line - origin.0)
+        } else {
+            (line - origin.1).atan2(t - origin.0)
+        }
+    };
+    let (a, b) = (angle(from), angle(to));
+    let (low, high) = (a.min(b), a.max(b));
+    if high - low <= PI {
+        vec![(low, high)]
+    } else {
+        // The edge straddles the direction of PI, where angles wrap around.
+        normalize(high, low + 2.0 * PI)
+    }
+}
+
+/// Returns the interval from `low` to `high`, within `2 * PI` of each other, wrapped into
+/// `[-PI, PI]`.
+fn normalize(low: f64, high: f64) -> Angles {
+    let (low, high) = if low < -PI {
+        (low + 2.0 * PI, high + 2.0 * PI)
+    } else {
+        (low, high)
+    };
+    if high > PI {
+        vec![(-PI, high - 2.0 * PI), (low, PI)]
+    } else {
+        vec![(low, high)]
+    }
+}
+
+/// Returns the angles in both sets, dropping intervals without width.
+fn intersect(a: &Angles, b: &Angles) -> Angles {
+    let mut result = Vec::new();
+    for &(a_low, a_high) in a {
+        for &(b_low, b_high) in b {
+            let (low, high) = (a_low.max(b_low), a_high.min(b_high));
+            if low < high {
+                result.push((low, high));
+            }
+        }
+    }
+    result.sort_by(|x, y| x.0.total_cmp(&y.0));
+    result
+}
+
+/// Returns the angles in either set.
+fn union(a: &Angles, b: &Angles) -> Angles {
+    let mut all: Angles = a.iter().chain(b).copied().collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let mut result: Angles = Vec::with_capacity(all.len());
+    for (low, high) in all {
+        match result.last_mut() {
+            Some(last) if low <= last.1 => last.1 = last.1.max(high),
+            _ => result.push((low, high)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_from() {
+        let mut tree = CNQuadtree::new(false, (0, 0, 16, 16));
+        let [nw, ne, sw, _] = tree.subdivide(tree.get_root(), [false; 4]).unwrap();
+        let nw_children = tree.subdivide(nw, [false, true, false, true]).unwrap();
+        tree.subdivide(ne, [false; 4]).unwrap();
+
+        // Every leaf is seen without walls, from inside or from a boundary.
+        assert_eq!(tree.visible_from((2, 2), |_| false).len(), 10);
+        assert_eq!(tree.visible_from((8, 8), |_| false).len(), 10);
+
+        // The wall down the right of NW hides NE and SE.
+        let mut expected: HashSet<_> = nw_children.into();
+        expected.insert(sw);
+        assert_eq!(tree.visible_from((2, 2), |&wall| wall), expected);
+
+        // Only the wall holding the origin is seen.
+        let wall = tree.point_locate((6, 2)).unwrap();
+        assert_eq!(
+            tree.visible_from((6, 2), |&wall| wall),
+            HashSet::from([wall])
+        );
+        assert!(tree.visible_from((20, 2), |_| false).is_empty());
+    }
+}
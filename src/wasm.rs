@@ -0,0 +1,192 @@
+//! JavaScript API over a [`CNQuadtree`] with `f64` coordinates and items, enabled by the `wasm`
+//! feature. The crate only builds as a Rust library by default, so build the module with
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! ```
+//!
+//! and generate the bindings with `wasm-bindgen`. CI checks that this build succeeds.
+//!
+//! Nodes are identified by `BigInt` keys that are only valid with the tree that returned them.
+//! Bounds are passed as flat `Float64Array`s of `[min_x, min_y, max_x, max_y]` quadruples, so
+//! drawing every leaf costs one copy across the boundary.
+
+use crate::id::NodeId;
+use crate::location::Cardinality;
+use crate::node::{Bounds, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use wasm_bindgen::prelude::*;
+
+/// A CN quadtree usable from JavaScript.
+#[wasm_bindgen(js_name = CNQuadtree)]
+pub struct WasmQuadtree {
+    tree: CNQuadtree<f64, f64>,
+}
+
+#[wasm_bindgen(js_class = CNQuadtree)]
+impl WasmQuadtree {
+    /// Returns a tree with a single leaf covering the bounds holding `item`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64, item: f64) -> Self {
+        Self {
+            tree: CNQuadtree::new(item, (min_x, min_y, max_x, max_y)),
+        }
+    }
+
+    /// Returns a tree uniformly subdivided down to `depth`, with every node holding `item`.
+    pub fn uniform(
+        min_x: f64,
+        min_y: f64,
+        max_x: f64,
+        max_y: f64,
+        depth: usize,
+        item: f64,
+    ) -> Result<WasmQuadtree, JsError> {
+        let mut tree = Self::new(min_x, min_y, max_x, max_y, item);
+        let mut frontier = vec![tree.tree.get_root()];
+        for _ in 0..depth {
+            let mut next = Vec::with_capacity(frontier.len() * 4);
+            for node in frontier {
+                let children = tree.tree.subdivide(node, [item; 4]).map_err(|e| e.source)?;
+                next.extend(children);
+            }
+            frontier = next;
+        }
+        Ok(tree)
+    }
+
+    /// Returns the root's key.
+    pub fn root(&self) -> u64 {
+        self.key(self.tree.get_root())
+    }
+
+    /// Subdivides a leaf, giving its children `items` in the order NW, NE, SW, SE. Returns the
+    /// children's keys in the same order.
+    pub fn subdivide(&mut self, node: u64, items: &[f64]) -> Result<Vec<u64>, JsError> {
+        let items: [f64; 4] = items
+            .try_into()
+            .map_err(|_| JsError::new("expected 4 items"))?;
+        let children = self
+            .tree
+            .subdivide(self.id(node), items)
+            .map_err(|e| e.source)?;
+        Ok(children.iter().map(|&child| self.key(child)).collect())
+    }
+
+    /// Removes a node's children, which must be leaves, and returns their items.
+    #[wasm_bindgen(js_name = popChildren)]
+    pub fn pop_children(&mut self, node: u64) -> Result<Vec<f64>, JsError> {
+        Ok(self.tree.pop_children(self.id(node))?.to_vec())
+    }
+
+    /// Returns the key of the leaf containing the point.
+    pub fn locate(&self, x: f64, y: f64) -> Option<u64> {
+        self.tree.point_locate((x, y)).map(|leaf| self.key(leaf))
+    }
+
+    /// Returns the keys of the leaves adjacent to a leaf at the given direction (0 west,
+    /// 1 north, 2 east, 3 south). Throws for internal nodes and broken neighbor links.
+    pub fn neighbors(&self, node: u64, direction: u8) -> Result<Vec<u64>, JsError> {
+        let direction = Cardinality::try_from(direction as usize)
+            .map_err(|_| JsError::new("direction must be between 0 and 3"))?;
+        let neighbors = self.tree.get_neighbors(self.id(node), direction)?;
+        Ok(neighbors.into_iter().map(|n| self.key(n)).collect())
+    }
+
+    /// Returns the keys of all leaves in Morton order.
+    pub fn leaves(&self) -> Vec<u64> {
+        self.tree
+            .leaves_morton()
+            .map(|leaf| self.key(leaf))
+            .collect()
+    }
+
+    /// Returns the bounds of all leaves, in the same order as [`WasmQuadtree::leaves`].
+    #[wasm_bindgen(js_name = leafBounds)]
+    pub fn leaf_bounds(&self) -> Vec<f64> {
+        self.tree
+            .leaves_morton()
+            .filter_map(|leaf| self.tree.get_node(leaf))
+            .flat_map(|node| {
+                let Bounds(min_x, min_y, max_x, max_y) = node.get_bounds();
+                [min_x, min_y, max_x, max_y]
+            })
+            .collect()
+    }
+
+    /// Returns the items of all leaves, in the same order as [`WasmQuadtree::leaves`].
+    #[wasm_bindgen(js_name = leafItems)]
+    pub fn leaf_items(&self) -> Vec<f64> {
+        self.tree
+            .leaves_morton()
+            .filter_map(|leaf| self.tree.get_node(leaf))
+            .map(|node| *node.get_item())
+            .collect()
+    }
+
+    /// Returns a node's bounds, or undefined if the key is invalid.
+    pub fn bounds(&self, node: u64) -> Option<Vec<f64>> {
+        let Bounds(min_x, min_y, max_x, max_y) = self.tree.get_node(self.id(node))?.get_bounds();
+        Some(vec![min_x, min_y, max_x, max_y])
+    }
+
+    /// Returns a node's item, or undefined if the key is invalid.
+    pub fn item(&self, node: u64) -> Option<f64> {
+        self.tree
+            .get_node(self.id(node))
+            .map(|node| *node.get_item())
+    }
+
+    /// Replaces a node's item. Returns false if the key is invalid.
+    #[wasm_bindgen(js_name = setItem)]
+    pub fn set_item(&mut self, node: u64, item: f64) -> bool {
+        match self.tree.get_node_mut(self.id(node)) {
+            Some(node) => {
+                *node.get_item_mut() = item;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl WasmQuadtree {
+    fn key(&self, id: NodeId) -> u64 {
+        id.to_ffi().0
+    }
+
+    fn id(&self, key: u64) -> NodeId {
+        let (_, tree) = self.tree.get_root().to_ffi();
+        NodeId::from_ffi(key, tree).expect("tree tags are nonzero")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Error paths construct JavaScript values, which only works on wasm targets.
+    #[test]
+    fn facade() {
+        let mut tree = WasmQuadtree::uniform(0.0, 0.0, 8.0, 8.0, 1, 0.0).unwrap();
+        assert_eq!(tree.leaves().len(), 4);
+        assert_eq!(tree.leaf_bounds().len(), 16);
+
+        let leaf = tree.locate(5.0, 1.0).unwrap();
+        assert_eq!(tree.bounds(leaf), Some(vec![4.0, 0.0, 8.0, 4.0]));
+        let children = tree.subdivide(leaf, &[1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(tree.locate(7.0, 3.0), Some(children[3]));
+        assert!(tree.set_item(children[0], 5.0));
+        assert_eq!(tree.item(children[0]), Some(5.0));
+
+        let west = tree.locate(1.0, 1.0).unwrap();
+        assert_eq!(
+            tree.neighbors(west, 2).unwrap(),
+            vec![children[2], children[0]]
+        );
+        assert_eq!(tree.pop_children(leaf).unwrap(), vec![5.0, 2.0, 3.0, 4.0]);
+        assert_eq!(tree.leaf_items(), vec![0.0; 4]);
+        assert_eq!(tree.locate(8.0, 0.0), None);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::id::NodeId;
+use crate::node::{Bounds, Point, RegionQuadtreeNode};
+use crate::slottree::CNQuadtree;
+use crate::tree::RegionQuadtree;
+use num_traits::{FromPrimitive, NumAssign, NumOps, ToPrimitive};
+
+/// A map of labeled zones, such as territories or the areas of a game map, answering which
+/// zone a position is in by descending a quadtree of its own.
+///
+/// Zones are painted as rectangles or as the leaves of a tree, each painting replacing the
+/// labels beneath it, so every position is in at most one zone. The map is kept apart from the
+/// tree it labels, so subdividing or merging that tree's leaves doesn't move their zones.
+/// Painted regions are split down to the map's maximum depth and rounded out to the cells
+/// there, and cells with equal labels are merged after each painting.
+#[derive(Clone, Debug)]
+pub struct Zones<L, S = u32>
+where
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    index: CNQuadtree<Option<L>, S>,
+}
+
+impl<L, S> Zones<L, S>
+where
+    L: Clone + PartialEq,
+    S: Copy + Clone + PartialOrd + PartialEq + NumAssign + ToPrimitive + NumOps + FromPrimitive,
+{
+    /// Returns an empty map of zones within `bounds`, split down to `max_depth` levels.
+    pub fn new(bounds: impl Into<Bounds<S>>, max_depth: usize) -> Self {
+        let mut index = CNQuadtree::new(None, bounds);
+        index.set_max_depth(Some(max_depth));
+        Self { index }
+    }
+
+    /// Puts `region` in the zone labeled `label`, taking it out of any other zone.
+    pub fn paint(&mut self, region: impl Into<Bounds<S>>, label: L) {
+        self.fill(region.into(), Some(label));
+    }
+
+    /// Puts the leaves of `tree` in the zone labeled `label`, taking them out of any other
+    /// zone. Indices that aren't in the tree are ignored.
+    pub fn paint_leaves<T>(
+        &mut self,
+        tree: &CNQuadtree<T, S>,
+        leaves: impl IntoIterator<Item = NodeId>,
+        label: L,
+    ) {
+        for leaf in leaves {
+            if let Some(node) = tree.get_node(leaf) {
+                self.fill(node.get_bounds(), Some(label.clone()));
+            }
+        }
+    }
+
+    /// Takes `region` out of every zone.
+    pub fn erase(&mut self, region: impl Into<Bounds<S>>) {
+        self.fill(region.into(), None);
+    }
+
+    /// Returns the label of the zone the point is in, or None if it isn't in one. Takes time
+    /// proportional to the depth of the map at the point.
+    pub fn zone_at(&self, point: Point<S>) -> Option<&L> {
+        let leaf = self.index.point_locate(point)?;
+        self.index.get_node(leaf)?.get_item().as_ref()
+    }
+
+    /// Returns the labels of the zones overlapping `region` with a non-zero area, each once.
+    pub fn zones_intersecting(&self, region: impl Into<Bounds<S>>) -> Vec<&L> {
+        let region = region.into();
+        let mut zones: Vec<&L> = Vec::new();
+        for leaf in self.index.region_locate(region).unwrap_or_default() {
+            let label = self
+                .index
+                .get_node(leaf)
+                .filter(|n| overlaps_with_area(n.get_bounds(), region))
+                .and_then(|n| n.get_item().as_ref());
+            if let Some(label) = label.filter(|label| !zones.contains(label)) {
+                zones.push(label);
+            }
+        }
+        zones
+    }
+
+    fn fill(&mut self, region: Bounds<S>, label: Option<L>) {
+        let mut stack = vec![self.index.get_root()];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.index.get_node(index) else {
+                continue;
+            };
+            let bounds = node.get_bounds();
+            if !bounds.intersects(region) {
+                continue;
+            }
+            if !region.contains_rect(bounds) {
+                let children = match node.get_children_index() {
+                    Some(children) => Some(children),
+                    None => {
+                        let item = node.get_item().clone();
+                        let items = std::array::from_fn(|_| item.clone());
+                        // Cells that can't be split are painted whole.
+                        self.index.subdivide(index, items).ok()
+                    }
+                };
+                if let Some(children) = children {
+                    stack.extend(children);
+                    continue;
+                }
+            }
+            if self.index.collapse(index).is_ok() {
+                if let Some(node) = self.index.get_node_mut(index) {
+                    *node.get_item_mut() = label.clone();
+                }
+            }
+        }
+        // Compressing only fails on dangling indices, which the index never has.
+        let _ = self.index.compress();
+    }
+}
+
+/// Returns true if the intersection of `a` and `b` has a non-zero area. Unlike
+/// [`Bounds::intersects`], this is false when either is a line or a point.
+fn overlaps_with_area<S: PartialOrd>(a: Bounds<S>, b: Bounds<S>) -> bool {
+    let spans = |a_min: S, a_max: S, b_min: S, b_max: S| {
+        a_min < a_max && b_min < b_max && a_min < b_max && b_min < a_max
+    };
+    spans(a.0, a.2, b.0, b.2) && spans(a.1, a.3, b.1, b.3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zones() {
+        let mut zones = Zones::new((0, 0, 64, 64), 6);
+        zones.paint((0, 0, 32, 64), "west");
+        zones.paint((16, 16, 48, 48), "town");
+        assert_eq!(zones.zone_at((1, 1)), Some(&"west"));
+        assert_eq!(zones.zone_at((20, 20)), Some(&"town"));
+        assert_eq!(zones.zone_at((40, 40)), Some(&"town"));
+        assert_eq!(zones.zone_at((60, 1)), None);
+        assert_eq!(zones.zones_intersecting((0, 0, 20, 20)), [&"west", &"town"]);
+        assert!(zones.zones_intersecting((48, 0, 64, 16)).is_empty());
+        // Zones that only touch the region's edges are left out.
+        assert_eq!(zones.zones_intersecting((0, 0, 16, 16)), [&"west"]);
+        assert!(zones.zones_intersecting((8, 0, 8, 64)).is_empty());
+
+        // Repainting merges cells back together.
+        zones.paint((16, 16, 48, 48), "west");
+        zones.erase((32, 0, 64, 64));
+        assert_eq!(zones.index.leaf_count(), 4);
+        assert_eq!(zones.zone_at((40, 40)), None);
+
+        // Zones of a tree's leaves stay put as the tree is subdivided.
+        let mut tree = CNQuadtree::new(0, (0, 0, 64, 64));
+        let [_, ne, ..] = tree.subdivide(tree.get_root(), [0; 4]).unwrap();
+        zones.paint_leaves(&tree, [ne], "east");
+        let [.., ne_se] = tree.subdivide(ne, [0; 4]).unwrap();
+        let center = tree.get_node(ne_se).unwrap().get_bounds().center().unwrap();
+        assert_eq!(zones.zone_at(center), Some(&"east"));
+    }
+}